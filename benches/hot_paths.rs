@@ -0,0 +1,105 @@
+//! `cargo bench --features bench`. The relay bench needs `DATABASE_URL` and
+//! is skipped without it; the events it creates are removed afterwards.
+
+use std::time::{Duration, Instant};
+
+use axum::http::Uri;
+use chrono::Utc;
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use rust_axum_postgres_api::bench::{self, Pagination, SchemaVersion};
+use serde_json::json;
+use sqlx::postgres::PgPoolOptions;
+
+fn ref_code(c: &mut Criterion) {
+    c.bench_function("ref_code/generate", |b| b.iter(|| bench::ref_code(black_box("charles"))));
+}
+
+fn event_serialization(c: &mut Criterion) {
+    let payload = json!({
+        "status": "success",
+        "event_type": "user_created",
+        "event_data": {"id": "5f0c2a44-3f6e-4f5b-9a55-6d1c0b7e2b11", "user_name": "charles", "ref_code": "chaa1b2"},
+    });
+    let created_at = Utc::now();
+
+    let mut group = c.benchmark_group("event");
+    group.bench_function("with_metadata", |b| {
+        b.iter_batched(|| payload.clone(), |payload| bench::event(42, payload, created_at), BatchSize::SmallInput)
+    });
+    let event = bench::event(42, payload, created_at);
+    for (name, version) in [("v1", SchemaVersion::V1), ("v2", SchemaVersion::V2)] {
+        group.bench_function(format!("render_{}", name), |b| {
+            b.iter(|| bench::render(black_box(&event), version).to_string())
+        });
+    }
+    group.finish();
+}
+
+fn pagination(c: &mut Criterion) {
+    let uri: Uri = "/api/users?page=7&limit=25&sort=-created_at".parse().unwrap();
+    let cursor: Uri = "/api/users?cursor=313530&limit=25".parse().unwrap();
+
+    let mut group = c.benchmark_group("pagination");
+    group.bench_function("from_uri", |b| b.iter(|| Pagination::from_uri(black_box(&uri), 100).unwrap()));
+    group.bench_function("from_uri_cursor", |b| b.iter(|| Pagination::from_uri(black_box(&cursor), 100).unwrap()));
+    let page = Pagination::from_uri(&uri, 100).unwrap();
+    group.bench_function("window", |b| {
+        b.iter(|| {
+            let page = black_box(&page);
+            let sort = page.sort(&["created_at", "user_name"], "created_at").unwrap();
+            (page.limit(10), page.offset(10), page.next_cursor(10, 25), sort)
+        })
+    });
+    group.finish();
+}
+
+const RELAY_EVENTS: u64 = 1_000;
+
+fn outbox_relay(c: &mut Criterion) {
+    let Ok(url) = std::env::var("DATABASE_URL") else {
+        eprintln!("DATABASE_URL is not set, skipping the outbox relay bench");
+        return;
+    };
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let (db, data) = runtime.block_on(async {
+        let db = PgPoolOptions::new().max_connections(5).connect(&url).await.unwrap();
+        let data = bench::state(db.clone()).await;
+        (db, data)
+    });
+    let _rx = bench::subscribe(&data);
+
+    let mut group = c.benchmark_group("outbox");
+    group.throughput(Throughput::Elements(RELAY_EVENTS));
+    group.sample_size(10);
+    group.bench_function("relay", |b| {
+        b.to_async(&runtime).iter_custom(|iters| {
+            let db = db.clone();
+            let data = data.clone();
+            async move {
+                let mut elapsed = Duration::ZERO;
+                for _ in 0..iters {
+                    sqlx::query(
+                        "INSERT INTO events (event_type, payload) SELECT 'bench', jsonb_build_object('status', 'success', 'event_type', 'bench', 'event_data', jsonb_build_object('n', n)) FROM generate_series(1, $1) AS n",
+                    )
+                    .bind(RELAY_EVENTS as i64)
+                    .execute(&db)
+                    .await
+                    .unwrap();
+
+                    let started = Instant::now();
+                    bench::relay(&data).await.unwrap();
+                    elapsed += started.elapsed();
+                }
+                elapsed
+            }
+        })
+    });
+    group.finish();
+
+    runtime.block_on(async {
+        sqlx::query("DELETE FROM events WHERE event_type = 'bench'").execute(&db).await.unwrap();
+    });
+}
+
+criterion_group!(benches, ref_code, event_serialization, pagination, outbox_relay);
+criterion_main!(benches);
@@ -0,0 +1,49 @@
+use std::{fs, process::Command, time::SystemTime};
+
+// The schema check embeds the migrations, rebuild when they change. The
+// build info records what was built: the commit, when, with which features
+// and up to which migration.
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs/heads");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+
+    let commit = Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|commit| commit.trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=BUILD_GIT_COMMIT={}", commit);
+
+    // reproducible builds pin the time
+    let built_at = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs())
+        });
+    println!("cargo:rustc-env=BUILD_UNIX_TIME={}", built_at);
+
+    let mut features: Vec<String> = std::env::vars()
+        .filter_map(|(name, _)| Some(name.strip_prefix("CARGO_FEATURE_")?.to_lowercase().replace('_', "-")))
+        .collect();
+    features.sort();
+    println!("cargo:rustc-env=BUILD_FEATURES={}", features.join(","));
+
+    let migration = fs::read_dir("migrations")
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name().into_string().ok()?;
+            name.split_once('_')?.0.parse::<i64>().ok()
+        })
+        .max()
+        .unwrap_or(0);
+    println!("cargo:rustc-env=BUILD_MIGRATION={}", migration);
+}
@@ -0,0 +1,12 @@
+-- Add up migration script here
+CREATE TABLE
+    IF NOT EXISTS audit_logs (
+        id UUID PRIMARY KEY NOT NULL DEFAULT (uuid_generate_v4()),
+        action VARCHAR(255) NOT NULL,
+        details JSONB NOT NULL DEFAULT '{}',
+        created_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW()
+    );
+
+CREATE INDEX IF NOT EXISTS audit_logs_created_at_idx ON audit_logs (created_at);
@@ -0,0 +1,19 @@
+-- Add up migration script here
+CREATE TABLE
+    IF NOT EXISTS contacts (
+        id UUID PRIMARY KEY NOT NULL DEFAULT (uuid_generate_v4()),
+        owner_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+        name VARCHAR(255) NOT NULL,
+        email VARCHAR(255) NOT NULL,
+        phone VARCHAR(64),
+        tags TEXT[] NOT NULL DEFAULT '{}',
+        user_id UUID REFERENCES users (id) ON DELETE SET NULL,
+        created_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW(),
+            updated_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW()
+    );
+
+CREATE UNIQUE INDEX IF NOT EXISTS contacts_owner_email_idx ON contacts (owner_id, lower(email));
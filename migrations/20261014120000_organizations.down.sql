@@ -0,0 +1,8 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS contacts_org_email_idx;
+DELETE FROM contacts WHERE org_id IS NOT NULL;
+ALTER TABLE contacts DROP COLUMN IF EXISTS org_id;
+DROP INDEX IF EXISTS contacts_owner_email_idx;
+CREATE UNIQUE INDEX IF NOT EXISTS contacts_owner_email_idx ON contacts (owner_id, lower(email));
+DROP TABLE IF EXISTS org_members;
+DROP TABLE IF EXISTS organizations;
@@ -0,0 +1,30 @@
+-- Add up migration script here
+CREATE TABLE
+    IF NOT EXISTS organizations (
+        id UUID PRIMARY KEY NOT NULL DEFAULT (uuid_generate_v4()),
+        name VARCHAR(255) NOT NULL,
+        created_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW(),
+            updated_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW()
+    );
+
+CREATE TABLE
+    IF NOT EXISTS org_members (
+        org_id UUID NOT NULL REFERENCES organizations (id) ON DELETE CASCADE,
+        user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+        role VARCHAR(32) NOT NULL DEFAULT 'member' CHECK (role IN ('owner', 'admin', 'member')),
+        created_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW(),
+            PRIMARY KEY (org_id, user_id)
+    );
+
+ALTER TABLE contacts ADD COLUMN IF NOT EXISTS org_id UUID REFERENCES organizations (id) ON DELETE CASCADE;
+
+-- personal and org address books dedup separately
+DROP INDEX IF EXISTS contacts_owner_email_idx;
+CREATE UNIQUE INDEX IF NOT EXISTS contacts_owner_email_idx ON contacts (owner_id, lower(email)) WHERE org_id IS NULL;
+CREATE UNIQUE INDEX IF NOT EXISTS contacts_org_email_idx ON contacts (org_id, lower(email)) WHERE org_id IS NOT NULL;
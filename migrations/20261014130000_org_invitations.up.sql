@@ -0,0 +1,17 @@
+-- Add up migration script here
+CREATE TABLE
+    IF NOT EXISTS org_invitations (
+        id UUID PRIMARY KEY NOT NULL DEFAULT (uuid_generate_v4()),
+        org_id UUID NOT NULL REFERENCES organizations (id) ON DELETE CASCADE,
+        email VARCHAR(255) NOT NULL,
+        role VARCHAR(32) NOT NULL DEFAULT 'member' CHECK (role IN ('admin', 'member')),
+        invited_by UUID REFERENCES users (id) ON DELETE SET NULL,
+        accepted_at TIMESTAMP
+        WITH
+            TIME ZONE,
+            created_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW()
+    );
+
+CREATE UNIQUE INDEX IF NOT EXISTS org_invitations_pending_idx ON org_invitations (org_id, lower(email)) WHERE accepted_at IS NULL;
@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS org_quotas;
+DROP TABLE IF EXISTS org_usage;
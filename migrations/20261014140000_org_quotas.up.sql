@@ -0,0 +1,18 @@
+-- Add up migration script here
+CREATE TABLE
+    IF NOT EXISTS org_usage (
+        org_id UUID NOT NULL REFERENCES organizations (id) ON DELETE CASCADE,
+        metric VARCHAR(64) NOT NULL,
+        period DATE NOT NULL,
+        used INT NOT NULL DEFAULT 0,
+        PRIMARY KEY (org_id, metric, period)
+    );
+
+-- per-organization overrides of the configured default limits
+CREATE TABLE
+    IF NOT EXISTS org_quotas (
+        org_id UUID NOT NULL REFERENCES organizations (id) ON DELETE CASCADE,
+        metric VARCHAR(64) NOT NULL,
+        max_value INT NOT NULL,
+        PRIMARY KEY (org_id, metric)
+    );
@@ -0,0 +1,8 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS stripe_events;
+ALTER TABLE organizations
+    DROP COLUMN IF EXISTS subscription_status,
+    DROP COLUMN IF EXISTS stripe_subscription_id,
+    DROP COLUMN IF EXISTS stripe_customer_id,
+    DROP COLUMN IF EXISTS plan_id;
+DROP TABLE IF EXISTS plans;
@@ -0,0 +1,36 @@
+-- Add up migration script here
+CREATE TABLE
+    IF NOT EXISTS plans (
+        id VARCHAR(64) PRIMARY KEY NOT NULL,
+        name VARCHAR(255) NOT NULL,
+        stripe_price_id VARCHAR(255) UNIQUE,
+        -- NULL limits fall back to the configured defaults
+        invites_per_month INT,
+        sse_connections INT,
+        features TEXT[] NOT NULL DEFAULT '{}',
+        created_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW()
+    );
+
+INSERT INTO plans (id, name, invites_per_month, sse_connections, features)
+VALUES
+    ('free', 'Free', NULL, NULL, '{}'),
+    ('pro', 'Pro', 1000, 500, '{custom_ref_codes,sms}')
+ON CONFLICT (id) DO NOTHING;
+
+ALTER TABLE organizations
+    ADD COLUMN IF NOT EXISTS plan_id VARCHAR(64) NOT NULL DEFAULT 'free' REFERENCES plans (id),
+    ADD COLUMN IF NOT EXISTS stripe_customer_id VARCHAR(255) UNIQUE,
+    ADD COLUMN IF NOT EXISTS stripe_subscription_id VARCHAR(255),
+    ADD COLUMN IF NOT EXISTS subscription_status VARCHAR(64);
+
+-- webhook deliveries already handled, Stripe retries and may send an event twice
+CREATE TABLE
+    IF NOT EXISTS stripe_events (
+        id VARCHAR(255) PRIMARY KEY NOT NULL,
+        event_type VARCHAR(255) NOT NULL,
+        received_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW()
+    );
@@ -0,0 +1,12 @@
+-- Add up migration script here
+CREATE TABLE
+    IF NOT EXISTS events (
+        id BIGSERIAL PRIMARY KEY NOT NULL,
+        event_type VARCHAR(255) NOT NULL,
+        payload JSONB NOT NULL,
+        created_at TIMESTAMP
+        WITH
+            TIME ZONE NOT NULL DEFAULT NOW()
+    );
+
+CREATE INDEX IF NOT EXISTS events_created_at_idx ON events (created_at);
@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS events_unpublished_idx;
+
+ALTER TABLE events DROP COLUMN IF EXISTS published_at;
@@ -0,0 +1,7 @@
+-- Add up migration script here
+ALTER TABLE events ADD COLUMN IF NOT EXISTS published_at TIMESTAMP WITH TIME ZONE;
+
+-- everything stored so far was broadcast right away
+UPDATE events SET published_at = created_at WHERE published_at IS NULL;
+
+CREATE INDEX IF NOT EXISTS events_unpublished_idx ON events (id) WHERE published_at IS NULL;
@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS events_bus_unpublished_idx;
+
+ALTER TABLE events DROP COLUMN IF EXISTS bus_published_at;
@@ -0,0 +1,7 @@
+-- Add up migration script here
+ALTER TABLE events ADD COLUMN IF NOT EXISTS bus_published_at TIMESTAMP WITH TIME ZONE;
+
+-- events from before the bus existed are not sent to it
+UPDATE events SET bus_published_at = created_at WHERE bus_published_at IS NULL;
+
+CREATE INDEX IF NOT EXISTS events_bus_unpublished_idx ON events (id) WHERE bus_published_at IS NULL;
@@ -0,0 +1,6 @@
+-- Add down migration script here
+DROP TRIGGER IF EXISTS org_invitations_change_feed ON org_invitations;
+
+DROP TRIGGER IF EXISTS users_change_feed ON users;
+
+DROP FUNCTION IF EXISTS record_row_change();
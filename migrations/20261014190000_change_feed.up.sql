@@ -0,0 +1,45 @@
+-- Add up migration script here
+
+-- Every write to these tables lands in the outbox as a `row_changed` event,
+-- including ones made outside the API. Only the row id is included, clients
+-- fetch the row if they need it.
+CREATE OR REPLACE FUNCTION record_row_change() RETURNS TRIGGER AS $$
+DECLARE
+    changed RECORD;
+    event JSONB;
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        changed := OLD;
+    ELSE
+        changed := NEW;
+    END IF;
+
+    event := jsonb_build_object(
+        'status', 'success',
+        'event_type', 'row_changed',
+        'event_data', jsonb_build_object(
+            'table', TG_TABLE_NAME,
+            'operation', lower(TG_OP),
+            'id', changed.id
+        )
+    );
+    -- invitations carry an email, so only the org's admins hear about them
+    IF TG_TABLE_NAME = 'org_invitations' THEN
+        event := event || jsonb_build_object(
+            'audience', jsonb_build_object('org_id', changed.org_id, 'roles', jsonb_build_array('owner', 'admin'))
+        );
+    END IF;
+
+    INSERT INTO events (event_type, payload) VALUES ('row_changed', event);
+    PERFORM pg_notify('events_outbox', '');
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER users_change_feed
+    AFTER INSERT OR UPDATE OR DELETE ON users
+    FOR EACH ROW EXECUTE FUNCTION record_row_change();
+
+CREATE TRIGGER org_invitations_change_feed
+    AFTER INSERT OR UPDATE OR DELETE ON org_invitations
+    FOR EACH ROW EXECUTE FUNCTION record_row_change();
@@ -0,0 +1,4 @@
+-- Add down migration script here
+ALTER TABLE org_invitations DROP COLUMN IF EXISTS rsvp_at;
+ALTER TABLE org_invitations DROP COLUMN IF EXISTS rsvp;
+ALTER TABLE org_invitations DROP COLUMN IF EXISTS token_version;
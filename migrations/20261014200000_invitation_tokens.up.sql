@@ -0,0 +1,5 @@
+-- Add up migration script here
+-- bumping the version revokes every invite link handed out so far
+ALTER TABLE org_invitations ADD COLUMN IF NOT EXISTS token_version INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE org_invitations ADD COLUMN IF NOT EXISTS rsvp VARCHAR(16) CHECK (rsvp IN ('accepted', 'declined'));
+ALTER TABLE org_invitations ADD COLUMN IF NOT EXISTS rsvp_at TIMESTAMP WITH TIME ZONE;
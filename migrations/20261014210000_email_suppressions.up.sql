@@ -0,0 +1,11 @@
+-- Add up migration script here
+-- addresses no email is sent to anymore, stored lower-cased
+CREATE TABLE
+    IF NOT EXISTS email_suppressions (
+        email VARCHAR(255) PRIMARY KEY NOT NULL,
+        reason VARCHAR(32) NOT NULL CHECK (reason IN ('unsubscribed', 'bounced', 'complained')),
+        details TEXT,
+        created_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW()
+    );
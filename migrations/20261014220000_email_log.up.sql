@@ -0,0 +1,30 @@
+-- Add up migration script here
+-- one row per email handed to the provider, moved along by its webhooks
+CREATE TABLE
+    IF NOT EXISTS email_log (
+        id UUID PRIMARY KEY NOT NULL DEFAULT (uuid_generate_v4()),
+        invitation_id UUID REFERENCES org_invitations (id) ON DELETE CASCADE,
+        email VARCHAR(255) NOT NULL,
+        status VARCHAR(16) NOT NULL DEFAULT 'queued' CHECK (status IN ('queued', 'sent', 'delivered', 'opened', 'bounced')),
+        details TEXT,
+        sent_at TIMESTAMP
+        WITH
+            TIME ZONE,
+            delivered_at TIMESTAMP
+        WITH
+            TIME ZONE,
+            opened_at TIMESTAMP
+        WITH
+            TIME ZONE,
+            bounced_at TIMESTAMP
+        WITH
+            TIME ZONE,
+            created_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW(),
+            updated_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW()
+    );
+
+CREATE INDEX IF NOT EXISTS email_log_invitation_idx ON email_log (invitation_id, created_at);
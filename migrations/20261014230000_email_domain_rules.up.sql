@@ -0,0 +1,19 @@
+-- Add up migration script here
+-- rules without an organization apply to signups and every invitation,
+-- an organization's own rules only to its invitations
+CREATE TABLE
+    IF NOT EXISTS email_domain_rules (
+        id UUID PRIMARY KEY NOT NULL DEFAULT (uuid_generate_v4()),
+        domain VARCHAR(255) NOT NULL,
+        rule VARCHAR(16) NOT NULL CHECK (rule IN ('block', 'allow')),
+        org_id UUID REFERENCES organizations (id) ON DELETE CASCADE,
+        created_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW()
+    );
+
+CREATE UNIQUE INDEX IF NOT EXISTS email_domain_rules_unique_idx ON email_domain_rules (
+    domain,
+    rule,
+    COALESCE(org_id, '00000000-0000-0000-0000-000000000000')
+);
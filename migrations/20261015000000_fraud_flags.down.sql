@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS fraud_flags;
+
+DROP TABLE IF EXISTS signups;
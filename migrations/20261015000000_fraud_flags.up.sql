@@ -0,0 +1,34 @@
+-- Add up migration script here
+-- where each signup came from, for spotting referral abuse
+CREATE TABLE
+    IF NOT EXISTS signups (
+        user_id UUID PRIMARY KEY NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+        ip VARCHAR(64),
+        referrer_id UUID REFERENCES users (id) ON DELETE SET NULL,
+        created_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW()
+    );
+
+CREATE INDEX IF NOT EXISTS signups_ip_idx ON signups (ip, created_at);
+
+CREATE INDEX IF NOT EXISTS signups_referrer_idx ON signups (referrer_id, created_at);
+
+-- referred signups that looked suspicious, the referrer is only credited once approved
+CREATE TABLE
+    IF NOT EXISTS fraud_flags (
+        id UUID PRIMARY KEY NOT NULL DEFAULT (uuid_generate_v4()),
+        user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+        referrer_id UUID REFERENCES users (id) ON DELETE SET NULL,
+        reasons TEXT[] NOT NULL,
+        details JSONB NOT NULL DEFAULT '{}',
+        status VARCHAR(16) NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'approved', 'rejected')),
+        reviewed_at TIMESTAMP
+        WITH
+            TIME ZONE,
+            created_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW()
+    );
+
+CREATE INDEX IF NOT EXISTS fraud_flags_status_idx ON fraud_flags (status, created_at);
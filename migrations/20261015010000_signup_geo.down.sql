@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE signups DROP COLUMN IF EXISTS region;
+ALTER TABLE signups DROP COLUMN IF EXISTS country;
@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- ISO 3166 codes looked up from the signup address, when a GeoIP database is configured
+ALTER TABLE signups ADD COLUMN IF NOT EXISTS country VARCHAR(2);
+ALTER TABLE signups ADD COLUMN IF NOT EXISTS region VARCHAR(8);
@@ -0,0 +1,10 @@
+-- Add down migration script here
+DROP MATERIALIZED VIEW IF EXISTS analytics_sse_hourly;
+
+DROP MATERIALIZED VIEW IF EXISTS analytics_invitations_daily;
+
+DROP MATERIALIZED VIEW IF EXISTS analytics_signups_daily;
+
+DROP TABLE IF EXISTS view_refreshes;
+
+DROP TABLE IF EXISTS sse_samples;
@@ -0,0 +1,63 @@
+-- Add up migration script here
+-- open SSE connections, sampled by each instance on a schedule
+CREATE TABLE
+    IF NOT EXISTS sse_samples (
+        instance_id UUID NOT NULL,
+        connections INT NOT NULL,
+        sampled_at TIMESTAMP
+        WITH
+            TIME ZONE NOT NULL DEFAULT NOW()
+    );
+
+CREATE INDEX IF NOT EXISTS sse_samples_sampled_at_idx ON sse_samples (sampled_at);
+
+-- when each materialized view was last refreshed by the scheduler
+CREATE TABLE
+    IF NOT EXISTS view_refreshes (
+        view_name VARCHAR(64) PRIMARY KEY NOT NULL,
+        refreshed_at TIMESTAMP
+        WITH
+            TIME ZONE NOT NULL DEFAULT NOW()
+    );
+
+CREATE MATERIALIZED VIEW IF NOT EXISTS analytics_signups_daily AS
+SELECT
+    date_trunc('day', u.created_at) AS day,
+    COUNT(*) AS signups,
+    COUNT(s.referrer_id) AS referred
+FROM users u
+    LEFT JOIN signups s ON s.user_id = u.id
+WHERE u.created_at IS NOT NULL
+GROUP BY 1;
+
+CREATE UNIQUE INDEX IF NOT EXISTS analytics_signups_daily_day_idx ON analytics_signups_daily (day);
+
+CREATE MATERIALIZED VIEW IF NOT EXISTS analytics_invitations_daily AS
+SELECT
+    date_trunc('day', created_at) AS day,
+    COUNT(*) AS sent,
+    COUNT(*) FILTER (WHERE rsvp IS NOT NULL OR accepted_at IS NOT NULL) AS answered,
+    COUNT(*) FILTER (WHERE accepted_at IS NOT NULL OR rsvp = 'accepted') AS accepted
+FROM org_invitations
+WHERE created_at IS NOT NULL
+GROUP BY 1;
+
+CREATE UNIQUE INDEX IF NOT EXISTS analytics_invitations_daily_day_idx ON analytics_invitations_daily (day);
+
+-- each instance's hourly average and peak, summed across instances
+CREATE MATERIALIZED VIEW IF NOT EXISTS analytics_sse_hourly AS
+SELECT
+    hour,
+    SUM(average)::FLOAT8 AS average,
+    SUM(peak)::INT AS peak
+FROM (
+        SELECT
+            date_trunc('hour', sampled_at) AS hour,
+            AVG(connections) AS average,
+            MAX(connections) AS peak
+        FROM sse_samples
+        GROUP BY 1, instance_id
+    ) instances
+GROUP BY hour;
+
+CREATE UNIQUE INDEX IF NOT EXISTS analytics_sse_hourly_hour_idx ON analytics_sse_hourly (hour);
@@ -0,0 +1,14 @@
+-- Add up migration script here
+-- referrers ranked by credited referrals, refreshed by the scheduler
+CREATE MATERIALIZED VIEW IF NOT EXISTS leaderboard AS
+SELECT
+    RANK() OVER (ORDER BY added_by_ref_code DESC) AS rank,
+    id AS user_id,
+    user_name,
+    added_by_ref_code AS referrals
+FROM users
+WHERE added_by_ref_code > 0;
+
+CREATE UNIQUE INDEX IF NOT EXISTS leaderboard_user_id_idx ON leaderboard (user_id);
+
+CREATE INDEX IF NOT EXISTS leaderboard_rank_idx ON leaderboard (rank, user_name);
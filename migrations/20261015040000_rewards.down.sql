@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS rewards;
+
+DROP TABLE IF EXISTS reward_rules;
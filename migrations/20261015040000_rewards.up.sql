@@ -0,0 +1,33 @@
+-- Add up migration script here
+-- what top referrers get, evaluated by the scheduler
+CREATE TABLE
+    IF NOT EXISTS reward_rules (
+        id UUID PRIMARY KEY NOT NULL DEFAULT (uuid_generate_v4()),
+        name VARCHAR(100) NOT NULL,
+        kind VARCHAR(32) NOT NULL CHECK (kind IN ('first_referrers', 'monthly_top')),
+        size INT NOT NULL CHECK (size > 0),
+        badge VARCHAR(64),
+        points INT,
+        created_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW(),
+            CHECK (badge IS NOT NULL OR points IS NOT NULL)
+    );
+
+-- one reward per rule, user and period, so evaluating again awards nothing twice
+CREATE TABLE
+    IF NOT EXISTS rewards (
+        id UUID PRIMARY KEY NOT NULL DEFAULT (uuid_generate_v4()),
+        user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+        rule_id UUID REFERENCES reward_rules (id) ON DELETE SET NULL,
+        period VARCHAR(16) NOT NULL,
+        badge VARCHAR(64),
+        points INT,
+        created_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW()
+    );
+
+CREATE UNIQUE INDEX IF NOT EXISTS rewards_rule_user_period_idx ON rewards (rule_id, user_id, period);
+
+CREATE INDEX IF NOT EXISTS rewards_user_idx ON rewards (user_id, created_at);
@@ -0,0 +1,11 @@
+-- Add up migration script here
+-- achievements users have earned, each at most once
+CREATE TABLE
+    IF NOT EXISTS badges (
+        user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+        badge VARCHAR(64) NOT NULL,
+        earned_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW(),
+            PRIMARY KEY (user_id, badge)
+    );
@@ -0,0 +1,14 @@
+-- Add up migration script here
+-- users who blocked other users
+CREATE TABLE
+    IF NOT EXISTS blocks (
+        blocker_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+        blocked_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+        created_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW(),
+            PRIMARY KEY (blocker_id, blocked_id),
+            CHECK (blocker_id <> blocked_id)
+    );
+
+CREATE INDEX IF NOT EXISTS blocks_blocked_idx ON blocks (blocked_id);
@@ -0,0 +1,12 @@
+-- Add up migration script here
+-- who gets to see what of a user's profile, users without a row get the defaults
+CREATE TABLE
+    IF NOT EXISTS privacy_settings (
+        user_id UUID PRIMARY KEY NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+        profile_visibility VARCHAR(16) NOT NULL DEFAULT 'public' CHECK (profile_visibility IN ('public', 'ref_code_only', 'private')),
+        hide_email BOOLEAN NOT NULL DEFAULT TRUE,
+        hide_referral_stats BOOLEAN NOT NULL DEFAULT FALSE,
+        updated_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW()
+    );
@@ -0,0 +1,10 @@
+-- Add down migration script here
+DROP TRIGGER IF EXISTS events_touch_collection ON events;
+
+DROP TRIGGER IF EXISTS privacy_settings_touch_collection ON privacy_settings;
+
+DROP TRIGGER IF EXISTS users_touch_collection ON users;
+
+DROP FUNCTION IF EXISTS touch_collection();
+
+DROP TABLE IF EXISTS collection_changes;
@@ -0,0 +1,35 @@
+-- Add up migration script here
+
+-- When each listed collection last changed, for Last-Modified and
+-- If-Modified-Since. Bumped by statement triggers so writes made outside the
+-- API count too, and deletes count where max(updated_at) wouldn't notice them.
+CREATE TABLE
+    IF NOT EXISTS collection_changes (
+        collection VARCHAR(64) PRIMARY KEY NOT NULL,
+        changed_at TIMESTAMP
+        WITH
+            TIME ZONE NOT NULL DEFAULT NOW()
+    );
+
+INSERT INTO collection_changes (collection) VALUES ('users'), ('events') ON CONFLICT DO NOTHING;
+
+CREATE OR REPLACE FUNCTION touch_collection() RETURNS TRIGGER AS $$
+BEGIN
+    INSERT INTO collection_changes (collection, changed_at) VALUES (TG_ARGV[0], NOW())
+    ON CONFLICT (collection) DO UPDATE SET changed_at = EXCLUDED.changed_at;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER users_touch_collection
+    AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON users
+    FOR EACH STATEMENT EXECUTE FUNCTION touch_collection('users');
+
+-- the user listing shows what privacy settings let through
+CREATE TRIGGER privacy_settings_touch_collection
+    AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON privacy_settings
+    FOR EACH STATEMENT EXECUTE FUNCTION touch_collection('users');
+
+CREATE TRIGGER events_touch_collection
+    AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON events
+    FOR EACH STATEMENT EXECUTE FUNCTION touch_collection('events');
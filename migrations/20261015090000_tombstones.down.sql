@@ -0,0 +1,8 @@
+-- Add down migration script here
+DROP TRIGGER IF EXISTS org_invitations_tombstone ON org_invitations;
+
+DROP TRIGGER IF EXISTS users_tombstone ON users;
+
+DROP FUNCTION IF EXISTS record_tombstone();
+
+DROP TABLE IF EXISTS tombstones;
@@ -0,0 +1,57 @@
+-- Add up migration script here
+
+-- What was deleted and when, for delta sync and webhook consumers. Each
+-- deletion is also put in the outbox as an `entity_deleted` event, whose id
+-- is kept so sync can tell which tombstones fall after a cursor.
+CREATE TABLE
+    IF NOT EXISTS tombstones (
+        entity_type VARCHAR(32) NOT NULL,
+        entity_id UUID NOT NULL,
+        -- set for entities only an organization's admins may see
+        org_id UUID,
+        event_id BIGINT NOT NULL,
+        deleted_at TIMESTAMP
+        WITH
+            TIME ZONE NOT NULL DEFAULT NOW(),
+            PRIMARY KEY (entity_type, entity_id)
+    );
+
+CREATE INDEX IF NOT EXISTS tombstones_event_id_idx ON tombstones (event_id);
+CREATE INDEX IF NOT EXISTS tombstones_deleted_at_idx ON tombstones (deleted_at);
+
+-- the variables are named apart from the tombstones columns, which would
+-- otherwise make the insert ambiguous
+CREATE OR REPLACE FUNCTION record_tombstone() RETURNS TRIGGER AS $$
+DECLARE
+    kind TEXT := TG_ARGV[0];
+    audience_org UUID;
+    event JSONB;
+    new_event_id BIGINT;
+BEGIN
+    event := jsonb_build_object(
+        'status', 'success',
+        'event_type', 'entity_deleted',
+        'event_data', jsonb_build_object('type', kind, 'id', OLD.id, 'deleted_at', NOW())
+    );
+    IF TG_TABLE_NAME = 'org_invitations' THEN
+        audience_org := OLD.org_id;
+        event := event || jsonb_build_object(
+            'audience', jsonb_build_object('org_id', audience_org, 'roles', jsonb_build_array('owner', 'admin'))
+        );
+    END IF;
+
+    INSERT INTO events (event_type, payload) VALUES ('entity_deleted', event) RETURNING id INTO new_event_id;
+    INSERT INTO tombstones (entity_type, entity_id, org_id, event_id) VALUES (kind, OLD.id, audience_org, new_event_id)
+    ON CONFLICT (entity_type, entity_id) DO UPDATE SET org_id = EXCLUDED.org_id, event_id = EXCLUDED.event_id, deleted_at = EXCLUDED.deleted_at;
+    PERFORM pg_notify('events_outbox', '');
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER users_tombstone
+    AFTER DELETE ON users
+    FOR EACH ROW EXECUTE FUNCTION record_tombstone('user');
+
+CREATE TRIGGER org_invitations_tombstone
+    AFTER DELETE ON org_invitations
+    FOR EACH ROW EXECUTE FUNCTION record_tombstone('invitation');
@@ -0,0 +1,2 @@
+-- Add up migration script here
+ALTER TABLE audit_logs ADD COLUMN ip VARCHAR(45);
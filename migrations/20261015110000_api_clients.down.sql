@@ -0,0 +1,6 @@
+-- Add down migration script here
+ALTER TABLE audit_logs DROP COLUMN api_client_id;
+
+DROP TABLE IF EXISTS request_signatures;
+
+DROP TABLE IF EXISTS api_clients;
@@ -0,0 +1,28 @@
+-- Add up migration script here
+-- backend integrations that sign their requests with a shared secret
+CREATE TABLE
+    IF NOT EXISTS api_clients (
+        id UUID PRIMARY KEY NOT NULL DEFAULT (uuid_generate_v4()),
+        name VARCHAR(100) NOT NULL UNIQUE,
+        secret VARCHAR(64) NOT NULL,
+        created_at TIMESTAMP
+        WITH
+            TIME ZONE DEFAULT NOW(),
+            revoked_at TIMESTAMP
+        WITH
+            TIME ZONE
+    );
+
+-- signatures seen within the tolerance window, so none is accepted twice
+CREATE TABLE
+    IF NOT EXISTS request_signatures (
+        signature VARCHAR(64) PRIMARY KEY NOT NULL,
+        client_id UUID NOT NULL REFERENCES api_clients (id) ON DELETE CASCADE,
+        created_at TIMESTAMP
+        WITH
+            TIME ZONE NOT NULL DEFAULT NOW()
+    );
+
+CREATE INDEX IF NOT EXISTS request_signatures_created_at_idx ON request_signatures (created_at);
+
+ALTER TABLE audit_logs ADD COLUMN api_client_id UUID;
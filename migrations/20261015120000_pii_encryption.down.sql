@@ -0,0 +1,17 @@
+-- Add down migration script here
+-- sealed values can't be turned back in SQL, run `reseal-pii` without a key first
+ALTER TABLE email_log ALTER COLUMN email TYPE VARCHAR(255);
+
+DROP INDEX IF EXISTS org_invitations_email_index_idx;
+DROP INDEX IF EXISTS org_invitations_pending_idx;
+CREATE UNIQUE INDEX IF NOT EXISTS org_invitations_pending_idx ON org_invitations (org_id, lower(email)) WHERE accepted_at IS NULL;
+ALTER TABLE org_invitations DROP COLUMN email_index, ALTER COLUMN email TYPE VARCHAR(255);
+
+DROP INDEX IF EXISTS contacts_owner_email_idx;
+DROP INDEX IF EXISTS contacts_org_email_idx;
+CREATE UNIQUE INDEX IF NOT EXISTS contacts_owner_email_idx ON contacts (owner_id, lower(email)) WHERE org_id IS NULL;
+CREATE UNIQUE INDEX IF NOT EXISTS contacts_org_email_idx ON contacts (org_id, lower(email)) WHERE org_id IS NOT NULL;
+ALTER TABLE contacts DROP COLUMN email_index, ALTER COLUMN email TYPE VARCHAR(255), ALTER COLUMN phone TYPE VARCHAR(64);
+
+DROP INDEX IF EXISTS users_email_index_key;
+ALTER TABLE users DROP COLUMN email_index, ALTER COLUMN email TYPE VARCHAR(255), ADD CONSTRAINT users_email_key UNIQUE (email);
@@ -0,0 +1,26 @@
+-- Add up migration script here
+-- emails and phone numbers are encrypted by the API, which makes them longer
+-- and different on every write; lookups and uniqueness go through a blind
+-- index instead, a plain SHA-256 of the lower-cased email until the API is
+-- given an index key and the rows are resealed
+ALTER TABLE users ALTER COLUMN email TYPE VARCHAR, ADD COLUMN email_index VARCHAR(64);
+UPDATE users SET email_index = encode(sha256(convert_to(lower(trim(email)), 'UTF8')), 'hex');
+ALTER TABLE users ALTER COLUMN email_index SET NOT NULL, DROP CONSTRAINT users_email_key;
+CREATE UNIQUE INDEX IF NOT EXISTS users_email_index_key ON users (email_index);
+
+ALTER TABLE contacts ALTER COLUMN email TYPE VARCHAR, ALTER COLUMN phone TYPE VARCHAR, ADD COLUMN email_index VARCHAR(64);
+UPDATE contacts SET email_index = encode(sha256(convert_to(lower(trim(email)), 'UTF8')), 'hex');
+ALTER TABLE contacts ALTER COLUMN email_index SET NOT NULL;
+DROP INDEX IF EXISTS contacts_owner_email_idx;
+DROP INDEX IF EXISTS contacts_org_email_idx;
+CREATE UNIQUE INDEX IF NOT EXISTS contacts_owner_email_idx ON contacts (owner_id, email_index) WHERE org_id IS NULL;
+CREATE UNIQUE INDEX IF NOT EXISTS contacts_org_email_idx ON contacts (org_id, email_index) WHERE org_id IS NOT NULL;
+
+ALTER TABLE org_invitations ALTER COLUMN email TYPE VARCHAR, ADD COLUMN email_index VARCHAR(64);
+UPDATE org_invitations SET email_index = encode(sha256(convert_to(lower(trim(email)), 'UTF8')), 'hex');
+ALTER TABLE org_invitations ALTER COLUMN email_index SET NOT NULL;
+DROP INDEX IF EXISTS org_invitations_pending_idx;
+CREATE UNIQUE INDEX IF NOT EXISTS org_invitations_pending_idx ON org_invitations (org_id, email_index) WHERE accepted_at IS NULL;
+CREATE INDEX IF NOT EXISTS org_invitations_email_index_idx ON org_invitations (email_index);
+
+ALTER TABLE email_log ALTER COLUMN email TYPE VARCHAR;
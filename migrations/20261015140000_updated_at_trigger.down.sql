@@ -0,0 +1,7 @@
+-- Add down migration script here
+DROP TRIGGER IF EXISTS email_log_updated_at ON email_log;
+DROP TRIGGER IF EXISTS privacy_settings_updated_at ON privacy_settings;
+DROP TRIGGER IF EXISTS organizations_updated_at ON organizations;
+DROP TRIGGER IF EXISTS contacts_updated_at ON contacts;
+DROP TRIGGER IF EXISTS users_updated_at ON users;
+DROP FUNCTION IF EXISTS set_updated_at();
@@ -0,0 +1,2 @@
+-- Add down migration script here
+ALTER TABLE org_invitations ALTER COLUMN id SET DEFAULT uuid_generate_v4();
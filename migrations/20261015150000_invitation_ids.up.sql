@@ -0,0 +1,4 @@
+-- Add up migration script here
+
+-- ids are UUIDv7 from the app from now on, see ids.rs
+ALTER TABLE org_invitations ALTER COLUMN id DROP DEFAULT;
@@ -0,0 +1,32 @@
+-- Add up migration script here
+-- exports too large to stream in one request, written out in the background
+CREATE TABLE
+    IF NOT EXISTS export_jobs (
+        id UUID PRIMARY KEY NOT NULL,
+        status VARCHAR(16) NOT NULL DEFAULT 'pending',
+        params JSONB NOT NULL,
+        total_rows BIGINT,
+        rows_read BIGINT NOT NULL DEFAULT 0,
+        rows_written BIGINT NOT NULL DEFAULT 0,
+        artifact VARCHAR(255),
+        error TEXT,
+        created_at TIMESTAMP
+        WITH
+            TIME ZONE NOT NULL DEFAULT NOW(),
+            started_at TIMESTAMP
+        WITH
+            TIME ZONE,
+            finished_at TIMESTAMP
+        WITH
+            TIME ZONE,
+            updated_at TIMESTAMP
+        WITH
+            TIME ZONE NOT NULL DEFAULT NOW()
+    );
+
+CREATE INDEX IF NOT EXISTS export_jobs_pending_idx ON export_jobs (created_at) WHERE status = 'pending';
+
+-- a running job keeps this fresh, one that stops doing so was abandoned
+CREATE TRIGGER export_jobs_updated_at
+    BEFORE UPDATE ON export_jobs
+    FOR EACH ROW EXECUTE FUNCTION set_updated_at();
@@ -0,0 +1,2 @@
+-- Add down migration script here
+-- the scrubbed emails aren't put back
@@ -0,0 +1,5 @@
+-- Add up migration script here
+
+-- user_created events are replayed and synced to anyone, they no longer
+-- carry the email and the ones already stored lose it too
+UPDATE events SET payload = payload #- '{event_data,email}' WHERE event_type = 'user_created';
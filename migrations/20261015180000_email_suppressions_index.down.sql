@@ -0,0 +1,3 @@
+-- Add down migration script here
+-- sealed values can't be turned back in SQL, run `reseal-pii` without a key first
+ALTER TABLE email_suppressions DROP CONSTRAINT email_suppressions_pkey, DROP COLUMN email_index, ADD PRIMARY KEY (email), ALTER COLUMN email TYPE VARCHAR(255);
@@ -0,0 +1,12 @@
+-- Add up migration script here
+-- suppressed addresses are sealed by the API like the other stored emails
+-- and looked up by their blind index, see pii.rs and 20261015120000
+ALTER TABLE email_suppressions ALTER COLUMN email TYPE VARCHAR, ADD COLUMN email_index VARCHAR(64);
+UPDATE email_suppressions SET email_index = encode(sha256(convert_to(lower(trim(email)), 'UTF8')), 'hex');
+ALTER TABLE email_suppressions DROP CONSTRAINT email_suppressions_pkey, ALTER COLUMN email_index SET NOT NULL, ADD PRIMARY KEY (email_index);
+
+-- audit entries and invitation events name addresses by blind index or id
+-- only, the addresses in the ones already written are dropped
+UPDATE audit_logs SET details = details - 'email' WHERE action IN ('email.suppressed', 'email.unsuppressed');
+UPDATE events SET payload = payload #- '{event_data,email}'
+    WHERE event_type IN ('org_member_invited', 'org_invitation_revoked', 'org_invitation_answered');
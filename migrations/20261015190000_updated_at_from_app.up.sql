@@ -0,0 +1,15 @@
+-- Add up migration script here
+
+-- The app sets updated_at from its own clock, the trigger keeps that and
+-- only stamps the rows other tools change. An update that changes nothing
+-- but updated_at leaves the row as it was.
+CREATE OR REPLACE FUNCTION set_updated_at() RETURNS TRIGGER AS $$
+BEGIN
+    IF to_jsonb(NEW) - 'updated_at' IS NOT DISTINCT FROM to_jsonb(OLD) - 'updated_at' THEN
+        NEW.updated_at := OLD.updated_at;
+    ELSIF NEW.updated_at IS NOT DISTINCT FROM OLD.updated_at THEN
+        NEW.updated_at := NOW();
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
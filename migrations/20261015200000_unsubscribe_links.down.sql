@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE email_suppressions DROP COLUMN id;
+DROP TABLE IF EXISTS unsubscribe_links;
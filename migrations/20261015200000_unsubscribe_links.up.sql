@@ -0,0 +1,15 @@
+-- Add up migration script here
+
+-- unsubscribe links name a random id instead of the address, the id stands
+-- for the sealed address here
+CREATE TABLE
+    IF NOT EXISTS unsubscribe_links (
+        id UUID NOT NULL PRIMARY KEY,
+        email VARCHAR NOT NULL,
+        email_index VARCHAR(64) NOT NULL UNIQUE,
+        created_at TIMESTAMP WITH TIME ZONE NOT NULL
+    );
+
+-- suppressions are removed by id, so addresses stay out of request paths
+ALTER TABLE email_suppressions ADD COLUMN id UUID NOT NULL DEFAULT uuid_generate_v4();
+ALTER TABLE email_suppressions ALTER COLUMN id DROP DEFAULT, ADD CONSTRAINT email_suppressions_id_key UNIQUE (id);
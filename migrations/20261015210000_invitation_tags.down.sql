@@ -0,0 +1,2 @@
+-- Add down migration script here
+ALTER TABLE org_invitations DROP COLUMN IF EXISTS tags;
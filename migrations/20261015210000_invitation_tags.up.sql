@@ -0,0 +1,4 @@
+-- Add up migration script here
+
+-- labels admins sort their guests into, like `family` or `vip`
+ALTER TABLE org_invitations ADD COLUMN IF NOT EXISTS tags TEXT[] NOT NULL DEFAULT '{}';
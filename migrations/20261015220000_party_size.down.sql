@@ -0,0 +1,5 @@
+-- Add down migration script here
+ALTER TABLE organizations DROP COLUMN IF EXISTS capacity;
+ALTER TABLE org_invitations DROP COLUMN IF EXISTS guest_names;
+ALTER TABLE org_invitations DROP COLUMN IF EXISTS guest_count;
+ALTER TABLE org_invitations DROP COLUMN IF EXISTS max_party_size;
@@ -0,0 +1,10 @@
+-- Add up migration script here
+
+-- how many people a guest may bring, themselves included, and how many
+-- they said are coming
+ALTER TABLE org_invitations ADD COLUMN IF NOT EXISTS max_party_size INTEGER NOT NULL DEFAULT 1 CHECK (max_party_size >= 1);
+ALTER TABLE org_invitations ADD COLUMN IF NOT EXISTS guest_count INTEGER CHECK (guest_count >= 1);
+ALTER TABLE org_invitations ADD COLUMN IF NOT EXISTS guest_names TEXT[] NOT NULL DEFAULT '{}';
+
+-- how many guests the organization has room for, no limit when null
+ALTER TABLE organizations ADD COLUMN IF NOT EXISTS capacity INTEGER CHECK (capacity >= 1);
@@ -0,0 +1,16 @@
+-- Add up migration script here
+
+-- files admins attach for the organization's members, kept in storage
+CREATE TABLE
+    IF NOT EXISTS org_attachments (
+        id UUID NOT NULL PRIMARY KEY,
+        org_id UUID NOT NULL REFERENCES organizations (id) ON DELETE CASCADE,
+        file_name VARCHAR(255) NOT NULL,
+        content_type VARCHAR(100) NOT NULL,
+        size BIGINT NOT NULL,
+        storage_key VARCHAR NOT NULL,
+        uploaded_by UUID REFERENCES users (id) ON DELETE SET NULL,
+        created_at TIMESTAMP WITH TIME ZONE NOT NULL
+    );
+
+CREATE INDEX IF NOT EXISTS org_attachments_org_idx ON org_attachments (org_id, created_at);
@@ -0,0 +1,103 @@
+use std::sync::Arc;
+
+use serde::Serialize;
+use serde_json::json;
+use tokio::sync::broadcast::error::RecvError;
+use uuid::Uuid;
+
+use crate::{events, fraud, model::BadgeModel, AppState};
+
+pub const EVENT_TYPE: &str = "badge_earned";
+
+/// Something a user can earn, shown as a badge on their profile.
+#[derive(Debug, Serialize)]
+pub struct Achievement {
+    pub badge: &'static str,
+    pub title: &'static str,
+    // credited referrals it takes
+    pub referrals: i32,
+}
+
+pub const ACHIEVEMENTS: [Achievement; 2] = [
+    Achievement {
+        badge: "first_referral",
+        title: "First referral",
+        referrals: 1,
+    },
+    Achievement {
+        badge: "ten_referrals",
+        title: "10 referrals",
+        referrals: 10,
+    },
+];
+
+pub fn find(badge: &str) -> Option<&'static Achievement> {
+    ACHIEVEMENTS.iter().find(|achievement| achievement.badge == badge)
+}
+
+/// Checks a referrer's achievements whenever one of their referrals is
+/// credited. Every instance sees every event, awarding is idempotent so
+/// only the first one to get to it awards and announces a badge.
+pub fn spawn_engine(data: Arc<AppState>) {
+    let mut events = data.tx.subscribe();
+    tokio::spawn(async move {
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return,
+            };
+            let Ok(event) = serde_json::from_str::<serde_json::Value>(&event) else {
+                continue;
+            };
+            if event["event_type"] != fraud::CREDITED_EVENT_TYPE {
+                continue;
+            }
+            let Some(referrer_id) = event["event_data"]["referrer_id"].as_str().and_then(|id| id.parse().ok()) else {
+                continue;
+            };
+            if let Err(e) = evaluate(&data, referrer_id).await {
+                println!("🔥 Failed to check achievements for {}: {:?}", referrer_id, e);
+            }
+        }
+    });
+}
+
+/// Awards the user every achievement they've reached and don't have yet.
+pub async fn evaluate(data: &AppState, user_id: Uuid) -> Result<Vec<BadgeModel>, sqlx::Error> {
+    let Some(referrals) = sqlx::query_scalar!("SELECT added_by_ref_code FROM users WHERE id = $1", user_id)
+        .fetch_optional(&data.db)
+        .await?
+    else {
+        return Ok(Vec::new());
+    };
+    let reached: Vec<String> = ACHIEVEMENTS
+        .iter()
+        .filter(|achievement| referrals >= achievement.referrals)
+        .map(|achievement| achievement.badge.to_string())
+        .collect();
+    if reached.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut tx = data.db.begin().await?;
+    let earned = sqlx::query_as!(
+        BadgeModel,
+        "INSERT INTO badges (user_id, badge) SELECT $1, * FROM UNNEST($2::text[]) ON CONFLICT DO NOTHING RETURNING *",
+        user_id,
+        &reached
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    for badge in &earned {
+        let event_to_send = json!({
+            "status": "success",
+            "event_type": EVENT_TYPE,
+            "audience": {"user_id": user_id},
+            "event_data": {"badge": badge, "achievement": find(&badge.badge)}
+        });
+        events::enqueue(&mut tx, event_to_send).await?;
+    }
+    tx.commit().await?;
+    Ok(earned)
+}
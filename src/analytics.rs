@@ -0,0 +1,66 @@
+use std::{sync::Arc, time::Duration};
+
+use uuid::Uuid;
+
+use crate::{scheduler, AppState};
+
+/// The materialized views behind the admin dashboard's charts.
+pub const VIEWS: [&str; 3] = [
+    "analytics_signups_daily",
+    "analytics_invitations_daily",
+    "analytics_sse_hourly",
+];
+
+/// How often the dashboard's numbers are brought up to date, and how often
+/// this instance records its open SSE connections for them.
+pub struct Analytics {
+    refresh_every: Duration,
+    sample_every: Duration,
+    // tells this instance's samples apart from the others'
+    instance_id: Uuid,
+}
+
+impl Analytics {
+    /// The views are refreshed every `ANALYTICS_REFRESH_MINUTES` (15 by
+    /// default) and SSE connections are counted every `SSE_SAMPLE_SECS`
+    /// (60).
+    pub fn from_env() -> Self {
+        let var = |name, default| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .filter(|value| *value > 0)
+                .unwrap_or(default)
+        };
+
+        Analytics {
+            refresh_every: Duration::from_secs(var("ANALYTICS_REFRESH_MINUTES", 15) * 60),
+            sample_every: Duration::from_secs(var("SSE_SAMPLE_SECS", 60)),
+            instance_id: Uuid::new_v4(),
+        }
+    }
+}
+
+pub async fn refresh(data: &AppState) -> Result<(), sqlx::Error> {
+    for view in VIEWS {
+        scheduler::refresh_view(&data.db, view, data.clock.now()).await?;
+    }
+    Ok(())
+}
+
+pub fn spawn_jobs(data: Arc<AppState>) {
+    let analytics = &data.analytics;
+    scheduler::every("sse_sample", analytics.sample_every, data.clone(), |data| async move {
+        sqlx::query!(
+            "INSERT INTO sse_samples (instance_id, connections) VALUES ($1, $2)",
+            data.analytics.instance_id,
+            data.sse.count() as i32
+        )
+        .execute(&data.db)
+        .await?;
+        Ok(())
+    });
+    scheduler::every("analytics_refresh", analytics.refresh_every, data.clone(), |data| async move {
+        refresh(&data).await
+    });
+}
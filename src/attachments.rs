@@ -0,0 +1,153 @@
+use axum::body::Bytes;
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+
+use crate::{error::AppError, model::OrgAttachmentModel, sanitize, timestamp, AppState};
+
+// room for the multipart framing around the file
+pub const FRAMING_BYTES: usize = 16 * 1024;
+
+/// Files an organization's admins attach for its members, like a flyer or
+/// a map, kept in storage. Members download them by links that expire,
+/// after `ATTACHMENT_URL_TTL_SECS`, 900 unless set. A file can be at most
+/// `ATTACHMENT_MAX_BYTES`, 10 MiB unless set.
+pub struct Attachments {
+    pub max_bytes: usize,
+    url_ttl: chrono::Duration,
+}
+
+impl Attachments {
+    pub fn from_env() -> Self {
+        let max_bytes = std::env::var("ATTACHMENT_MAX_BYTES")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .filter(|bytes| *bytes > 0)
+            .unwrap_or(10 * 1024 * 1024);
+        let url_ttl_secs = std::env::var("ATTACHMENT_URL_TTL_SECS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .filter(|secs| *secs > 0)
+            .unwrap_or(900);
+
+        Attachments {
+            max_bytes,
+            url_ttl: chrono::Duration::seconds(url_ttl_secs),
+        }
+    }
+}
+
+/// An attachment as members see it, with a fresh link to download it.
+pub fn to_json(data: &AppState, attachment: &OrgAttachmentModel, now: DateTime<Utc>) -> Value {
+    let expires_at = now + data.attachments.url_ttl;
+    let mut value = json!(attachment);
+    value["download"] = json!({
+        "url": data.storage.signed_url(&attachment.storage_key, expires_at),
+        "expires_at": timestamp::json(&expires_at)
+    });
+    value
+}
+
+/// A file as it was uploaded, the name it had and what's in it.
+pub struct Upload {
+    pub file_name: String,
+    pub data: Bytes,
+}
+
+/// The first file in a `multipart/form-data` body, the first part whose
+/// `Content-Disposition` names a file. Other fields are skipped.
+pub fn file_from_multipart(content_type: &str, body: &Bytes) -> Result<Upload, AppError> {
+    let (mime, params) = content_type.split_once(';').ok_or(AppError::UploadInvalid)?;
+    if !mime.trim().eq_ignore_ascii_case("multipart/form-data") {
+        return Err(AppError::UploadInvalid);
+    }
+    let boundary = params
+        .split(';')
+        .find_map(|param| param.trim().strip_prefix("boundary="))
+        .map(|boundary| boundary.trim_matches('"'))
+        .filter(|boundary| !boundary.is_empty())
+        .ok_or(AppError::UploadInvalid)?;
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let part_end = [b"\r\n".as_slice(), &delimiter].concat();
+
+    let start = find(body, &delimiter).ok_or(AppError::UploadInvalid)?;
+    let mut rest = &body[start + delimiter.len()..];
+    // the last delimiter has `--` after it
+    while !rest.starts_with(b"--") {
+        rest = rest.strip_prefix(b"\r\n").ok_or(AppError::UploadInvalid)?;
+        let headers_end = find(rest, b"\r\n\r\n").ok_or(AppError::UploadInvalid)?;
+        let headers = std::str::from_utf8(&rest[..headers_end]).map_err(|_| AppError::UploadInvalid)?;
+        let content = &rest[headers_end + 4..];
+        let content_end = find(content, &part_end).ok_or(AppError::UploadInvalid)?;
+
+        if let Some(file_name) = file_name(headers) {
+            return Ok(Upload {
+                file_name: clean_file_name(&file_name)?,
+                data: body.slice_ref(&content[..content_end]),
+            });
+        }
+        rest = &content[content_end + part_end.len()..];
+    }
+    Err(AppError::UploadInvalid)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+// the `filename` of a part's `Content-Disposition`
+fn file_name(headers: &str) -> Option<String> {
+    let disposition = headers.split("\r\n").find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim().eq_ignore_ascii_case("content-disposition").then_some(value)
+    })?;
+    disposition
+        .split(';')
+        .find_map(|param| param.trim().strip_prefix("filename="))
+        .map(|name| name.trim_matches('"').to_string())
+}
+
+// browsers on some systems send the whole path, only the name is kept
+fn clean_file_name(name: &str) -> Result<String, AppError> {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or(name);
+    sanitize::text("file_name", name, sanitize::NAME_MAX)
+}
+
+/// What a file is, as its content type and the extension it's stored with,
+/// going by how it starts rather than by what the upload says it is. Only
+/// images and PDFs can be attached.
+pub fn sniff(data: &[u8]) -> Option<(&'static str, &'static str)> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some(("image/png", "png"))
+    } else if data.starts_with(b"\xff\xd8\xff") {
+        Some(("image/jpeg", "jpg"))
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        Some(("image/gif", "gif"))
+    } else if data.len() >= 12 && data.starts_with(b"RIFF") && &data[8..12] == b"WEBP" {
+        Some(("image/webp", "webp"))
+    } else if data.starts_with(b"%PDF-") {
+        Some(("application/pdf", "pdf"))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_file_part_is_found_among_the_fields() {
+        let body = Bytes::from_static(
+            b"preamble\r\n--xyz\r\nContent-Disposition: form-data; name=\"title\"\r\n\r\nMap\r\n--xyz\r\nContent-Disposition: form-data; name=\"file\"; filename=\"C:\\maps\\map.png\"\r\nContent-Type: image/png\r\n\r\n\x89PNG\r\n\x1a\nrest\r\n--xyz--\r\n",
+        );
+        let upload = file_from_multipart("multipart/form-data; boundary=\"xyz\"", &body).unwrap();
+        assert_eq!(upload.file_name, "map.png");
+        assert_eq!(&upload.data[..], b"\x89PNG\r\n\x1a\nrest");
+        assert_eq!(sniff(&upload.data), Some(("image/png", "png")));
+
+        let fields_only = Bytes::from_static(b"--xyz\r\nContent-Disposition: form-data; name=\"title\"\r\n\r\nMap\r\n--xyz--\r\n");
+        assert!(file_from_multipart("multipart/form-data; boundary=xyz", &fields_only).is_err());
+        assert!(file_from_multipart("application/json", &body).is_err());
+        assert_eq!(sniff(b"<svg onload=alert(1)>"), None);
+    }
+}
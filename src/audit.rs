@@ -0,0 +1,22 @@
+use sqlx::{Executor, Postgres};
+
+use crate::{client_ip, request_signing};
+
+/// Logs `action`, with the address of the client whose request caused it
+/// and the API client that signed it, if any.
+pub async fn record<'e, E>(executor: E, action: &str, details: serde_json::Value) -> Result<(), sqlx::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    sqlx::query!(
+        "INSERT INTO audit_logs (action, details, ip, api_client_id) VALUES ($1, $2, $3, $4)",
+        action,
+        details,
+        client_ip::current().map(|ip| ip.to_string()),
+        request_signing::current()
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,31 @@
+//! What the criterion benches in `benches/` measure. They only see the
+//! public API, so the hot paths are reached through here. Built with the
+//! `bench` feature.
+
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use sqlx::{Pool, Postgres};
+
+pub use crate::{events::render, events::SchemaVersion, pagination::Pagination, ref_code::generate as ref_code};
+use crate::{events, query_metrics::QueryMetrics, AppState};
+
+/// A stored event with the metadata the relay adds before broadcasting it.
+pub fn event(id: i64, payload: serde_json::Value, created_at: DateTime<Utc>) -> serde_json::Value {
+    events::with_metadata(id, payload, created_at)
+}
+
+/// App state configured from the environment, on top of `db`.
+pub async fn state(db: Pool<Postgres>) -> Arc<AppState> {
+    Arc::new(AppState::from_env(db, QueryMetrics::from_env()).await)
+}
+
+/// One pass of the outbox relay, broadcasting to this instance only.
+pub async fn relay(data: &AppState) -> Result<(), sqlx::Error> {
+    events::relay_pending(data, false).await
+}
+
+/// A receiver for the state's broadcasts, so relayed events have somewhere to go.
+pub fn subscribe(data: &AppState) -> tokio::sync::broadcast::Receiver<String> {
+    data.tx.subscribe()
+}
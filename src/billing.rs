@@ -0,0 +1,77 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::secrets;
+
+// Stripe's own libraries reject signatures older than five minutes
+const DEFAULT_TOLERANCE_SECS: i64 = 300;
+
+pub struct Billing {
+    webhook_secret: Option<String>,
+    tolerance_secs: i64,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SignatureError {
+    Malformed,
+    Expired,
+    Mismatch,
+}
+
+impl Billing {
+    pub fn from_env() -> Self {
+        Billing {
+            webhook_secret: secrets::var("STRIPE_WEBHOOK_SECRET"),
+            tolerance_secs: std::env::var("STRIPE_WEBHOOK_TOLERANCE_SECS")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(DEFAULT_TOLERANCE_SECS),
+        }
+    }
+
+    pub fn is_configured(&self) -> bool {
+        self.webhook_secret.is_some()
+    }
+
+    /// Checks a `Stripe-Signature` header (`t=<unix time>,v1=<hex hmac>,...`)
+    /// against the raw request body: the signature is an HMAC-SHA256 of
+    /// `"<t>.<body>"` keyed with the endpoint's webhook secret.
+    pub fn verify_signature(&self, header: &str, payload: &[u8], now: i64) -> Result<(), SignatureError> {
+        let secret = self.webhook_secret.as_deref().ok_or(SignatureError::Mismatch)?;
+
+        let mut timestamp = None;
+        let mut signatures = Vec::new();
+        for part in header.split(',') {
+            match part.trim().split_once('=') {
+                Some(("t", value)) => timestamp = value.parse::<i64>().ok(),
+                Some(("v1", value)) => signatures.push(value),
+                _ => {}
+            }
+        }
+
+        let timestamp = timestamp.ok_or(SignatureError::Malformed)?;
+        if signatures.is_empty() {
+            return Err(SignatureError::Malformed);
+        }
+        if (now - timestamp).abs() > self.tolerance_secs {
+            return Err(SignatureError::Expired);
+        }
+
+        let matches = signatures.iter().any(|signature| {
+            let Ok(expected) = hex::decode(signature) else {
+                return false;
+            };
+            let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+            mac.update(timestamp.to_string().as_bytes());
+            mac.update(b".");
+            mac.update(payload);
+            mac.verify_slice(&expected).is_ok()
+        });
+
+        if matches {
+            Ok(())
+        } else {
+            Err(SignatureError::Mismatch)
+        }
+    }
+}
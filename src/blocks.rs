@@ -0,0 +1,60 @@
+use std::collections::HashSet;
+
+use sqlx::{Executor, Postgres};
+use uuid::Uuid;
+
+/// Whether `blocker` blocked `user`. Nobody is blocked from an anonymous
+/// viewer, there's no one to block.
+pub async fn has_blocked<'e>(
+    db: impl Executor<'e, Database = Postgres>,
+    blocker: Uuid,
+    user: Option<Uuid>,
+) -> Result<bool, sqlx::Error> {
+    let Some(user) = user else {
+        return Ok(false);
+    };
+    let blocked = sqlx::query_scalar!(
+        "SELECT EXISTS(SELECT 1 FROM blocks WHERE blocker_id = $1 AND blocked_id = $2)",
+        blocker,
+        user
+    )
+    .fetch_one(db)
+    .await?;
+    Ok(blocked == Some(true))
+}
+
+/// Which of `users` blocked `viewer`, for lists of users.
+pub async fn blockers_of<'e>(
+    db: impl Executor<'e, Database = Postgres>,
+    users: &[Uuid],
+    viewer: Option<Uuid>,
+) -> Result<HashSet<Uuid>, sqlx::Error> {
+    let Some(viewer) = viewer else {
+        return Ok(HashSet::new());
+    };
+    let blockers = sqlx::query_scalar!(
+        "SELECT blocker_id FROM blocks WHERE blocked_id = $1 AND blocker_id = ANY($2)",
+        viewer,
+        users
+    )
+    .fetch_all(db)
+    .await?;
+    Ok(blockers.into_iter().collect())
+}
+
+/// Whether the user with this email address blocked `user`, the check made
+/// before inviting an address.
+pub async fn email_has_blocked<'e>(
+    db: impl Executor<'e, Database = Postgres>,
+    email: &str,
+    user: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let blocked = sqlx::query_scalar!(
+        "SELECT EXISTS(SELECT 1 FROM blocks b JOIN users u ON u.id = b.blocker_id WHERE u.email_index = $1 AND b.blocked_id = $2)",
+        crate::pii::blind_index(email),
+        user
+    )
+    .fetch_one(db)
+    .await?;
+    Ok(blocked == Some(true))
+}
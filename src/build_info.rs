@@ -0,0 +1,36 @@
+use chrono::{TimeZone, Utc};
+use serde_json::{json, Value};
+
+/// What this binary was built from, recorded by the build script.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+pub const GIT_COMMIT: &str = env!("BUILD_GIT_COMMIT");
+const BUILD_UNIX_TIME: &str = env!("BUILD_UNIX_TIME");
+const FEATURES: &str = env!("BUILD_FEATURES");
+/// The latest migration this build ships, the schema it expects.
+pub const MIGRATION: &str = env!("BUILD_MIGRATION");
+
+pub fn features() -> Vec<&'static str> {
+    FEATURES.split(',').filter(|feature| !feature.is_empty()).collect()
+}
+
+/// The build as JSON, for `/api/version` and the startup banner.
+pub fn to_json() -> Value {
+    let built_at = BUILD_UNIX_TIME
+        .parse()
+        .ok()
+        .and_then(|secs| Utc.timestamp_opt(secs, 0).single());
+    json!({
+        "version": VERSION,
+        "git_commit": GIT_COMMIT,
+        "built_at": built_at.map(|built_at| crate::timestamp::json(&built_at)),
+        "features": features(),
+        "migration": MIGRATION.parse::<i64>().unwrap_or(0),
+    })
+}
+
+/// One line saying what is starting, as JSON so log search can pick it out.
+pub fn print_banner() {
+    let mut banner = to_json();
+    banner["kind"] = json!("startup");
+    println!("🚀 {}", banner);
+}
@@ -0,0 +1,169 @@
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+
+pub type BusError = Box<dyn std::error::Error + Send + Sync>;
+
+/// An external message bus that outbox events are also published to, so
+/// other systems can consume them without polling the API.
+#[async_trait]
+pub trait EventBus: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Sends an event, stamped `now` where the bus keeps a timestamp.
+    async fn publish(&self, event_type: &str, payload: &[u8], now: DateTime<Utc>) -> Result<(), BusError>;
+
+    /// A round trip to the bus, for the readiness check.
+    async fn ping(&self) -> Result<(), BusError>;
+}
+
+/// Connects to the bus selected by `EVENT_BUS`, `nats` or `kafka`. Either
+/// one needs the cargo feature of the same name. Events go to the subject or
+/// topic `EVENT_BUS_PREFIX` (`invito.events` by default) followed by the
+/// event type.
+pub async fn from_env() -> Option<Box<dyn EventBus>> {
+    let connected: Result<Box<dyn EventBus>, BusError> = match std::env::var("EVENT_BUS").ok()?.as_str() {
+        #[cfg(feature = "nats")]
+        "nats" => nats::NatsBus::connect(prefix()).await.map(|bus| Box::new(bus) as Box<dyn EventBus>),
+        #[cfg(feature = "kafka")]
+        "kafka" => kafka::KafkaBus::connect(prefix()).await.map(|bus| Box::new(bus) as Box<dyn EventBus>),
+        other => Err(format!("`{}` is not a supported event bus in this build", other).into()),
+    };
+
+    match connected {
+        Ok(bus) => {
+            println!("✅ Publishing events to {}", bus.name());
+            Some(bus)
+        }
+        Err(e) => {
+            println!("🔥 Failed to connect to the event bus: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(any(feature = "nats", feature = "kafka"))]
+fn prefix() -> String {
+    std::env::var("EVENT_BUS_PREFIX").unwrap_or_else(|_| "invito.events".to_string())
+}
+
+#[cfg(feature = "nats")]
+mod nats {
+    use super::*;
+
+    pub struct NatsBus {
+        client: async_nats::Client,
+        prefix: String,
+    }
+
+    impl NatsBus {
+        /// Connects to `NATS_URL`, `nats://localhost:4222` unless set.
+        pub async fn connect(prefix: String) -> Result<Self, BusError> {
+            let url = std::env::var("NATS_URL").unwrap_or_else(|_| "nats://localhost:4222".to_string());
+            let client = async_nats::connect(url).await?;
+            Ok(NatsBus { client, prefix })
+        }
+    }
+
+    #[async_trait]
+    impl EventBus for NatsBus {
+        fn name(&self) -> &'static str {
+            "NATS"
+        }
+
+        async fn publish(&self, event_type: &str, payload: &[u8], _now: DateTime<Utc>) -> Result<(), BusError> {
+            let subject = format!("{}.{}", self.prefix, event_type);
+            self.client.publish(subject, payload.to_vec().into()).await?;
+            // the relay only marks events published once the server has them
+            self.client.flush().await?;
+            Ok(())
+        }
+
+        async fn ping(&self) -> Result<(), BusError> {
+            self.client.flush().await?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(feature = "kafka")]
+mod kafka {
+    use std::{collections::BTreeMap, sync::Arc};
+
+    use rskafka::{
+        client::{
+            partition::{Compression, PartitionClient, UnknownTopicHandling},
+            Client, ClientBuilder,
+        },
+        record::Record,
+    };
+    use tokio::sync::Mutex;
+
+    use super::*;
+
+    pub struct KafkaBus {
+        client: Client,
+        prefix: String,
+        // one partition client per topic, created on first use
+        partitions: Mutex<BTreeMap<String, Arc<PartitionClient>>>,
+    }
+
+    impl KafkaBus {
+        /// Connects to the comma separated brokers in `KAFKA_BROKERS`,
+        /// `localhost:9092` unless set.
+        pub async fn connect(prefix: String) -> Result<Self, BusError> {
+            let brokers = std::env::var("KAFKA_BROKERS")
+                .unwrap_or_else(|_| "localhost:9092".to_string())
+                .split(',')
+                .map(|broker| broker.trim().to_string())
+                .collect();
+            let client = ClientBuilder::new(brokers).build().await?;
+            Ok(KafkaBus {
+                client,
+                prefix,
+                partitions: Mutex::new(BTreeMap::new()),
+            })
+        }
+
+        async fn partition(&self, topic: String) -> Result<Arc<PartitionClient>, BusError> {
+            let mut partitions = self.partitions.lock().await;
+            if let Some(partition) = partitions.get(&topic) {
+                return Ok(partition.clone());
+            }
+            // a single partition keeps events of one type in order
+            let partition = Arc::new(
+                self.client
+                    .partition_client(topic.clone(), 0, UnknownTopicHandling::Retry)
+                    .await?,
+            );
+            partitions.insert(topic, partition.clone());
+            Ok(partition)
+        }
+    }
+
+    #[async_trait]
+    impl EventBus for KafkaBus {
+        fn name(&self) -> &'static str {
+            "Kafka"
+        }
+
+        async fn publish(&self, event_type: &str, payload: &[u8], now: DateTime<Utc>) -> Result<(), BusError> {
+            let topic = format!("{}.{}", self.prefix, event_type);
+            let record = Record {
+                key: Some(event_type.as_bytes().to_vec()),
+                value: Some(payload.to_vec()),
+                headers: BTreeMap::new(),
+                timestamp: now,
+            };
+            self.partition(topic)
+                .await?
+                .produce(vec![record], Compression::NoCompression)
+                .await?;
+            Ok(())
+        }
+
+        async fn ping(&self) -> Result<(), BusError> {
+            self.client.list_topics().await?;
+            Ok(())
+        }
+    }
+}
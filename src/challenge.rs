@@ -0,0 +1,342 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    async_trait,
+    body::Body,
+    extract::State,
+    http::{Method, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::{error::AppError, rate_limit, secrets, AppState};
+
+pub type ChallengeError = Box<dyn std::error::Error + Send + Sync>;
+
+pub const RESPONSE_HEADER: &str = "x-challenge-response";
+// proof of work challenges have to be solved and used within this
+const POW_TTL_SECS: i64 = 300;
+
+/// Something a client has to get right before it's let through to a
+/// protected route, to keep bots off signup.
+#[async_trait]
+pub trait Challenge: Send + Sync {
+    /// What the client needs to produce a response, served at
+    /// `GET /api/challenge`.
+    fn issue(&self, now: DateTime<Utc>) -> serde_json::Value;
+
+    /// Whether the client's response, from the `X-Challenge-Response`
+    /// header, is a pass. An error means the answer couldn't be checked.
+    async fn verify(&self, response: &str, client: &str, now: DateTime<Utc>) -> Result<bool, ChallengeError>;
+}
+
+/// hCaptcha and Cloudflare Turnstile, which both check the widget's token
+/// with a form POST of the secret and the token to a siteverify endpoint.
+struct Captcha {
+    provider: &'static str,
+    verify_url: &'static str,
+    site_key: Option<String>,
+    secret: String,
+    http: reqwest::Client,
+}
+
+#[async_trait]
+impl Challenge for Captcha {
+    fn issue(&self, _now: DateTime<Utc>) -> serde_json::Value {
+        json!({"provider": self.provider, "site_key": self.site_key})
+    }
+
+    async fn verify(&self, response: &str, client: &str, _now: DateTime<Utc>) -> Result<bool, ChallengeError> {
+        let mut form = vec![("secret", self.secret.as_str()), ("response", response)];
+        if client != "unknown" {
+            form.push(("remoteip", client));
+        }
+        let result: serde_json::Value = self
+            .http
+            .post(self.verify_url)
+            .form(&form)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(result["success"].as_bool().unwrap_or(false))
+    }
+}
+
+/// A hashcash-style puzzle that needs no third party: find a nonce such that
+/// SHA-256 of `<challenge>:<nonce>` starts with `difficulty` zero bits. The
+/// response is `<challenge>:<nonce>`, and each challenge passes only once.
+/// Spent challenges are remembered until they expire, and forgotten then,
+/// an expired one wouldn't pass anyway.
+struct ProofOfWork {
+    secret: String,
+    difficulty: u32,
+    spent: Mutex<HashMap<String, i64>>,
+}
+
+impl ProofOfWork {
+    fn sign(&self, claims: &str) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.secret.as_bytes()).unwrap();
+        mac.update(claims.as_bytes());
+        mac
+    }
+
+    // a challenge we issued ourselves and that hasn't expired, with its expiry
+    fn check_challenge(&self, challenge: &str, now: i64) -> Option<i64> {
+        let (claims, signature) = challenge.rsplit_once('.')?;
+        let signature = hex::decode(signature).ok()?;
+        self.sign(claims).verify_slice(&signature).ok()?;
+        let expires_at: i64 = claims.split('.').next()?.parse().ok()?;
+        (expires_at > now).then_some(expires_at)
+    }
+}
+
+#[async_trait]
+impl Challenge for ProofOfWork {
+    fn issue(&self, now: DateTime<Utc>) -> serde_json::Value {
+        let mut salt = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut salt);
+        let claims = format!("{}.{}", now.timestamp() + POW_TTL_SECS, hex::encode(salt));
+        let signature = hex::encode(self.sign(&claims).finalize().into_bytes());
+        json!({
+            "provider": "pow",
+            "challenge": format!("{}.{}", claims, signature),
+            "difficulty": self.difficulty,
+        })
+    }
+
+    async fn verify(&self, response: &str, _client: &str, now: DateTime<Utc>) -> Result<bool, ChallengeError> {
+        let Some((challenge, _nonce)) = response.rsplit_once(':') else {
+            return Ok(false);
+        };
+        let now = now.timestamp();
+        let Some(expires_at) = self.check_challenge(challenge, now) else {
+            return Ok(false);
+        };
+        if leading_zero_bits(&Sha256::digest(response.as_bytes())) < self.difficulty {
+            return Ok(false);
+        }
+
+        let mut spent = self.spent.lock().unwrap();
+        spent.retain(|_, expires_at| *expires_at > now);
+        if spent.contains_key(challenge) {
+            return Ok(false);
+        }
+        spent.insert(challenge.to_string(), expires_at);
+        Ok(true)
+    }
+}
+
+fn leading_zero_bits(hash: &[u8]) -> u32 {
+    let mut bits = 0;
+    for byte in hash {
+        bits += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    bits
+}
+
+/// The challenge in use, if any, and the routes it guards.
+pub struct Challenges {
+    challenge: Option<Arc<dyn Challenge>>,
+    routes: HashSet<(Method, String)>,
+    fail_open: bool,
+}
+
+impl Challenges {
+    /// `CHALLENGE_PROVIDER` is `hcaptcha`, `turnstile` or `pow`, and no
+    /// challenge is asked for while it's unset. Each needs
+    /// `CHALLENGE_SECRET`: the provider's secret key for the captchas, which
+    /// also take the widget's `CHALLENGE_SITE_KEY`, or a key to sign proof
+    /// of work challenges with. Proof of work asks for `POW_DIFFICULTY` zero
+    /// bits, 20 unless set. The guarded routes are listed in
+    /// `CHALLENGE_ROUTES` as comma-separated `<METHOD> <path>`, signup
+    /// (`POST /api/users`) by default. Requests are turned away while the
+    /// captcha provider can't be reached, unless `CHALLENGE_FAIL_OPEN` is
+    /// `true`.
+    pub fn from_env() -> Self {
+        let provider = std::env::var("CHALLENGE_PROVIDER").ok().filter(|s| !s.is_empty());
+        let secret = secrets::var("CHALLENGE_SECRET");
+        let site_key = std::env::var("CHALLENGE_SITE_KEY").ok();
+
+        let challenge: Option<Arc<dyn Challenge>> = match (provider.as_deref(), secret) {
+            (None, _) => None,
+            (Some(provider), None) => {
+                println!("🔥 CHALLENGE_PROVIDER is `{}` but CHALLENGE_SECRET is not set", provider);
+                std::process::exit(1);
+            }
+            (Some("hcaptcha"), Some(secret)) => Some(Arc::new(Captcha {
+                provider: "hcaptcha",
+                verify_url: "https://api.hcaptcha.com/siteverify",
+                site_key,
+                secret,
+                http: reqwest::Client::new(),
+            })),
+            (Some("turnstile"), Some(secret)) => Some(Arc::new(Captcha {
+                provider: "turnstile",
+                verify_url: "https://challenges.cloudflare.com/turnstile/v0/siteverify",
+                site_key,
+                secret,
+                http: reqwest::Client::new(),
+            })),
+            (Some("pow"), Some(secret)) => Some(Arc::new(ProofOfWork {
+                secret,
+                difficulty: std::env::var("POW_DIFFICULTY")
+                    .ok()
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or(20),
+                spent: Mutex::new(HashMap::new()),
+            })),
+            (Some(other), Some(_)) => {
+                println!("🔥 `{}` is not a supported challenge provider", other);
+                std::process::exit(1);
+            }
+        };
+
+        let routes = std::env::var("CHALLENGE_ROUTES")
+            .unwrap_or_else(|_| "POST /api/users".to_string())
+            .split(',')
+            .filter_map(|route| {
+                let (method, path) = route.trim().split_once(' ')?;
+                Some((method.trim().to_uppercase().parse().ok()?, path.trim().to_string()))
+            })
+            .collect();
+
+        let fail_open = std::env::var("CHALLENGE_FAIL_OPEN").is_ok_and(|value| value == "true");
+        if fail_open && challenge.is_some() {
+            println!("🔥 CHALLENGE_FAIL_OPEN is set, guarded routes go unchecked while the challenge provider is down");
+        }
+
+        Challenges { challenge, routes, fail_open }
+    }
+
+    pub fn issue(&self, now: DateTime<Utc>) -> serde_json::Value {
+        self.challenge
+            .as_ref()
+            .map_or(serde_json::Value::Null, |challenge| challenge.issue(now))
+    }
+}
+
+/// Turns away requests to guarded routes that don't come with a passing
+/// response, and with 503 while the provider can't be reached unless
+/// `CHALLENGE_FAIL_OPEN` lets them through.
+pub async fn require_challenge(State(data): State<Arc<AppState>>, req: Request<Body>, next: Next<Body>) -> Response {
+    let challenges = &data.challenges;
+    let Some(challenge) = &challenges.challenge else {
+        return next.run(req).await;
+    };
+    if !challenges
+        .routes
+        .contains(&(req.method().clone(), req.uri().path().to_string()))
+    {
+        return next.run(req).await;
+    }
+
+    let Some(response) = req
+        .headers()
+        .get(RESPONSE_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty())
+    else {
+        return AppError::ChallengeRequired.into_response();
+    };
+
+    match challenge.verify(response, &rate_limit::client_key(&data, &req), data.clock.now()).await {
+        Ok(true) => next.run(req).await,
+        Ok(false) => AppError::ChallengeFailed.into_response(),
+        Err(e) if challenges.fail_open => {
+            println!("🔥 Could not check the challenge response, letting the request through: {}", e);
+            next.run(req).await
+        }
+        Err(e) => {
+            println!("🔥 Could not check the challenge response: {}", e);
+            AppError::ChallengeUnavailable.into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+    use chrono::{Duration, TimeZone};
+
+    use super::*;
+    use crate::testing::{builder, TestApp};
+
+    // a captcha provider that can't be reached
+    struct Unreachable;
+
+    #[async_trait]
+    impl Challenge for Unreachable {
+        fn issue(&self, _now: DateTime<Utc>) -> serde_json::Value {
+            json!({"provider": "unreachable"})
+        }
+
+        async fn verify(&self, _response: &str, _client: &str, _now: DateTime<Utc>) -> Result<bool, ChallengeError> {
+            Err("connection refused".into())
+        }
+    }
+
+    fn unreachable(fail_open: bool) -> Challenges {
+        Challenges {
+            challenge: Some(Arc::new(Unreachable)),
+            routes: HashSet::from([(Method::POST, "/api/users".to_string())]),
+            fail_open,
+        }
+    }
+
+    #[tokio::test]
+    async fn signup_closes_while_the_provider_is_down_unless_told_otherwise() {
+        let signup = json!({"user_name": "ada", "email": "ada@example.com"});
+
+        let app = TestApp::with(|state| state.challenges = unreachable(false)).await;
+        let request = builder(Method::POST, "/api/users").header(RESPONSE_HEADER, "token");
+        let (status, body) = app.send(request, Some(signup.clone())).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["code"], "CHALLENGE_UNAVAILABLE");
+
+        let app = TestApp::with(|state| state.challenges = unreachable(true)).await;
+        let request = builder(Method::POST, "/api/users").header(RESPONSE_HEADER, "token");
+        let (status, body) = app.send(request, Some(signup)).await;
+        assert_eq!(status, StatusCode::CREATED, "{}", body);
+    }
+
+    #[tokio::test]
+    async fn spent_challenges_pass_once_and_are_forgotten_once_expired() {
+        let pow = ProofOfWork {
+            secret: "pow secret".to_string(),
+            difficulty: 4,
+            spent: Mutex::new(HashMap::new()),
+        };
+        let issued_at = Utc.timestamp_opt(1_760_520_600, 0).unwrap();
+        let solve = |challenge: &str| {
+            (0u64..)
+                .map(|nonce| format!("{}:{}", challenge, nonce))
+                .find(|response| leading_zero_bits(&Sha256::digest(response.as_bytes())) >= 4)
+                .unwrap()
+        };
+
+        let first = solve(pow.issue(issued_at)["challenge"].as_str().unwrap());
+        assert!(pow.verify(&first, "client", issued_at).await.unwrap());
+        assert!(!pow.verify(&first, "client", issued_at).await.unwrap());
+        assert_eq!(pow.spent.lock().unwrap().len(), 1);
+
+        // checking a challenge issued later sweeps out the expired one
+        let later = issued_at + Duration::seconds(POW_TTL_SECS);
+        let second = solve(pow.issue(later)["challenge"].as_str().unwrap());
+        assert!(pow.verify(&second, "client", later).await.unwrap());
+        assert_eq!(pow.spent.lock().unwrap().len(), 1);
+        assert!(!pow.verify(&first, "client", later).await.unwrap());
+    }
+}
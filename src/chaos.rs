@@ -0,0 +1,102 @@
+use sqlx::postgres::PgPoolOptions;
+
+// Fault injection, for checking that retries, the outbox relay and SSE
+// reconnection hold up when things fail. It only exists in builds with the
+// `chaos` cargo feature, and even then does nothing until configured:
+// - `CHAOS_DB_FAIL_PERCENT`: share of pool checkouts whose connection is
+//   cut, so the caller's next query fails the way a dropped connection does
+// - `CHAOS_DB_DELAY_PERCENT`: share of pool checkouts held up by
+//   `CHAOS_DB_DELAY_MS`, 200 unless set
+// - `CHAOS_BROADCAST_FAIL_PERCENT`: share of events not broadcast to this
+//   instance's SSE clients, as if they had missed them
+#[cfg(feature = "chaos")]
+mod faults {
+    use std::{sync::OnceLock, time::Duration};
+
+    pub struct Faults {
+        pub db_fail: f64,
+        pub db_delay: f64,
+        pub delay: Duration,
+        pub broadcast_fail: f64,
+    }
+
+    static FAULTS: OnceLock<Faults> = OnceLock::new();
+
+    fn percent(name: &str) -> f64 {
+        std::env::var(name)
+            .ok()
+            .and_then(|value| value.parse::<f64>().ok())
+            .map_or(0.0, |percent| percent.clamp(0.0, 100.0) / 100.0)
+    }
+
+    pub fn get() -> &'static Faults {
+        FAULTS.get_or_init(|| {
+            let faults = Faults {
+                db_fail: percent("CHAOS_DB_FAIL_PERCENT"),
+                db_delay: percent("CHAOS_DB_DELAY_PERCENT"),
+                delay: Duration::from_millis(
+                    std::env::var("CHAOS_DB_DELAY_MS")
+                        .ok()
+                        .and_then(|value| value.parse().ok())
+                        .unwrap_or(200),
+                ),
+                broadcast_fail: percent("CHAOS_BROADCAST_FAIL_PERCENT"),
+            };
+            if faults.db_fail + faults.db_delay + faults.broadcast_fail > 0.0 {
+                println!(
+                    "💥 Injecting faults: {}% of DB checkouts fail, {}% are delayed {:?}, {}% of broadcasts are dropped",
+                    faults.db_fail * 100.0,
+                    faults.db_delay * 100.0,
+                    faults.delay,
+                    faults.broadcast_fail * 100.0
+                );
+            }
+            faults
+        })
+    }
+
+    pub fn roll(chance: f64) -> bool {
+        chance > 0.0 && rand::random::<f64>() < chance
+    }
+}
+
+/// Adds the database faults to the pool, when built with them.
+#[cfg(feature = "chaos")]
+pub fn install(options: PgPoolOptions) -> PgPoolOptions {
+    let faults = faults::get();
+    if faults.db_fail == 0.0 && faults.db_delay == 0.0 {
+        return options;
+    }
+
+    options.before_acquire(|conn, _| {
+        Box::pin(async move {
+            let faults = faults::get();
+            if faults::roll(faults.db_delay) {
+                tokio::time::sleep(faults.delay).await;
+            }
+            if faults::roll(faults.db_fail) {
+                // the backend goes away mid-statement, so this errors by design
+                let _ = sqlx::query("SELECT pg_terminate_backend(pg_backend_pid())")
+                    .execute(&mut *conn)
+                    .await;
+            }
+            Ok::<_, sqlx::Error>(true)
+        })
+    })
+}
+
+#[cfg(not(feature = "chaos"))]
+pub fn install(options: PgPoolOptions) -> PgPoolOptions {
+    options
+}
+
+/// Whether to drop this broadcast.
+#[cfg(feature = "chaos")]
+pub fn drop_broadcast() -> bool {
+    faults::roll(faults::get().broadcast_fail)
+}
+
+#[cfg(not(feature = "chaos"))]
+pub fn drop_broadcast() -> bool {
+    false
+}
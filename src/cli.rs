@@ -0,0 +1,99 @@
+mod admin;
+mod api_client;
+mod pii;
+mod seed;
+mod snapshot;
+
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+pub type CommandError = Box<dyn std::error::Error>;
+
+/// Runs the API server, or one of the maintenance commands.
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Fills the database with fake users, referral chains and organizations
+    /// with members and invitations, for local development and load testing
+    Seed {
+        /// How many users to create
+        #[arg(long, default_value_t = 100)]
+        users: usize,
+        /// How many organizations to spread them over
+        #[arg(long, default_value_t = 10)]
+        orgs: usize,
+    },
+    /// Makes a user an admin of an organization, adding them if needed
+    CreateAdmin {
+        /// Id of the organization
+        #[arg(long)]
+        org: Uuid,
+        /// User name of the new admin
+        #[arg(long)]
+        user: String,
+        /// Make them an owner instead
+        #[arg(long)]
+        owner: bool,
+    },
+    /// Replaces a user's referral code with a new one
+    RegenerateRefCode {
+        /// User name of the user
+        user: String,
+    },
+    /// Registers a backend integration that signs its requests, printing its
+    /// id and secret
+    CreateApiClient {
+        /// Name to tell the integration by
+        name: String,
+    },
+    /// Lists the API clients, revoked ones included, without their secrets
+    ListApiClients,
+    /// Stops accepting requests signed by an API client
+    RevokeApiClient {
+        /// Name of the API client
+        name: String,
+    },
+    /// Re-encrypts stored emails and phone numbers under the current keys and
+    /// recomputes the email lookup indexes, after setting or rotating
+    /// `PII_ENCRYPTION_KEY` or `PII_INDEX_KEY`
+    ResealPii,
+    /// Writes a consistent snapshot of the core tables as gzipped JSON lines,
+    /// encrypted with `SNAPSHOT_KEY`, for backing up a small deployment
+    Snapshot {
+        /// File to write, `invito-snapshot-<time>.sealed` unless given
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Decrypts a snapshot with `SNAPSHOT_KEY` into its gzipped JSON lines
+    OpenSnapshot {
+        /// The snapshot to open
+        file: PathBuf,
+        /// File to write, the snapshot's name ending in `.jsonl.gz` unless given
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+}
+
+pub async fn run(command: Command, db: &Pool<Postgres>) -> Result<(), CommandError> {
+    match command {
+        Command::Seed { users, orgs } => seed::run(db, users, orgs).await?,
+        Command::CreateAdmin { org, user, owner } => admin::create_admin(db, org, &user, owner).await?,
+        Command::RegenerateRefCode { user } => admin::regenerate_ref_code(db, &user).await?,
+        Command::CreateApiClient { name } => api_client::create_api_client(db, &name).await?,
+        Command::ListApiClients => api_client::list_api_clients(db).await?,
+        Command::RevokeApiClient { name } => api_client::revoke_api_client(db, &name).await?,
+        Command::ResealPii => pii::reseal_pii(db).await?,
+        Command::Snapshot { out } => snapshot::snapshot(db, out).await?,
+        Command::OpenSnapshot { file, out } => snapshot::open_snapshot(file, out).await?,
+    }
+    Ok(())
+}
@@ -0,0 +1,69 @@
+use serde_json::json;
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+use super::CommandError;
+use crate::audit;
+
+/// Makes a user an admin, or the owner, of an organization, whether or not
+/// they were a member already.
+pub async fn create_admin(db: &Pool<Postgres>, org_id: Uuid, user_name: &str, owner: bool) -> Result<(), CommandError> {
+    let role = if owner { "owner" } else { "admin" };
+    let mut tx = db.begin().await?;
+
+    let org = sqlx::query_scalar!("SELECT name FROM organizations WHERE id = $1", org_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| format!("No organization with id {}", org_id))?;
+    let user_id = sqlx::query_scalar!("SELECT id FROM users WHERE user_name = $1", user_name)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| format!("No user named `{}`", user_name))?;
+
+    // an owner asked to be made admin stays owner, demoting owners is done through the API
+    let role = sqlx::query_scalar!(
+        "INSERT INTO org_members (org_id, user_id, role) VALUES ($1, $2, $3) ON CONFLICT (org_id, user_id) DO UPDATE SET role = CASE WHEN org_members.role = 'owner' THEN 'owner' ELSE EXCLUDED.role END RETURNING role",
+        org_id,
+        user_id,
+        role
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    audit::record(&mut *tx, "org.admin_granted", json!({"org_id": org_id, "user_id": user_id, "role": role, "via": "cli"})).await?;
+    tx.commit().await?;
+
+    println!("✅ {} is now {} of {}", user_name, role, org);
+    Ok(())
+}
+
+/// Gives a user a new referral code. Links shared with the old one stop
+/// working, the referrals already counted are kept.
+pub async fn regenerate_ref_code(db: &Pool<Postgres>, user_name: &str) -> Result<(), CommandError> {
+    let mut tx = db.begin().await?;
+
+    let user = sqlx::query!("SELECT id, ref_code FROM users WHERE user_name = $1 FOR UPDATE", user_name)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| format!("No user named `{}`", user_name))?;
+
+    let ref_code = crate::ref_code::generate(user_name);
+    sqlx::query!(
+        "UPDATE users SET ref_code = $1, version = version + 1 WHERE id = $2",
+        ref_code,
+        user.id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    audit::record(
+        &mut *tx,
+        "user.ref_code_regenerated",
+        json!({"user_id": user.id, "old_ref_code": user.ref_code, "ref_code": ref_code, "via": "cli"}),
+    )
+    .await?;
+    tx.commit().await?;
+
+    println!("✅ {} now has referral code {} (was {})", user_name, ref_code, user.ref_code);
+    Ok(())
+}
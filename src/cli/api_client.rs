@@ -0,0 +1,68 @@
+use rand::RngCore;
+use serde_json::json;
+use sqlx::{Pool, Postgres};
+
+use super::CommandError;
+use crate::audit;
+
+/// Registers a backend integration that signs its requests, and prints the
+/// id and secret it signs with. The secret isn't shown again.
+pub async fn create_api_client(db: &Pool<Postgres>, name: &str) -> Result<(), CommandError> {
+    let mut secret = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut secret);
+    let secret = hex::encode(secret);
+    let mut tx = db.begin().await?;
+
+    let id = sqlx::query_scalar!(
+        "INSERT INTO api_clients (name, secret) VALUES ($1, $2) ON CONFLICT (name) DO NOTHING RETURNING id",
+        name,
+        secret
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| format!("There is already an API client named `{}`", name))?;
+
+    audit::record(&mut *tx, "api_client.created", json!({"api_client_id": id, "name": name, "via": "cli"})).await?;
+    tx.commit().await?;
+
+    println!("✅ Created API client {}", name);
+    println!("   id:     {}", id);
+    println!("   secret: {}", secret);
+    Ok(())
+}
+
+/// Prints every API client, when it was created and whether it's revoked.
+pub async fn list_api_clients(db: &Pool<Postgres>) -> Result<(), CommandError> {
+    let clients = sqlx::query!("SELECT id, name, created_at, revoked_at FROM api_clients ORDER BY created_at, name")
+        .fetch_all(db)
+        .await?;
+
+    for client in &clients {
+        let created = client.created_at.map(|at| at.to_rfc3339()).unwrap_or_default();
+        match client.revoked_at {
+            Some(revoked_at) => println!("{}  {}  created {}  revoked {}", client.id, client.name, created, revoked_at.to_rfc3339()),
+            None => println!("{}  {}  created {}", client.id, client.name, created),
+        }
+    }
+    println!("✅ {} API clients", clients.len());
+    Ok(())
+}
+
+/// Stops accepting the signatures of an API client.
+pub async fn revoke_api_client(db: &Pool<Postgres>, name: &str) -> Result<(), CommandError> {
+    let mut tx = db.begin().await?;
+
+    let id = sqlx::query_scalar!(
+        "UPDATE api_clients SET revoked_at = NOW() WHERE name = $1 AND revoked_at IS NULL RETURNING id",
+        name
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| format!("No active API client named `{}`", name))?;
+
+    audit::record(&mut *tx, "api_client.revoked", json!({"api_client_id": id, "name": name, "via": "cli"})).await?;
+    tx.commit().await?;
+
+    println!("✅ Revoked API client {}", name);
+    Ok(())
+}
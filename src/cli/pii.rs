@@ -0,0 +1,85 @@
+use sqlx::{Pool, Postgres};
+
+use super::CommandError;
+use crate::pii::{self, Pii};
+
+/// Rewrites every stored email and phone number under the current keys:
+/// plaintext left from before encryption was turned on gets sealed, values
+/// sealed under `PII_ENCRYPTION_KEY_PREVIOUS` move to the new key, and the
+/// blind indexes are recomputed after `PII_INDEX_KEY` is set or changed.
+pub async fn reseal_pii(db: &Pool<Postgres>) -> Result<(), CommandError> {
+    let mut tx = db.begin().await?;
+
+    let users = sqlx::query!("SELECT id, email FROM users").fetch_all(&mut *tx).await?;
+    for user in &users {
+        let email = Pii::from(user.email.clone());
+        sqlx::query!(
+            "UPDATE users SET email = $2, email_index = $3 WHERE id = $1",
+            user.id,
+            pii::seal(&email),
+            pii::blind_index(&email)
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    let contacts = sqlx::query!("SELECT id, email, phone FROM contacts").fetch_all(&mut *tx).await?;
+    for contact in &contacts {
+        let email = Pii::from(contact.email.clone());
+        let phone = Pii::from(contact.phone.clone());
+        sqlx::query!(
+            "UPDATE contacts SET email = $2, email_index = $3, phone = $4 WHERE id = $1",
+            contact.id,
+            pii::seal(&email),
+            pii::blind_index(&email),
+            pii::seal_opt(phone.as_deref())
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    let invitations = sqlx::query!("SELECT id, email FROM org_invitations").fetch_all(&mut *tx).await?;
+    for invitation in &invitations {
+        let email = Pii::from(invitation.email.clone());
+        sqlx::query!(
+            "UPDATE org_invitations SET email = $2, email_index = $3 WHERE id = $1",
+            invitation.id,
+            pii::seal(&email),
+            pii::blind_index(&email)
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    let emails = sqlx::query!("SELECT id, email FROM email_log").fetch_all(&mut *tx).await?;
+    for logged in &emails {
+        let email = Pii::from(logged.email.clone());
+        sqlx::query!("UPDATE email_log SET email = $2 WHERE id = $1", logged.id, pii::seal(&email))
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    let suppressions = sqlx::query!("SELECT email_index, email FROM email_suppressions").fetch_all(&mut *tx).await?;
+    for suppressed in &suppressions {
+        let email = Pii::from(suppressed.email.clone());
+        sqlx::query!(
+            "UPDATE email_suppressions SET email = $2, email_index = $3 WHERE email_index = $1",
+            suppressed.email_index,
+            pii::seal(&email),
+            pii::blind_index(&email)
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    println!(
+        "✅ Resealed {} users, {} contacts, {} invitations, {} logged emails and {} suppressions",
+        users.len(),
+        contacts.len(),
+        invitations.len(),
+        emails.len(),
+        suppressions.len()
+    );
+    Ok(())
+}
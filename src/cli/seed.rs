@@ -0,0 +1,184 @@
+use chrono::{DateTime, Duration, Utc};
+use fake::{
+    faker::{company::en::CompanyName, internet::en::SafeEmail, name::en::FirstName, name::en::LastName},
+    Fake,
+};
+use rand::{seq::SliceRandom, Rng};
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+use crate::pii;
+
+// share of users that signed up with someone else's referral code
+const REFERRED_SHARE: f64 = 0.6;
+
+struct Users {
+    ids: Vec<Uuid>,
+    user_names: Vec<String>,
+    emails: Vec<String>,
+    ref_codes: Vec<String>,
+    referrals: Vec<i32>,
+}
+
+#[derive(Default)]
+struct Orgs {
+    ids: Vec<Uuid>,
+    names: Vec<String>,
+    member_orgs: Vec<Uuid>,
+    member_users: Vec<Uuid>,
+    member_roles: Vec<String>,
+    invitation_ids: Vec<Uuid>,
+    invitation_orgs: Vec<Uuid>,
+    invitation_emails: Vec<String>,
+    invitation_roles: Vec<String>,
+    invitation_senders: Vec<Uuid>,
+    invitation_accepted: Vec<Option<DateTime<Utc>>>,
+}
+
+/// Creates `users` users and `orgs` organizations in one transaction.
+pub async fn run(db: &Pool<Postgres>, users: usize, orgs: usize) -> Result<(), sqlx::Error> {
+    // names get a per-run tag so seeding twice doesn't collide
+    let tag = Uuid::new_v4().simple().to_string()[..6].to_string();
+    let users = fake_users(users, &tag);
+    let orgs = fake_orgs(&users, orgs, &tag);
+
+    let sealed = |emails: &[String]| -> (Vec<String>, Vec<String>) {
+        emails.iter().map(|email| (pii::seal(email), pii::blind_index(email))).unzip()
+    };
+    let (user_emails, user_email_indexes) = sealed(&users.emails);
+    let (invitation_emails, invitation_email_indexes) = sealed(&orgs.invitation_emails);
+
+    let mut tx = db.begin().await?;
+    sqlx::query!(
+        "INSERT INTO users (id, user_name, email, email_index, ref_code, added_by_ref_code) SELECT * FROM UNNEST($1::uuid[], $2::text[], $3::text[], $4::text[], $5::text[], $6::int[])",
+        &users.ids,
+        &users.user_names,
+        &user_emails,
+        &user_email_indexes,
+        &users.ref_codes,
+        &users.referrals
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        "INSERT INTO organizations (id, name) SELECT * FROM UNNEST($1::uuid[], $2::text[])",
+        &orgs.ids,
+        &orgs.names
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        "INSERT INTO org_members (org_id, user_id, role) SELECT * FROM UNNEST($1::uuid[], $2::uuid[], $3::text[])",
+        &orgs.member_orgs,
+        &orgs.member_users,
+        &orgs.member_roles
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        "INSERT INTO org_invitations (id, org_id, email, email_index, role, invited_by, accepted_at) SELECT * FROM UNNEST($1::uuid[], $2::uuid[], $3::text[], $4::text[], $5::text[], $6::uuid[], $7::timestamptz[])",
+        &orgs.invitation_ids,
+        &orgs.invitation_orgs,
+        &invitation_emails,
+        &invitation_email_indexes,
+        &orgs.invitation_roles,
+        &orgs.invitation_senders,
+        &orgs.invitation_accepted as &[Option<DateTime<Utc>>]
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    let accepted = orgs.invitation_accepted.iter().filter(|at| at.is_some()).count();
+    println!(
+        "✅ Seeded {} users ({} referred), {} organizations with {} members, {} accepted and {} pending invitations",
+        users.ids.len(),
+        users.referrals.iter().sum::<i32>(),
+        orgs.ids.len(),
+        orgs.member_users.len(),
+        accepted,
+        orgs.invitation_emails.len() - accepted
+    );
+    Ok(())
+}
+
+fn fake_users(count: usize, tag: &str) -> Users {
+    let mut rng = rand::thread_rng();
+    let mut users = Users {
+        ids: Vec::with_capacity(count),
+        user_names: Vec::with_capacity(count),
+        emails: Vec::with_capacity(count),
+        ref_codes: Vec::with_capacity(count),
+        referrals: vec![0; count],
+    };
+
+    for i in 0..count {
+        let first: String = FirstName().fake_with_rng(&mut rng);
+        let last: String = LastName().fake_with_rng(&mut rng);
+        let user_name = format!("{}{}_{}{}", first, last, tag, i).to_lowercase();
+        let email: String = SafeEmail().fake_with_rng(&mut rng);
+        let domain = email.split('@').nth(1).unwrap_or("example.com");
+
+        // referrers signed up earlier, which makes chains of referrals
+        if i > 0 && rng.gen_bool(REFERRED_SHARE) {
+            users.referrals[rng.gen_range(0..i)] += 1;
+        }
+
+        users.ids.push(Uuid::new_v4());
+        users.emails.push(format!("{}@{}", user_name, domain));
+        users.ref_codes.push(crate::ref_code::generate(&user_name));
+        users.user_names.push(user_name);
+    }
+    users
+}
+
+fn fake_orgs(users: &Users, count: usize, tag: &str) -> Orgs {
+    let mut rng = rand::thread_rng();
+    let mut orgs = Orgs::default();
+    if users.ids.is_empty() {
+        return orgs;
+    }
+    let people: Vec<usize> = (0..users.ids.len()).collect();
+
+    for i in 0..count {
+        let org_id = Uuid::new_v4();
+        orgs.ids.push(org_id);
+        orgs.names.push(CompanyName().fake_with_rng(&mut rng));
+
+        let size = rng.gen_range(1..=12).min(people.len());
+        let mut members = people.choose_multiple(&mut rng, size);
+        let owner = *members.next().unwrap();
+        orgs.member_orgs.push(org_id);
+        orgs.member_users.push(users.ids[owner]);
+        orgs.member_roles.push("owner".to_string());
+
+        // everyone else joined by accepting an invitation from the owner
+        for &member in members {
+            let role = if rng.gen_bool(0.2) { "admin" } else { "member" };
+            orgs.member_orgs.push(org_id);
+            orgs.member_users.push(users.ids[member]);
+            orgs.member_roles.push(role.to_string());
+
+            let days_ago = Duration::days(rng.gen_range(0..90));
+            orgs.invitation_ids.push(crate::ids::new());
+            orgs.invitation_orgs.push(org_id);
+            orgs.invitation_emails.push(users.emails[member].clone());
+            orgs.invitation_roles.push(role.to_string());
+            orgs.invitation_senders.push(users.ids[owner]);
+            orgs.invitation_accepted.push(Some(Utc::now() - days_ago));
+        }
+
+        // and a few have not answered yet
+        for n in 0..rng.gen_range(0..=5) {
+            let first: String = FirstName().fake_with_rng(&mut rng);
+            orgs.invitation_ids.push(crate::ids::new());
+            orgs.invitation_orgs.push(org_id);
+            orgs.invitation_emails
+                .push(format!("{}.{}{}{}@example.org", first.to_lowercase(), tag, i, n));
+            orgs.invitation_roles.push("member".to_string());
+            orgs.invitation_senders.push(users.ids[owner]);
+            orgs.invitation_accepted.push(None);
+        }
+    }
+    orgs
+}
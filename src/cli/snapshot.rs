@@ -0,0 +1,41 @@
+use std::path::PathBuf;
+
+use serde_json::json;
+use sqlx::{Pool, Postgres};
+
+use super::CommandError;
+use crate::{audit, snapshot};
+
+const KEY_MISSING: &str = "SNAPSHOT_KEY must be set to 32 bytes in hex to take or open snapshots";
+
+/// Writes an encrypted snapshot of the core tables to `out`, or to a
+/// timestamped file in the current directory.
+pub async fn snapshot(db: &Pool<Postgres>, out: Option<PathBuf>) -> Result<(), CommandError> {
+    let cipher = snapshot::key().ok_or(KEY_MISSING)?;
+    let taken_at = chrono::Utc::now();
+    let path = out.unwrap_or_else(|| PathBuf::from(snapshot::file_name(taken_at)));
+    let mut file = tokio::fs::File::create(&path).await?;
+
+    let counts = snapshot::write(db, cipher, taken_at, &mut file).await.map_err(|e| -> CommandError { e })?;
+    audit::record(db, "snapshot.exported", json!({"file": path, "via": "cli"})).await?;
+
+    let rows: u64 = counts.as_object().into_iter().flatten().filter_map(|(_, count)| count.as_u64()).sum();
+    println!("✅ Wrote {} rows from {} tables to {}", rows, snapshot::TABLES.len(), path.display());
+    Ok(())
+}
+
+/// Decrypts a snapshot into the gzipped JSON lines it holds, written to
+/// `out` or next to it with `.jsonl.gz` in place of `.sealed`.
+pub async fn open_snapshot(file: PathBuf, out: Option<PathBuf>) -> Result<(), CommandError> {
+    let cipher = snapshot::key().ok_or(KEY_MISSING)?;
+    let out = out.unwrap_or_else(|| file.with_extension("jsonl.gz"));
+    let mut archive = tokio::io::BufReader::new(tokio::fs::File::open(&file).await?);
+    let mut opened = tokio::fs::File::create(&out).await?;
+
+    if let Err(e) = snapshot::open(&cipher, &mut archive, &mut opened).await {
+        let _ = tokio::fs::remove_file(&out).await;
+        return Err(e);
+    }
+    println!("✅ Opened {} into {}", file.display(), out.display());
+    Ok(())
+}
@@ -0,0 +1,201 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+
+use axum::{
+    async_trait,
+    body::Body,
+    extract::{ConnectInfo, FromRequestParts, State},
+    http::{header, request::Parts, Extensions, HeaderMap, Request},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::{error::AppError, AppState};
+
+tokio::task_local! {
+    static CLIENT_IP: Option<IpAddr>;
+}
+
+/// How many proxies sit in front of the API, each adding the address it got
+/// the request from to a forwarding header. Set with `TRUSTED_PROXY_HOPS`;
+/// with none, forwarding headers are ignored since any client could have
+/// written them. `RATE_LIMIT_TRUST_PROXY=true` still means one.
+/// `TRUSTED_PROXY_HEADER` names the header the proxies write,
+/// `x-forwarded-for` unless set to `forwarded`. The other one is never
+/// read, it can only have come from the client.
+pub struct TrustedProxies {
+    hops: usize,
+    header: ForwardingHeader,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ForwardingHeader {
+    XForwardedFor,
+    Forwarded,
+}
+
+impl TrustedProxies {
+    pub fn from_env() -> Self {
+        let legacy = std::env::var("RATE_LIMIT_TRUST_PROXY").is_ok_and(|value| value == "true");
+        let hops = std::env::var("TRUSTED_PROXY_HOPS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(if legacy { 1 } else { 0 });
+        let header = match std::env::var("TRUSTED_PROXY_HEADER").ok().as_deref().map(str::to_lowercase).as_deref() {
+            None | Some("x-forwarded-for") => ForwardingHeader::XForwardedFor,
+            Some("forwarded") => ForwardingHeader::Forwarded,
+            Some(other) => {
+                println!("🔥 `{}` is not a supported TRUSTED_PROXY_HEADER, use x-forwarded-for or forwarded", other);
+                std::process::exit(1);
+            }
+        };
+
+        TrustedProxies { hops, header }
+    }
+
+    /// The address of the client, past the trusted proxies. Only the entries
+    /// those proxies added are believed, so a client can't pick its own by
+    /// sending the headers itself. A chain shorter than the trusted hops
+    /// didn't come through all of them, and the address the request came
+    /// from is taken instead.
+    pub fn client_ip(&self, headers: &HeaderMap, extensions: &Extensions) -> Option<IpAddr> {
+        let peer = extensions.get::<ConnectInfo<SocketAddr>>().map(|ConnectInfo(addr)| addr.ip());
+        if self.hops == 0 {
+            return peer;
+        }
+
+        let chain = match self.header {
+            ForwardingHeader::XForwardedFor => x_forwarded_for_chain(headers),
+            ForwardingHeader::Forwarded => forwarded_chain(headers),
+        };
+        match chain.iter().rev().nth(self.hops - 1) {
+            Some(entry) => parse_node(entry),
+            None => peer,
+        }
+    }
+}
+
+// every value of every header line, in the order the hops appended them
+fn header_entries<'a>(headers: &'a HeaderMap, name: &header::HeaderName) -> impl Iterator<Item = &'a str> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+}
+
+fn x_forwarded_for_chain(headers: &HeaderMap) -> Vec<&str> {
+    header_entries(headers, &header::HeaderName::from_static("x-forwarded-for")).collect()
+}
+
+fn forwarded_chain(headers: &HeaderMap) -> Vec<&str> {
+    header_entries(headers, &header::FORWARDED)
+        .map(|element| {
+            element
+                .split(';')
+                .filter_map(|pair| pair.trim().split_once('='))
+                .find(|(name, _)| name.eq_ignore_ascii_case("for"))
+                .map(|(_, node)| node.trim_matches('"'))
+                .unwrap_or("")
+        })
+        .collect()
+}
+
+// `203.0.113.7`, `203.0.113.7:4711`, `2001:db8::1` or `[2001:db8::1]:4711`;
+// `unknown` and obfuscated names have no address
+fn parse_node(node: &str) -> Option<IpAddr> {
+    if let Ok(ip) = node.parse() {
+        return Some(ip);
+    }
+    if let Some(bracketed) = node.strip_prefix('[') {
+        return bracketed.split_once(']').and_then(|(ip, _)| ip.parse().ok());
+    }
+    node.rsplit_once(':').and_then(|(ip, _)| ip.parse().ok())
+}
+
+/// Makes the client's address available to code that has no request at
+/// hand, like the audit log.
+pub async fn client_ip_layer(State(data): State<Arc<AppState>>, req: Request<Body>, next: Next<Body>) -> Response {
+    let ip = data.trusted_proxies.client_ip(req.headers(), req.extensions());
+    CLIENT_IP.scope(ip, next.run(req)).await
+}
+
+/// The address of the client making the request being handled.
+pub fn current() -> Option<IpAddr> {
+    CLIENT_IP.try_with(|ip| *ip).ok().flatten()
+}
+
+/// The address of the client making a request. `None` when there's no
+/// telling.
+#[derive(Debug, Clone)]
+pub struct ClientIp(pub Option<String>);
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for ClientIp {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &Arc<AppState>) -> Result<Self, Self::Rejection> {
+        let ip = state.trusted_proxies.client_ip(&parts.headers, &parts.extensions);
+        Ok(ClientIp(ip.map(|ip| ip.to_string())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(hops: usize, headers: &[(&'static str, &'static str)]) -> Option<IpAddr> {
+        resolve_from(hops, ForwardingHeader::XForwardedFor, headers)
+    }
+
+    fn resolve_from(hops: usize, header: ForwardingHeader, headers: &[(&'static str, &'static str)]) -> Option<IpAddr> {
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            map.append(*name, value.parse().unwrap());
+        }
+        let mut extensions = Extensions::new();
+        extensions.insert(ConnectInfo(SocketAddr::from(([10, 0, 0, 1], 5000))));
+        TrustedProxies { hops, header }.client_ip(&map, &extensions)
+    }
+
+    #[test]
+    fn ignores_forwarding_headers_without_trusted_proxies() {
+        assert_eq!(resolve(0, &[("x-forwarded-for", "1.2.3.4")]), Some("10.0.0.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn takes_the_entry_the_outermost_trusted_proxy_added() {
+        let headers = [("x-forwarded-for", "6.6.6.6, 1.2.3.4"), ("x-forwarded-for", "10.0.0.2")];
+        assert_eq!(resolve(1, &headers), Some("10.0.0.2".parse().unwrap()));
+        assert_eq!(resolve(2, &headers), Some("1.2.3.4".parse().unwrap()));
+        assert_eq!(resolve(3, &headers), Some("6.6.6.6".parse().unwrap()));
+    }
+
+    #[test]
+    fn falls_back_to_the_peer_when_the_chain_is_short() {
+        // the first entry is whatever the client sent, it doesn't count
+        let headers = [("x-forwarded-for", "6.6.6.6, 1.2.3.4"), ("x-forwarded-for", "10.0.0.2")];
+        assert_eq!(resolve(5, &headers), Some("10.0.0.1".parse().unwrap()));
+        assert_eq!(resolve(1, &[]), Some("10.0.0.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn only_reads_the_configured_header() {
+        let headers = [("forwarded", "for=6.6.6.6"), ("x-forwarded-for", "1.2.3.4")];
+        assert_eq!(resolve(1, &headers), Some("1.2.3.4".parse().unwrap()));
+        assert_eq!(resolve_from(1, ForwardingHeader::Forwarded, &headers), Some("6.6.6.6".parse().unwrap()));
+        assert_eq!(resolve_from(1, ForwardingHeader::Forwarded, &headers[1..]), Some("10.0.0.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn reads_forwarded_nodes() {
+        let resolve = |headers| resolve_from(1, ForwardingHeader::Forwarded, headers);
+        let headers = [("forwarded", r#"for=6.6.6.6, for="[2001:db8::1]:4711";proto=https"#)];
+        assert_eq!(resolve(&headers), Some("2001:db8::1".parse().unwrap()));
+        assert_eq!(resolve(&[("forwarded", "for=1.2.3.4:80")]), Some("1.2.3.4".parse().unwrap()));
+        assert_eq!(resolve(&[("forwarded", "for=unknown")]), None);
+    }
+}
@@ -0,0 +1,97 @@
+use std::time::Duration;
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+
+/// Where the time comes from for logic that depends on it, such as token
+/// expiry, quota periods and when scheduled jobs run, so tests can move it
+/// along instead of waiting. Queries bind it for the timestamps they write,
+/// `updated_at` included; the database only stamps rows changed outside
+/// the app.
+#[async_trait]
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+
+    /// Returns once `duration` has passed by this clock.
+    async fn sleep(&self, duration: Duration);
+}
+
+/// The wall clock, what the server runs on.
+pub struct SystemClock;
+
+#[async_trait]
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        tokio::time::sleep(duration).await;
+    }
+}
+
+/// A clock that stands still until a test moves it with `advance`, which
+/// wakes up whoever is sleeping past the new time.
+#[cfg(test)]
+pub struct ManualClock {
+    now: tokio::sync::watch::Sender<DateTime<Utc>>,
+}
+
+#[cfg(test)]
+impl ManualClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        ManualClock { now: tokio::sync::watch::channel(now).0 }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.now.send_modify(|now| *now += to_chrono(by));
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.borrow()
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        let mut now = self.now.subscribe();
+        let wake_at = *now.borrow_and_update() + to_chrono(duration);
+        while *now.borrow_and_update() < wake_at {
+            if now.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn to_chrono(duration: Duration) -> chrono::Duration {
+    chrono::Duration::from_std(duration).unwrap_or_else(|_| chrono::Duration::max_value())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn sleepers_wake_once_the_clock_passes_their_time() {
+        let clock = std::sync::Arc::new(ManualClock::new(Utc.timestamp_opt(1_760_520_600, 0).unwrap()));
+        let sleeper = tokio::spawn({
+            let clock = clock.clone();
+            async move { clock.sleep(Duration::from_secs(60)).await }
+        });
+        tokio::task::yield_now().await;
+
+        clock.advance(Duration::from_secs(59));
+        tokio::task::yield_now().await;
+        assert!(!sleeper.is_finished());
+
+        clock.advance(Duration::from_secs(1));
+        sleeper.await.unwrap();
+        assert_eq!(clock.now(), Utc.timestamp_opt(1_760_520_660, 0).unwrap());
+    }
+}
@@ -0,0 +1,149 @@
+use std::{collections::HashMap, future::Future, sync::Mutex};
+
+use serde_json::{json, Value};
+use tokio::sync::broadcast;
+
+type Key = (&'static str, String);
+
+#[derive(Default)]
+struct Counts {
+    loaded: u64,
+    coalesced: u64,
+}
+
+/// Lets concurrent identical reads share one trip to the database: the
+/// first request for a key runs the query, and those arriving while it is
+/// underway get its result instead of running it again. Only for reads
+/// whose result is the same for everyone asking, like the leaderboard.
+#[derive(Default)]
+pub struct Coalescer {
+    in_flight: Mutex<HashMap<Key, broadcast::Sender<Option<Value>>>>,
+    counts: Mutex<HashMap<&'static str, Counts>>,
+}
+
+impl Coalescer {
+    /// What `load` returns for `key`, shared with every call for the same
+    /// `name` and `key` made while it runs. A failed or abandoned load
+    /// isn't shared, those waiting on it run their own.
+    pub async fn run<F, Fut, E>(&self, name: &'static str, key: String, load: F) -> Result<Value, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Value, E>>,
+    {
+        let key = (name, key);
+        let waiting = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            match in_flight.get(&key) {
+                Some(leader) => Some(leader.subscribe()),
+                None => {
+                    in_flight.insert(key.clone(), broadcast::channel(1).0);
+                    None
+                }
+            }
+        };
+
+        if let Some(mut leader) = waiting {
+            if let Ok(Some(value)) = leader.recv().await {
+                self.count(name, |counts| counts.coalesced += 1);
+                return Ok(value);
+            }
+            self.count(name, |counts| counts.loaded += 1);
+            return load().await;
+        }
+
+        let flight = Flight { coalescer: self, key: Some(key) };
+        let result = load().await;
+        self.count(name, |counts| counts.loaded += 1);
+        flight.land(result.as_ref().ok().cloned());
+        result
+    }
+
+    /// How many reads of each kind ran, and how many were served by one
+    /// that was already running.
+    pub fn to_json(&self) -> Value {
+        let counts = self.counts.lock().unwrap();
+        let mut reads: Vec<Value> = counts
+            .iter()
+            .map(|(name, counts)| json!({"name": name, "loaded": counts.loaded, "coalesced": counts.coalesced}))
+            .collect();
+        reads.sort_by(|a, b| a["name"].as_str().cmp(&b["name"].as_str()));
+        Value::Array(reads)
+    }
+
+    fn count(&self, name: &'static str, add: impl FnOnce(&mut Counts)) {
+        add(self.counts.lock().unwrap().entry(name).or_default());
+    }
+}
+
+// A load underway. Taken off the in-flight list once it lands, or when the
+// request running it goes away first so the next one for the key runs anew.
+struct Flight<'a> {
+    coalescer: &'a Coalescer,
+    key: Option<Key>,
+}
+
+impl Flight<'_> {
+    fn land(mut self, value: Option<Value>) {
+        let key = self.key.take().unwrap();
+        if let Some(waiting) = self.coalescer.in_flight.lock().unwrap().remove(&key) {
+            let _ = waiting.send(value);
+        }
+    }
+}
+
+impl Drop for Flight<'_> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.coalescer.in_flight.lock().unwrap().remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn concurrent_reads_share_one_load() {
+        let coalescer = Arc::new(Coalescer::default());
+        let loads = Arc::new(AtomicUsize::new(0));
+        let (release, released) = tokio::sync::watch::channel(false);
+
+        let reads: Vec<_> = (0..5)
+            .map(|_| {
+                let (coalescer, loads, mut released) = (coalescer.clone(), loads.clone(), released.clone());
+                tokio::spawn(async move {
+                    coalescer
+                        .run("page", "1".to_string(), || async move {
+                            loads.fetch_add(1, Ordering::SeqCst);
+                            let _ = released.wait_for(|released| *released).await;
+                            Ok::<_, ()>(json!({"page": 1}))
+                        })
+                        .await
+                })
+            })
+            .collect();
+        tokio::task::yield_now().await;
+        release.send(true).unwrap();
+
+        for read in reads {
+            assert_eq!(read.await.unwrap(), Ok(json!({"page": 1})));
+        }
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+        assert_eq!(coalescer.to_json(), json!([{"name": "page", "loaded": 1, "coalesced": 4}]));
+    }
+
+    #[tokio::test]
+    async fn a_failed_load_is_not_shared() {
+        let coalescer = Coalescer::default();
+        let first = coalescer.run("page", "1".to_string(), || async { Err::<Value, _>("down") });
+        assert_eq!(first.await, Err("down"));
+        let second = coalescer.run("page", "1".to_string(), || async { Ok::<_, &str>(json!(2)) });
+        assert_eq!(second.await, Ok(json!(2)));
+    }
+}
@@ -0,0 +1,249 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+use axum::{
+    body::{self, Body, HttpBody, StreamBody},
+    extract::{MatchedPath, State},
+    http::Request,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::{error::AppError, rate_limit, AppState};
+
+/// Caps how many requests to each expensive route (exports, imports, bulk
+/// erasure) run at once, and how many of those one user can have running,
+/// so a single tenant can't tie up the database for everyone.
+/// `CONCURRENCY_PER_ROUTE` is 4 unless set, `CONCURRENCY_PER_USER` 1, and
+/// `CONCURRENCY_QUEUE`, 8 unless set, is how many more can wait for a slot
+/// before the rest are turned away with 429. Users are told apart by the
+/// `:id` in the path, or by their address on routes without one.
+pub struct ConcurrencyLimits {
+    per_route: usize,
+    per_user: usize,
+    queue: usize,
+    routes: Mutex<HashMap<String, Arc<Route>>>,
+}
+
+struct Route {
+    slots: Arc<Semaphore>,
+    waiting: AtomicUsize,
+    running_by_user: Mutex<HashMap<String, usize>>,
+    // moving average of how long a request holds its slot, for Retry-After
+    average_secs: Mutex<f64>,
+}
+
+// A request counted against its user's limit, until dropped.
+struct Running {
+    route: Arc<Route>,
+    user: String,
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        let mut running = self.route.running_by_user.lock().unwrap();
+        if let Some(count) = running.get_mut(&self.user) {
+            *count -= 1;
+            if *count == 0 {
+                running.remove(&self.user);
+            }
+        }
+    }
+}
+
+// A request's slot, given back once its response body is done with so a
+// streamed export counts for as long as it streams.
+struct Hold {
+    running: Running,
+    started: Instant,
+    _slot: OwnedSemaphorePermit,
+}
+
+impl Drop for Hold {
+    fn drop(&mut self) {
+        let took = self.started.elapsed().as_secs_f64();
+        let mut average = self.running.route.average_secs.lock().unwrap();
+        *average = if *average == 0.0 { took } else { *average * 0.8 + took * 0.2 };
+    }
+}
+
+// the count of waiting requests, decremented however the wait ends
+struct Waiting<'a>(&'a AtomicUsize);
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn setting(name: &str, default: usize) -> usize {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(default)
+}
+
+impl ConcurrencyLimits {
+    pub fn from_env() -> Self {
+        ConcurrencyLimits {
+            per_route: setting("CONCURRENCY_PER_ROUTE", 4),
+            per_user: setting("CONCURRENCY_PER_USER", 1),
+            queue: setting("CONCURRENCY_QUEUE", 8),
+            routes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn route(&self, path: &str) -> Arc<Route> {
+        self.routes
+            .lock()
+            .unwrap()
+            .entry(path.to_string())
+            .or_insert_with(|| {
+                Arc::new(Route {
+                    slots: Arc::new(Semaphore::new(self.per_route)),
+                    waiting: AtomicUsize::new(0),
+                    running_by_user: Mutex::new(HashMap::new()),
+                    average_secs: Mutex::new(0.0),
+                })
+            })
+            .clone()
+    }
+
+    // a wait of `ahead` requests' worth, going `per_route` at a time
+    fn retry_after(&self, route: &Route, ahead: usize) -> u64 {
+        let average = *route.average_secs.lock().unwrap();
+        (average * ahead as f64 / self.per_route as f64).ceil().max(1.0) as u64
+    }
+
+    async fn hold(&self, path: &str, user: String) -> Result<Hold, AppError> {
+        let route = self.route(path);
+        {
+            let mut running = route.running_by_user.lock().unwrap();
+            let count = running.entry(user.clone()).or_default();
+            if *count >= self.per_user {
+                return Err(AppError::ConcurrencyLimited {
+                    scope: "user",
+                    running: *count,
+                    queue_position: None,
+                    retry_after: self.retry_after(&route, 1),
+                });
+            }
+            *count += 1;
+        }
+        let running = Running { route: route.clone(), user };
+
+        let slot = match route.slots.clone().try_acquire_owned() {
+            Ok(slot) => slot,
+            Err(_) => {
+                let ahead = route.waiting.fetch_add(1, Ordering::SeqCst);
+                let _waiting = Waiting(&route.waiting);
+                if ahead >= self.queue {
+                    return Err(AppError::ConcurrencyLimited {
+                        scope: "route",
+                        running: self.per_route,
+                        queue_position: Some(ahead + 1),
+                        retry_after: self.retry_after(&route, ahead + 1),
+                    });
+                }
+                // first come, first served, the semaphore queues fairly
+                route.slots.clone().acquire_owned().await.expect("the slots are never closed")
+            }
+        };
+        Ok(Hold {
+            running,
+            started: Instant::now(),
+            _slot: slot,
+        })
+    }
+}
+
+// the `:id` the route was called with, when it has one
+fn path_user(matched: &str, path: &str) -> Option<String> {
+    matched
+        .split('/')
+        .zip(path.split('/'))
+        .find(|(pattern, _)| *pattern == ":id")
+        .map(|(_, id)| id.to_string())
+}
+
+/// Holds the request until the route has a free slot, or turns it away
+/// with 429 when the user already has as many running as allowed or the
+/// queue is full. For the expensive routes only, layered onto each.
+pub async fn limit_concurrency(
+    State(data): State<Arc<AppState>>,
+    matched: MatchedPath,
+    req: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    let user = path_user(matched.as_str(), req.uri().path()).unwrap_or_else(|| rate_limit::client_key(&data, &req));
+    let hold = match data.concurrency.hold(matched.as_str(), user).await {
+        Ok(hold) => hold,
+        Err(e) => return e.into_response(),
+    };
+
+    let response = next.run(req).await;
+    let (parts, body) = response.into_parts();
+    let body = futures_util::stream::unfold((body, hold), |(mut body, hold)| async move {
+        let chunk = body.data().await?;
+        Some((chunk, (body, hold)))
+    });
+    Response::from_parts(parts, body::boxed(StreamBody::new(body)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(per_route: usize, per_user: usize, queue: usize) -> ConcurrencyLimits {
+        ConcurrencyLimits {
+            per_route,
+            per_user,
+            queue,
+            routes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    #[tokio::test]
+    async fn a_user_gets_their_slot_back_when_done() {
+        let limits = limits(4, 1, 8);
+        let first = limits.hold("/export", "a".to_string()).await.unwrap();
+        assert!(matches!(
+            limits.hold("/export", "a".to_string()).await,
+            Err(AppError::ConcurrencyLimited { scope: "user", .. })
+        ));
+        assert!(limits.hold("/export", "b".to_string()).await.is_ok());
+
+        drop(first);
+        assert!(limits.hold("/export", "a".to_string()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn past_a_full_queue_requests_are_told_their_place() {
+        let limits = Arc::new(limits(1, 10, 1));
+        let running = limits.hold("/export", "a".to_string()).await.unwrap();
+        let queued = tokio::spawn({
+            let limits = limits.clone();
+            async move { limits.hold("/export", "b".to_string()).await.is_ok() }
+        });
+        tokio::task::yield_now().await;
+
+        assert!(matches!(
+            limits.hold("/export", "c".to_string()).await,
+            Err(AppError::ConcurrencyLimited {
+                scope: "route",
+                queue_position: Some(2),
+                ..
+            })
+        ));
+        drop(running);
+        assert!(queued.await.unwrap());
+    }
+}
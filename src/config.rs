@@ -0,0 +1,125 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, OnceLock, RwLock},
+};
+
+use tokio::signal::unix::{signal, SignalKind};
+
+use crate::{cors::CorsOrigins, http_log::HttpLog, AppState};
+
+/// The settings that take effect on a reload, the rest are read once at
+/// startup and need a restart to change.
+pub const RELOADABLE: [&str; 3] = ["rate_limit", "cors", "http_log"];
+
+// The config file, `.env` unless `CONFIG_FILE` names another. Variables set
+// in the process environment win over the file, as they do at startup.
+struct Config {
+    file: String,
+    environment: HashSet<String>,
+    values: RwLock<HashMap<String, String>>,
+}
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+// the iterator is the only way to read the file without loading it into the
+// environment, whatever its deprecation note says
+#[allow(deprecated)]
+fn read(file: &str) -> Result<HashMap<String, String>, dotenv::Error> {
+    match dotenv::from_filename_iter(file) {
+        Ok(lines) => lines.collect(),
+        Err(e) if e.not_found() => Ok(HashMap::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Loads the config file into the environment, remembering what came from
+/// where so a reload can tell the file's values apart.
+pub fn init() {
+    let environment = std::env::vars_os().filter_map(|(name, _)| name.into_string().ok()).collect();
+    let file = std::env::var("CONFIG_FILE").unwrap_or_else(|_| ".env".to_string());
+    let values = read(&file).unwrap_or_default();
+    dotenv::from_filename(&file).ok();
+    CONFIG.get_or_init(|| Config {
+        file,
+        environment,
+        values: RwLock::new(values),
+    });
+}
+
+/// The setting `name` as of the last reload, for settings in `RELOADABLE`.
+pub fn var(name: &str) -> Option<String> {
+    match CONFIG.get() {
+        Some(config) if !config.environment.contains(name) => config.values.read().unwrap().get(name).cloned(),
+        _ => std::env::var(name).ok(),
+    }
+}
+
+/// A setting that can be swapped out while running. Requests holding the
+/// old one finish with it.
+pub struct Reloadable<T>(RwLock<Arc<T>>);
+
+impl<T> Reloadable<T> {
+    pub fn new(value: T) -> Self {
+        Reloadable(RwLock::new(Arc::new(value)))
+    }
+
+    pub fn get(&self) -> Arc<T> {
+        self.0.read().unwrap().clone()
+    }
+
+    pub fn set(&self, value: T) {
+        *self.0.write().unwrap() = Arc::new(value);
+    }
+}
+
+/// Reads the config file again and applies the settings in `RELOADABLE`.
+/// A file that can't be read or parsed changes nothing.
+pub fn reload(data: &AppState) -> Result<(), String> {
+    if let Some(config) = CONFIG.get() {
+        let values = read(&config.file).map_err(|e| format!("{}: {}", config.file, e))?;
+        *config.values.write().unwrap() = values;
+    }
+
+    data.rate_limit.reload();
+    data.cors.set(CorsOrigins::from_env());
+    data.http_log.set(HttpLog::from_env());
+    println!("✅ Reloaded the configuration: {}", RELOADABLE.join(", "));
+    Ok(())
+}
+
+/// Reloads the configuration on SIGHUP, without dropping connections.
+pub fn spawn_reload_on_hangup(data: Arc<AppState>) {
+    tokio::spawn(async move {
+        let mut hangups = match signal(SignalKind::hangup()) {
+            Ok(hangups) => hangups,
+            Err(err) => {
+                println!("🔥 Cannot listen for SIGHUP, the configuration won't reload: {}", err);
+                return;
+            }
+        };
+        while hangups.recv().await.is_some() {
+            if let Err(e) = reload(&data) {
+                println!("🔥 Failed to reload the configuration, keeping the current one: {}", e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_missing_file_reads_as_empty() {
+        assert_eq!(read("does-not-exist.env").unwrap(), HashMap::new());
+    }
+
+    #[test]
+    fn a_malformed_file_is_an_error() {
+        let path = std::env::temp_dir().join(format!("invito-config-{}.env", std::process::id()));
+        std::fs::write(&path, "RATE_LIMIT_PER_MINUTE=60\nnot a setting\n").unwrap();
+        let read = read(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        assert!(read.is_err());
+    }
+}
@@ -0,0 +1,53 @@
+use std::sync::Arc;
+
+use axum::http::{
+    header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE},
+    HeaderName, HeaderValue, Method,
+};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+use crate::{challenge, config};
+
+/// The origins browsers may call the API from, with credentials.
+/// `CORS_ORIGINS` is a comma separated list of them,
+/// `http://localhost:4000` unless set.
+pub struct CorsOrigins(Vec<HeaderValue>);
+
+impl CorsOrigins {
+    pub fn from_env() -> Self {
+        let origins = config::var("CORS_ORIGINS").unwrap_or_else(|| "http://localhost:4000".to_string());
+        CorsOrigins(
+            origins
+                .split(',')
+                .map(|origin| origin.trim().trim_end_matches('/'))
+                .filter(|origin| !origin.is_empty())
+                .filter_map(|origin| match origin.parse() {
+                    Ok(origin) => Some(origin),
+                    Err(_) => {
+                        println!("🔥 Ignoring `{}` in CORS_ORIGINS, it isn't a valid origin", origin);
+                        None
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    fn allows(&self, origin: &HeaderValue) -> bool {
+        self.0.contains(origin)
+    }
+}
+
+/// The CORS layer, checking origins against whatever `origins` holds at
+/// the time so a reload applies to the next preflight.
+pub fn layer(origins: Arc<config::Reloadable<CorsOrigins>>) -> CorsLayer {
+    CorsLayer::new()
+        .allow_origin(AllowOrigin::predicate(move |origin, _| origins.get().allows(origin)))
+        .allow_methods([Method::GET, Method::POST, Method::PATCH, Method::DELETE])
+        .allow_credentials(true)
+        .allow_headers([
+            AUTHORIZATION,
+            ACCEPT,
+            CONTENT_TYPE,
+            HeaderName::from_static(challenge::RESPONSE_HEADER),
+        ])
+}
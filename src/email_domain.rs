@@ -0,0 +1,47 @@
+use sqlx::{Executor, Postgres};
+use uuid::Uuid;
+
+use crate::error::AppError;
+
+/// The lower-cased domain of an email address.
+pub fn domain_of(email: &str) -> Option<String> {
+    let (_, domain) = email.trim().rsplit_once('@')?;
+    (!domain.is_empty()).then(|| domain.to_lowercase())
+}
+
+/// A domain as rules store it, without the `@` or leading dot people tend to
+/// type.
+pub fn normalize(domain: &str) -> String {
+    domain.trim().trim_start_matches(['@', '.']).to_lowercase()
+}
+
+/// Checks an email address against the domain rules, the global ones and,
+/// for an invitation, the organization's. A rule matches its domain and any
+/// subdomain. A block rule turns the address away, and once a scope has
+/// allow rules only addresses matching one of them get in.
+pub async fn check<'e>(
+    db: impl Executor<'e, Database = Postgres>,
+    email: &str,
+    org_id: Option<Uuid>,
+) -> Result<(), AppError> {
+    // a malformed address is left for the insert to deal with
+    let Some(domain) = domain_of(email) else {
+        return Ok(());
+    };
+
+    let rules = sqlx::query!(
+        r#"SELECT COALESCE(bool_or(rule = 'block' AND matches), false) AS "blocked!", COALESCE(bool_or(rule = 'allow' AND org_id IS NULL), false) AS "global_allow_list!", COALESCE(bool_or(rule = 'allow' AND org_id IS NULL AND matches), false) AS "global_allowed!", COALESCE(bool_or(rule = 'allow' AND org_id IS NOT NULL), false) AS "org_allow_list!", COALESCE(bool_or(rule = 'allow' AND org_id IS NOT NULL AND matches), false) AS "org_allowed!" FROM (SELECT rule, org_id, ($1 = domain OR $1 LIKE '%.' || domain) AS matches FROM email_domain_rules WHERE org_id IS NULL OR org_id = $2) rules"#,
+        domain,
+        org_id
+    )
+    .fetch_one(db)
+    .await?;
+
+    if rules.blocked {
+        return Err(AppError::EmailDomainBlocked(domain));
+    }
+    if (rules.global_allow_list && !rules.global_allowed) || (rules.org_allow_list && !rules.org_allowed) {
+        return Err(AppError::EmailDomainNotAllowed(domain));
+    }
+    Ok(())
+}
@@ -0,0 +1,45 @@
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, Postgres};
+use uuid::Uuid;
+
+// how far an email got, a webhook arriving late never moves one back and a
+// bounce is final
+const PROGRESS: [&str; 4] = ["queued", "sent", "delivered", "opened"];
+
+/// Starts tracking an email about to be handed to the provider. The id
+/// returned goes along with the email so the provider's webhooks can name it.
+pub async fn queue<'e>(
+    db: impl Executor<'e, Database = Postgres>,
+    invitation_id: Option<Uuid>,
+    email: &str,
+) -> Result<Uuid, sqlx::Error> {
+    sqlx::query_scalar!(
+        "INSERT INTO email_log (invitation_id, email) VALUES ($1, $2) RETURNING id",
+        invitation_id,
+        crate::pii::seal(email)
+    )
+    .fetch_one(db)
+    .await
+}
+
+/// Moves a tracked email to `status`, one of `sent`, `delivered`, `opened`
+/// or `bounced`. `false` when no email has that id.
+pub async fn record<'e>(
+    db: impl Executor<'e, Database = Postgres>,
+    message_id: Uuid,
+    status: &str,
+    details: Option<&str>,
+    now: DateTime<Utc>,
+) -> Result<bool, sqlx::Error> {
+    let updated = sqlx::query_scalar!(
+        "UPDATE email_log SET status = CASE WHEN status = 'bounced' OR array_position($3::text[], status) >= array_position($3::text[], $2) THEN status ELSE $2 END, sent_at = CASE WHEN $2 = 'sent' THEN COALESCE(sent_at, $5) ELSE sent_at END, delivered_at = CASE WHEN $2 = 'delivered' THEN COALESCE(delivered_at, $5) ELSE delivered_at END, opened_at = CASE WHEN $2 = 'opened' THEN COALESCE(opened_at, $5) ELSE opened_at END, bounced_at = CASE WHEN $2 = 'bounced' THEN COALESCE(bounced_at, $5) ELSE bounced_at END, details = COALESCE($4, details), updated_at = $5 WHERE id = $1 RETURNING id",
+        message_id,
+        status,
+        &PROGRESS.map(str::to_string) as &[String],
+        details,
+        now
+    )
+    .fetch_optional(db)
+    .await?;
+    Ok(updated.is_some())
+}
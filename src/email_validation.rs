@@ -0,0 +1,114 @@
+use std::{collections::HashSet, time::Duration};
+
+use hickory_resolver::{error::ResolveErrorKind, TokioAsyncResolver};
+
+use crate::{email_domain, error::AppError};
+
+const DISPOSABLE_DOMAINS: &str = include_str!("../data/disposable_domains.txt");
+
+/// Optional checks on the email address of a signup: that it isn't at a
+/// disposable provider, and that its domain takes mail at all.
+pub struct EmailValidation {
+    disposable: HashSet<String>,
+    reject_disposable: bool,
+    resolver: Option<TokioAsyncResolver>,
+    timeout: Duration,
+}
+
+impl EmailValidation {
+    /// `EMAIL_CHECK_DISPOSABLE=true` turns signups at the providers in
+    /// `data/disposable_domains.txt` away, plus those listed one per line in
+    /// the file at `DISPOSABLE_DOMAINS_FILE`. `EMAIL_CHECK_MX=true` looks up
+    /// the domain's MX records, giving up after `EMAIL_MX_TIMEOUT_MS`
+    /// (2000 by default).
+    pub fn from_env() -> Self {
+        let enabled = |name| std::env::var(name).is_ok_and(|value| value == "true");
+
+        let mut disposable = parse_domains(DISPOSABLE_DOMAINS);
+        if let Ok(path) = std::env::var("DISPOSABLE_DOMAINS_FILE") {
+            match std::fs::read_to_string(&path) {
+                Ok(extra) => disposable.extend(parse_domains(&extra)),
+                Err(err) => println!("🔥 Could not read {}: {}", path, err),
+            }
+        }
+
+        let resolver = enabled("EMAIL_CHECK_MX")
+            .then(|| match TokioAsyncResolver::tokio_from_system_conf() {
+                Ok(resolver) => Some(resolver),
+                Err(err) => {
+                    println!("🔥 MX checks are off, no DNS configuration: {}", err);
+                    None
+                }
+            })
+            .flatten();
+
+        let timeout_ms = std::env::var("EMAIL_MX_TIMEOUT_MS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(2000);
+
+        EmailValidation {
+            disposable,
+            reject_disposable: enabled("EMAIL_CHECK_DISPOSABLE"),
+            resolver,
+            timeout: Duration::from_millis(timeout_ms),
+        }
+    }
+
+    /// Whether the domain, or one it's a subdomain of, is a disposable
+    /// provider, whether or not signups there are turned away.
+    pub fn is_disposable(&self, domain: &str) -> bool {
+        let mut candidate = domain;
+        loop {
+            if self.disposable.contains(candidate) {
+                return true;
+            }
+            match candidate.split_once('.') {
+                Some((_, parent)) => candidate = parent,
+                None => return false,
+            }
+        }
+    }
+
+    pub async fn check(&self, email: &str) -> Result<(), AppError> {
+        // a malformed address is left for the insert to deal with
+        let Some(domain) = email_domain::domain_of(email) else {
+            return Ok(());
+        };
+        if self.reject_disposable && self.is_disposable(&domain) {
+            return Err(AppError::EmailDisposable(domain));
+        }
+
+        let Some(resolver) = &self.resolver else {
+            return Ok(());
+        };
+        // our own DNS being slow or down shouldn't stop anyone signing up
+        let lookup = match tokio::time::timeout(self.timeout, resolver.mx_lookup(format!("{}.", domain))).await {
+            Ok(lookup) => lookup,
+            Err(_) => {
+                println!("🔥 MX lookup for {} timed out", domain);
+                return Ok(());
+            }
+        };
+        match lookup {
+            // a lone `.` exchange is a null MX, the domain says it takes no mail
+            Ok(records) if records.iter().any(|mx| !mx.exchange().is_root()) => Ok(()),
+            Ok(_) => Err(AppError::EmailDomainNoMx(domain)),
+            Err(err) if matches!(err.kind(), ResolveErrorKind::NoRecordsFound { .. }) => {
+                Err(AppError::EmailDomainNoMx(domain))
+            }
+            Err(err) => {
+                println!("🔥 MX lookup for {} failed: {}", domain, err);
+                Ok(())
+            }
+        }
+    }
+}
+
+fn parse_domains(list: &str) -> HashSet<String> {
+    list.lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+        .map(email_domain::normalize)
+        .collect()
+}
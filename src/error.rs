@@ -0,0 +1,552 @@
+use axum::{
+    extract::rejection::{JsonRejection, PathRejection, QueryRejection, TypedHeaderRejection},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use uuid::Uuid;
+
+use crate::{
+    billing::SignatureError, extract::Json, i18n, invite_token::TokenError, report, request_signing::SigningError,
+};
+
+/// Every error the API returns. Each variant maps to a status code and a
+/// machine-readable code, and its message is rendered in the request's locale.
+#[derive(Debug)]
+pub enum AppError {
+    Database(sqlx::Error),
+    Unexpected,
+    BodyInvalid(JsonRejection),
+    PathInvalid(PathRejection),
+    QueryInvalid(QueryRejection),
+    HeaderInvalid(TypedHeaderRejection),
+    RouteNotFound,
+    MethodNotAllowed,
+    UsersFetchFailed(sqlx::Error),
+    UserNotFound(String),
+    RefCodeNotFound(String),
+    UserEmailTaken,
+    UserNameTaken,
+    UserVersionRequired,
+    UserVersionConflict(Uuid),
+    BatchTooLarge(usize),
+    ReplaySinceInvalid(String),
+    BulkSizeInvalid(usize),
+    PatchEmpty,
+    ContactNotFound(Uuid),
+    ContactEmailTaken(String),
+    CsvInvalid(String),
+    CsvMissingColumns,
+    OrgRequired,
+    OrgIdInvalid(String),
+    OrgNotFound(Uuid),
+    OrgRoleInvalid(Vec<&'static str>),
+    OrgMemberNotFound(Uuid),
+    OrgMembershipRequired(Uuid),
+    OrgAlreadyMember(Uuid),
+    OrgAdminRequired,
+    OrgOwnerRequired,
+    ChallengeRequired,
+    ChallengeFailed,
+    ChallengeUnavailable,
+    OrgLastOwner,
+    OrgInvitationPending(String),
+    OrgInvitationNotFound(Uuid),
+    OrgInvitationAnswered(Uuid),
+    PartySizeInvalid(i32),
+    GuestNamesTooMany(i32),
+    OrgCapacityInvalid,
+    OrgCapacityReached(i64),
+    InviteTokensNotConfigured,
+    InviteToken(TokenError),
+    SessionRequired,
+    Session(TokenError),
+    UserAccessDenied,
+    QuotaExceeded {
+        metric: &'static str,
+        limit: i32,
+        reset_at: chrono::DateTime<chrono::Utc>,
+    },
+    ConnectionLimitReached(i32),
+    RateLimited(u64),
+    ConcurrencyLimited {
+        scope: &'static str,
+        running: usize,
+        queue_position: Option<usize>,
+        retry_after: u64,
+    },
+    MaintenanceMode(u64),
+    BillingNotConfigured,
+    WebhookSignature(SignatureError),
+    WebhookBodyInvalid,
+    EmailWebhookNotConfigured,
+    EmailWebhookSignature,
+    UnsubscribeTokenInvalid,
+    SuppressionNotFound(Uuid),
+    EmailDomainBlocked(String),
+    EmailDomainNotAllowed(String),
+    EmailDomainRuleNotFound(Uuid),
+    EmailDomainRuleExists(String),
+    EmailDisposable(String),
+    EmailDomainNoMx(String),
+    FraudFlagNotFound(Uuid),
+    AnalyticsSeriesNotFound(String),
+    RewardRuleNotFound(Uuid),
+    RewardRuleInvalid,
+    InviteeBlocked,
+    BlockSelf,
+    BlockNotFound(Uuid),
+    TextInvalid(&'static str),
+    TextRequired(&'static str),
+    TextTooLong(&'static str, usize),
+    PageInvalid,
+    PageLimitInvalid(usize),
+    SortInvalid(Vec<&'static str>),
+    CursorInvalid,
+    IncludeInvalid(Vec<&'static str>),
+    UserIdAmbiguous(String),
+    RequestSignature(SigningError),
+    ConfigReloadFailed(String),
+    ExportNotFound(Uuid),
+    DownloadLinkInvalid,
+    DownloadNotFound,
+    UploadInvalid,
+    AttachmentTooLarge(usize),
+    AttachmentTypeUnsupported,
+    AttachmentNotFound(Uuid),
+    ImportColumnUnknown(String),
+    SnapshotKeyMissing,
+}
+
+impl AppError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::Database(_)
+            | AppError::Unexpected
+            | AppError::UsersFetchFailed(_)
+            | AppError::ConfigReloadFailed(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+            AppError::BodyInvalid(e) => e.status(),
+            AppError::PathInvalid(e) => e.status(),
+            AppError::QueryInvalid(e) => e.status(),
+            AppError::RouteNotFound => StatusCode::NOT_FOUND,
+            AppError::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            AppError::UserNotFound(_)
+            | AppError::RefCodeNotFound(_)
+            | AppError::ContactNotFound(_)
+            | AppError::OrgNotFound(_)
+            | AppError::OrgMemberNotFound(_)
+            | AppError::OrgInvitationNotFound(_)
+            | AppError::SuppressionNotFound(_)
+            | AppError::EmailDomainRuleNotFound(_)
+            | AppError::FraudFlagNotFound(_)
+            | AppError::AnalyticsSeriesNotFound(_)
+            | AppError::RewardRuleNotFound(_)
+            | AppError::BlockNotFound(_)
+            | AppError::ExportNotFound(_)
+            | AppError::AttachmentNotFound(_)
+            | AppError::DownloadNotFound => StatusCode::NOT_FOUND,
+            AppError::UserEmailTaken
+            | AppError::UserNameTaken
+            | AppError::UserVersionConflict(_)
+            | AppError::ContactEmailTaken(_)
+            | AppError::OrgAlreadyMember(_)
+            | AppError::OrgLastOwner
+            | AppError::OrgInvitationPending(_)
+            | AppError::OrgInvitationAnswered(_)
+            | AppError::OrgCapacityReached(_)
+            | AppError::EmailDomainRuleExists(_) => StatusCode::CONFLICT,
+            AppError::UserVersionRequired => StatusCode::PRECONDITION_REQUIRED,
+            AppError::BatchTooLarge(_)
+            | AppError::BulkSizeInvalid(_)
+            | AppError::PatchEmpty
+            | AppError::ReplaySinceInvalid(_)
+            | AppError::CsvInvalid(_)
+            | AppError::CsvMissingColumns
+            | AppError::ImportColumnUnknown(_)
+            | AppError::OrgRequired
+            | AppError::OrgIdInvalid(_)
+            | AppError::OrgRoleInvalid(_)
+            | AppError::PartySizeInvalid(_)
+            | AppError::GuestNamesTooMany(_)
+            | AppError::OrgCapacityInvalid
+            | AppError::UploadInvalid
+            | AppError::HeaderInvalid(_)
+            | AppError::WebhookSignature(_)
+            | AppError::WebhookBodyInvalid
+            | AppError::EmailWebhookSignature
+            | AppError::UnsubscribeTokenInvalid
+            | AppError::EmailDomainBlocked(_)
+            | AppError::EmailDomainNotAllowed(_)
+            | AppError::EmailDisposable(_)
+            | AppError::EmailDomainNoMx(_)
+            | AppError::RewardRuleInvalid
+            | AppError::BlockSelf
+            | AppError::TextInvalid(_)
+            | AppError::TextRequired(_)
+            | AppError::TextTooLong(..) => StatusCode::BAD_REQUEST,
+            AppError::PageInvalid
+            | AppError::PageLimitInvalid(_)
+            | AppError::SortInvalid(_)
+            | AppError::CursorInvalid
+            | AppError::IncludeInvalid(_)
+            | AppError::UserIdAmbiguous(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::InviteToken(_)
+            | AppError::SessionRequired
+            | AppError::Session(_)
+            | AppError::RequestSignature(_) => StatusCode::UNAUTHORIZED,
+            AppError::OrgMembershipRequired(_)
+            | AppError::OrgAdminRequired
+            | AppError::OrgOwnerRequired
+            | AppError::UserAccessDenied
+            | AppError::ChallengeRequired
+            | AppError::ChallengeFailed
+            | AppError::InviteeBlocked
+            | AppError::DownloadLinkInvalid => StatusCode::FORBIDDEN,
+            // limits that lift on their own in a moment, unlike a monthly
+            // allowance that takes a bigger plan
+            AppError::ConnectionLimitReached(_) | AppError::RateLimited(_) | AppError::ConcurrencyLimited { .. } => {
+                StatusCode::TOO_MANY_REQUESTS
+            }
+            AppError::QuotaExceeded { .. } => StatusCode::PAYMENT_REQUIRED,
+            AppError::AttachmentTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::AttachmentTypeUnsupported => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            AppError::BillingNotConfigured
+            | AppError::InviteTokensNotConfigured
+            | AppError::EmailWebhookNotConfigured
+            | AppError::SnapshotKeyMissing
+            | AppError::ChallengeUnavailable
+            | AppError::MaintenanceMode(_) => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::Database(_) => "INTERNAL_ERROR",
+            AppError::Unexpected => "UNEXPECTED_ERROR",
+            AppError::BodyInvalid(_) => "BODY_INVALID",
+            AppError::PathInvalid(_) => "PATH_INVALID",
+            AppError::QueryInvalid(_) => "QUERY_INVALID",
+            AppError::HeaderInvalid(_) => "HEADER_INVALID",
+            AppError::RouteNotFound => "ROUTE_NOT_FOUND",
+            AppError::MethodNotAllowed => "METHOD_NOT_ALLOWED",
+            AppError::UsersFetchFailed(_) => "USERS_FETCH_FAILED",
+            AppError::UserNotFound(_) => "USER_NOT_FOUND",
+            AppError::RefCodeNotFound(_) => "REF_CODE_NOT_FOUND",
+            AppError::UserEmailTaken => "USER_EMAIL_TAKEN",
+            AppError::UserNameTaken => "USER_NAME_TAKEN",
+            AppError::UserVersionRequired => "USER_VERSION_REQUIRED",
+            AppError::UserVersionConflict(_) => "USER_VERSION_CONFLICT",
+            AppError::BatchTooLarge(_) => "BATCH_TOO_LARGE",
+            AppError::BulkSizeInvalid(_) => "BULK_SIZE_INVALID",
+            AppError::PatchEmpty => "PATCH_EMPTY",
+            AppError::ReplaySinceInvalid(_) => "REPLAY_SINCE_INVALID",
+            AppError::ContactNotFound(_) => "CONTACT_NOT_FOUND",
+            AppError::ContactEmailTaken(_) => "CONTACT_EMAIL_TAKEN",
+            AppError::CsvInvalid(_) => "CSV_INVALID",
+            AppError::CsvMissingColumns => "CSV_MISSING_COLUMNS",
+            AppError::ImportColumnUnknown(_) => "IMPORT_COLUMN_UNKNOWN",
+            AppError::OrgRequired => "ORG_REQUIRED",
+            AppError::OrgIdInvalid(_) => "ORG_ID_INVALID",
+            AppError::OrgNotFound(_) => "ORG_NOT_FOUND",
+            AppError::OrgRoleInvalid(_) => "ORG_ROLE_INVALID",
+            AppError::OrgMemberNotFound(_) => "ORG_MEMBER_NOT_FOUND",
+            AppError::OrgMembershipRequired(_) => "ORG_MEMBERSHIP_REQUIRED",
+            AppError::OrgAlreadyMember(_) => "ORG_ALREADY_MEMBER",
+            AppError::OrgAdminRequired => "ORG_ADMIN_REQUIRED",
+            AppError::OrgOwnerRequired => "ORG_OWNER_REQUIRED",
+            AppError::ChallengeRequired => "CHALLENGE_REQUIRED",
+            AppError::ChallengeFailed => "CHALLENGE_FAILED",
+            AppError::ChallengeUnavailable => "CHALLENGE_UNAVAILABLE",
+            AppError::OrgLastOwner => "ORG_LAST_OWNER",
+            AppError::OrgInvitationPending(_) => "ORG_INVITATION_PENDING",
+            AppError::OrgInvitationNotFound(_) => "ORG_INVITATION_NOT_FOUND",
+            AppError::OrgInvitationAnswered(_) => "ORG_INVITATION_ANSWERED",
+            AppError::PartySizeInvalid(_) => "PARTY_SIZE_INVALID",
+            AppError::GuestNamesTooMany(_) => "GUEST_NAMES_TOO_MANY",
+            AppError::OrgCapacityInvalid => "ORG_CAPACITY_INVALID",
+            AppError::OrgCapacityReached(_) => "ORG_CAPACITY_REACHED",
+            AppError::InviteTokensNotConfigured => "INVITE_TOKENS_NOT_CONFIGURED",
+            AppError::InviteToken(TokenError::Malformed) => "INVITE_TOKEN_MALFORMED",
+            AppError::InviteToken(TokenError::Expired) => "INVITE_TOKEN_EXPIRED",
+            AppError::InviteToken(TokenError::Mismatch) => "INVITE_TOKEN_INVALID",
+            AppError::SessionRequired => "SESSION_REQUIRED",
+            AppError::Session(TokenError::Malformed) => "SESSION_MALFORMED",
+            AppError::Session(TokenError::Expired) => "SESSION_EXPIRED",
+            AppError::Session(TokenError::Mismatch) => "SESSION_INVALID",
+            AppError::UserAccessDenied => "USER_ACCESS_DENIED",
+            AppError::QuotaExceeded { .. } => "QUOTA_EXCEEDED",
+            AppError::ConnectionLimitReached(_) => "CONNECTION_LIMIT_REACHED",
+            AppError::RateLimited(_) => "RATE_LIMITED",
+            AppError::ConcurrencyLimited { .. } => "CONCURRENCY_LIMITED",
+            AppError::MaintenanceMode(_) => "MAINTENANCE_MODE",
+            AppError::BillingNotConfigured => "BILLING_NOT_CONFIGURED",
+            AppError::WebhookSignature(SignatureError::Malformed) => "WEBHOOK_SIGNATURE_MALFORMED",
+            AppError::WebhookSignature(SignatureError::Expired) => "WEBHOOK_SIGNATURE_EXPIRED",
+            AppError::WebhookSignature(SignatureError::Mismatch) => "WEBHOOK_SIGNATURE_MISMATCH",
+            AppError::WebhookBodyInvalid => "WEBHOOK_BODY_INVALID",
+            AppError::EmailWebhookNotConfigured => "EMAIL_WEBHOOK_NOT_CONFIGURED",
+            AppError::EmailWebhookSignature => "EMAIL_WEBHOOK_SIGNATURE_MISMATCH",
+            AppError::UnsubscribeTokenInvalid => "UNSUBSCRIBE_TOKEN_INVALID",
+            AppError::SuppressionNotFound(_) => "SUPPRESSION_NOT_FOUND",
+            AppError::EmailDomainBlocked(_) => "EMAIL_DOMAIN_BLOCKED",
+            AppError::EmailDomainNotAllowed(_) => "EMAIL_DOMAIN_NOT_ALLOWED",
+            AppError::EmailDomainRuleNotFound(_) => "EMAIL_DOMAIN_RULE_NOT_FOUND",
+            AppError::EmailDomainRuleExists(_) => "EMAIL_DOMAIN_RULE_EXISTS",
+            AppError::EmailDisposable(_) => "EMAIL_DISPOSABLE",
+            AppError::EmailDomainNoMx(_) => "EMAIL_DOMAIN_NO_MX",
+            AppError::FraudFlagNotFound(_) => "FRAUD_FLAG_NOT_FOUND",
+            AppError::AnalyticsSeriesNotFound(_) => "ANALYTICS_SERIES_NOT_FOUND",
+            AppError::RewardRuleNotFound(_) => "REWARD_RULE_NOT_FOUND",
+            AppError::RewardRuleInvalid => "REWARD_RULE_INVALID",
+            AppError::InviteeBlocked => "INVITEE_BLOCKED",
+            AppError::BlockSelf => "BLOCK_SELF",
+            AppError::BlockNotFound(_) => "BLOCK_NOT_FOUND",
+            AppError::TextInvalid(_) => "TEXT_INVALID",
+            AppError::TextRequired(_) => "TEXT_REQUIRED",
+            AppError::TextTooLong(..) => "TEXT_TOO_LONG",
+            AppError::PageInvalid => "PAGE_INVALID",
+            AppError::PageLimitInvalid(_) => "PAGE_LIMIT_INVALID",
+            AppError::SortInvalid(_) => "SORT_INVALID",
+            AppError::CursorInvalid => "CURSOR_INVALID",
+            AppError::IncludeInvalid(_) => "INCLUDE_INVALID",
+            AppError::UserIdAmbiguous(_) => "USER_ID_AMBIGUOUS",
+            AppError::RequestSignature(SigningError::Required) => "REQUEST_SIGNATURE_REQUIRED",
+            AppError::RequestSignature(SigningError::Malformed) => "REQUEST_SIGNATURE_MALFORMED",
+            AppError::RequestSignature(SigningError::Expired) => "REQUEST_SIGNATURE_EXPIRED",
+            AppError::RequestSignature(SigningError::Mismatch) => "REQUEST_SIGNATURE_MISMATCH",
+            AppError::RequestSignature(SigningError::Replayed) => "REQUEST_SIGNATURE_REPLAYED",
+            AppError::ConfigReloadFailed(_) => "CONFIG_RELOAD_FAILED",
+            AppError::ExportNotFound(_) => "EXPORT_NOT_FOUND",
+            AppError::DownloadLinkInvalid => "DOWNLOAD_LINK_INVALID",
+            AppError::DownloadNotFound => "DOWNLOAD_NOT_FOUND",
+            AppError::UploadInvalid => "UPLOAD_INVALID",
+            AppError::AttachmentTooLarge(_) => "ATTACHMENT_TOO_LARGE",
+            AppError::AttachmentTypeUnsupported => "ATTACHMENT_TYPE_UNSUPPORTED",
+            AppError::AttachmentNotFound(_) => "ATTACHMENT_NOT_FOUND",
+            AppError::SnapshotKeyMissing => "SNAPSHOT_KEY_MISSING",
+        }
+    }
+
+    // values substituted into the message template
+    fn message_args(&self) -> Vec<(&'static str, String)> {
+        match self {
+            AppError::BodyInvalid(e) => vec![("details", e.body_text())],
+            AppError::PathInvalid(e) => {
+                let details = e.body_text();
+                vec![("details", details.strip_prefix("Invalid URL: ").unwrap_or(&details).to_string())]
+            }
+            AppError::QueryInvalid(e) => vec![("details", e.body_text())],
+            AppError::HeaderInvalid(e) => vec![("details", e.to_string())],
+            AppError::UserNotFound(user) => vec![("user", user.clone())],
+            AppError::RefCodeNotFound(ref_code) => vec![("ref_code", ref_code.clone())],
+            AppError::UserVersionConflict(id)
+            | AppError::OrgMemberNotFound(id)
+            | AppError::OrgMembershipRequired(id)
+            | AppError::OrgAlreadyMember(id) => vec![("user", id.to_string())],
+            AppError::BatchTooLarge(max) | AppError::BulkSizeInvalid(max) => vec![("max", max.to_string())],
+            AppError::PartySizeInvalid(max) | AppError::GuestNamesTooMany(max) => vec![("max", max.to_string())],
+            AppError::OrgCapacityReached(remaining) => vec![("remaining", remaining.to_string())],
+            AppError::ContactNotFound(id) => vec![("contact", id.to_string())],
+            AppError::SuppressionNotFound(id) => vec![("suppression", id.to_string())],
+            AppError::ContactEmailTaken(email)
+            | AppError::OrgInvitationPending(email) => {
+                vec![("email", email.clone())]
+            }
+            AppError::CsvInvalid(details) | AppError::ConfigReloadFailed(details) => {
+                vec![("details", details.clone())]
+            }
+            AppError::ReplaySinceInvalid(since) => vec![("since", since.clone())],
+            AppError::ImportColumnUnknown(column) => vec![("column", column.clone())],
+            AppError::OrgIdInvalid(org) => vec![("org", org.clone())],
+            AppError::EmailDomainBlocked(domain)
+            | AppError::EmailDomainNotAllowed(domain)
+            | AppError::EmailDomainRuleExists(domain)
+            | AppError::EmailDisposable(domain)
+            | AppError::EmailDomainNoMx(domain) => vec![("domain", domain.clone())],
+            AppError::EmailDomainRuleNotFound(id) => vec![("rule", id.to_string())],
+            AppError::FraudFlagNotFound(id) => vec![("flag", id.to_string())],
+            AppError::AnalyticsSeriesNotFound(series) => vec![("series", series.clone())],
+            AppError::RewardRuleNotFound(id) => vec![("rule", id.to_string())],
+            AppError::ExportNotFound(id) => vec![("export", id.to_string())],
+            AppError::AttachmentNotFound(id) => vec![("attachment", id.to_string())],
+            AppError::AttachmentTooLarge(max) => vec![("max", max.to_string())],
+            AppError::OrgNotFound(id) => vec![("org", id.to_string())],
+            AppError::OrgRoleInvalid(roles) => vec![("roles", roles.join(", "))],
+            AppError::OrgInvitationNotFound(id) | AppError::OrgInvitationAnswered(id) => {
+                vec![("invitation", id.to_string())]
+            }
+            AppError::QuotaExceeded { metric, limit, .. } => {
+                vec![("metric", metric.to_string()), ("limit", limit.to_string())]
+            }
+            AppError::ConnectionLimitReached(limit) => vec![("limit", limit.to_string())],
+            AppError::RateLimited(retry_after) | AppError::MaintenanceMode(retry_after) => {
+                vec![("retry_after", retry_after.to_string())]
+            }
+            AppError::ConcurrencyLimited { running, retry_after, .. } => {
+                vec![("running", running.to_string()), ("retry_after", retry_after.to_string())]
+            }
+            AppError::BlockNotFound(id) => vec![("user", id.to_string())],
+            AppError::TextInvalid(field) => vec![("field", field.to_string())],
+            AppError::TextRequired(field) => vec![("field", field.to_string())],
+            AppError::TextTooLong(field, max) => {
+                vec![("field", field.to_string()), ("max", max.to_string())]
+            }
+            AppError::PageLimitInvalid(max) => vec![("max", max.to_string())],
+            AppError::SortInvalid(keys) => vec![("keys", keys.join(", "))],
+            AppError::IncludeInvalid(relations) => vec![("relations", relations.join(", "))],
+            AppError::UserIdAmbiguous(raw) => vec![("user", raw.clone())],
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn message(&self) -> String {
+        i18n::t(self.code(), &self.message_args())
+    }
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(err: sqlx::Error) -> Self {
+        AppError::Database(err)
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        // panics are reported by the panic hook, where the backtrace is, and
+        // maintenance mode is on purpose
+        if status.is_server_error() && !matches!(self, AppError::Unexpected | AppError::MaintenanceMode(_)) {
+            let details = match &self {
+                AppError::Database(e) | AppError::UsersFetchFailed(e) => format!("{:?}", e),
+                other => format!("{:?}", other),
+            };
+            report::capture("error", &format!("{}: {}", self.code(), details));
+        }
+
+        // the details stay in the report, clients get the request id to quote instead
+        let mut error_response = serde_json::json!({
+            "status": if status.is_server_error() { "error" } else { "fail" },
+            "code": self.code(),
+            "message": self.message(),
+        });
+        if status.is_server_error() {
+            if let Some(request_id) = report::current().request_id {
+                error_response["request_id"] = serde_json::json!(request_id);
+            }
+        }
+
+        match &self {
+            AppError::QuotaExceeded { limit, reset_at, .. } => {
+                error_response["limit"] = serde_json::json!(limit);
+                error_response["reset_at"] = crate::timestamp::json(reset_at);
+            }
+            AppError::ConnectionLimitReached(limit) => {
+                error_response["limit"] = serde_json::json!(limit);
+            }
+            AppError::RateLimited(retry_after) | AppError::MaintenanceMode(retry_after) => {
+                error_response["retry_after"] = serde_json::json!(retry_after);
+            }
+            AppError::ConcurrencyLimited {
+                scope,
+                running,
+                queue_position,
+                retry_after,
+            } => {
+                error_response["scope"] = serde_json::json!(scope);
+                error_response["running"] = serde_json::json!(running);
+                error_response["queue_position"] = serde_json::json!(queue_position);
+                error_response["retry_after"] = serde_json::json!(retry_after);
+            }
+            _ => {}
+        }
+
+        let mut response = (status, Json(error_response)).into_response();
+        if let AppError::RateLimited(retry_after)
+        | AppError::MaintenanceMode(retry_after)
+        | AppError::ConcurrencyLimited { retry_after, .. } = self
+        {
+            response.headers_mut().insert(header::RETRY_AFTER, retry_after.into());
+        }
+        response
+    }
+}
+
+/// Turns a panic in a handler into a 500 with the usual error body.
+pub fn handle_panic(_panic: Box<dyn std::any::Any + Send + 'static>) -> Response {
+    AppError::Unexpected.into_response()
+}
+
+/// Answers requests for paths no route matches.
+pub async fn route_not_found() -> AppError {
+    AppError::RouteNotFound
+}
+
+/// Gives the bare 405 axum sends for a known path with the wrong method the
+/// usual error body, keeping its `Allow` header. OPTIONS is added to it, every
+/// route answers that.
+pub async fn method_not_allowed(response: Response) -> Response {
+    if response.status() != StatusCode::METHOD_NOT_ALLOWED {
+        return response;
+    }
+
+    let mut replaced = AppError::MethodNotAllowed.into_response();
+    let allow = response
+        .headers()
+        .get(header::ALLOW)
+        .and_then(|allow| allow.to_str().ok())
+        .and_then(|allow| header::HeaderValue::from_str(&format!("{},OPTIONS", allow)).ok());
+    if let Some(allow) = allow {
+        replaced.headers_mut().insert(header::ALLOW, allow);
+    }
+    replaced
+}
+
+/// The constraint a unique violation tripped over, if that's what `err` is.
+pub fn unique_violation(err: &sqlx::Error) -> Option<&str> {
+    err.as_database_error()
+        .filter(|e| e.is_unique_violation())
+        .map(|e| e.constraint().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Method;
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing::{builder, TestApp};
+
+    #[tokio::test]
+    async fn errors_keep_their_code_whatever_the_language() {
+        let app = TestApp::new().await;
+        app.create_user("ada", "ada@example.com").await;
+
+        let (status, body) = app.post("/api/users", json!({"user_name": "ada2", "email": "ada@example.com"})).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["status"], "fail");
+        assert_eq!(body["code"], "USER_EMAIL_TAKEN");
+        assert_eq!(body["message"], "user with that email already exists");
+
+        let signup = json!({"user_name": "bob", "email": "bob@example.com", "ref_code": "nope"});
+        let request = builder(Method::POST, "/api/users").header(header::ACCEPT_LANGUAGE, "es");
+        let (status, body) = app.send(request, Some(signup)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["code"], "REF_CODE_NOT_FOUND");
+        assert_eq!(body["message"], "No hay ningún usuario con el código de referido: nope");
+    }
+
+    #[tokio::test]
+    async fn database_errors_stay_on_the_server() {
+        let app = TestApp::new().await;
+        sqlx::query("ALTER TABLE users RENAME TO users_moved").execute(app.db()).await.unwrap();
+
+        let (status, body) = app.post("/api/users", json!({"user_name": "ada", "email": "ada@example.com"})).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body["status"], "error");
+        assert_eq!(body["code"], "INTERNAL_ERROR");
+        assert!(body["request_id"].is_string(), "{}", body);
+        let body = body.to_string();
+        assert!(!body.contains("relation") && !body.contains("users"), "{}", body);
+    }
+}
@@ -0,0 +1,347 @@
+use std::{sync::Arc, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{postgres::PgListener, PgConnection};
+use tokio::sync::broadcast;
+
+use crate::{bus::EventBus, AppState};
+
+const OUTBOX_CHANNEL: &str = "events_outbox";
+const FANOUT_CHANNEL: &str = "events_fanout";
+const RELAY_BATCH: i64 = 100;
+
+/// Adds an event to the outbox as part of the caller's transaction, so it is
+/// only published if the change it describes commits. The relay broadcasts
+/// it afterwards with the row's id as `event_id`.
+pub async fn enqueue(conn: &mut PgConnection, event: serde_json::Value) -> Result<(), sqlx::Error> {
+    let event_type = event["event_type"].as_str().unwrap_or_default().to_string();
+    sqlx::query!(
+        "INSERT INTO events (event_type, payload) VALUES ($1, $2)",
+        event_type,
+        &event
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    // delivered on commit, wakes the relay up without waiting for its next poll
+    sqlx::query("SELECT pg_notify($1, '')")
+        .bind(OUTBOX_CHANNEL)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+/// Publishes outbox events to the broadcast channel, and to the message bus
+/// if one is configured, and marks them published. It wakes up when an
+/// outbox transaction commits and polls every `OUTBOX_POLL_MS` (1000 by
+/// default) in case a wakeup was missed.
+///
+/// Each event is relayed by one instance only, which then announces it on
+/// `events_fanout` so every instance broadcasts it to its own SSE clients.
+pub fn spawn_relay(data: Arc<AppState>) {
+    let poll = Duration::from_millis(
+        std::env::var("OUTBOX_POLL_MS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(1000),
+    );
+
+    tokio::spawn(async move {
+        let mut listener = listen(&data, OUTBOX_CHANNEL).await;
+        if listener.is_none() {
+            println!("🔥 Outbox relay could not LISTEN, falling back to polling");
+        }
+
+        let fanout = match listen(&data, FANOUT_CHANNEL).await {
+            Some(fanout) => {
+                tokio::spawn(fan_out(data.clone(), fanout));
+                true
+            }
+            None => {
+                println!("🔥 Could not LISTEN for events from other instances, only local ones are streamed");
+                false
+            }
+        };
+
+        loop {
+            if let Err(e) = relay_pending(&data, fanout).await {
+                println!("🔥 Outbox relay failed: {:?}", e);
+            }
+            match listener.as_mut() {
+                Some(listener) => {
+                    let _ = tokio::time::timeout(poll, listener.recv()).await;
+                }
+                None => tokio::time::sleep(poll).await,
+            }
+        }
+    });
+}
+
+async fn listen(data: &AppState, channel: &str) -> Option<PgListener> {
+    let mut listener = PgListener::connect_with(&data.db).await.ok()?;
+    listener.listen(channel).await.ok()?;
+    Some(listener)
+}
+
+pub(crate) async fn relay_pending(data: &AppState, fanout: bool) -> Result<(), sqlx::Error> {
+    loop {
+        let mut tx = data.db.begin().await?;
+        // SKIP LOCKED lets several instances relay without sending an event twice
+        let rows = sqlx::query!(
+            "SELECT id, payload, created_at FROM events WHERE published_at IS NULL ORDER BY id LIMIT $1 FOR UPDATE SKIP LOCKED",
+            RELAY_BATCH
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let ids: Vec<i64> = rows.iter().map(|row| row.id).collect();
+        if fanout {
+            // ids only, payloads can be bigger than a notification may carry
+            let ids = ids.iter().map(i64::to_string).collect::<Vec<_>>().join(",");
+            if !ids.is_empty() {
+                sqlx::query("SELECT pg_notify($1, $2)")
+                    .bind(FANOUT_CHANNEL)
+                    .bind(ids)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        } else {
+            for row in rows {
+                publish(&data.tx, with_metadata(row.id, row.payload, row.created_at));
+            }
+        }
+
+        sqlx::query!("UPDATE events SET published_at = $2 WHERE id = ANY($1)", &ids, data.clock.now())
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        if (ids.len() as i64) < RELAY_BATCH {
+            break;
+        }
+    }
+
+    match &data.bus {
+        Some(bus) => relay_to_bus(data, bus.as_ref()).await,
+        None => Ok(()),
+    }
+}
+
+// Broadcasts the events any instance relayed to this instance's SSE clients.
+async fn fan_out(data: Arc<AppState>, mut listener: PgListener) {
+    loop {
+        // the listener reconnects by itself, events announced meanwhile are
+        // left to the replay endpoint
+        let notification = match listener.recv().await {
+            Ok(notification) => notification,
+            Err(e) => {
+                println!("🔥 Lost the events fanout connection: {:?}", e);
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                continue;
+            }
+        };
+        let ids: Vec<i64> = notification
+            .payload()
+            .split(',')
+            .filter_map(|id| id.parse().ok())
+            .collect();
+
+        let rows = sqlx::query!(
+            "SELECT id, payload, created_at FROM events WHERE id = ANY($1) ORDER BY id",
+            &ids
+        )
+        .fetch_all(&data.db)
+        .await;
+        match rows {
+            Ok(rows) => {
+                for row in rows {
+                    publish(&data.tx, with_metadata(row.id, row.payload, row.created_at));
+                }
+            }
+            Err(e) => println!("🔥 Failed to load fanned out events: {:?}", e),
+        }
+    }
+}
+
+pub(crate) fn with_metadata(
+    id: i64,
+    mut event: serde_json::Value,
+    created_at: chrono::DateTime<chrono::Utc>,
+) -> serde_json::Value {
+    event["event_id"] = json!(id);
+    event["created_at"] = crate::timestamp::json(&created_at);
+    event
+}
+
+// Tracked apart from `published_at` so a bus outage doesn't hold up SSE
+// clients, and events it missed are sent once it is back.
+async fn relay_to_bus(data: &AppState, bus: &dyn EventBus) -> Result<(), sqlx::Error> {
+    loop {
+        let mut tx = data.db.begin().await?;
+        let rows = sqlx::query!(
+            "SELECT id, event_type, payload, created_at FROM events WHERE bus_published_at IS NULL ORDER BY id LIMIT $1 FOR UPDATE SKIP LOCKED",
+            RELAY_BATCH
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let batch = rows.len() as i64;
+        let mut ids = Vec::new();
+        let mut failed = false;
+        for row in rows {
+            let event = with_metadata(row.id, row.payload, row.created_at);
+            if let Err(e) = bus.publish(&row.event_type, event.to_string().as_bytes(), data.clock.now()).await {
+                println!("🔥 Failed to publish event {} to {}: {}", row.id, bus.name(), e);
+                failed = true;
+                break;
+            }
+            ids.push(row.id);
+        }
+
+        sqlx::query!("UPDATE events SET bus_published_at = $2 WHERE id = ANY($1)", &ids, data.clock.now())
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        // the rest is retried on the next wakeup, in order
+        if failed || batch < RELAY_BATCH {
+            return Ok(());
+        }
+    }
+}
+
+/// The shape events are handed to clients in. Every subscriber picks one,
+/// so clients can move to a new shape at their own pace while a deploy
+/// rolls out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "u8", into = "u8")]
+pub enum SchemaVersion {
+    /// The original shape: `status`, `event_type`, `event_data`, `event_id`
+    /// and `created_at`. Over SSE the event is sent as a JSON string.
+    V1,
+    /// `id`, `type`, `occurred_at` and `data`, sent as a JSON object.
+    V2,
+}
+
+impl TryFrom<u8> for SchemaVersion {
+    type Error = String;
+
+    fn try_from(version: u8) -> Result<Self, Self::Error> {
+        match version {
+            1 => Ok(SchemaVersion::V1),
+            2 => Ok(SchemaVersion::V2),
+            other => Err(format!("unknown schema version {}, expected 1 or 2", other)),
+        }
+    }
+}
+
+impl From<SchemaVersion> for u8 {
+    fn from(version: SchemaVersion) -> Self {
+        match version {
+            SchemaVersion::V1 => 1,
+            SchemaVersion::V2 => 2,
+        }
+    }
+}
+
+/// Renders a stored event in the given shape, tagged with `schema_version`.
+pub fn render(event: &serde_json::Value, version: SchemaVersion) -> serde_json::Value {
+    match version {
+        SchemaVersion::V1 => {
+            let mut event = event.clone();
+            event["schema_version"] = json!(1);
+            event
+        }
+        // the audience only matters for routing, it isn't part of the event
+        SchemaVersion::V2 => json!({
+            "schema_version": 2,
+            "id": event["event_id"],
+            "type": event["event_type"],
+            "occurred_at": event["created_at"],
+            "data": event["event_data"],
+        }),
+    }
+}
+
+/// Broadcasts an event to the connected SSE clients and returns how many got
+/// it. Nobody being connected is normal, the event is dropped and that's all.
+pub fn publish(tx: &broadcast::Sender<String>, event: serde_json::Value) -> usize {
+    if crate::chaos::drop_broadcast() {
+        println!("💥 Dropped `{}` event on purpose", event["event_type"].as_str().unwrap_or("unknown"));
+        return 0;
+    }
+    match tx.send(event.to_string()) {
+        Ok(receivers) => receivers,
+        Err(_) => {
+            println!("No SSE clients connected, dropped `{}` event", event["event_type"].as_str().unwrap_or("unknown"));
+            0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn publish_without_subscribers_drops_the_event() {
+        let (tx, rx) = broadcast::channel(4);
+        drop(rx);
+
+        let event = serde_json::json!({"status": "success", "event_type": "user_created", "event_data": {}});
+        assert_eq!(publish(&tx, event), 0);
+    }
+
+    #[test]
+    fn publish_reaches_every_subscriber() {
+        let (tx, mut first) = broadcast::channel(4);
+        let mut second = tx.subscribe();
+
+        let event = serde_json::json!({"status": "success", "event_type": "user_created", "event_data": {}});
+        assert_eq!(publish(&tx, event.clone()), 2);
+        assert_eq!(first.try_recv().unwrap(), event.to_string());
+        assert_eq!(second.try_recv().unwrap(), event.to_string());
+    }
+
+    #[test]
+    fn subscribers_joining_later_can_still_receive() {
+        let (tx, rx) = broadcast::channel(4);
+        drop(rx);
+        publish(&tx, serde_json::json!({"event_type": "missed"}));
+
+        let mut late = tx.subscribe();
+        assert_eq!(publish(&tx, serde_json::json!({"event_type": "seen"})), 1);
+        assert_eq!(late.try_recv().unwrap(), r#"{"event_type":"seen"}"#);
+    }
+
+    #[tokio::test]
+    async fn outbox_events_go_out_once_and_only_if_their_change_commits() {
+        let app = crate::testing::TestApp::new().await;
+        let data = app.state();
+        let mut received = data.tx.subscribe();
+
+        let mut tx = app.db().begin().await.unwrap();
+        enqueue(&mut tx, json!({"status": "success", "event_type": "rolled_back", "event_data": {}})).await.unwrap();
+        tx.rollback().await.unwrap();
+        let mut tx = app.db().begin().await.unwrap();
+        enqueue(&mut tx, json!({"status": "success", "event_type": "committed", "event_data": {}})).await.unwrap();
+        tx.commit().await.unwrap();
+
+        relay_pending(data, false).await.unwrap();
+        let event: serde_json::Value = serde_json::from_str(&received.try_recv().unwrap()).unwrap();
+        assert_eq!(event["event_type"], "committed");
+        assert!(event["event_id"].is_i64(), "{}", event);
+        assert!(received.try_recv().is_err());
+
+        let published = sqlx::query_scalar!(r#"SELECT published_at IS NOT NULL AS "published!" FROM events"#)
+            .fetch_all(app.db())
+            .await
+            .unwrap();
+        assert_eq!(published, vec![true]);
+
+        // already relayed, so not sent again
+        relay_pending(data, false).await.unwrap();
+        assert!(received.try_recv().is_err());
+    }
+}
@@ -0,0 +1,293 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use serde_json::{json, Value};
+use tokio::{io::AsyncWriteExt, sync::Notify};
+use uuid::Uuid;
+
+use crate::{
+    audit,
+    fields::Fields,
+    model::{ExportJobModel, UserModel},
+    scheduler,
+    schema::{ExportFormat, ExportUsersSchema},
+    stream::{self, Layout},
+    timestamp, AppState,
+};
+
+type Failure = Box<dyn std::error::Error + Send + Sync>;
+
+// how often a running job records its progress, which also shows it's alive
+const PROGRESS_EVERY: Duration = Duration::from_secs(1);
+// a running job that hasn't recorded progress for this long was abandoned
+const ABANDONED_AFTER_SECS: i64 = 300;
+
+/// User exports too large for one request, written to storage in the
+/// background, one job at a time per instance. Instances take jobs from
+/// the same queue, and pick up those abandoned by one that went away.
+/// Download links last `EXPORT_URL_TTL_SECS`, 900 unless set, and jobs are
+/// deleted with their files after `EXPORT_RETENTION_HOURS`, 24 unless set.
+pub struct Exports {
+    url_ttl: chrono::Duration,
+    retention_hours: i32,
+    wake: Notify,
+}
+
+impl Exports {
+    pub fn from_env() -> Self {
+        let url_ttl_secs = std::env::var("EXPORT_URL_TTL_SECS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .filter(|secs| *secs > 0)
+            .unwrap_or(900);
+        let retention_hours = std::env::var("EXPORT_RETENTION_HOURS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .filter(|hours| *hours > 0)
+            .unwrap_or(24);
+
+        Exports {
+            url_ttl: chrono::Duration::seconds(url_ttl_secs),
+            retention_hours,
+            wake: Notify::new(),
+        }
+    }
+}
+
+/// Queues an export of the users `params` asks for.
+pub async fn enqueue(data: &AppState, params: &ExportUsersSchema) -> Result<ExportJobModel, sqlx::Error> {
+    let mut tx = data.db.begin().await?;
+    let job = sqlx::query_as!(
+        ExportJobModel,
+        "INSERT INTO export_jobs (id, params, created_at, updated_at) VALUES ($1, $2, $3, $3) RETURNING *",
+        crate::ids::new(),
+        json!(params),
+        data.clock.now()
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    audit::record(&mut *tx, "users.export_queued", json!({"export": job.id, "params": job.params})).await?;
+    tx.commit().await?;
+
+    data.exports.wake.notify_one();
+    Ok(job)
+}
+
+/// A job as clients see it: how far along it is and, once it's done, a
+/// fresh link to download the file from.
+pub fn to_json(data: &AppState, job: &ExportJobModel, now: DateTime<Utc>) -> Value {
+    let mut value = json!(job);
+    value["progress"] = match job.total_rows {
+        Some(0) => json!(1.0),
+        Some(total) => json!((job.rows_read as f64 / total as f64).min(1.0)),
+        None => Value::Null,
+    };
+    value["download"] = match (&job.artifact, job.status.as_str()) {
+        (Some(key), "done") => {
+            let expires_at = now + data.exports.url_ttl;
+            json!({"url": data.storage.signed_url(key, expires_at), "expires_at": timestamp::json(&expires_at)})
+        }
+        _ => Value::Null,
+    };
+    value
+}
+
+/// Works through the queue in the background, woken by new jobs on this
+/// instance and checking every few seconds for those queued elsewhere.
+pub fn spawn_worker(data: Arc<AppState>) {
+    tokio::spawn(async move {
+        loop {
+            match claim(&data).await {
+                Ok(Some(job)) => {
+                    run(&data, job).await;
+                    continue;
+                }
+                Ok(None) => {}
+                Err(e) => println!("🔥 Failed to take an export job: {:?}", e),
+            }
+            let _ = tokio::time::timeout(Duration::from_secs(5), data.exports.wake.notified()).await;
+        }
+    });
+}
+
+/// Deletes jobs past their retention, and their files.
+pub fn spawn_pruning(data: Arc<AppState>) {
+    scheduler::every("export_pruning", Duration::from_secs(60 * 60), data, |data| async move {
+        let artifacts = sqlx::query_scalar!(
+            "DELETE FROM export_jobs WHERE created_at < $1 RETURNING artifact",
+            data.clock.now() - chrono::Duration::hours(data.exports.retention_hours.into())
+        )
+        .fetch_all(&data.db)
+        .await?;
+        for key in artifacts.iter().flatten() {
+            if let Err(e) = data.storage.delete(key).await {
+                println!("🔥 Failed to delete export {}: {}", key, e);
+            }
+        }
+        if !artifacts.is_empty() {
+            println!("🧹 Pruned {} export jobs", artifacts.len());
+        }
+        Ok(())
+    });
+}
+
+// the oldest queued job, or one whose worker stopped reporting progress
+async fn claim(data: &AppState) -> Result<Option<ExportJobModel>, sqlx::Error> {
+    let now = data.clock.now();
+    sqlx::query_as!(
+        ExportJobModel,
+        "UPDATE export_jobs SET status = 'running', started_at = $1, updated_at = $1, rows_read = 0, rows_written = 0 \
+         WHERE id = (SELECT id FROM export_jobs \
+             WHERE status = 'pending' OR (status = 'running' AND updated_at < $2) \
+             ORDER BY created_at LIMIT 1 FOR UPDATE SKIP LOCKED) \
+         RETURNING *",
+        now,
+        now - chrono::Duration::seconds(ABANDONED_AFTER_SECS)
+    )
+    .fetch_optional(&data.db)
+    .await
+}
+
+async fn run(data: &AppState, job: ExportJobModel) {
+    // each attempt writes its own file, in case an abandoned one wakes up
+    let started_at = job.started_at.unwrap_or_else(|| data.clock.now());
+    let attempt = Attempt { data, id: job.id, started_at };
+
+    match write(data, &job, &attempt).await {
+        Ok((key, read, written)) => {
+            let finished = sqlx::query!(
+                "UPDATE export_jobs SET status = 'done', artifact = $3, rows_read = $4, rows_written = $5, finished_at = $6, updated_at = $6 \
+                 WHERE id = $1 AND started_at = $2",
+                job.id,
+                started_at,
+                key,
+                read,
+                written,
+                data.clock.now()
+            )
+            .execute(&data.db)
+            .await;
+            match finished {
+                Ok(result) if result.rows_affected() == 1 => {}
+                Ok(_) => {
+                    // taken over, or pruned, while it ran
+                    let _ = data.storage.delete(&key).await;
+                }
+                Err(e) => println!("🔥 Failed to record export {} as done: {:?}", job.id, e),
+            }
+        }
+        Err((key, e)) => {
+            println!("🔥 Export {} failed: {}", job.id, e);
+            if let Some(key) = key {
+                let _ = data.storage.delete(&key).await;
+            }
+            let _ = sqlx::query!(
+                "UPDATE export_jobs SET status = 'failed', error = $3, finished_at = $4, updated_at = $4 WHERE id = $1 AND started_at = $2",
+                job.id,
+                started_at,
+                e.to_string(),
+                data.clock.now()
+            )
+            .execute(&data.db)
+            .await;
+        }
+    }
+}
+
+struct Attempt<'a> {
+    data: &'a AppState,
+    id: Uuid,
+    started_at: DateTime<Utc>,
+}
+
+impl Attempt<'_> {
+    async fn progress(&self, total: Option<i64>, read: i64, written: i64) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "UPDATE export_jobs SET total_rows = COALESCE($3, total_rows), rows_read = $4, rows_written = $5, updated_at = $6 \
+             WHERE id = $1 AND started_at = $2",
+            self.id,
+            self.started_at,
+            total,
+            read,
+            written,
+            self.data.clock.now()
+        )
+        .execute(&self.data.db)
+        .await?;
+        Ok(())
+    }
+}
+
+// Writes the file, returning its key and how many rows were read and
+// written. On failure, the key of the partial file if one was started.
+async fn write(data: &AppState, job: &ExportJobModel, attempt: &Attempt<'_>) -> Result<(String, i64, i64), (Option<String>, Failure)> {
+    let params: ExportUsersSchema = serde_json::from_value(job.params.clone()).map_err(|e| (None, e.into()))?;
+    let (layout, extension) = match params.format {
+        ExportFormat::Json => (Layout::Array, "json"),
+        ExportFormat::Ndjson => (Layout::Lines, "ndjson"),
+    };
+    let key = format!("exports/users-{}-{}.{}", job.id, attempt.started_at.timestamp_millis(), extension);
+
+    let total = sqlx::query_scalar!(
+        "SELECT COUNT(*) AS \"count!\" FROM users WHERE ($1::timestamptz IS NULL OR created_at > $1) AND ($2::timestamptz IS NULL OR created_at < $2)",
+        params.created_after,
+        params.created_before
+    )
+    .fetch_one(&data.db)
+    .await
+    .map_err(|e| (None, e.into()))?;
+    attempt.progress(Some(total), 0, 0).await.map_err(|e| (None, e.into()))?;
+
+    let mut file = data.storage.create(&key).await.map_err(|e| (None, e.into()))?;
+    let counts = write_rows(data, &params, layout, attempt, &mut file).await;
+    let finished = match counts {
+        Ok(counts) => file.shutdown().await.map(|_| counts).map_err(Failure::from),
+        Err(e) => Err(e),
+    };
+    finished.map(|(read, written)| (key.clone(), read, written)).map_err(|e| (Some(key), e))
+}
+
+async fn write_rows(
+    data: &AppState,
+    params: &ExportUsersSchema,
+    layout: Layout,
+    attempt: &Attempt<'_>,
+    file: &mut crate::storage::Writer,
+) -> Result<(i64, i64), Failure> {
+    let fields = Fields::from(params.fields.clone());
+    let rows = sqlx::query_as!(
+        UserModel,
+        "SELECT * FROM users WHERE ($1::timestamptz IS NULL OR created_at > $1) AND ($2::timestamptz IS NULL OR created_at < $2) ORDER BY id",
+        params.created_after,
+        params.created_before
+    )
+    .fetch(&data.db);
+
+    if layout == Layout::Array {
+        file.write_all(b"[").await?;
+    }
+    let mut batches = std::pin::pin!(rows.chunks(stream::BATCH));
+    let (mut read, mut written) = (0, 0);
+    let mut reported = Instant::now();
+    while let Some(batch) = batches.next().await {
+        let batch = batch.into_iter().collect::<Result<Vec<_>, _>>()?;
+        read += batch.len();
+
+        let mut chunk = Vec::new();
+        stream::encode(data, &batch, None, &fields, layout, &mut chunk, &mut written).await?;
+        file.write_all(&chunk).await?;
+
+        if reported.elapsed() >= PROGRESS_EVERY {
+            attempt.progress(None, read as i64, written as i64).await?;
+            reported = Instant::now();
+        }
+    }
+    if layout == Layout::Array {
+        file.write_all(b"]").await?;
+    }
+    Ok((read as i64, written as i64))
+}
@@ -0,0 +1,128 @@
+use std::ops::Deref;
+
+use axum::{
+    async_trait,
+    extract::{FromRequest, FromRequestParts},
+    http::{request::Parts, Request},
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+
+use crate::{error::AppError, format, sanitize::Sanitize};
+
+// Drop-in replacements for axum's extractors whose rejections come back in
+// the usual error envelope, with a code, instead of as plain text. `Json`
+// responses are written in whatever format the client negotiated.
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Json<T>(pub T);
+
+#[async_trait]
+impl<T, S, B> FromRequest<S, B> for Json<T>
+where
+    axum::Json<T>: FromRequest<S, B, Rejection = axum::extract::rejection::JsonRejection>,
+    S: Send + Sync,
+    B: Send + 'static,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::Json(value) = axum::Json::<T>::from_request(req, state)
+            .await
+            .map_err(AppError::BodyInvalid)?;
+        Ok(Json(value))
+    }
+}
+
+impl<T: Serialize> IntoResponse for Json<T> {
+    fn into_response(self) -> Response {
+        format::render(&self.0)
+    }
+}
+
+impl<T> Deref for Json<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+// A JSON body whose free text went through `Sanitize`, so what reaches the
+// handler is what gets stored.
+#[derive(Debug)]
+pub struct Sanitized<T>(pub T);
+
+#[async_trait]
+impl<T, S, B> FromRequest<S, B> for Sanitized<T>
+where
+    T: Sanitize,
+    Json<T>: FromRequest<S, B, Rejection = AppError>,
+    S: Send + Sync,
+    B: Send + 'static,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(mut value) = Json::<T>::from_request(req, state).await?;
+        value.sanitize()?;
+        Ok(Sanitized(value))
+    }
+}
+
+#[derive(Debug)]
+pub struct Path<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for Path<T>
+where
+    axum::extract::Path<T>: FromRequestParts<S, Rejection = axum::extract::rejection::PathRejection>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::extract::Path(value) = axum::extract::Path::<T>::from_request_parts(parts, state)
+            .await
+            .map_err(AppError::PathInvalid)?;
+        Ok(Path(value))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Query<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for Query<T>
+where
+    axum::extract::Query<T>: FromRequestParts<S, Rejection = axum::extract::rejection::QueryRejection>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::extract::Query(value) = axum::extract::Query::<T>::from_request_parts(parts, state)
+            .await
+            .map_err(AppError::QueryInvalid)?;
+        Ok(Query(value))
+    }
+}
+
+#[derive(Debug)]
+pub struct TypedHeader<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for TypedHeader<T>
+where
+    axum::TypedHeader<T>: FromRequestParts<S, Rejection = axum::extract::rejection::TypedHeaderRejection>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::TypedHeader(value) = axum::TypedHeader::<T>::from_request_parts(parts, state)
+            .await
+            .map_err(AppError::HeaderInvalid)?;
+        Ok(TypedHeader(value))
+    }
+}
@@ -0,0 +1,78 @@
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Query},
+    http::request::Parts,
+};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::error::AppError;
+
+#[derive(Deserialize, Debug, Default)]
+struct FieldsParams {
+    fields: Option<String>,
+}
+
+/// The `?fields=id,user_name,ref_code` of a request, the fields a client
+/// wants of each user or event it gets back. Everything when it's not set,
+/// names that aren't fields are ignored.
+#[derive(Debug, Clone, Default)]
+pub struct Fields(Option<Vec<String>>);
+
+impl Fields {
+    /// `value` with only the fields asked for, or each element of it with
+    /// only those for an array. Anything but an object is left as it is.
+    pub fn select(&self, value: Value) -> Value {
+        let Some(fields) = &self.0 else {
+            return value;
+        };
+        match value {
+            Value::Array(items) => Value::Array(items.into_iter().map(|item| self.select(item)).collect()),
+            Value::Object(mut object) => {
+                object.retain(|key, _| fields.iter().any(|field| field == key));
+                Value::Object(object)
+            }
+            other => other,
+        }
+    }
+}
+
+impl From<Option<Vec<String>>> for Fields {
+    fn from(fields: Option<Vec<String>>) -> Self {
+        Fields(fields)
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Fields {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(params) = Query::<FieldsParams>::from_request_parts(parts, state)
+            .await
+            .map_err(AppError::QueryInvalid)?;
+        let fields = params.fields.map(|fields| {
+            fields
+                .split(',')
+                .map(|field| field.trim().to_string())
+                .filter(|field| !field.is_empty())
+                .collect()
+        });
+        Ok(Fields(fields))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn keeps_only_the_fields_asked_for() {
+        let fields = Fields(Some(vec!["id".to_string(), "user_name".to_string(), "nope".to_string()]));
+        let users = json!([{"id": 1, "user_name": "ada", "email": "ada@example.com"}, {"id": 2}]);
+        assert_eq!(fields.select(users), json!([{"id": 1, "user_name": "ada"}, {"id": 2}]));
+        assert_eq!(Fields::default().select(json!({"id": 1, "email": "x"})), json!({"id": 1, "email": "x"}));
+    }
+}
@@ -0,0 +1,116 @@
+use axum::{
+    http::{header, HeaderValue, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+
+use crate::error::AppError;
+
+/// What response bodies are serialized to. JSON unless the client prefers
+/// one of the binary formats, which are smaller on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    MessagePack,
+    Cbor,
+}
+
+const MEDIA_TYPES: [(&str, Format); 4] = [
+    ("application/json", Format::Json),
+    ("application/msgpack", Format::MessagePack),
+    ("application/x-msgpack", Format::MessagePack),
+    ("application/cbor", Format::Cbor),
+];
+
+tokio::task_local! {
+    static FORMAT: Format;
+}
+
+impl Format {
+    fn content_type(self) -> &'static str {
+        match self {
+            Format::Json => "application/json",
+            Format::MessagePack => "application/msgpack",
+            Format::Cbor => "application/cbor",
+        }
+    }
+}
+
+/// Picks the format from an `Accept` header, honoring q-values. Wildcards
+/// and types we don't serve leave it at JSON.
+pub fn negotiate(accept: Option<&str>) -> Format {
+    let Some(accept) = accept else {
+        return Format::Json;
+    };
+
+    let mut ranges: Vec<(&str, f32)> = accept
+        .split(',')
+        .filter_map(|range| {
+            let mut parts = range.trim().split(';');
+            let media_type = parts.next()?.trim();
+            let quality = parts
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .and_then(|q| q.parse().ok())
+                .unwrap_or(1.0);
+            (!media_type.is_empty() && quality > 0.0).then_some((media_type, quality))
+        })
+        .collect();
+    // stable, so equally weighted ranges keep the client's order
+    ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    ranges
+        .iter()
+        .find_map(|(media_type, _)| {
+            MEDIA_TYPES
+                .iter()
+                .find(|(known, _)| known.eq_ignore_ascii_case(media_type))
+                .map(|(_, format)| *format)
+        })
+        .unwrap_or(Format::Json)
+}
+
+/// The format negotiated for the request being handled.
+pub fn current() -> Format {
+    FORMAT.try_with(|format| *format).unwrap_or(Format::Json)
+}
+
+/// Serializes `value` in the current format.
+pub fn render<T: Serialize>(value: &T) -> Response {
+    let format = current();
+    let body = match format {
+        Format::Json => serde_json::to_vec(value).map_err(|_| ()),
+        Format::MessagePack => rmp_serde::to_vec_named(value).map_err(|_| ()),
+        Format::Cbor => {
+            let mut body = Vec::new();
+            ciborium::into_writer(value, &mut body).map(|_| body).map_err(|_| ())
+        }
+    };
+    match body {
+        Ok(body) => ([(header::CONTENT_TYPE, format.content_type())], body).into_response(),
+        Err(()) => AppError::Unexpected.into_response(),
+    }
+}
+
+/// Negotiates the response format from `Accept` and makes it available to
+/// everything that runs while handling the request.
+pub async fn format_layer<B>(req: Request<B>, next: Next<B>) -> Response {
+    let format = negotiate(req.headers().get(header::ACCEPT).and_then(|value| value.to_str().ok()));
+
+    let mut response = FORMAT.scope(format, next.run(req)).await;
+    response.headers_mut().append(header::VARY, HeaderValue::from_static("accept"));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiates_binary_formats() {
+        assert_eq!(negotiate(None), Format::Json);
+        assert_eq!(negotiate(Some("application/msgpack")), Format::MessagePack);
+        assert_eq!(negotiate(Some("application/json;q=0.5, application/cbor")), Format::Cbor);
+        assert_eq!(negotiate(Some("text/html, */*")), Format::Json);
+    }
+}
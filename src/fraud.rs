@@ -0,0 +1,197 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::{audit, email_domain, events, model::FraudFlagModel, AppState};
+
+pub const CREDITED_EVENT_TYPE: &str = "referral_credited";
+
+/// Thresholds for telling referral abuse apart from a popular referrer.
+pub struct FraudRules {
+    window_minutes: i32,
+    max_signups_per_ip: i64,
+    max_referrals: i64,
+    blocked_countries: HashSet<String>,
+}
+
+impl FraudRules {
+    /// Within any `FRAUD_WINDOW_MINUTES` (60 by default), a referred signup
+    /// is flagged once its address made more than `FRAUD_MAX_SIGNUPS_PER_IP`
+    /// signups (5) or its referrer got more than `FRAUD_MAX_REFERRALS` (10).
+    /// Signups with a disposable email address are always flagged, and so
+    /// are those from the countries in `FRAUD_BLOCKED_COUNTRIES`, a
+    /// comma-separated list of ISO codes that needs a GeoIP database.
+    pub fn from_env() -> Self {
+        let var = |name, default| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+
+        FraudRules {
+            window_minutes: var("FRAUD_WINDOW_MINUTES", 60) as i32,
+            max_signups_per_ip: var("FRAUD_MAX_SIGNUPS_PER_IP", 5),
+            max_referrals: var("FRAUD_MAX_REFERRALS", 10),
+            blocked_countries: std::env::var("FRAUD_BLOCKED_COUNTRIES")
+                .unwrap_or_default()
+                .split(',')
+                .map(|country| country.trim().to_uppercase())
+                .filter(|country| !country.is_empty())
+                .collect(),
+        }
+    }
+}
+
+/// Records where a signup came from and credits its referrer, unless the
+/// signup looks like referral abuse. Then the credit is held back in a
+/// pending flag until someone reviews it.
+pub async fn record_signup(
+    conn: &mut PgConnection,
+    data: &AppState,
+    user_id: Uuid,
+    email: &str,
+    referrer_id: Option<Uuid>,
+    ip: Option<&str>,
+) -> Result<Option<FraudFlagModel>, sqlx::Error> {
+    let location = ip.map(|ip| data.geo.locate(ip)).unwrap_or_default();
+    sqlx::query!(
+        "INSERT INTO signups (user_id, ip, referrer_id, country, region, created_at) VALUES ($1, $2, $3, $4, $5, $6)",
+        user_id,
+        ip,
+        referrer_id,
+        location.country,
+        location.region,
+        data.clock.now()
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    // nobody gets credited for an unreferred signup, so there's nothing to hold back
+    let Some(referrer_id) = referrer_id else {
+        return Ok(None);
+    };
+
+    let rules = &data.fraud;
+    let counts = sqlx::query!(
+        r#"SELECT COUNT(*) FILTER (WHERE ip = $1) AS "from_ip!", COUNT(*) FILTER (WHERE referrer_id = $2) AS "referred!" FROM signups WHERE created_at > $3"#,
+        ip,
+        referrer_id,
+        data.clock.now() - chrono::Duration::minutes(rules.window_minutes.into())
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let mut reasons = Vec::new();
+    if ip.is_some() && counts.from_ip > rules.max_signups_per_ip {
+        reasons.push("ip_velocity".to_string());
+    }
+    if email_domain::domain_of(email).is_some_and(|domain| data.email_validation.is_disposable(&domain)) {
+        reasons.push("disposable_email".to_string());
+    }
+    if counts.referred > rules.max_referrals {
+        reasons.push("referral_velocity".to_string());
+    }
+    if location.country.as_ref().is_some_and(|country| rules.blocked_countries.contains(country)) {
+        reasons.push("blocked_country".to_string());
+    }
+
+    if reasons.is_empty() {
+        credit_referrer(&mut *conn, referrer_id, user_id, data.clock.now()).await?;
+        return Ok(None);
+    }
+
+    let details = json!({
+        "ip": ip,
+        "country": location.country,
+        "signups_from_ip": counts.from_ip,
+        "referrals": counts.referred,
+        "window_minutes": rules.window_minutes,
+    });
+    let flag = sqlx::query_as!(
+        FraudFlagModel,
+        "INSERT INTO fraud_flags (user_id, referrer_id, reasons, details) VALUES ($1, $2, $3, $4) RETURNING *",
+        user_id,
+        referrer_id,
+        &reasons,
+        details
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    audit::record(&mut *conn, "fraud.flagged", json!({"flag_id": flag.id, "user_id": user_id, "referrer_id": referrer_id, "reasons": reasons}))
+        .await?;
+    Ok(Some(flag))
+}
+
+/// Counts a referral towards its referrer and lets them know, which is also
+/// what their achievements are checked on.
+pub async fn credit_referrer(conn: &mut PgConnection, referrer_id: Uuid, user_id: Uuid, now: DateTime<Utc>) -> Result<(), sqlx::Error> {
+    let referrals = sqlx::query_scalar!(
+        "UPDATE users SET added_by_ref_code = added_by_ref_code + 1, updated_at = $2 WHERE id = $1 RETURNING added_by_ref_code",
+        referrer_id,
+        now
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let event_to_send = json!({
+        "status": "success",
+        "event_type": CREDITED_EVENT_TYPE,
+        "audience": {"user_id": referrer_id},
+        "event_data": {"referrer_id": referrer_id, "user_id": user_id, "referrals": referrals}
+    });
+    events::enqueue(conn, event_to_send).await
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+
+    use super::*;
+    use crate::testing::TestApp;
+
+    #[tokio::test]
+    async fn suspicious_referrals_wait_for_review_before_they_count() {
+        let app = TestApp::with(|state| {
+            state.fraud = FraudRules {
+                window_minutes: 60,
+                max_signups_per_ip: 1,
+                max_referrals: 10,
+                blocked_countries: HashSet::new(),
+            };
+        })
+        .await;
+        let ada = app.create_user("ada", "ada@example.com").await;
+        let referrals = || async {
+            sqlx::query_scalar!("SELECT added_by_ref_code FROM users WHERE user_name = 'ada'")
+                .fetch_one(app.db())
+                .await
+                .unwrap()
+        };
+
+        // a second signup from the same address within the window
+        let (status, body) = app
+            .post("/api/users", json!({"user_name": "bob", "email": "bob@example.com", "ref_code": ada["ref_code"]}))
+            .await;
+        assert_eq!(status, StatusCode::CREATED, "{}", body);
+        assert_eq!(referrals().await, 0);
+
+        let (status, queue) = app.signed(Method::GET, "/api/admin/fraud-flags", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(queue["results"], 1);
+        let flag = &queue["flags"][0];
+        assert_eq!(flag["referrer_id"], ada["id"]);
+        assert_eq!(flag["reasons"], json!(["ip_velocity"]));
+
+        let review = format!("/api/admin/fraud-flags/{}/review", flag["id"].as_str().unwrap());
+        let (status, reviewed) = app.signed(Method::POST, &review, Some(json!({"decision": "approve"}))).await;
+        assert_eq!(status, StatusCode::OK, "{}", reviewed);
+        assert_eq!(referrals().await, 1);
+        let (_, queue) = app.signed(Method::GET, "/api/admin/fraud-flags?status=pending", None).await;
+        assert_eq!(queue["results"], 0);
+    }
+}
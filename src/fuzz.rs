@@ -0,0 +1,37 @@
+//! What the cargo-fuzz targets in `fuzz/` feed arbitrary bytes to: request
+//! bodies decoded and cleaned the way `Sanitized` does it, and the invite
+//! token parser. Built with the `fuzz` feature.
+
+use chrono::Utc;
+use serde::de::DeserializeOwned;
+
+use crate::{
+    invite_token::InviteTokens,
+    sanitize::Sanitize,
+    schema::{CreateUserSchema, RsvpSchema, UpdateUserSchema},
+};
+
+pub fn create_user(body: &[u8]) {
+    sanitized::<CreateUserSchema>(body);
+}
+
+pub fn update_user(body: &[u8]) {
+    sanitized::<UpdateUserSchema>(body);
+}
+
+pub fn rsvp(body: &[u8]) {
+    let _ = serde_json::from_slice::<RsvpSchema>(body);
+}
+
+/// Set `INVITE_TOKEN_SECRET` to get past the parsing into the signature check.
+pub fn invite_token(token: &[u8]) {
+    if let Ok(token) = std::str::from_utf8(token) {
+        let _ = InviteTokens::from_env().verify(token, Utc::now());
+    }
+}
+
+fn sanitized<T: DeserializeOwned + Sanitize>(body: &[u8]) {
+    if let Ok(mut value) = serde_json::from_slice::<T>(body) {
+        let _ = value.sanitize();
+    }
+}
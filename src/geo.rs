@@ -0,0 +1,58 @@
+use std::net::IpAddr;
+
+use maxminddb::{geoip2, Reader};
+use serde::Serialize;
+
+/// Where an address is, as ISO 3166 codes: the country and, with a City
+/// database, the first-level subdivision (state, region, ...).
+#[derive(Debug, Default, Serialize)]
+pub struct Location {
+    pub country: Option<String>,
+    pub region: Option<String>,
+}
+
+/// Looks addresses up in a local MaxMind database.
+pub struct Geo {
+    reader: Option<Reader<Vec<u8>>>,
+}
+
+impl Geo {
+    /// Reads the GeoIP2 or GeoLite2 Country or City database at
+    /// `GEOIP_DATABASE`. Nothing is looked up while it's unset.
+    pub fn from_env() -> Self {
+        let Ok(path) = std::env::var("GEOIP_DATABASE") else {
+            return Geo { reader: None };
+        };
+
+        match Reader::open_readfile(&path) {
+            Ok(reader) => {
+                println!("✅ GeoIP database {} loaded", reader.metadata.database_type);
+                Geo { reader: Some(reader) }
+            }
+            Err(err) => {
+                println!("🔥 Failed to open the GeoIP database at {}: {}", path, err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    /// Empty for addresses the database doesn't know, private ones among
+    /// them, and while no database is configured.
+    pub fn locate(&self, ip: &str) -> Location {
+        let (Some(reader), Ok(ip)) = (&self.reader, ip.parse::<IpAddr>()) else {
+            return Location::default();
+        };
+        let Ok(city) = reader.lookup::<geoip2::City>(ip) else {
+            return Location::default();
+        };
+
+        Location {
+            country: city.country.and_then(|country| country.iso_code).map(str::to_string),
+            region: city
+                .subdivisions
+                .and_then(|subdivisions| subdivisions.into_iter().next())
+                .and_then(|subdivision| subdivision.iso_code)
+                .map(str::to_string),
+        }
+    }
+}
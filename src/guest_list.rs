@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+
+use serde_json::{json, Value};
+
+use crate::{
+    error::AppError,
+    schema::{ColumnMapping, GuestListFormat},
+};
+
+// Google Contacts puts every address of a contact in one cell
+const GOOGLE_SEPARATOR: &str = ":::";
+
+/// A guest list exported from elsewhere: a plain CSV with a header row, or
+/// a Google Contacts CSV export.
+pub struct GuestList {
+    format: GuestListFormat,
+    columns: Vec<String>,
+    // by line in the file, or why the line couldn't be read
+    rows: Vec<(usize, Result<csv::StringRecord, String>)>,
+}
+
+/// One row of the list, read with a column mapping.
+pub struct Guest {
+    pub line: usize,
+    pub email: Option<String>,
+    pub role: Option<String>,
+}
+
+/// A row the CSV reader gave up on.
+pub struct Unreadable {
+    pub line: usize,
+    pub message: String,
+}
+
+impl GuestList {
+    pub fn parse(format: GuestListFormat, data: &str) -> Result<Self, AppError> {
+        // spreadsheets like to save with a byte order mark
+        let data = data.strip_prefix('\u{feff}').unwrap_or(data);
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .flexible(true)
+            .from_reader(data.as_bytes());
+
+        let columns: Vec<String> = match reader.headers() {
+            Ok(headers) => headers.iter().map(str::to_string).collect(),
+            Err(e) => return Err(AppError::CsvInvalid(e.to_string())),
+        };
+        if columns.iter().all(String::is_empty) {
+            return Err(AppError::CsvInvalid("the header row is empty".to_string()));
+        }
+        // line 1 is the header
+        let rows = (2..)
+            .zip(reader.records())
+            .map(|(line, record)| (line, record.map_err(|e| e.to_string())))
+            .collect();
+
+        Ok(GuestList { format, columns, rows })
+    }
+
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    pub fn row_count(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// A mapping to start from: the column that looks like it holds emails
+    /// and, when there is one, roles.
+    pub fn suggest(&self) -> Option<ColumnMapping> {
+        let find = |matches: &dyn Fn(&str) -> bool| {
+            self.columns
+                .iter()
+                .find(|column| matches(&column.to_lowercase()))
+                .cloned()
+        };
+        let email = match self.format {
+            GuestListFormat::Csv => find(&|column| {
+                ["email", "e-mail", "email address", "e-mail address", "mail"].contains(&column)
+            })
+            .or_else(|| find(&|column| column.contains("mail"))),
+            // "E-mail 1 - Value", the contact's first address
+            GuestListFormat::GoogleContacts => {
+                find(&|column| column.starts_with("e-mail") && column.ends_with("- value"))
+            }
+        }?;
+        Some(ColumnMapping {
+            email,
+            role: find(&|column| column == "role"),
+        })
+    }
+
+    /// The first few rows by column, to check a mapping against.
+    pub fn preview(&self, rows: usize) -> Vec<Value> {
+        self.rows
+            .iter()
+            .filter_map(|(_, record)| record.as_ref().ok())
+            .take(rows)
+            .map(|record| {
+                let row: HashMap<&str, &str> = self.columns.iter().map(String::as_str).zip(record.iter()).collect();
+                json!(row)
+            })
+            .collect()
+    }
+
+    fn column(&self, name: &str) -> Result<usize, AppError> {
+        self.columns
+            .iter()
+            .position(|column| column == name)
+            .ok_or_else(|| AppError::ImportColumnUnknown(name.to_string()))
+    }
+
+    /// Every row read with `mapping`, or why a row couldn't be read. Fails
+    /// when the mapping names a column the list doesn't have.
+    pub fn guests(&self, mapping: &ColumnMapping) -> Result<Vec<Result<Guest, Unreadable>>, AppError> {
+        let email_col = self.column(&mapping.email)?;
+        let role_col = mapping.role.as_deref().map(|role| self.column(role)).transpose()?;
+
+        Ok(self
+            .rows
+            .iter()
+            .map(|(line, record)| {
+                let record = record.as_ref().map_err(|e| Unreadable {
+                    line: *line,
+                    message: e.clone(),
+                })?;
+                let field = |col: Option<usize>| {
+                    col.and_then(|c| record.get(c))
+                        .filter(|value| !value.is_empty())
+                        .map(str::to_string)
+                };
+                let email = match self.format {
+                    GuestListFormat::Csv => field(Some(email_col)),
+                    GuestListFormat::GoogleContacts => field(Some(email_col)).and_then(|emails| {
+                        emails
+                            .split(GOOGLE_SEPARATOR)
+                            .map(str::trim)
+                            .find(|email| !email.is_empty())
+                            .map(str::to_string)
+                    }),
+                };
+                Ok(Guest {
+                    line: *line,
+                    email,
+                    role: field(role_col).map(|role| role.to_lowercase()),
+                })
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GOOGLE: &str = "\u{feff}First Name,Last Name,E-mail 1 - Label,E-mail 1 - Value,Phone 1 - Value\n\
+        Ada,Lovelace,* Home,ada@example.com ::: ada@work.example,555\n\
+        Alan,Turing,,,\n";
+
+    #[test]
+    fn google_contacts_exports_map_to_the_first_address() {
+        let list = GuestList::parse(GuestListFormat::GoogleContacts, GOOGLE).unwrap();
+        let mapping = list.suggest().unwrap();
+        assert_eq!(mapping.email, "E-mail 1 - Value");
+        assert_eq!(mapping.role, None);
+
+        let guests = list.guests(&mapping).unwrap();
+        let emails: Vec<_> = guests.iter().map(|guest| guest.as_ref().ok().unwrap().email.clone()).collect();
+        assert_eq!(emails, vec![Some("ada@example.com".to_string()), None]);
+    }
+
+    #[test]
+    fn mappings_must_name_columns_the_file_has() {
+        let list = GuestList::parse(GuestListFormat::Csv, "Guest,Mail,Role\nAda,ada@example.com,Admin\n").unwrap();
+        assert_eq!(
+            list.suggest(),
+            Some(ColumnMapping {
+                email: "Mail".to_string(),
+                role: Some("Role".to_string())
+            })
+        );
+
+        let guests = list.guests(&list.suggest().unwrap()).unwrap();
+        assert_eq!(guests[0].as_ref().ok().unwrap().role.as_deref(), Some("admin"));
+        assert!(matches!(
+            list.guests(&ColumnMapping {
+                email: "Email".to_string(),
+                role: None
+            }),
+            Err(AppError::ImportColumnUnknown(_))
+        ));
+    }
+}
@@ -1,267 +1,1430 @@
+pub mod analytics;
+pub mod attachment;
+pub mod billing;
+pub mod block;
+pub mod contact;
+pub mod email;
+pub mod export;
+pub mod fraud;
+pub mod invitation;
+#[cfg(feature = "invite-page")]
+pub mod invite_page;
+pub mod leaderboard;
+pub mod load_test;
+pub mod org;
+pub mod retention;
+pub mod reward;
+pub mod snapshot;
+pub mod sync;
+
 use sqlx::*;
 use std::sync::Arc;
-use tokio_stream::{StreamExt as _ , wrappers::BroadcastStream};
-use futures_util::stream::{self, Stream};
+use tokio_stream::{StreamExt as _ , wrappers::{BroadcastStream, IntervalStream, errors::BroadcastStreamRecvError}};
+use futures_util::{stream::Stream, TryStreamExt as _};
 
 use axum::{
-    extract::{Path, Query, State},
-    http::StatusCode,
-    response::{IntoResponse, sse::{Event, Sse}},
-    Json,
-    TypedHeader,
-    headers,
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response, sse::{Event, Sse}},
+    headers::{self, HeaderMapExt},
 };
 use serde_json::json;
 use uuid::Uuid;
 
 use crate::{
-    model::UserModel,
-    schema::{CreateUserSchema, FilterOptions, UpdateUserSchema},
+    achievements, audit, blocks, build_info, config, email_domain,
+    error::{unique_violation, AppError},
+    events::{self, SchemaVersion},
+    extract::{Json, Query, Sanitized, TypedHeader},
+    fields::Fields,
+    format::{self, Format},
+    i18n, include,
+    last_modified::{self, Changed},
+    maintenance,
+    model::{BadgeModel, PrivacySettingsModel, RewardModel, UserModel},
+    pagination::Pagination,
+    pii, ref_code,
+    privacy, schema_check, stream,
+    client_ip::ClientIp,
+    schema::{
+        BatchGetUsersSchema, BulkDeleteUsersSchema, BulkUpdateUsersSchema, CreateUserSchema,
+        DryRunOptions, MaintenanceSchema, ProfileOptions, ReplayOptions, SseOptions, UpdateUserSchema,
+        UpdatePrivacySchema, UserListOptions,
+    },
+    session::{Caller, Viewer},
+    user_ref::UserRef,
     AppState,
 };
 
-pub async fn health_checker_handler() -> impl IntoResponse {
-    const MESSAGE: &str = "Invito is running...";
-
+pub async fn health_checker_handler(State(data): State<Arc<AppState>>) -> impl IntoResponse {
     let json_response = serde_json::json!({
         "status": "success",
-        "message": MESSAGE
+        "message": i18n::t("HEALTH_OK", &[]),
+        "maintenance": data.maintenance.to_json()
     });
 
     Json(json_response)
 }
 
+/// What's deployed: the build this instance runs and the latest migration
+/// applied to its database, `null` when migrations aren't applied with sqlx.
+pub async fn version_handler(State(data): State<Arc<AppState>>) -> Result<impl IntoResponse, AppError> {
+    let applied = schema_check::applied_migration(&data.db).await?;
+    Ok(Json(json!({
+        "status": "success",
+        "build": build_info::to_json(),
+        "database": {"migration": applied}
+    })))
+}
+
+/// Whether this instance can serve traffic, with the status and latency of
+/// each dependency it needs. 503 while any of them is down.
+pub async fn readiness_handler(State(data): State<Arc<AppState>>) -> impl IntoResponse {
+    let (ready, dependencies) = data.readiness.check(&data).await;
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    let json_response = json!({
+        "status": if ready { "success" } else { "fail" },
+        "dependencies": dependencies
+    });
+
+    (status, Json(json_response))
+}
+
+/// The challenge to solve before calling a guarded route, `null` when none
+/// is asked for.
+pub async fn challenge_handler(State(data): State<Arc<AppState>>) -> impl IntoResponse {
+    Json(serde_json::json!({"status": "success", "data": {"challenge": data.challenges.issue(data.clock.now())}}))
+}
 
+/// The live event stream. Events addressed to a user or to an
+/// organization's members only reach the signed-in user they're meant for,
+/// so clients pass their session token in the `Authorization` header.
 pub async fn sse_handler(
     State(app): State<Arc<AppState>>,
     TypedHeader(user_agent): TypedHeader<headers::UserAgent>,
-) -> Sse<impl Stream<Item = Result<Event, serde_json::Error>>> {
+    Viewer(user_id): Viewer,
+    opts: Option<Query<SseOptions>>,
+) -> Result<Sse<impl Stream<Item = Result<Event, serde_json::Error>>>, AppError> {
     println!("`{}` connected", user_agent.as_str());
-   
-    let stream = BroadcastStream::new(app.tx.subscribe())
-        .map(|i| Event::default().json_data(i.unwrap()));
+    let Query(opts) = opts.unwrap_or_default();
+
+    // memberships are looked up once, when the client connects
+    let memberships = user_memberships(&app, user_id).await;
+
+    let org_connection = match (opts.org_id, user_id) {
+        (Some(org_id), Some(_)) if memberships.iter().any(|(member_org, _)| *member_org == org_id) => {
+            Some(app.quotas.acquire_connection(&app.db, org_id).await?)
+        }
+        (Some(_), Some(user_id)) => return Err(AppError::OrgMembershipRequired(user_id)),
+        (Some(_), None) => return Err(AppError::SessionRequired),
+        (None, _) => None,
+    };
+    let schema_version = opts.schema_version.unwrap_or(app.sse.schema_version);
+    let connection = app.sse.register(user_id, opts.org_id, user_agent.as_str(), schema_version, app.clock.now());
+    let state = connection.state.clone();
+    let max_lag = app.sse.max_lag;
+
+    let events = BroadcastStream::new(app.tx.subscribe()).filter_map(move |i| match i {
+        Ok(msg) => {
+            let event = serde_json::from_str::<serde_json::Value>(&msg).unwrap_or_default();
+            if !audience_allows(&event, user_id, &memberships) {
+                return None;
+            }
+            // lets clients resume from the replay API with the last id they saw
+            let sse_event = match event["event_id"].as_i64() {
+                Some(event_id) => Event::default().id(event_id.to_string()),
+                None => Event::default(),
+            };
+            Some(match schema_version {
+                SchemaVersion::V1 => sse_event.json_data(events::render(&event, schema_version).to_string()),
+                SchemaVersion::V2 => sse_event.json_data(events::render(&event, schema_version)),
+            })
+        }
+        // a client that can't keep up misses events, and is dropped once it misses too many
+        Err(BroadcastStreamRecvError::Lagged(missed)) => {
+            if state.record_lag(missed, max_lag) {
+                println!("Disconnecting SSE connection {}, it fell {} events behind", state.id, missed);
+            }
+            None
+        }
+    });
+    let heartbeat = app.sse.heartbeat;
+    let heartbeats = IntervalStream::new(tokio::time::interval_at(tokio::time::Instant::now() + heartbeat, heartbeat))
+        .map(|_| Ok(Event::default().comment("heartbeat")));
+
+    let state = connection.state.clone();
+    let closing = connection.state.clone();
+    let clock = app.clock.clone();
+    let stream = futures_util::StreamExt::take_until(
+        events.merge(heartbeats).map(move |event| {
+            // released when the client goes away and the stream is dropped
+            let _connections = (&connection, &org_connection);
+            state.touch(clock.now());
+            event
+        }),
+        async move { closing.closed().await },
+    );
 
-    let res = stream::once(async move {
+    let res = futures_util::stream::once(async move {
     let user_response = serde_json::json!({"status": "success","event_data": serde_json::json!({})});
         Event::default().json_data(user_response)
     });
 
-    let keep_alive_response = serde_json::json!({"status": "success","event_data": serde_json::json!({})});
-    Sse::new(res.chain(stream))
-    .keep_alive(axum::response::sse::KeepAlive::new().text(keep_alive_response.to_string()))
+    Ok(Sse::new(res.chain(stream)))
+}
+
+pub async fn sse_connections_handler(State(data): State<Arc<AppState>>) -> impl IntoResponse {
+    let connections = data.sse.list(data.clock.now());
+
+    Json(json!({
+        "status": "success",
+        "results": connections.len(),
+        "connections": connections
+    }))
+}
+
+pub async fn maintenance_handler(State(data): State<Arc<AppState>>) -> impl IntoResponse {
+    Json(json!({"status": "success", "maintenance": data.maintenance.to_json()}))
+}
+
+/// Turns maintenance mode on or off. The change takes effect here right
+/// away and reaches the other instances and SSE clients as an event.
+pub async fn set_maintenance_handler(
+    State(data): State<Arc<AppState>>,
+    Json(body): Json<MaintenanceSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    data.maintenance.set(body.enabled, body.retry_after);
+    let state = data.maintenance.to_json();
+
+    let mut tx = data.db.begin().await?;
+    let action = if body.enabled { "maintenance.enabled" } else { "maintenance.disabled" };
+    audit::record(&mut *tx, action, state.clone()).await?;
+    let event_to_send = json!({"status": "success","event_type": maintenance::EVENT_TYPE,"event_data": state});
+    events::enqueue(&mut tx, event_to_send).await?;
+    tx.commit().await?;
+
+    Ok(Json(json!({"status": "success", "maintenance": state})))
+}
+
+/// Reads the config file again and applies what can change while running,
+/// the same as sending the process SIGHUP.
+pub async fn reload_config_handler(State(data): State<Arc<AppState>>) -> Result<impl IntoResponse, AppError> {
+    config::reload(&data).map_err(AppError::ConfigReloadFailed)?;
+    audit::record(&data.db, "config.reloaded", json!({"settings": config::RELOADABLE})).await?;
+    Ok(Json(json!({"status": "success", "reloaded": config::RELOADABLE})))
+}
+
+pub async fn query_metrics_handler(State(data): State<Arc<AppState>>) -> impl IntoResponse {
+    let statements = data.query_metrics.snapshot();
+
+    Json(json!({
+        "status": "success",
+        "results": statements.len(),
+        "statements": statements,
+        "coalesced_reads": data.coalescer.to_json()
+    }))
+}
+
+const MAX_REPLAY_EVENTS: usize = 1000;
+
+/// Returns the events stored after `since`, an event id or an RFC 3339
+/// timestamp, so clients that were offline can catch up before
+/// resubscribing to the live stream. Events addressed to a user or an
+/// organization's members are only included for the signed-in user they're
+/// meant for.
+pub async fn replay_events_handler(
+    Query(opts): Query<ReplayOptions>,
+    Viewer(user_id): Viewer,
+    fields: Fields,
+    headers: HeaderMap,
+    State(data): State<Arc<AppState>>,
+) -> Result<Response, AppError> {
+    let (since_id, since_time) = match opts.since.trim().parse::<i64>() {
+        Ok(event_id) => (Some(event_id), None),
+        Err(_) => match chrono::DateTime::parse_from_rfc3339(opts.since.trim()) {
+            Ok(time) => (None, Some(time.with_timezone(&chrono::Utc))),
+            Err(_) => return Err(AppError::ReplaySinceInvalid(opts.since)),
+        },
+    };
+    let limit = opts.limit.unwrap_or(100).clamp(1, MAX_REPLAY_EVENTS);
+
+    let changed = Changed::of(&data.db, last_modified::EVENTS).await?;
+    if let Some(not_modified) = changed.not_modified(&headers) {
+        return Ok(not_modified);
+    }
+
+    let rows = sqlx::query!(
+        "SELECT id, payload, created_at FROM events WHERE ($1::bigint IS NULL OR id > $1) AND ($2::timestamptz IS NULL OR created_at > $2) ORDER BY id LIMIT $3",
+        since_id,
+        since_time,
+        limit as i64
+    )
+    .fetch_all(&data.db)
+    .await?;
+
+    let memberships = user_memberships(&data, user_id).await;
+    let schema_version = opts.schema_version.unwrap_or(data.sse.schema_version);
+    let has_more = rows.len() == limit;
+    let next = rows.last().map(|row| row.id);
+    let events: Vec<serde_json::Value> = rows
+        .into_iter()
+        .filter(|row| audience_allows(&row.payload, user_id, &memberships))
+        .map(|row| {
+            let mut event = row.payload;
+            event["event_id"] = json!(row.id);
+            event["created_at"] = json!(row.created_at);
+            fields.select(events::render(&event, schema_version))
+        })
+        .collect();
+
+    Ok(changed.stamp(
+        Json(json!({
+            "status": "success",
+            "results": events.len(),
+            "events": events,
+            "next": next,
+            "has_more": has_more
+        }))
+        .into_response(),
+    ))
+}
+
+async fn user_memberships(data: &AppState, user_id: Option<Uuid>) -> Vec<(Uuid, String)> {
+    match user_id {
+        Some(user_id) => sqlx::query!("SELECT org_id, role FROM org_members WHERE user_id = $1", user_id)
+            .fetch_all(&data.db)
+            .await
+            .map(|rows| rows.into_iter().map(|row| (row.org_id, row.role)).collect())
+            .unwrap_or_default(),
+        None => Vec::new(),
+    }
+}
+
+// events with an `audience` only go to the user it names, or to members of
+// its org holding one of its roles
+fn audience_allows(event: &serde_json::Value, user_id: Option<Uuid>, memberships: &[(Uuid, String)]) -> bool {
+    let Some(audience) = event.get("audience") else {
+        return true;
+    };
+    if let Some(recipient) = audience.get("user_id") {
+        return user_id.is_some_and(|user_id| recipient.as_str() == Some(user_id.to_string().as_str()));
+    }
+
+    let org_id = audience["org_id"].as_str().and_then(|id| id.parse::<Uuid>().ok());
+    let roles = audience["roles"].as_array();
+    memberships.iter().any(|(member_org, role)| {
+        Some(*member_org) == org_id
+            && roles.is_none_or(|roles| roles.iter().any(|r| r.as_str() == Some(role.as_str())))
+    })
 }
 
 pub async fn users_list_handler(
-    opts: Option<Query<FilterOptions>>,
+    pagination: Pagination,
+    // not optional, so a malformed timestamp is rejected rather than ignored
+    Query(opts): Query<UserListOptions>,
+    fields: Fields,
+    headers: HeaderMap,
+    Viewer(viewer): Viewer,
     State(data): State<Arc<AppState>>,
-) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
-    let Query(opts) = opts.unwrap_or_default();
+) -> Result<impl IntoResponse, AppError> {
 
-    let limit = opts.limit.unwrap_or(10);
-    let offset = (opts.page.unwrap_or(1) - 1) * limit;
+    let changed = Changed::of(&data.db, last_modified::USERS).await?;
+    if let Some(not_modified) = changed.not_modified(&headers) {
+        return Ok(not_modified);
+    }
 
-    let query_result = sqlx::query_as!(
+    let limit = pagination.limit(10);
+    let offset = pagination.offset(10);
+    let sort = pagination.sort(&["id", "user_name", "created_at"], "id")?;
+
+    // JSON is written out as the rows come in, so a big page is never held
+    // in memory whole. The binary formats need the whole value to encode.
+    if format::current() == Format::Json {
+        let body = stream::body(move |sink| async move {
+            sink.send(r#"{"status":"success","users":["#).await?;
+            let rows = users_page(&data.db, &sort, limit, offset, &opts);
+            let (read, written) =
+                stream::users(&data, rows, viewer, &fields, stream::Layout::Array, &sink).await?;
+            let next_cursor = json!(pagination.next_cursor(10, read));
+            sink.send(format!(r#"],"results":{},"next_cursor":{}}}"#, written, next_cursor)).await
+        });
+        let response = ([(header::CONTENT_TYPE, "application/json"), (header::VARY, "accept")], body).into_response();
+        return Ok(changed.stamp(response));
+    }
+
+    let query_result = users_page(&data.db, &sort, limit, offset, &opts).try_collect::<Vec<_>>().await;
+
+    let users = query_result.map_err(AppError::UsersFetchFailed)?;
+    // private profiles are left out after paging, the cursor goes by what was read
+    let next_cursor = pagination.next_cursor(10, users.len());
+    let users = privacy::present_all(&data.db, &users, viewer).await?;
+
+    let json_response = serde_json::json!({
+        "status": "success",
+        "results": users.len(),
+        "users": fields.select(json!(users)),
+        "next_cursor": next_cursor
+    });
+    Ok(changed.stamp(Json(json_response).into_response()))
+}
+
+fn users_page<'a>(
+    db: &'a PgPool,
+    sort: &'a str,
+    limit: usize,
+    offset: usize,
+    opts: &'a UserListOptions,
+) -> impl Stream<Item = Result<UserModel, sqlx::Error>> + Send + 'a {
+    sqlx::query_as!(
         UserModel,
-        "SELECT * FROM users ORDER by id LIMIT $1 OFFSET $2",
+        "SELECT * FROM users WHERE ($4::timestamptz IS NULL OR created_at > $4) AND ($5::timestamptz IS NULL OR created_at < $5) ORDER BY CASE WHEN $1 = 'user_name' THEN user_name END, CASE WHEN $1 = '-user_name' THEN user_name END DESC, CASE WHEN $1 = 'created_at' THEN created_at END, CASE WHEN $1 = '-created_at' THEN created_at END DESC, CASE WHEN $1 = '-id' THEN id END DESC, id LIMIT $2 OFFSET $3",
+        sort,
         limit as i32,
-        offset as i32
+        offset as i32,
+        opts.created_after,
+        opts.created_before
+    )
+    .fetch(db)
+}
+
+/// Every user matching the filters, ordered by id, written out as they're
+/// read. One JSON document per line when the client accepts
+/// `application/x-ndjson`, otherwise a JSON array. There's no envelope, a
+/// failure partway through cuts the response off instead. Operators only,
+/// the users are exported as anyone would see them.
+pub async fn users_export_handler(
+    Query(opts): Query<UserListOptions>,
+    fields: Fields,
+    headers: HeaderMap,
+    State(data): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    let ndjson = headers
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("application/x-ndjson"));
+    let (layout, content_type) = if ndjson {
+        (stream::Layout::Lines, "application/x-ndjson")
+    } else {
+        (stream::Layout::Array, "application/json")
+    };
+
+    let body = stream::body(move |sink| async move {
+        if layout == stream::Layout::Array {
+            sink.send("[").await?;
+        }
+        let rows = sqlx::query_as!(
+            UserModel,
+            "SELECT * FROM users WHERE ($1::timestamptz IS NULL OR created_at > $1) AND ($2::timestamptz IS NULL OR created_at < $2) ORDER BY id",
+            opts.created_after,
+            opts.created_before
+        )
+        .fetch(&data.db);
+        stream::users(&data, rows, None, &fields, layout, &sink).await?;
+        if layout == stream::Layout::Array {
+            sink.send("]").await?;
+        }
+        Ok(())
+    });
+    ([(header::CONTENT_TYPE, content_type)], body)
+}
+
+const MAX_BATCH_SIZE: usize = 100;
+
+pub async fn batch_get_users_handler(
+    Viewer(viewer): Viewer,
+    fields: Fields,
+    State(data): State<Arc<AppState>>,
+    Json(body): Json<BatchGetUsersSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    if body.ids.len() + body.user_names.len() > MAX_BATCH_SIZE {
+        return Err(AppError::BatchTooLarge(MAX_BATCH_SIZE));
+    }
+
+    let query_result = sqlx::query_as!(
+        UserModel,
+        "SELECT * FROM users WHERE id = ANY($1) OR user_name = ANY($2) ORDER by id",
+        &body.ids,
+        &body.user_names
     )
     .fetch_all(&data.db)
     .await;
 
-    if query_result.is_err() {
-        let error_response = serde_json::json!({
-            "status": "fail",
-            "message": "Something bad happened while fetching all user items",
-        });
-        return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(error_response)));
-    }
+    let users = query_result.map_err(AppError::UsersFetchFailed)?;
+    // private profiles are reported as not found
+    let users = privacy::present_all(&data.db, &users, viewer).await?;
 
-    let users = query_result.unwrap();
+    // report what was asked for but doesn't exist, so clients don't have to diff
+    let not_found: Vec<String> = body
+        .ids
+        .iter()
+        .filter(|id| !users.iter().any(|user| user["id"] == id.to_string()))
+        .map(|id| id.to_string())
+        .chain(
+            body.user_names
+                .iter()
+                .filter(|name| !users.iter().any(|user| user["user_name"] == **name))
+                .cloned(),
+        )
+        .collect();
 
     let json_response = serde_json::json!({
         "status": "success",
         "results": users.len(),
-        "users": users
+        "users": fields.select(json!(users)),
+        "not_found": not_found
     });
-    Ok(Json(json_response).into_response())
+    Ok(Json(json_response))
 }
 
 pub async fn create_user_handler(
+    ClientIp(ip): ClientIp,
     State(data): State<Arc<AppState>>,
-    Json(body): Json<CreateUserSchema>,
-) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
-    // checks if signup is with referral code
-    if let Some(x) = body.ref_code {
-        // check if code exits
-        // increment user count for code owner
-        let query_result = sqlx::query_as!(UserModel, "SELECT * FROM users WHERE ref_code = $1", x)
-            .fetch_one(&data.db)
-            .await;
+    Sanitized(body): Sanitized<CreateUserSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    email_domain::check(&data.db, &body.email, None).await?;
+    data.email_validation.check(&body.email).await?;
 
-        match query_result {
-            Ok(user) => {
-                //update the ref user count
-                let _ = sqlx::query_as!(
-                    UserModel,
-                    "UPDATE users SET added_by_ref_code = added_by_ref_code + 1 WHERE id = $1",
-                    user.id
-                )
-                .fetch_one(&data.db).await;
-            }
-            Err(_) => {
-                let error_response = serde_json::json!({
-                    "status": "fail",
-                    "message": format!("User with referral code: {} not found", x)
-                });
-                return Err((StatusCode::NOT_FOUND, Json(error_response)));
-            }
-        }
-    }
+    // checks if signup is with referral code, its owner is credited once the user exists
+    let referrer_id = match &body.ref_code {
+        Some(ref_code) => Some(
+            sqlx::query_scalar!("SELECT id FROM users WHERE ref_code = $1", ref_code)
+                .fetch_optional(&data.db)
+                .await?
+                .ok_or_else(|| AppError::RefCodeNotFound(ref_code.clone()))?,
+        ),
+        None => None,
+    };
 
     // creates new referral code
-    let ref_id = Uuid::new_v4().to_string();
-    let code = format!("{}{}", &body.user_name[0..3], &ref_id[0..4]);
+    let code = ref_code::generate(&body.user_name);
 
     // add user to db
+    let mut tx = data.db.begin().await?;
     let query_result = sqlx::query_as!(
         UserModel,
-        "INSERT INTO users (email, user_name, ref_code, added_by_ref_code) VALUES ($1, $2, $3, $4) RETURNING *",
-        body.email.to_string(),
+        "INSERT INTO users (email, email_index, user_name, ref_code, added_by_ref_code) VALUES ($1, $2, $3, $4, $5) RETURNING *",
+        pii::seal(&body.email),
+        pii::blind_index(&body.email),
         body.user_name.to_string(),
         code, 
         0
     )
-    .fetch_one(&data.db)
+    .fetch_one(&mut *tx)
     .await;
 
     match query_result {
         Ok(user) => {
+            crate::fraud::record_signup(&mut tx, &data, user.id, &user.email, referrer_id, ip.as_deref()).await?;
+
+            // send notification to connected clients, who can be anyone, so
+            // it shows the user as a stranger would: a new user is still on
+            // the default settings
+            let profile = privacy::Privacy::default().present(&user, None);
+            let event_to_send = serde_json::json!({"status": "success","event_type": "user_created","event_data": profile});
+            events::enqueue(&mut tx, event_to_send).await?;
+            tx.commit().await?;
+
             let user_response = json!({"status": "success",
-                "message": "User created successfully",
+                "message": i18n::t("USER_CREATED", &[]),
                 "data": json!({
-                "user": user
+                "user": user,
+                "session": data.sessions.to_json(user.id, data.clock.now())
             })});
 
-            // send notification to connected clients
-            let event_to_send = serde_json::json!({"status": "success","event_data": user});
-            data.tx.send(Json(event_to_send).to_string()).unwrap();
-
-            return Ok((StatusCode::CREATED, Json(user_response)));
-        }
-        Err(e) => {
-            if e.to_string()
-                .contains("duplicate key value violates unique constraint")
-            {
-                let error_response = serde_json::json!({
-                    "status": "fail",
-                    "message": "user with that email already exists",
-                });
-                return Err((StatusCode::CONFLICT, Json(error_response)));
+            // signing up accepts any organization invitations sent to this email
+            if let Err(e) = org::claim_pending_invitations(&data, &user).await {
+                println!("🔥 Failed to claim invitations for {}: {:?}", user.id, e);
             }
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({"status": "error","message": format!("{:?}", e)})),
-            ));
+
+            Ok((StatusCode::CREATED, Json(user_response)))
         }
+        Err(e) => match unique_violation(&e) {
+            Some("users_user_name_key") => Err(AppError::UserNameTaken),
+            Some(_) => Err(AppError::UserEmailTaken),
+            None => Err(e.into()),
+        },
     }
 }
 
+const PROFILE_RELATIONS: [&str; 3] = ["referrals", "badges", "events"];
+
+/// A user's profile. `?include=` embeds related collections, each with an
+/// optional limit, e.g. `referrals,events:5`: the users they referred,
+/// their badges, and the stored events about them. Referrals follow the
+/// user's privacy settings and leave out the users they blocked, events are
+/// only embedded for the user themselves.
 pub async fn get_user_handler(
-    Path(user_name): Path<String>,
+    UserRef(id): UserRef,
+    opts: Option<Query<ProfileOptions>>,
+    fields: Fields,
+    Viewer(viewer): Viewer,
     State(data): State<Arc<AppState>>,
-) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
-    let query_result = sqlx::query_as!(
-        UserModel,
-        "SELECT * FROM users WHERE user_name = $1",
-        user_name
-    )
-    .fetch_one(&data.db)
-    .await;
+) -> Result<impl IntoResponse, AppError> {
+    let Query(opts) = opts.unwrap_or_default();
+    let included = include::parse(opts.include.as_deref(), &PROFILE_RELATIONS)?;
+    let query_result = sqlx::query_as!(UserModel, "SELECT * FROM users WHERE id = $1", id)
+        .fetch_one(&data.db)
+        .await;
 
     match query_result {
+        // to someone they blocked, a user doesn't exist
+        Ok(user) if blocks::has_blocked(&data.db, user.id, viewer).await? => {
+            Err(AppError::UserNotFound(id.to_string()))
+        }
         Ok(user) => {
-            let user_response = serde_json::json!({"status": "success","data": serde_json::json!({
-                "user": user
+            let privacy = privacy::load(&data.db, user.id).await?;
+            let Some(profile) = privacy.present(&user, viewer) else {
+                return Err(AppError::UserNotFound(id.to_string()));
+            };
+            let mut user_response = serde_json::json!({"status": "success","data": serde_json::json!({
+                "user": fields.select(profile)
             })});
 
-            return Ok((StatusCode::OK, Json(user_response)));
-        }
-        Err(_) => {
-            let error_response = serde_json::json!({
-                "status": "fail",
-                "message": format!("{} not found", user_name)
-            });
-            return Err((StatusCode::NOT_FOUND, Json(error_response)));
+            if privacy.shows_referral_stats(user.id, viewer) {
+                let rewards = sqlx::query_as!(
+                    RewardModel,
+                    "SELECT * FROM rewards WHERE user_id = $1 ORDER BY created_at DESC, id",
+                    user.id
+                )
+                .fetch_all(&data.db)
+                .await?;
+                let points: i64 = rewards.iter().filter_map(|reward| reward.points).map(i64::from).sum();
+                user_response["data"]["rewards"] = json!(rewards);
+                user_response["data"]["points"] = json!(points);
+            }
+
+            for (relation, limit) in included {
+                let embedded = match relation {
+                    "referrals" if privacy.shows_referral_stats(user.id, viewer) => {
+                        let referred = sqlx::query_as!(
+                            UserModel,
+                            "SELECT users.* FROM signups JOIN users ON users.id = signups.user_id WHERE signups.referrer_id = $1 AND NOT EXISTS (SELECT 1 FROM blocks WHERE blocker_id = $1 AND blocked_id = signups.user_id) ORDER BY signups.created_at DESC, users.id LIMIT $2",
+                            user.id,
+                            limit as i64
+                        )
+                        .fetch_all(&data.db)
+                        .await?;
+                        json!(privacy::present_all(&data.db, &referred, viewer).await?)
+                    }
+                    "badges" => json!(sqlx::query_as!(
+                        BadgeModel,
+                        "SELECT * FROM badges WHERE user_id = $1 ORDER BY earned_at DESC, badge LIMIT $2",
+                        user.id,
+                        limit as i64
+                    )
+                    .fetch_all(&data.db)
+                    .await?),
+                    "events" if viewer == Some(user.id) => {
+                        let rows = sqlx::query!(
+                            "SELECT id, payload, created_at FROM events WHERE payload->'event_data'->>'id' = $1 OR payload->'event_data'->>'user_id' = $1 ORDER BY id DESC LIMIT $2",
+                            user.id.to_string(),
+                            limit as i64
+                        )
+                        .fetch_all(&data.db)
+                        .await?;
+                        json!(rows
+                            .into_iter()
+                            .map(|row| {
+                                let mut event = row.payload;
+                                event["event_id"] = json!(row.id);
+                                event["created_at"] = json!(row.created_at);
+                                events::render(&event, data.sse.schema_version)
+                            })
+                            .collect::<Vec<_>>())
+                    }
+                    _ => json!([]),
+                };
+                user_response["data"][relation] = embedded;
+            }
+
+            let etag = user_etag(&user);
+            Ok((StatusCode::OK, [(header::ETAG, etag)], Json(user_response)))
         }
+        Err(_) => Err(AppError::UserNotFound(id.to_string())),
     }
 }
 
+/// How many signups a user referred, how many of those still wait on fraud
+/// review, and where the referred signups came from.
+pub async fn referral_stats_handler(
+    UserRef(id): UserRef,
+    Viewer(viewer): Viewer,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let referrals = sqlx::query_scalar!("SELECT added_by_ref_code FROM users WHERE id = $1", id)
+        .fetch_optional(&data.db)
+        .await?
+        .ok_or_else(|| AppError::UserNotFound(id.to_string()))?;
+    if !privacy::load(&data.db, id).await?.shows_referral_stats(id, viewer)
+        || blocks::has_blocked(&data.db, id, viewer).await?
+    {
+        return Err(AppError::UserNotFound(id.to_string()));
+    }
+
+    let pending_review = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "count!" FROM fraud_flags WHERE referrer_id = $1 AND status = 'pending'"#,
+        id
+    )
+    .fetch_one(&data.db)
+    .await?;
+
+    // signups from before locations were recorded, or from unknown addresses, have no country
+    let by_country: Vec<serde_json::Value> = sqlx::query!(
+        r#"SELECT country, region, COUNT(*) AS "signups!" FROM signups WHERE referrer_id = $1 GROUP BY country, region ORDER BY 3 DESC, 1, 2"#,
+        id
+    )
+    .fetch_all(&data.db)
+    .await?
+    .into_iter()
+    .map(|row| json!({"country": row.country, "region": row.region, "signups": row.signups}))
+    .collect();
+
+    Ok(Json(json!({
+        "status": "success",
+        "data": json!({
+            "referrals": referrals,
+            "pending_review": pending_review,
+            "by_country": by_country
+        })
+    })))
+}
+
+/// The badges a user earned, with every achievement there is so profiles
+/// can show the ones still to get.
+pub async fn user_badges_handler(
+    UserRef(id): UserRef,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    sqlx::query_scalar!("SELECT id FROM users WHERE id = $1", id)
+        .fetch_optional(&data.db)
+        .await?
+        .ok_or_else(|| AppError::UserNotFound(id.to_string()))?;
+
+    let badges: Vec<serde_json::Value> = sqlx::query_as!(
+        BadgeModel,
+        "SELECT * FROM badges WHERE user_id = $1 ORDER BY earned_at, badge",
+        id
+    )
+    .fetch_all(&data.db)
+    .await?
+    .into_iter()
+    .map(|badge| {
+        let title = achievements::find(&badge.badge).map(|achievement| achievement.title);
+        json!({"badge": badge.badge, "title": title, "earnedAt": badge.earned_at})
+    })
+    .collect();
+
+    Ok(Json(json!({
+        "status": "success",
+        "results": badges.len(),
+        "badges": badges,
+        "achievements": achievements::ACHIEVEMENTS
+    })))
+}
+
+/// What a user did, newest first: joining, signups they referred, answers
+/// to organization invitations and the badges and rewards they earned.
+/// Everyone sees the badges, rewards and that a referral happened. Only the
+/// user sees who they referred, and invitation answers are shown to them
+/// and to the admins of the inviting organization. Users they blocked are
+/// left out, and users who blocked the viewer hide their whole timeline.
+pub async fn user_activity_handler(
+    UserRef(id): UserRef,
+    pagination: Pagination,
+    Viewer(viewer): Viewer,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+
+    let limit = pagination.limit(20);
+    let offset = pagination.offset(20);
+    let sort = pagination.sort(&["occurred_at"], "-occurred_at")?;
+    let own = viewer == Some(id);
+
+    let email_index = sqlx::query_scalar!("SELECT email_index FROM users WHERE id = $1", id)
+        .fetch_optional(&data.db)
+        .await?
+        .ok_or_else(|| AppError::UserNotFound(id.to_string()))?;
+    if blocks::has_blocked(&data.db, id, viewer).await? {
+        return Err(AppError::UserNotFound(id.to_string()));
+    }
+
+    let entries: Vec<serde_json::Value> = sqlx::query!(
+        r#"SELECT kind AS "kind!", occurred_at AS "occurred_at!", details AS "details!" FROM (
+            SELECT 'joined' AS kind, created_at AS occurred_at, jsonb_build_object('user_name', user_name) AS details, NULL::uuid AS org_id FROM users WHERE id = $1
+            UNION ALL
+            SELECT 'referral', created_at, CASE WHEN $3 THEN jsonb_build_object('user_id', user_id) ELSE '{}'::jsonb END, NULL FROM signups WHERE referrer_id = $1 AND NOT EXISTS (SELECT 1 FROM blocks WHERE blocker_id = $1 AND blocked_id = signups.user_id)
+            UNION ALL
+            SELECT 'rsvp', rsvp_at, jsonb_build_object('invitation_id', id, 'org_id', org_id, 'rsvp', rsvp), org_id FROM org_invitations WHERE email_index = $2 AND rsvp_at IS NOT NULL
+            UNION ALL
+            SELECT 'badge', earned_at, jsonb_build_object('badge', badge), NULL FROM badges WHERE user_id = $1
+            UNION ALL
+            SELECT 'reward', created_at, jsonb_build_object('badge', badge, 'points', points, 'period', period), NULL FROM rewards WHERE user_id = $1
+        ) activity
+        WHERE occurred_at IS NOT NULL AND (kind <> 'rsvp' OR $3 OR org_id IN (SELECT org_id FROM org_members WHERE user_id = $4 AND role = ANY($5)))
+        ORDER BY CASE WHEN $8 = 'occurred_at' THEN occurred_at END, occurred_at DESC, kind LIMIT $6 OFFSET $7"#,
+        id,
+        email_index,
+        own,
+        viewer,
+        &org::ORG_ADMIN_ROLES.map(String::from),
+        limit as i64,
+        offset as i64,
+        sort
+    )
+    .fetch_all(&data.db)
+    .await?
+    .into_iter()
+    .map(|row| json!({"kind": row.kind, "occurredAt": row.occurred_at, "details": row.details}))
+    .collect();
+
+    Ok(Json(json!({
+        "status": "success",
+        "results": entries.len(),
+        "activity": entries,
+        "next_cursor": pagination.next_cursor(20, entries.len())
+    })))
+}
+
+/// A user's own privacy settings, only they can see them.
+pub async fn privacy_settings_handler(
+    UserRef(id): UserRef,
+    caller: Caller,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    caller.require(id)?;
+    let privacy = privacy::load(&data.db, id).await?;
+
+    Ok(Json(json!({
+        "status": "success",
+        "data": json!({
+            "profile_visibility": privacy.profile_visibility,
+            "hide_email": privacy.hide_email,
+            "hide_referral_stats": privacy.hide_referral_stats
+        })
+    })))
+}
+
+/// Changes the settings given, the others keep their current value. Only
+/// the user themselves can.
+pub async fn update_privacy_settings_handler(
+    UserRef(id): UserRef,
+    caller: Caller,
+    State(data): State<Arc<AppState>>,
+    Json(body): Json<UpdatePrivacySchema>,
+) -> Result<impl IntoResponse, AppError> {
+    caller.require(id)?;
+    let current = privacy::load(&data.db, id).await?;
+    let mut tx = data.db.begin().await?;
+
+    let query_result = sqlx::query_as!(
+        PrivacySettingsModel,
+        "INSERT INTO privacy_settings (user_id, profile_visibility, hide_email, hide_referral_stats, updated_at) VALUES ($1, $2, $3, $4, $5) ON CONFLICT (user_id) DO UPDATE SET profile_visibility = EXCLUDED.profile_visibility, hide_email = EXCLUDED.hide_email, hide_referral_stats = EXCLUDED.hide_referral_stats, updated_at = EXCLUDED.updated_at RETURNING *",
+        id,
+        body.profile_visibility.map_or(current.profile_visibility.as_str(), |visibility| visibility.as_str()),
+        body.hide_email.unwrap_or(current.hide_email),
+        body.hide_referral_stats.unwrap_or(current.hide_referral_stats),
+        data.clock.now()
+    )
+    .fetch_one(&mut *tx)
+    .await;
+
+    let settings = match query_result {
+        Ok(settings) => settings,
+        Err(e) if e.as_database_error().is_some_and(|e| e.is_foreign_key_violation()) => {
+            return Err(AppError::UserNotFound(id.to_string()))
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    audit::record(&mut *tx, "user.privacy_updated", json!(settings)).await?;
+    tx.commit().await?;
+
+    Ok(Json(json!({"status": "success","data": settings})))
+}
+
 pub async fn edit_user_handler(
-    Path(id): Path<uuid::Uuid>,
+    UserRef(id): UserRef,
+    caller: Caller,
     State(data): State<Arc<AppState>>,
-    Json(body): Json<UpdateUserSchema>,
-) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    headers: HeaderMap,
+    Sanitized(body): Sanitized<UpdateUserSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    caller.require(id)?;
     let query_result = sqlx::query_as!(UserModel, "SELECT * FROM users WHERE id = $1", id)
         .fetch_one(&data.db)
         .await;
 
-    if query_result.is_err() {
-        let error_response = serde_json::json!({
-            "status": "fail",
-            "message": format!("User with ID: {} not found", id)
-        });
-        return Err((StatusCode::NOT_FOUND, Json(error_response)));
+    let Ok(user) = query_result else {
+        return Err(AppError::UserNotFound(id.to_string()));
+    };
+
+    // the client must tell us which version it edited, either in the body or via If-Match
+    let if_match = headers
+        .contains_key(header::IF_MATCH)
+        .then(|| headers.typed_get::<headers::IfMatch>())
+        .flatten();
+    let up_to_date = match (body.version, if_match) {
+        (Some(version), _) => version == user.version,
+        (None, Some(if_match)) => user_etag(&user)
+            .parse::<headers::ETag>()
+            .map(|etag| if_match.precondition_passes(&etag))
+            .unwrap_or(false),
+        (None, None) => return Err(AppError::UserVersionRequired),
+    };
+
+    if !up_to_date {
+        return Err(AppError::UserVersionConflict(user.id));
     }
 
-    let now = chrono::Utc::now();
-    let user = query_result.unwrap();
+    // only the fields sent that differ from what's stored are written, and
+    // nothing at all when none do
+    let email = body.email.as_deref().filter(|email| *email != user.email.as_str());
+    let user_name = body.user_name.as_deref().filter(|user_name| *user_name != user.user_name);
+    if email.is_none() && user_name.is_none() {
+        let etag = user_etag(&user);
+        let user_response = json!({"status": "success", "changed": false, "data": json!({ "user": user })});
+        return Ok(([(header::ETAG, etag)], Json(user_response)));
+    }
 
-    let query_result = sqlx::query_as!(
-        UserModel,
-        "UPDATE users SET email = $1, user_name = $2, updated_at = $3 WHERE id = $4 RETURNING *",
-        body.email.to_owned().unwrap_or(user.email),
-        body.user_name.to_owned().unwrap_or(user.user_name),
-        now,
-        id
-    )
-    .fetch_one(&data.db)
-    .await;
+    let mut query = QueryBuilder::<Postgres>::new("UPDATE users SET ");
+    let mut set = query.separated(", ");
+    if let Some(email) = email {
+        set.push("email = ").push_bind_unseparated(pii::seal(email));
+        set.push("email_index = ").push_bind_unseparated(pii::blind_index(email));
+    }
+    if let Some(user_name) = user_name {
+        set.push("user_name = ").push_bind_unseparated(user_name);
+    }
+    set.push("version = version + 1");
+    set.push("updated_at = ").push_bind_unseparated(data.clock.now());
+    query
+        .push(" WHERE id = ")
+        .push_bind(id)
+        .push(" AND version = ")
+        .push_bind(user.version)
+        .push(" RETURNING *");
+
+    let query_result = query.build_query_as::<UserModel>().fetch_optional(&data.db).await;
 
     match query_result {
-        Ok(user) => {
-            let user_response = serde_json::json!({"status": "success","data": serde_json::json!({
-                "user": user
-            })});
+        Ok(Some(user)) => {
+            let etag = user_etag(&user);
+            let user_response = json!({"status": "success", "changed": true, "data": json!({ "user": user })});
 
-            return Ok(Json(user_response));
-        }
-        Err(err) => {
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({"status": "error","message": format!("{:?}", err)})),
-            ));
+            Ok(([(header::ETAG, etag)], Json(user_response)))
         }
+        // someone else updated the user between our read and write
+        Ok(None) => Err(AppError::UserVersionConflict(user.id)),
+        Err(err) => match unique_violation(&err) {
+            Some("users_user_name_key") => Err(AppError::UserNameTaken),
+            Some(_) => Err(AppError::UserEmailTaken),
+            None => Err(err.into()),
+        },
     }
 }
 
+fn user_etag(user: &UserModel) -> String {
+    format!("\"{}\"", user.version)
+}
+
 pub async fn delete_user_handler(
-    Path(id): Path<uuid::Uuid>,
+    UserRef(id): UserRef,
+    caller: Caller,
     State(data): State<Arc<AppState>>,
-) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+) -> Result<impl IntoResponse, AppError> {
+    caller.require(id)?;
     let rows_affected = sqlx::query!("DELETE FROM users WHERE id = $1", id)
         .execute(&data.db)
-        .await
-        .unwrap()
+        .await?
         .rows_affected();
 
     if rows_affected == 0 {
-        let error_response = serde_json::json!({
-            "status": "fail",
-            "message": format!("User with ID: {} not found", id)
-        });
-        return Err((StatusCode::NOT_FOUND, Json(error_response)));
+        return Err(AppError::UserNotFound(id.to_string()));
     }
 
     Ok(StatusCode::NO_CONTENT)
 }
+
+/// A new session token for a user, for the integration that signs users in
+/// once it checked who they are.
+pub async fn create_session_handler(
+    UserRef(id): UserRef,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let session = data.sessions.to_json(id, data.clock.now());
+    audit::record(&data.db, "user.session_issued", json!({"user_id": id})).await?;
+
+    Ok((StatusCode::CREATED, Json(json!({"status": "success", "data": {"session": session}}))))
+}
+
+/// With `?dry_run=true` the users are deleted in a transaction that is
+/// rolled back, so the response shows exactly what would happen, along with
+/// the rows that would go with them.
+pub async fn bulk_delete_users_handler(
+    State(data): State<Arc<AppState>>,
+    Query(opts): Query<DryRunOptions>,
+    Json(body): Json<BulkDeleteUsersSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    check_bulk_size(&body.ids)?;
+
+    let mut tx = data.db.begin().await?;
+    // counted first, the delete cascades to them
+    let cascades = if opts.dry_run.unwrap_or(false) {
+        Some(delete_cascades(&mut tx, &body.ids).await?)
+    } else {
+        None
+    };
+
+    let deleted: Vec<Uuid> = sqlx::query_scalar!(
+        "DELETE FROM users WHERE id = ANY($1) RETURNING id",
+        &body.ids
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let results: Vec<serde_json::Value> = body
+        .ids
+        .iter()
+        .map(|id| {
+            let status = if deleted.contains(id) { "deleted" } else { "not_found" };
+            json!({"id": id, "status": status})
+        })
+        .collect();
+
+    if let Some(cascades) = cascades {
+        tx.rollback().await?;
+        return Ok(Json(json!({
+            "status": "success",
+            "dry_run": true,
+            "deleted": deleted.len(),
+            "cascades": cascades,
+            "results": results
+        })));
+    }
+
+    let summary = json!({"requested": body.ids.len(), "deleted": deleted, "count": deleted.len()});
+    audit::record(&mut *tx, "users.bulk_delete", summary.clone())
+        .await?;
+    let event_to_send = json!({"status": "success","event_type": "users_bulk_deleted","event_data": summary});
+    events::enqueue(&mut tx, event_to_send).await?;
+    tx.commit().await?;
+
+    Ok(Json(json!({
+        "status": "success",
+        "deleted": deleted.len(),
+        "results": results
+    })))
+}
+
+/// With `?dry_run=true` the updates run in a transaction that is rolled
+/// back, so conflicts show up in the results without anything changing.
+pub async fn bulk_update_users_handler(
+    State(data): State<Arc<AppState>>,
+    Query(opts): Query<DryRunOptions>,
+    Sanitized(body): Sanitized<BulkUpdateUsersSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    check_bulk_size(&body.ids)?;
+
+    let patch = &body.patch;
+    if patch.user_name.is_none() && patch.email.is_none() && patch.added_by_ref_code.is_none() {
+        return Err(AppError::PatchEmpty);
+    }
+
+    let mut tx = data.db.begin().await?;
+    let mut results = Vec::with_capacity(body.ids.len());
+    let mut updated = Vec::new();
+
+    for id in &body.ids {
+        // each row gets its own savepoint so one failure doesn't abort the whole batch
+        let mut savepoint = tx.begin().await?;
+
+        let query_result = sqlx::query_scalar!(
+            "UPDATE users SET email = COALESCE($1, email), email_index = COALESCE($2, email_index), user_name = COALESCE($3, user_name), added_by_ref_code = COALESCE($4, added_by_ref_code), version = version + 1, updated_at = $6 WHERE id = $5 RETURNING id",
+            pii::seal_opt(patch.email.as_deref()),
+            patch.email.as_deref().map(pii::blind_index),
+            patch.user_name,
+            patch.added_by_ref_code,
+            id,
+            data.clock.now()
+        )
+        .fetch_optional(&mut *savepoint)
+        .await;
+
+        match query_result {
+            Ok(Some(_)) => {
+                savepoint.commit().await?;
+                updated.push(*id);
+                results.push(json!({"id": id, "status": "updated"}));
+            }
+            Ok(None) => {
+                savepoint.rollback().await?;
+                results.push(json!({"id": id, "status": "not_found"}));
+            }
+            Err(e) => {
+                savepoint.rollback().await?;
+                let status = if unique_violation(&e).is_some() { "conflict" } else { "failed" };
+                results.push(json!({"id": id, "status": status}));
+            }
+        }
+    }
+
+    if opts.dry_run.unwrap_or(false) {
+        tx.rollback().await?;
+        return Ok(Json(json!({
+            "status": "success",
+            "dry_run": true,
+            "updated": updated.len(),
+            "results": results
+        })));
+    }
+
+    let summary = json!({
+        "requested": body.ids.len(),
+        "updated": updated,
+        "count": updated.len(),
+        "fields": {
+            "user_name": patch.user_name.is_some(),
+            "email": patch.email.is_some(),
+            "added_by_ref_code": patch.added_by_ref_code.is_some()
+        }
+    });
+    audit::record(&mut *tx, "users.bulk_update", summary.clone())
+        .await?;
+    let event_to_send = json!({"status": "success","event_type": "users_bulk_updated","event_data": summary});
+    events::enqueue(&mut tx, event_to_send).await?;
+    tx.commit().await?;
+
+    Ok(Json(json!({
+        "status": "success",
+        "updated": updated.len(),
+        "results": results
+    })))
+}
+
+const DRY_RUN_SAMPLE: i64 = 10;
+
+// rows removed along with the users, with a few of their ids
+async fn delete_cascades(conn: &mut PgConnection, ids: &[Uuid]) -> Result<serde_json::Value, sqlx::Error> {
+    let contacts = sqlx::query!(
+        r#"SELECT COUNT(*) OVER () AS "count!", id FROM contacts WHERE owner_id = ANY($1) ORDER BY id LIMIT $2"#,
+        ids,
+        DRY_RUN_SAMPLE
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    let memberships = sqlx::query!(
+        r#"SELECT COUNT(*) OVER () AS "count!", org_id, user_id FROM org_members WHERE user_id = ANY($1) ORDER BY org_id, user_id LIMIT $2"#,
+        ids,
+        DRY_RUN_SAMPLE
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(json!({
+        "contacts": {
+            "count": contacts.first().map_or(0, |row| row.count),
+            "sample": contacts.iter().map(|row| row.id).collect::<Vec<_>>()
+        },
+        "org_memberships": {
+            "count": memberships.first().map_or(0, |row| row.count),
+            "sample": memberships.iter().map(|row| json!({"org_id": row.org_id, "user_id": row.user_id})).collect::<Vec<_>>()
+        }
+    }))
+}
+
+fn check_bulk_size(ids: &[Uuid]) -> Result<(), AppError> {
+    if ids.is_empty() || ids.len() > MAX_BATCH_SIZE {
+        return Err(AppError::BulkSizeInvalid(MAX_BATCH_SIZE));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use axum::http::{Method, StatusCode};
+    use chrono::{TimeZone, Utc};
+    use serde_json::json;
+
+    use crate::{clock::ManualClock, testing::TestApp, timestamp};
+
+    #[tokio::test]
+    async fn hidden_emails_stay_out_of_replay_and_sync() {
+        let app = TestApp::new().await;
+        let user = app.create_user("ada", "ada@example.com").await;
+
+        let (_, replay) = app.get("/api/events/stream/replay?since=0").await;
+        let created = replay["events"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|event| event["event_type"] == "user_created")
+            .unwrap();
+        assert_eq!(created["event_data"]["id"], user["id"]);
+
+        let (_, sync) = app.get("/api/sync?since=0").await;
+        assert_eq!(sync["users"][0]["id"], user["id"]);
+        for body in [replay, sync] {
+            assert!(!body.to_string().contains("ada@example.com"), "{}", body);
+        }
+    }
+
+    #[tokio::test]
+    async fn users_who_blocked_the_viewer_are_left_out_of_every_list() {
+        let app = TestApp::new().await;
+        let ada = app.create_user("ada", "ada@example.com").await;
+        let bob = app.create_user("bob", "bob@example.com").await;
+        let (ada_id, bob_id) = (ada["id"].as_str().unwrap(), bob["id"].as_str().unwrap());
+        let blocks = format!("/api/user/{}/blocks", ada_id);
+        // only ada blocks for ada, and only she sees or lifts her blocks
+        let (status, _) = app.as_user(&bob, Method::POST, &blocks, Some(json!({"user_id": bob_id}))).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = app.post(&blocks, json!({"user_id": bob_id})).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = app.as_user(&ada, Method::POST, &blocks, Some(json!({"user_id": bob_id}))).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let (status, _) = app.as_user(&bob, Method::GET, &blocks, None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = app.as_user(&bob, Method::DELETE, &format!("{}/{}", blocks, bob_id), None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let ids = |users: &serde_json::Value| -> Vec<String> {
+            users.as_array().unwrap().iter().map(|user| user["id"].as_str().unwrap().to_string()).collect()
+        };
+
+        let (_, list) = app.as_user(&bob, Method::GET, "/api/users", None).await;
+        assert_eq!(ids(&list["users"]), vec![bob_id]);
+
+        let (_, batch) = app
+            .as_user(&bob, Method::POST, "/api/users/batch-get", Some(json!({"ids": [ada_id, bob_id]})))
+            .await;
+        assert_eq!(ids(&batch["users"]), vec![bob_id]);
+        assert_eq!(batch["not_found"], json!([ada_id]));
+
+        let (_, sync) = app.as_user(&bob, Method::GET, "/api/sync?since=0", None).await;
+        assert_eq!(ids(&sync["users"]), vec![bob_id]);
+        assert_eq!(sync["deleted"], json!([{"type": "user", "id": ada_id}]));
+
+        let (status, _) = app.as_user(&bob, Method::GET, &format!("/api/user/{}/referral-stats", ada_id), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        // everyone else still sees ada
+        let (_, list) = app.get("/api/users").await;
+        assert_eq!(ids(&list["users"]).len(), 2);
+        let (status, _) = app.get(&format!("/api/user/{}/referral-stats", ada_id)).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn embedded_referrals_leave_out_users_the_referrer_blocked() {
+        let app = TestApp::new().await;
+        let ada = app.create_user("ada", "ada@example.com").await;
+        let mut referred = Vec::new();
+        for name in ["carl", "dan"] {
+            let body = json!({"user_name": name, "email": format!("{}@example.com", name), "ref_code": ada["ref_code"]});
+            let (status, body) = app.post("/api/users", body).await;
+            assert_eq!(status, StatusCode::CREATED, "{}", body);
+            referred.push(body["data"]["user"]["id"].clone());
+        }
+        let uri = format!("/api/user/{}/blocks", ada["id"].as_str().unwrap());
+        let (status, _) = app.as_user(&ada, Method::POST, &uri, Some(json!({"user_id": referred[0]}))).await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let (_, profile) = app.get(&format!("/api/user/{}?include=referrals", ada["id"].as_str().unwrap())).await;
+        let ids: Vec<_> = profile["data"]["referrals"].as_array().unwrap().iter().map(|user| user["id"].clone()).collect();
+        assert_eq!(ids, vec![referred[1].clone()]);
+    }
+
+    #[tokio::test]
+    async fn renaming_to_a_taken_name_or_email_conflicts() {
+        let app = TestApp::new().await;
+        app.create_user("ada", "ada@example.com").await;
+        let bob = app.create_user("bob", "bob@example.com").await;
+        let uri = format!("/api/user/{}", bob["id"].as_str().unwrap());
+
+        for (body, code) in [
+            (json!({"user_name": "ada", "version": 1}), "USER_NAME_TAKEN"),
+            (json!({"email": "ada@example.com", "version": 1}), "USER_EMAIL_TAKEN"),
+        ] {
+            let (status, body) = app.as_user(&bob, Method::PATCH, &uri, Some(body)).await;
+            assert_eq!(status, StatusCode::CONFLICT);
+            assert_eq!(body["code"], code);
+        }
+    }
+
+    #[tokio::test]
+    async fn edits_made_to_a_stale_version_conflict() {
+        let app = TestApp::new().await;
+        let ada = app.create_user("ada", "ada@example.com").await;
+        let uri = format!("/api/user/{}", ada["id"].as_str().unwrap());
+
+        let (status, body) = app.as_user(&ada, Method::PATCH, &uri, Some(json!({"user_name": "ada2"}))).await;
+        assert_eq!(status, StatusCode::PRECONDITION_REQUIRED);
+        assert_eq!(body["code"], "USER_VERSION_REQUIRED");
+
+        let (status, edited) = app
+            .as_user(&ada, Method::PATCH, &uri, Some(json!({"user_name": "ada2", "version": 1})))
+            .await;
+        assert_eq!(status, StatusCode::OK, "{}", edited);
+        assert_eq!(edited["data"]["user"]["version"], 2);
+
+        // a second editor still holding version 1
+        let (status, body) = app
+            .as_user(&ada, Method::PATCH, &uri, Some(json!({"user_name": "ada3", "version": 1})))
+            .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["code"], "USER_VERSION_CONFLICT");
+
+        for (etag, expected) in [("\"1\"", StatusCode::CONFLICT), ("\"2\"", StatusCode::OK)] {
+            let request = crate::testing::builder(Method::PATCH, &uri)
+                .header(axum::http::header::AUTHORIZATION, app.bearer(&ada))
+                .header(axum::http::header::IF_MATCH, etag);
+            let (status, _) = app.send(request, Some(json!({"user_name": "ada3"}))).await;
+            assert_eq!(status, expected, "{}", etag);
+        }
+        let (_, user) = app.get(&uri).await;
+        assert_eq!(user["data"]["user"]["user_name"], "ada3");
+        assert_eq!(user["data"]["user"]["version"], 3);
+    }
+
+    #[tokio::test]
+    async fn updates_are_stamped_by_the_app_clock() {
+        let start = Utc.timestamp_opt(1_893_456_000, 0).unwrap();
+        let clock = Arc::new(ManualClock::new(start));
+        let app = TestApp::with(|state| state.clock = clock.clone()).await;
+        let ada = app.create_user("ada", "ada@example.com").await;
+        let uri = format!("/api/user/{}", ada["id"].as_str().unwrap());
+
+        let (_, edited) = app.as_user(&ada, Method::PATCH, &uri, Some(json!({"user_name": "ada2", "version": 1}))).await;
+        assert_eq!(edited["data"]["user"]["updatedAt"], timestamp::format(&start));
+
+        let privacy = json!({"hide_email": true});
+        let (_, settings) = app.as_user(&ada, Method::PATCH, &format!("{}/privacy", uri), Some(privacy.clone())).await;
+        assert_eq!(settings["data"]["updatedAt"], timestamp::format(&start));
+        // the same settings again change nothing, and keep when they last did
+        clock.advance(Duration::from_secs(60));
+        let (_, settings) = app.as_user(&ada, Method::PATCH, &format!("{}/privacy", uri), Some(privacy)).await;
+        assert_eq!(settings["data"]["updatedAt"], timestamp::format(&start));
+    }
+
+    #[tokio::test]
+    async fn users_are_only_themselves_with_their_own_session() {
+        let app = TestApp::new().await;
+        let ada = app.create_user("ada", "ada@example.com").await;
+        let bob = app.create_user("bob", "bob@example.com").await;
+        let uri = format!("/api/user/{}", ada["id"].as_str().unwrap());
+        let hidden = json!({"hide_email": true, "hide_referral_stats": true});
+        let (status, _) = app.as_user(&ada, Method::PATCH, &format!("{}/privacy", uri), Some(hidden)).await;
+        assert_eq!(status, StatusCode::OK);
+
+        // naming someone else in the query no longer makes you them
+        let (_, profile) = app.get(&format!("{}?viewer_id={}", uri, ada["id"].as_str().unwrap())).await;
+        assert!(profile["data"]["user"].get("email").is_none(), "{}", profile);
+        let (_, profile) = app.as_user(&ada, Method::GET, &uri, None).await;
+        assert_eq!(profile["data"]["user"]["email"], "ada@example.com");
+
+        for (method, path, body) in [
+            (Method::GET, format!("{}/privacy", uri), None),
+            (Method::PATCH, format!("{}/privacy", uri), Some(json!({"hide_email": false}))),
+            (Method::PATCH, uri.clone(), Some(json!({"user_name": "bob2", "version": 1}))),
+            (Method::DELETE, uri.clone(), None),
+        ] {
+            let (status, denied) = app.as_user(&bob, method.clone(), &path, body.clone()).await;
+            assert_eq!(status, StatusCode::FORBIDDEN, "{} {}", method, path);
+            assert_eq!(denied["code"], "USER_ACCESS_DENIED");
+            let (status, denied) = app.request(method, &path, body).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+            assert_eq!(denied["code"], "SESSION_REQUIRED");
+        }
+
+        let bearer = |token: &str| {
+            crate::testing::builder(Method::GET, &uri).header(axum::http::header::AUTHORIZATION, format!("Bearer {}", token))
+        };
+        let (status, body) = app.send(bearer("forged"), None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["code"], "SESSION_MALFORMED");
+
+        // the integration signing users in gets them new tokens
+        let sessions = format!("/api/admin/users/{}/sessions", ada["id"].as_str().unwrap());
+        let (status, body) = app.signed(Method::POST, &sessions, None).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let (_, profile) = app.send(bearer(body["data"]["session"]["token"].as_str().unwrap()), None).await;
+        assert_eq!(profile["data"]["user"]["email"], "ada@example.com");
+    }
+
+    #[tokio::test]
+    async fn addressed_events_only_reach_the_signed_in_audience() {
+        let app = TestApp::new().await;
+        let ada = app.create_user("ada", "ada@example.com").await;
+        let bob = app.create_user("bob", "bob@example.com").await;
+        let (_, created) = app.as_user(&ada, Method::POST, "/api/orgs", Some(json!({"name": "Acme"}))).await;
+        let org_id = created["data"]["organization"]["id"].as_str().unwrap().to_string();
+        let (status, _) = app
+            .as_user(&ada, Method::POST, &format!("/api/orgs/{}/members", org_id), Some(json!({"user_id": bob["id"]})))
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let added = |replay: &serde_json::Value| {
+            replay["events"].as_array().unwrap().iter().filter(|event| event["event_type"] == "org_member_added").count()
+        };
+        // naming the admin in the query doesn't make you them
+        let (_, replay) = app.get(&format!("/api/events/stream/replay?since=0&user_id={}", ada["id"].as_str().unwrap())).await;
+        assert_eq!(added(&replay), 0);
+        let (_, replay) = app.as_user(&bob, Method::GET, "/api/events/stream/replay?since=0", None).await;
+        assert_eq!(added(&replay), 0);
+        let (_, replay) = app.as_user(&ada, Method::GET, "/api/events/stream/replay?since=0", None).await;
+        assert_eq!(added(&replay), 1);
+
+        let stream = format!("/api/user-events?org_id={}", org_id);
+        let builder = crate::testing::builder(Method::GET, &stream).header(axum::http::header::USER_AGENT, "tests");
+        let (status, body) = app.send(builder, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["code"], "SESSION_REQUIRED");
+    }
+
+    #[tokio::test]
+    async fn synced_invitations_go_to_admins_without_the_address() {
+        let app = TestApp::new().await;
+        let ada = app.create_user("ada", "ada@example.com").await;
+        let (_, created) = app.as_user(&ada, Method::POST, "/api/orgs", Some(json!({"name": "Acme"}))).await;
+        let org = format!("/api/orgs/{}", created["data"]["organization"]["id"].as_str().unwrap());
+        let (status, invited) = app
+            .as_user(&ada, Method::POST, &format!("{}/invitations", org), Some(json!({"email": "guest@example.com"})))
+            .await;
+        assert_eq!(status, StatusCode::CREATED, "{}", invited);
+
+        let (_, sync) = app.as_user(&ada, Method::GET, "/api/sync?since=0", None).await;
+        assert_eq!(sync["invitations"][0]["id"], invited["data"]["invitation"]["id"]);
+        assert!(!sync.to_string().contains("guest@example.com"), "{}", sync);
+
+        let (_, sync) = app.get(&format!("/api/sync?since=0&user_id={}", ada["id"].as_str().unwrap())).await;
+        assert_eq!(sync["invitations"], json!([]));
+    }
+
+    #[tokio::test]
+    async fn exports_are_for_signed_operators_and_show_the_public_profile() {
+        let app = TestApp::new().await;
+        let ada = app.create_user("ada", "ada@example.com").await;
+
+        for uri in ["/api/admin/users/export", &format!("/api/admin/exports/{}", uuid::Uuid::new_v4())] {
+            let (status, _) = app.as_user(&ada, Method::GET, uri, None).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED, "{}", uri);
+        }
+        let (status, _) = app.as_user(&ada, Method::POST, "/api/admin/users/export", Some(json!({}))).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = app.as_user(&ada, Method::GET, "/api/users/export", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, export) = app.signed(Method::GET, "/api/admin/users/export", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(export[0]["id"], ada["id"]);
+        assert!(!export.to_string().contains("ada@example.com"), "{}", export);
+
+        let (status, queued) = app.signed(Method::POST, "/api/admin/users/export", Some(json!({}))).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        let job = format!("/api/admin/exports/{}", queued["data"]["export"]["id"].as_str().unwrap());
+        let (status, _) = app.signed(Method::GET, &job, None).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+}
@@ -0,0 +1,117 @@
+use std::sync::Arc;
+
+use axum::{extract::State, response::IntoResponse};
+use serde_json::json;
+
+use crate::{
+    analytics,
+    error::AppError,
+    extract::{Json, Path, Query},
+    scheduler,
+    schema::AnalyticsOptions,
+    AppState,
+};
+
+const SERIES: [&str; 3] = ["signups", "invitations", "sse_connections"];
+const MAX_DAYS: i32 = 365;
+
+/// Every chart's series at once, as of the last scheduled refresh.
+pub async fn analytics_handler(
+    opts: Option<Query<AnalyticsOptions>>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let Query(opts) = opts.unwrap_or_default();
+    let days = opts.days.unwrap_or(30).clamp(1, MAX_DAYS);
+
+    let mut series = serde_json::Map::new();
+    for name in SERIES {
+        series.insert(name.to_string(), load_series(&data, name, days).await?);
+    }
+
+    Ok(Json(json!({
+        "status": "success",
+        "refreshed_at": scheduler::refreshed_at(&data.db, &analytics::VIEWS).await?,
+        "days": days,
+        "data": series
+    })))
+}
+
+pub async fn analytics_series_handler(
+    Path(name): Path<String>,
+    opts: Option<Query<AnalyticsOptions>>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let Query(opts) = opts.unwrap_or_default();
+    let days = opts.days.unwrap_or(30).clamp(1, MAX_DAYS);
+    if !SERIES.contains(&name.as_str()) {
+        return Err(AppError::AnalyticsSeriesNotFound(name));
+    }
+
+    Ok(Json(json!({
+        "status": "success",
+        "refreshed_at": scheduler::refreshed_at(&data.db, &analytics::VIEWS).await?,
+        "days": days,
+        "data": { name.as_str(): load_series(&data, &name, days).await? }
+    })))
+}
+
+// oldest point first, the way charts draw them
+async fn load_series(data: &AppState, name: &str, days: i32) -> Result<serde_json::Value, sqlx::Error> {
+    let since = data.clock.now() - chrono::Duration::days(days.into());
+    let points = match name {
+        "signups" => {
+            // the share of signups that came in through a referral
+            let rows = sqlx::query!(
+                r#"SELECT day AS "day!", signups AS "signups!", referred AS "referred!" FROM analytics_signups_daily WHERE day > $1::timestamptz ORDER BY day"#,
+                since
+            )
+            .fetch_all(&data.db)
+            .await?;
+            rows.iter()
+                .map(|row| {
+                    json!({
+                        "day": row.day,
+                        "signups": row.signups,
+                        "referred": row.referred,
+                        "referral_conversion_rate": rate(row.referred, row.signups),
+                    })
+                })
+                .collect()
+        }
+        "invitations" => {
+            let rows = sqlx::query!(
+                r#"SELECT day AS "day!", sent AS "sent!", answered AS "answered!", accepted AS "accepted!" FROM analytics_invitations_daily WHERE day > $1::timestamptz ORDER BY day"#,
+                since
+            )
+            .fetch_all(&data.db)
+            .await?;
+            rows.iter()
+                .map(|row| {
+                    json!({
+                        "day": row.day,
+                        "sent": row.sent,
+                        "answered": row.answered,
+                        "accepted": row.accepted,
+                        "rsvp_rate": rate(row.answered, row.sent),
+                    })
+                })
+                .collect()
+        }
+        _ => {
+            let rows = sqlx::query!(
+                r#"SELECT hour AS "hour!", average AS "average!", peak AS "peak!" FROM analytics_sse_hourly WHERE hour > $1::timestamptz ORDER BY hour"#,
+                since
+            )
+            .fetch_all(&data.db)
+            .await?;
+            rows.iter()
+                .map(|row| json!({"hour": row.hour, "average": row.average, "peak": row.peak}))
+                .collect()
+        }
+    };
+    Ok(serde_json::Value::Array(points))
+}
+
+fn rate(part: i64, total: i64) -> Option<f64> {
+    (total > 0).then(|| part as f64 / total as f64)
+}
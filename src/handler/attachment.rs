@@ -0,0 +1,289 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Bytes,
+    extract::{rejection::BytesRejection, State},
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
+};
+use serde_json::json;
+use tokio::io::AsyncWriteExt;
+use uuid::Uuid;
+
+use crate::{
+    attachments, audit,
+    error::AppError,
+    extract::{Json, Path},
+    ids,
+    model::OrgAttachmentModel,
+    pagination::Pagination,
+    tenant::Tenant,
+    AppState,
+};
+
+/// Attaches a file for the organization's members, a flyer or a map,
+/// uploaded as `multipart/form-data`. Only images and PDFs are taken, told
+/// apart by what's in them, and no bigger than `ATTACHMENT_MAX_BYTES`.
+pub async fn upload_attachment_handler(
+    tenant: Tenant,
+    State(data): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Result<Bytes, BytesRejection>,
+) -> Result<impl IntoResponse, AppError> {
+    tenant.require_admin()?;
+    let max_bytes = data.attachments.max_bytes;
+    let body = body.map_err(|e| match e.status() {
+        StatusCode::PAYLOAD_TOO_LARGE => AppError::AttachmentTooLarge(max_bytes),
+        _ => AppError::UploadInvalid,
+    })?;
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let upload = attachments::file_from_multipart(content_type, &body)?;
+    if upload.data.len() > max_bytes {
+        return Err(AppError::AttachmentTooLarge(max_bytes));
+    }
+    let (content_type, extension) = attachments::sniff(&upload.data).ok_or(AppError::AttachmentTypeUnsupported)?;
+
+    let id = ids::new();
+    let key = format!("attachments/{}/{}.{}", tenant.org_id, id, extension);
+    let stored = async {
+        let mut file = data.storage.create(&key).await?;
+        file.write_all(&upload.data).await?;
+        file.shutdown().await
+    };
+    if let Err(e) = stored.await {
+        println!("🔥 Failed to store attachment {}: {}", key, e);
+        let _ = data.storage.delete(&key).await;
+        return Err(AppError::Unexpected);
+    }
+
+    let now = data.clock.now();
+    let recorded = async {
+        let mut tx = tenant.db(&data).begin().await?;
+        let attachment = sqlx::query_as!(
+            OrgAttachmentModel,
+            "INSERT INTO org_attachments (id, org_id, file_name, content_type, size, storage_key, uploaded_by, created_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING *",
+            id,
+            tenant.org_id,
+            upload.file_name,
+            content_type,
+            upload.data.len() as i64,
+            key,
+            tenant.user_id,
+            now
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        audit::record(
+            &mut *tx,
+            "org.attachment_uploaded",
+            json!({"org_id": tenant.org_id, "attachment_id": attachment.id, "content_type": attachment.content_type, "size": attachment.size, "uploaded_by": tenant.user_id}),
+        )
+        .await?;
+        tx.commit().await?;
+        Ok::<_, sqlx::Error>(attachment)
+    };
+    let attachment = match recorded.await {
+        Ok(attachment) => attachment,
+        Err(e) => {
+            // the file would be left with nothing pointing to it
+            let _ = data.storage.delete(&key).await;
+            return Err(e.into());
+        }
+    };
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({"status": "success","data": json!({ "attachment": attachments::to_json(&data, &attachment, now) })})),
+    ))
+}
+
+/// The organization's attachments, each with a link to download it that
+/// expires after `ATTACHMENT_URL_TTL_SECS`, for any of its members.
+pub async fn attachments_list_handler(
+    tenant: Tenant,
+    pagination: Pagination,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let limit = pagination.limit(50);
+    let offset = pagination.offset(50);
+    let sort = pagination.sort(&["created_at"], "created_at")?;
+
+    let attachments = sqlx::query_as!(
+        OrgAttachmentModel,
+        "SELECT * FROM org_attachments WHERE org_id = $1 ORDER BY CASE WHEN $4 = '-created_at' THEN created_at END DESC, created_at, id LIMIT $2 OFFSET $3",
+        tenant.org_id,
+        limit as i32,
+        offset as i32,
+        sort
+    )
+    .fetch_all(tenant.db(&data))
+    .await?;
+
+    let now = data.clock.now();
+    let listed: Vec<_> = attachments
+        .iter()
+        .map(|attachment| attachments::to_json(&data, attachment, now))
+        .collect();
+    Ok(Json(json!({
+        "status": "success",
+        "results": listed.len(),
+        "attachments": listed,
+        "next_cursor": pagination.next_cursor(50, attachments.len())
+    })))
+}
+
+/// One attachment with a fresh link to download it.
+pub async fn get_attachment_handler(
+    tenant: Tenant,
+    Path((_, attachment_id)): Path<(Uuid, Uuid)>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let attachment = sqlx::query_as!(
+        OrgAttachmentModel,
+        "SELECT * FROM org_attachments WHERE id = $1 AND org_id = $2",
+        attachment_id,
+        tenant.org_id
+    )
+    .fetch_optional(tenant.db(&data))
+    .await?
+    .ok_or(AppError::AttachmentNotFound(attachment_id))?;
+
+    Ok(Json(json!({
+        "status": "success",
+        "data": json!({ "attachment": attachments::to_json(&data, &attachment, data.clock.now()) })
+    })))
+}
+
+/// Removes an attachment along with its file. Links already handed out
+/// stop working.
+pub async fn delete_attachment_handler(
+    tenant: Tenant,
+    Path((_, attachment_id)): Path<(Uuid, Uuid)>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    tenant.require_admin()?;
+    let mut tx = tenant.db(&data).begin().await?;
+
+    let attachment = sqlx::query_as!(
+        OrgAttachmentModel,
+        "DELETE FROM org_attachments WHERE id = $1 AND org_id = $2 RETURNING *",
+        attachment_id,
+        tenant.org_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(AppError::AttachmentNotFound(attachment_id))?;
+
+    audit::record(
+        &mut *tx,
+        "org.attachment_deleted",
+        json!({"org_id": tenant.org_id, "attachment_id": attachment.id, "deleted_by": tenant.user_id}),
+    )
+    .await?;
+    tx.commit().await?;
+
+    if let Err(e) = data.storage.delete(&attachment.storage_key).await {
+        println!("🔥 Failed to delete attachment {}: {}", attachment.storage_key, e);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use axum::http::{header, Method, StatusCode};
+    use chrono::{TimeZone, Utc};
+    use serde_json::{json, Value};
+
+    use crate::{clock::ManualClock, testing::{self, TestApp}};
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\nnot much of a picture";
+
+    async fn upload(app: &TestApp, user: &Value, uri: &str, file_name: &str, file: &[u8]) -> (StatusCode, Value) {
+        let mut body = format!(
+            "--b0undary\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\nContent-Type: image/png\r\n\r\n",
+            file_name
+        )
+        .into_bytes();
+        body.extend_from_slice(file);
+        body.extend_from_slice(b"\r\n--b0undary--\r\n");
+        let builder = testing::builder(Method::POST, uri).header(header::AUTHORIZATION, app.bearer(user));
+        app.send_bytes(builder, "multipart/form-data; boundary=b0undary", body).await
+    }
+
+    // the path and query of a download link, to follow it through the app
+    fn link(attachment: &Value) -> String {
+        let url = attachment["download"]["url"].as_str().unwrap();
+        url[url.find("/api/storage/").unwrap()..].to_string()
+    }
+
+    #[tokio::test]
+    async fn members_download_attachments_by_links_that_expire() {
+        let clock = Arc::new(ManualClock::new(Utc.timestamp_opt(1_893_456_000, 0).unwrap()));
+        let app = TestApp::with(|state| {
+            state.clock = clock.clone();
+            state.attachments.max_bytes = 1024;
+        })
+        .await;
+        let ada = app.create_user("ada", "ada@example.com").await;
+        let (_, created) = app.as_user(&ada, Method::POST, "/api/orgs", Some(json!({"name": "Acme"}))).await;
+        let org = format!("/api/orgs/{}", created["data"]["organization"]["id"].as_str().unwrap());
+        let attachments = format!("{}/attachments", org);
+
+        let (status, body) = upload(&app, &ada, &attachments, "flyer.png", PNG).await;
+        assert_eq!(status, StatusCode::CREATED, "{}", body);
+        let flyer = &body["data"]["attachment"];
+        assert_eq!((&flyer["file_name"], &flyer["content_type"]), (&json!("flyer.png"), &json!("image/png")));
+        assert!(flyer.get("storage_key").is_none());
+
+        // what an upload says it is doesn't matter, nor does the name
+        let (status, body) = upload(&app, &ada, &attachments, "map.png", b"<svg onload=alert(1)>").await;
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(body["code"], "ATTACHMENT_TYPE_UNSUPPORTED");
+        for size in [1025, 20_000] {
+            let (status, body) = upload(&app, &ada, &attachments, "big.png", &[PNG, &vec![0; size]].concat()).await;
+            assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE, "{} bytes", size);
+            assert_eq!(body["code"], "ATTACHMENT_TOO_LARGE");
+        }
+        let (status, body) = app.as_user(&ada, Method::POST, &attachments, Some(json!({"file": "flyer.png"}))).await;
+        assert_eq!((status, &body["code"]), (StatusCode::BAD_REQUEST, &json!("UPLOAD_INVALID")));
+
+        // members see them, only admins add them, strangers neither
+        let carl = app.create_user("carl", "carl@example.com").await;
+        let body = json!({"user_id": carl["id"], "role": "member"});
+        app.as_user(&ada, Method::POST, &format!("{}/members", org), Some(body)).await;
+        let (status, listed) = app.as_user(&carl, Method::GET, &attachments, None).await;
+        assert_eq!(status, StatusCode::OK, "{}", listed);
+        assert_eq!(listed["results"], 1);
+        let (_, body) = upload(&app, &carl, &attachments, "flyer.png", PNG).await;
+        assert_eq!(body["code"], "ORG_ADMIN_REQUIRED");
+        let mallory = app.create_user("mallory", "mallory@example.com").await;
+        let (status, _) = app.as_user(&mallory, Method::GET, &attachments, None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let download = link(&listed["attachments"][0]);
+        let response = app.respond(testing::builder(Method::GET, &download), None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "image/png");
+        assert_eq!(&hyper::body::to_bytes(response.into_body()).await.unwrap()[..], PNG);
+
+        clock.advance(Duration::from_secs(901));
+        let (status, body) = app.get(&download).await;
+        assert_eq!((status, &body["code"]), (StatusCode::FORBIDDEN, &json!("DOWNLOAD_LINK_INVALID")));
+
+        let one = format!("{}/{}", attachments, flyer["id"].as_str().unwrap());
+        let (_, fresh) = app.as_user(&carl, Method::GET, &one, None).await;
+        let download = link(&fresh["data"]["attachment"]);
+        let (status, _) = app.as_user(&carl, Method::DELETE, &one, None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = app.as_user(&ada, Method::DELETE, &one, None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, body) = app.get(&download).await;
+        assert_eq!((status, &body["code"]), (StatusCode::NOT_FOUND, &json!("DOWNLOAD_NOT_FOUND")));
+    }
+}
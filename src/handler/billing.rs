@@ -0,0 +1,127 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::HeaderMap,
+    response::IntoResponse,
+};
+use serde_json::json;
+use uuid::Uuid;
+
+use super::org::notify_org_admins;
+use crate::{audit, error::AppError, extract::Json, model::OrganizationModel, AppState};
+
+/// Receives Stripe webhook events and keeps each organization's plan and
+/// subscription status in sync with Stripe.
+pub async fn stripe_webhook_handler(
+    State(data): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<impl IntoResponse, AppError> {
+    if !data.billing.is_configured() {
+        return Err(AppError::BillingNotConfigured);
+    }
+
+    let signature = headers
+        .get("stripe-signature")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+
+    data.billing
+        .verify_signature(signature, &body, data.clock.now().timestamp())
+        .map_err(AppError::WebhookSignature)?;
+
+    let Ok(event) = serde_json::from_slice::<serde_json::Value>(&body) else {
+        return Err(AppError::WebhookBodyInvalid);
+    };
+
+    let event_id = event["id"].as_str().unwrap_or_default();
+    let event_type = event["type"].as_str().unwrap_or_default();
+    let object = &event["data"]["object"];
+
+    let mut tx = data.db.begin().await?;
+
+    let first_delivery = sqlx::query_scalar!(
+        "INSERT INTO stripe_events (id, event_type) VALUES ($1, $2) ON CONFLICT DO NOTHING RETURNING id",
+        event_id,
+        event_type
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    if first_delivery.is_none() {
+        return Ok(Json(json!({"status": "success", "received": true, "duplicate": true})));
+    }
+
+    let org_id = object["metadata"]["org_id"]
+        .as_str()
+        .or(object["client_reference_id"].as_str())
+        .and_then(|id| id.parse::<Uuid>().ok());
+    let customer = object["customer"].as_str();
+
+    let updated = match event_type {
+        "checkout.session.completed" => sqlx::query_as!(
+            OrganizationModel,
+            "UPDATE organizations SET stripe_customer_id = COALESCE($1, stripe_customer_id), stripe_subscription_id = COALESCE($2, stripe_subscription_id), updated_at = $4 WHERE id = $3 RETURNING *",
+            customer,
+            object["subscription"].as_str(),
+            org_id,
+            data.clock.now()
+        )
+        .fetch_optional(&mut *tx)
+        .await?,
+        "customer.subscription.created" | "customer.subscription.updated" | "customer.subscription.deleted" => {
+            let deleted = event_type == "customer.subscription.deleted";
+            let status = if deleted {
+                Some("canceled")
+            } else {
+                object["status"].as_str()
+            };
+            let price_id = object["items"]["data"][0]["price"]["id"].as_str();
+
+            // unknown prices keep the organization on the plan it already has
+            sqlx::query_as!(
+                OrganizationModel,
+                "UPDATE organizations SET plan_id = CASE WHEN $1 THEN 'free' ELSE COALESCE((SELECT id FROM plans WHERE stripe_price_id = $2), plan_id) END, subscription_status = $3, stripe_subscription_id = $4, stripe_customer_id = COALESCE(stripe_customer_id, $5), updated_at = $7 WHERE stripe_customer_id = $5 OR id = $6 RETURNING *",
+                deleted,
+                price_id,
+                status,
+                object["id"].as_str(),
+                customer,
+                org_id,
+                data.clock.now()
+            )
+            .fetch_optional(&mut *tx)
+            .await?
+        }
+        _ => None,
+    };
+
+    if let Some(org) = &updated {
+        audit::record(
+            &mut *tx,
+            "billing.subscription_updated",
+            json!({
+                "org_id": org.id,
+                "stripe_event_id": event_id,
+                "event_type": event_type,
+                "plan_id": org.plan_id,
+                "subscription_status": org.subscription_status
+            }),
+        )
+        .await?;
+
+        notify_org_admins(
+            &mut tx,
+            org.id,
+            "org_subscription_updated",
+            json!({"plan_id": org.plan_id, "subscription_status": org.subscription_status}),
+        )
+        .await?;
+    }
+    tx.commit().await?;
+
+    // Stripe only needs a 2xx, events we don't handle are acknowledged too
+    Ok(Json(json!({"status": "success", "received": true})))
+}
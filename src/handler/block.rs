@@ -0,0 +1,104 @@
+use std::sync::Arc;
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::{
+    audit,
+    error::AppError,
+    extract::{Json, Path},
+    model::BlockModel,
+    schema::BlockUserSchema,
+    session::Caller,
+    user_ref::UserRef,
+    AppState,
+};
+
+/// The users a user blocked, only shown to them.
+pub async fn blocks_list_handler(
+    UserRef(id): UserRef,
+    caller: Caller,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    caller.require(id)?;
+    let blocks = sqlx::query_as!(
+        BlockModel,
+        "SELECT * FROM blocks WHERE blocker_id = $1 ORDER BY created_at DESC, blocked_id",
+        id
+    )
+    .fetch_all(&data.db)
+    .await?;
+
+    Ok(Json(json!({
+        "status": "success",
+        "results": blocks.len(),
+        "blocks": blocks
+    })))
+}
+
+/// Blocks a user: they can no longer invite this user or see their profile,
+/// and drop out of this user's referral views. Blocking twice is a no-op.
+/// Only the user themselves can block someone.
+pub async fn block_user_handler(
+    UserRef(id): UserRef,
+    caller: Caller,
+    State(data): State<Arc<AppState>>,
+    Json(body): Json<BlockUserSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    caller.require(id)?;
+    if body.user_id == id {
+        return Err(AppError::BlockSelf);
+    }
+    let mut tx = data.db.begin().await?;
+
+    let query_result = sqlx::query_as!(
+        BlockModel,
+        "INSERT INTO blocks (blocker_id, blocked_id) VALUES ($1, $2) ON CONFLICT (blocker_id, blocked_id) DO UPDATE SET blocker_id = EXCLUDED.blocker_id RETURNING *",
+        id,
+        body.user_id
+    )
+    .fetch_one(&mut *tx)
+    .await;
+
+    let block = match query_result {
+        Ok(block) => block,
+        Err(e) if e.as_database_error().is_some_and(|e| e.is_foreign_key_violation()) => {
+            let missing = match sqlx::query_scalar!("SELECT id FROM users WHERE id = $1", id).fetch_optional(&data.db).await? {
+                Some(_) => body.user_id,
+                None => id,
+            };
+            return Err(AppError::UserNotFound(missing.to_string()));
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    audit::record(&mut *tx, "user.blocked", json!({"user_id": id, "blocked_id": body.user_id})).await?;
+    tx.commit().await?;
+
+    Ok((StatusCode::CREATED, Json(json!({"status": "success","data": json!({ "block": block })}))))
+}
+
+pub async fn unblock_user_handler(
+    UserRef(id): UserRef,
+    Path((_, blocked_id)): Path<(String, Uuid)>,
+    caller: Caller,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    caller.require(id)?;
+    let mut tx = data.db.begin().await?;
+
+    sqlx::query!(
+        "DELETE FROM blocks WHERE blocker_id = $1 AND blocked_id = $2 RETURNING blocked_id",
+        id,
+        blocked_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(AppError::BlockNotFound(blocked_id))?;
+
+    audit::record(&mut *tx, "user.unblocked", json!({"user_id": id, "blocked_id": blocked_id})).await?;
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
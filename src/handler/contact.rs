@@ -0,0 +1,323 @@
+use std::sync::Arc;
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::{
+    error::{unique_violation, AppError},
+    extract::{Json, Path, Query, Sanitized},
+    model::ContactModel,
+    pagination::Pagination,
+    pii,
+    schema::{ContactFilterOptions, CreateContactSchema, UpdateContactSchema},
+    session::Caller,
+    user_ref::UserRef,
+    AppState,
+};
+
+pub async fn contacts_list_handler(
+    UserRef(owner_id): UserRef,
+    caller: Caller,
+    pagination: Pagination,
+    opts: Option<Query<ContactFilterOptions>>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let Query(opts) = opts.unwrap_or_default();
+    caller.require(owner_id)?;
+
+    let limit = pagination.limit(10);
+    let offset = pagination.offset(10);
+    let sort = pagination.sort(&["name", "created_at"], "name")?;
+
+    let contacts = sqlx::query_as!(
+        ContactModel,
+        "SELECT * FROM contacts WHERE owner_id = $1 AND org_id IS NULL AND ($2::text IS NULL OR $2 = ANY(tags)) ORDER BY CASE WHEN $5 = '-name' THEN name END DESC, CASE WHEN $5 = 'created_at' THEN created_at END, CASE WHEN $5 = '-created_at' THEN created_at END DESC, name, id LIMIT $3 OFFSET $4",
+        owner_id,
+        opts.tag,
+        limit as i32,
+        offset as i32,
+        sort
+    )
+    .fetch_all(&data.db)
+    .await?;
+
+    Ok(Json(json!({
+        "status": "success",
+        "results": contacts.len(),
+        "contacts": contacts,
+        "next_cursor": pagination.next_cursor(10, contacts.len())
+    })))
+}
+
+pub async fn create_contact_handler(
+    UserRef(owner_id): UserRef,
+    caller: Caller,
+    State(data): State<Arc<AppState>>,
+    Sanitized(body): Sanitized<CreateContactSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    caller.require(owner_id)?;
+
+    let contact = insert_contact(&data, owner_id, None, &body)
+        .await?
+        .ok_or_else(|| AppError::ContactEmailTaken(body.email.clone()))?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({"status": "success","data": json!({ "contact": contact })})),
+    ))
+}
+
+pub async fn get_contact_handler(
+    UserRef(owner_id): UserRef,
+    caller: Caller,
+    Path((_, contact_id)): Path<(String, Uuid)>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    caller.require(owner_id)?;
+    let contact = sqlx::query_as!(
+        ContactModel,
+        "SELECT * FROM contacts WHERE id = $1 AND owner_id = $2 AND org_id IS NULL",
+        contact_id,
+        owner_id
+    )
+    .fetch_optional(&data.db)
+    .await?
+    .ok_or(AppError::ContactNotFound(contact_id))?;
+
+    Ok(Json(json!({"status": "success","data": json!({ "contact": contact })})))
+}
+
+pub async fn edit_contact_handler(
+    UserRef(owner_id): UserRef,
+    caller: Caller,
+    Path((_, contact_id)): Path<(String, Uuid)>,
+    State(data): State<Arc<AppState>>,
+    Sanitized(body): Sanitized<UpdateContactSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    caller.require(owner_id)?;
+    let email = body.email.as_deref().map(str::trim);
+    let email_index = email.map(pii::blind_index);
+
+    // a changed email is re-matched against registered users
+    let query_result = sqlx::query_as!(
+        ContactModel,
+        "UPDATE contacts SET name = COALESCE($1, name), email = COALESCE($2, email), email_index = COALESCE($3, email_index), phone = COALESCE($4, phone), tags = COALESCE($5, tags), user_id = CASE WHEN $3::text IS NULL THEN user_id ELSE (SELECT id FROM users WHERE email_index = $3 LIMIT 1) END, updated_at = $8 WHERE id = $6 AND owner_id = $7 AND org_id IS NULL RETURNING *",
+        body.name,
+        pii::seal_opt(email),
+        email_index,
+        pii::seal_opt(body.phone.as_deref()),
+        body.tags.as_deref(),
+        contact_id,
+        owner_id,
+        data.clock.now()
+    )
+    .fetch_optional(&data.db)
+    .await;
+
+    match query_result {
+        Ok(Some(contact)) => Ok(Json(json!({"status": "success","data": json!({ "contact": contact })}))),
+        Ok(None) => Err(AppError::ContactNotFound(contact_id)),
+        Err(e) if unique_violation(&e).is_some() => {
+            Err(AppError::ContactEmailTaken(email.unwrap_or_default().to_string()))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub async fn delete_contact_handler(
+    UserRef(owner_id): UserRef,
+    caller: Caller,
+    Path((_, contact_id)): Path<(String, Uuid)>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    caller.require(owner_id)?;
+    let rows_affected = sqlx::query!(
+        "DELETE FROM contacts WHERE id = $1 AND owner_id = $2 AND org_id IS NULL",
+        contact_id,
+        owner_id
+    )
+    .execute(&data.db)
+    .await?
+    .rows_affected();
+
+    if rows_affected == 0 {
+        return Err(AppError::ContactNotFound(contact_id));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Imports contacts from a CSV body with a `name,email,phone,tags` header,
+/// where tags are separated by `;`. Rows whose email is already in the
+/// address book are skipped.
+pub async fn import_contacts_handler(
+    UserRef(owner_id): UserRef,
+    caller: Caller,
+    State(data): State<Arc<AppState>>,
+    body: String,
+) -> Result<impl IntoResponse, AppError> {
+    caller.require(owner_id)?;
+
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .from_reader(body.as_bytes());
+
+    let headers = match reader.headers() {
+        Ok(headers) => headers.clone(),
+        Err(e) => return Err(AppError::CsvInvalid(e.to_string())),
+    };
+    let column = |name: &str| headers.iter().position(|h| h.eq_ignore_ascii_case(name));
+    let (Some(name_col), Some(email_col)) = (column("name"), column("email")) else {
+        return Err(AppError::CsvMissingColumns);
+    };
+    let phone_col = column("phone");
+    let tags_col = column("tags");
+
+    let mut results = Vec::new();
+    let (mut created, mut duplicates, mut invalid) = (0, 0, 0);
+
+    // line 1 is the header
+    for (line, record) in (2..).zip(reader.records()) {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                invalid += 1;
+                results.push(json!({"line": line, "status": "invalid", "message": e.to_string()}));
+                continue;
+            }
+        };
+        let field = |col: Option<usize>| {
+            col.and_then(|c| record.get(c))
+                .filter(|v| !v.is_empty())
+                .map(str::to_string)
+        };
+
+        let (Some(name), Some(email)) = (field(Some(name_col)), field(Some(email_col))) else {
+            invalid += 1;
+            results.push(json!({"line": line, "status": "invalid", "message": "name and email are required"}));
+            continue;
+        };
+        if !email.contains('@') {
+            invalid += 1;
+            results.push(json!({"line": line, "status": "invalid", "message": format!("{} is not an email", email)}));
+            continue;
+        }
+
+        let contact = CreateContactSchema {
+            name,
+            email,
+            phone: field(phone_col),
+            tags: field(tags_col)
+                .map(|tags| {
+                    tags.split(';')
+                        .map(str::trim)
+                        .filter(|t| !t.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or_default(),
+        };
+
+        match insert_contact(&data, owner_id, None, &contact).await? {
+            Some(inserted) => {
+                created += 1;
+                results.push(json!({"line": line, "status": "created", "id": inserted.id}));
+            }
+            None => {
+                duplicates += 1;
+                results.push(json!({"line": line, "status": "duplicate", "email": contact.email}));
+            }
+        }
+    }
+
+    Ok(Json(json!({
+        "status": "success",
+        "created": created,
+        "duplicates": duplicates,
+        "invalid": invalid,
+        "results": results
+    })))
+}
+
+/// Adds a contact to the owner's personal address book, or to the
+/// organization's when `org_id` is given. Returns `None` when that address
+/// book already has a contact with this email.
+pub(crate) async fn insert_contact(
+    data: &AppState,
+    owner_id: Uuid,
+    org_id: Option<Uuid>,
+    body: &CreateContactSchema,
+) -> Result<Option<ContactModel>, sqlx::Error> {
+    let email = body.email.trim();
+
+    match org_id {
+        Some(org_id) => {
+            sqlx::query_as!(
+                ContactModel,
+                "INSERT INTO contacts (owner_id, org_id, name, email, email_index, phone, tags, user_id) VALUES ($1, $2, $3, $4, $5::text, $6, $7, (SELECT id FROM users WHERE email_index = $5 LIMIT 1)) ON CONFLICT (org_id, email_index) WHERE org_id IS NOT NULL DO NOTHING RETURNING *",
+                owner_id,
+                org_id,
+                body.name,
+                pii::seal(email),
+                pii::blind_index(email),
+                pii::seal_opt(body.phone.as_deref()),
+                &body.tags
+            )
+            .fetch_optional(data.shards.pool(org_id))
+            .await
+        }
+        None => {
+            sqlx::query_as!(
+                ContactModel,
+                "INSERT INTO contacts (owner_id, name, email, email_index, phone, tags, user_id) VALUES ($1, $2, $3, $4::text, $5, $6, (SELECT id FROM users WHERE email_index = $4 LIMIT 1)) ON CONFLICT (owner_id, email_index) WHERE org_id IS NULL DO NOTHING RETURNING *",
+                owner_id,
+                body.name,
+                pii::seal(email),
+                pii::blind_index(email),
+                pii::seal_opt(body.phone.as_deref()),
+                &body.tags
+            )
+            .fetch_optional(&data.db)
+            .await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+    use serde_json::json;
+
+    use crate::testing::TestApp;
+
+    #[tokio::test]
+    async fn address_books_are_only_open_to_their_owner() {
+        let app = TestApp::new().await;
+        let ada = app.create_user("ada", "ada@example.com").await;
+        let bob = app.create_user("bob", "bob@example.com").await;
+        let contacts = format!("/api/user/{}/contacts", ada["id"].as_str().unwrap());
+
+        let body = json!({"name": "Carl", "email": "carl@example.com"});
+        let (status, created) = app.as_user(&ada, Method::POST, &contacts, Some(body.clone())).await;
+        assert_eq!(status, StatusCode::CREATED, "{}", created);
+        let contact = format!("{}/{}", contacts, created["data"]["contact"]["id"].as_str().unwrap());
+
+        for (method, uri, body) in [
+            (Method::GET, &contacts, None),
+            (Method::POST, &contacts, Some(body)),
+            (Method::GET, &contact, None),
+            (Method::PATCH, &contact, Some(json!({"name": "Carla"}))),
+            (Method::DELETE, &contact, None),
+        ] {
+            let (status, _) = app.as_user(&bob, method.clone(), uri, body.clone()).await;
+            assert_eq!(status, StatusCode::FORBIDDEN, "{} {}", method, uri);
+            let (status, _) = app.request(method, uri, body).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+        }
+
+        let (_, list) = app.as_user(&ada, Method::GET, &contacts, None).await;
+        assert_eq!(list["results"], 1);
+    }
+}
@@ -0,0 +1,259 @@
+use std::sync::Arc;
+
+use axum::{body::Bytes, extract::State, http::HeaderMap, http::StatusCode, response::IntoResponse};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::{
+    audit, email_domain, email_log,
+    error::{unique_violation, AppError},
+    extract::{Json, Path, Query},
+    model::{EmailDomainRuleModel, EmailSuppressionModel},
+    pagination::Pagination,
+    pii,
+    schema::{CreateEmailDomainRuleSchema, EmailDomainRuleOptions, UnsubscribeOptions},
+    suppression, AppState,
+};
+
+/// What an unsubscribe link is for. Doesn't unsubscribe, link scanners and
+/// prefetching mail clients open links on their own.
+pub async fn unsubscribe_info_handler(
+    Query(opts): Query<UnsubscribeOptions>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let email = data
+        .suppressions
+        .verify_unsubscribe(&data.db, &opts.token)
+        .await?
+        .ok_or(AppError::UnsubscribeTokenInvalid)?;
+    let suppressed = suppression::is_suppressed(&data.db, &email).await?;
+
+    Ok(Json(json!({"status": "success","data": json!({ "email": email, "suppressed": suppressed })})))
+}
+
+/// One-click unsubscribe (RFC 8058). Mail clients POST
+/// `List-Unsubscribe=One-Click` to the link, the body isn't needed.
+pub async fn unsubscribe_handler(
+    Query(opts): Query<UnsubscribeOptions>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let email = data
+        .suppressions
+        .verify_unsubscribe(&data.db, &opts.token)
+        .await?
+        .ok_or(AppError::UnsubscribeTokenInvalid)?;
+
+    let mut tx = data.db.begin().await?;
+    if suppression::suppress(&mut *tx, &email, "unsubscribed", None).await? {
+        audit::record(
+            &mut *tx,
+            "email.suppressed",
+            json!({"email_index": pii::blind_index(&email), "reason": "unsubscribed"}),
+        )
+        .await?;
+    }
+    tx.commit().await?;
+
+    Ok(Json(json!({"status": "success","data": json!({ "email": email, "suppressed": true })})))
+}
+
+/// Delivery notifications from the email provider, one event or a list of
+/// them, each `{"email", "event", "message_id"?, "bounce_type"?, "reason"?}`.
+/// Events naming a tracked email move it along in the email log. Hard
+/// bounces, complaints and unsubscribes done at the provider also suppress
+/// the address. Everything else is acknowledged and ignored.
+pub async fn email_webhook_handler(
+    State(data): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<impl IntoResponse, AppError> {
+    if !data.suppressions.webhook_configured() {
+        return Err(AppError::EmailWebhookNotConfigured);
+    }
+
+    let signature = headers
+        .get("x-webhook-signature")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    if !data.suppressions.verify_webhook(signature, &body) {
+        return Err(AppError::EmailWebhookSignature);
+    }
+
+    let events = match serde_json::from_slice::<serde_json::Value>(&body) {
+        Ok(serde_json::Value::Array(events)) => events,
+        Ok(event @ serde_json::Value::Object(_)) => vec![event],
+        _ => return Err(AppError::WebhookBodyInvalid),
+    };
+
+    let mut tx = data.db.begin().await?;
+    let mut tracked = 0;
+    let mut suppressed = 0;
+    for event in &events {
+        let kind = event["event"].as_str().unwrap_or_default();
+        // soft bounces are a full inbox or a server that's down, worth retrying
+        let hard_bounce = matches!(kind, "bounce" | "bounced") && event["bounce_type"] != "soft";
+
+        let message_id = event["message_id"].as_str().and_then(|id| id.parse::<Uuid>().ok());
+        let status = match kind {
+            "processed" | "sent" => Some("sent"),
+            "delivered" | "delivery" => Some("delivered"),
+            "open" | "opened" => Some("opened"),
+            _ if hard_bounce => Some("bounced"),
+            _ => None,
+        };
+        if let (Some(message_id), Some(status)) = (message_id, status) {
+            if email_log::record(&mut *tx, message_id, status, event["reason"].as_str(), data.clock.now()).await? {
+                tracked += 1;
+            }
+        }
+
+        let Some(email) = event["email"].as_str() else {
+            continue;
+        };
+        let reason = match kind {
+            _ if hard_bounce => "bounced",
+            "complaint" | "spamreport" => "complained",
+            "unsubscribe" => "unsubscribed",
+            _ => continue,
+        };
+        if suppression::suppress(&mut *tx, email, reason, event["reason"].as_str()).await? {
+            audit::record(
+                &mut *tx,
+                "email.suppressed",
+                json!({"email_index": pii::blind_index(email), "reason": reason, "via": "webhook"}),
+            )
+            .await?;
+            suppressed += 1;
+        }
+    }
+    tx.commit().await?;
+
+    Ok(Json(json!({"status": "success", "received": events.len(), "tracked": tracked, "suppressed": suppressed})))
+}
+
+pub async fn suppressions_list_handler(
+    pagination: Pagination,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let limit = pagination.limit(10);
+    let offset = pagination.offset(10);
+    // addresses are sealed, so there's no ordering by them
+    let sort = pagination.sort(&["created_at"], "-created_at")?;
+
+    let suppressions = sqlx::query_as!(
+        EmailSuppressionModel,
+        "SELECT * FROM email_suppressions ORDER BY CASE WHEN $3 = 'created_at' THEN created_at END, created_at DESC, email_index LIMIT $1 OFFSET $2",
+        limit as i32,
+        offset as i32,
+        sort
+    )
+    .fetch_all(&data.db)
+    .await?;
+
+    Ok(Json(json!({
+        "status": "success",
+        "results": suppressions.len(),
+        "suppressions": suppressions,
+        "next_cursor": pagination.next_cursor(10, suppressions.len())
+    })))
+}
+
+/// Lets email reach an address again, e.g. after a bounce was fixed. Goes
+/// by the suppression's id, addresses stay out of request paths and the
+/// logs that keep them.
+pub async fn delete_suppression_handler(
+    Path(id): Path<Uuid>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let mut tx = data.db.begin().await?;
+
+    let removed = sqlx::query_as!(
+        EmailSuppressionModel,
+        "DELETE FROM email_suppressions WHERE id = $1 RETURNING *",
+        id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(AppError::SuppressionNotFound(id))?;
+
+    audit::record(
+        &mut *tx,
+        "email.unsuppressed",
+        json!({"suppression": removed.id, "email_index": removed.email_index, "reason": removed.reason}),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn email_domain_rules_list_handler(
+    opts: Option<Query<EmailDomainRuleOptions>>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let Query(opts) = opts.unwrap_or_default();
+
+    let rules = sqlx::query_as!(
+        EmailDomainRuleModel,
+        "SELECT * FROM email_domain_rules WHERE org_id IS NOT DISTINCT FROM $1 ORDER by domain, rule",
+        opts.org_id
+    )
+    .fetch_all(&data.db)
+    .await?;
+
+    Ok(Json(json!({
+        "status": "success",
+        "results": rules.len(),
+        "rules": rules
+    })))
+}
+
+/// Adds a rule, in effect from the next signup or invitation on.
+pub async fn create_email_domain_rule_handler(
+    State(data): State<Arc<AppState>>,
+    Json(body): Json<CreateEmailDomainRuleSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    let domain = email_domain::normalize(&body.domain);
+    let mut tx = data.db.begin().await?;
+
+    let query_result = sqlx::query_as!(
+        EmailDomainRuleModel,
+        "INSERT INTO email_domain_rules (domain, rule, org_id) VALUES ($1, $2, $3) RETURNING *",
+        domain,
+        body.rule.as_str(),
+        body.org_id
+    )
+    .fetch_one(&mut *tx)
+    .await;
+
+    let rule = match query_result {
+        Ok(rule) => rule,
+        Err(e) if unique_violation(&e).is_some() => return Err(AppError::EmailDomainRuleExists(domain)),
+        Err(e) if e.as_database_error().is_some_and(|e| e.is_foreign_key_violation()) => {
+            return Err(AppError::OrgNotFound(body.org_id.unwrap_or_default()))
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    audit::record(&mut *tx, "email.domain_rule_added", json!(rule)).await?;
+    tx.commit().await?;
+
+    Ok((StatusCode::CREATED, Json(json!({"status": "success","data": json!({ "rule": rule })}))))
+}
+
+pub async fn delete_email_domain_rule_handler(
+    Path(id): Path<Uuid>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let mut tx = data.db.begin().await?;
+
+    let removed = sqlx::query_as!(EmailDomainRuleModel, "DELETE FROM email_domain_rules WHERE id = $1 RETURNING *", id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(AppError::EmailDomainRuleNotFound(id))?;
+
+    audit::record(&mut *tx, "email.domain_rule_removed", json!(removed)).await?;
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,81 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::IntoResponse,
+};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::{
+    error::AppError,
+    exports,
+    extract::{Json, Path, Query},
+    model::ExportJobModel,
+    schema::{ExportUsersSchema, SignedLinkOptions},
+    stream, AppState,
+};
+
+/// Queues an export of the users, written to storage in the background
+/// and polled for at the `Location` it answers with. Operators only, the
+/// users are exported as anyone would see them.
+pub async fn create_export_handler(
+    State(data): State<Arc<AppState>>,
+    Json(body): Json<ExportUsersSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    let job = exports::enqueue(&data, &body).await?;
+
+    Ok((
+        StatusCode::ACCEPTED,
+        [(header::LOCATION, format!("/api/admin/exports/{}", job.id))],
+        Json(json!({"status": "success", "data": {"export": exports::to_json(&data, &job, data.clock.now())}})),
+    ))
+}
+
+/// How far along an export is, and once it's done, where to download it.
+pub async fn export_handler(
+    Path(id): Path<Uuid>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let job = sqlx::query_as!(ExportJobModel, "SELECT * FROM export_jobs WHERE id = $1", id)
+        .fetch_optional(&data.db)
+        .await?
+        .ok_or(AppError::ExportNotFound(id))?;
+
+    Ok(Json(
+        json!({"status": "success", "data": {"export": exports::to_json(&data, &job, data.clock.now())}}),
+    ))
+}
+
+/// A file from storage, for whoever has a link signed for it.
+pub async fn storage_download_handler(
+    Path(key): Path<String>,
+    Query(link): Query<SignedLinkOptions>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let reader = data
+        .storage
+        .open_signed(&key, link.expires, &link.signature, data.clock.now())
+        .await?;
+
+    let file_name = key.rsplit('/').next().unwrap_or(&key).to_string();
+    let content_type = match file_name.rsplit_once('.') {
+        Some((_, "json")) => "application/json",
+        Some((_, "ndjson")) => "application/x-ndjson",
+        // attachments, stored with the extension of what they were found to be
+        Some((_, "png")) => "image/png",
+        Some((_, "jpg")) => "image/jpeg",
+        Some((_, "gif")) => "image/gif",
+        Some((_, "webp")) => "image/webp",
+        Some((_, "pdf")) => "application/pdf",
+        _ => "application/octet-stream",
+    };
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", file_name)),
+        ],
+        stream::read(reader),
+    ))
+}
@@ -0,0 +1,87 @@
+use std::sync::Arc;
+
+use axum::{extract::State, response::IntoResponse};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::{
+    audit,
+    error::AppError,
+    extract::{Json, Path, Query},
+    fraud,
+    model::FraudFlagModel,
+    pagination::Pagination,
+    schema::{FraudFlagOptions, ReviewDecision, ReviewFraudFlagSchema},
+    AppState,
+};
+
+/// The review queue: flagged signups, oldest first so nothing waits forever.
+pub async fn fraud_flags_list_handler(
+    pagination: Pagination,
+    opts: Option<Query<FraudFlagOptions>>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let Query(opts) = opts.unwrap_or_default();
+
+    let limit = pagination.limit(10);
+    let offset = pagination.offset(10);
+    let sort = pagination.sort(&["created_at"], "created_at")?;
+    let status = opts.status.as_deref().unwrap_or("pending");
+
+    let flags = sqlx::query_as!(
+        FraudFlagModel,
+        "SELECT * FROM fraud_flags WHERE status = $1 ORDER BY CASE WHEN $4 = '-created_at' THEN created_at END DESC, created_at, id LIMIT $2 OFFSET $3",
+        status,
+        limit as i32,
+        offset as i32,
+        sort
+    )
+    .fetch_all(&data.db)
+    .await?;
+
+    Ok(Json(json!({
+        "status": "success",
+        "results": flags.len(),
+        "flags": flags,
+        "next_cursor": pagination.next_cursor(10, flags.len())
+    })))
+}
+
+/// Settles a flagged signup. Approving it credits the referrer the point
+/// that was held back, rejecting it means they never get it.
+pub async fn review_fraud_flag_handler(
+    Path(id): Path<Uuid>,
+    State(data): State<Arc<AppState>>,
+    Json(body): Json<ReviewFraudFlagSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    let status = match body.decision {
+        ReviewDecision::Approve => "approved",
+        ReviewDecision::Reject => "rejected",
+    };
+    let mut tx = data.db.begin().await?;
+
+    let flag = sqlx::query_as!(
+        FraudFlagModel,
+        "UPDATE fraud_flags SET status = $2, reviewed_at = $3 WHERE id = $1 AND status = 'pending' RETURNING *",
+        id,
+        status,
+        data.clock.now()
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(AppError::FraudFlagNotFound(id))?;
+
+    if let (ReviewDecision::Approve, Some(referrer_id)) = (body.decision, flag.referrer_id) {
+        fraud::credit_referrer(&mut tx, referrer_id, flag.user_id, data.clock.now()).await?;
+    }
+
+    audit::record(
+        &mut *tx,
+        "fraud.reviewed",
+        json!({"flag_id": flag.id, "user_id": flag.user_id, "referrer_id": flag.referrer_id, "status": status}),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Json(json!({"status": "success","data": json!({ "flag": flag })})))
+}
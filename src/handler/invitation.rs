@@ -0,0 +1,157 @@
+use std::sync::Arc;
+
+use axum::{extract::State, response::IntoResponse};
+use serde_json::json;
+
+use super::org::{self, notify_org_admins};
+use crate::{
+    audit,
+    error::AppError,
+    extract::{Json, Sanitized},
+    invite_token::InvitationGuest,
+    model::{OrgInvitationModel, OrgMemberModel},
+    schema::{RsvpResponse, RsvpSchema},
+    AppState,
+};
+
+/// The name of the organization an invitation is to, and of who sent it.
+pub(crate) async fn inviters(
+    data: &AppState,
+    invitation: &OrgInvitationModel,
+) -> Result<(String, Option<String>), AppError> {
+    let org = sqlx::query!(
+        "SELECT o.name, u.user_name AS \"invited_by?\" FROM organizations o LEFT JOIN users u ON u.id = $2 WHERE o.id = $1",
+        invitation.org_id,
+        invitation.invited_by
+    )
+    .fetch_one(&data.db)
+    .await?;
+    Ok((org.name, org.invited_by))
+}
+
+pub async fn guest_invitation_handler(
+    guest: InvitationGuest,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let invitation = guest.invitation;
+    let (org_name, invited_by) = inviters(&data, &invitation).await?;
+
+    Ok(Json(json!({
+        "status": "success",
+        "data": json!({
+            "invitation": invitation,
+            "organization": {"id": invitation.org_id, "name": org_name},
+            "invited_by": invited_by,
+        })
+    })))
+}
+
+/// Records the guest's answer. Accepting makes them a member straight away
+/// when they already have an account, otherwise when they sign up with the
+/// invited email. They can change their mind until the membership exists.
+/// An accepting guest says how many are coming, up to the invitation's
+/// party size, and the whole party has to fit in what's left of the
+/// organization's capacity.
+pub async fn guest_rsvp_handler(
+    guest: InvitationGuest,
+    State(data): State<Arc<AppState>>,
+    Sanitized(body): Sanitized<RsvpSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    let (invitation, member) = answer(&data, &guest, &body, "invite_link").await?;
+
+    Ok(Json(json!({
+        "status": "success",
+        "data": json!({ "invitation": invitation, "member": member })
+    })))
+}
+
+pub(crate) async fn answer(
+    data: &AppState,
+    guest: &InvitationGuest,
+    body: &RsvpSchema,
+    via: &str,
+) -> Result<(OrgInvitationModel, Option<OrgMemberModel>), AppError> {
+    let response = body.response;
+    let invitation_id = guest.invitation.id;
+    // a declined invitation has no party
+    let (guest_count, guest_names) = match response {
+        RsvpResponse::Accepted => {
+            let guest_count = body.guest_count.unwrap_or(1);
+            if !(1..=guest.invitation.max_party_size).contains(&guest_count) {
+                return Err(AppError::PartySizeInvalid(guest.invitation.max_party_size));
+            }
+            if body.guest_names.len() >= guest_count as usize {
+                return Err(AppError::GuestNamesTooMany(guest_count - 1));
+            }
+            (Some(guest_count), body.guest_names.as_slice())
+        }
+        RsvpResponse::Declined => (None, &[][..]),
+    };
+    let mut tx = data.db.begin().await?;
+
+    if let Some(guest_count) = guest_count {
+        // one acceptance at a time per organization, so two can't both take the last places
+        let capacity = sqlx::query_scalar!(
+            "SELECT capacity FROM organizations WHERE id = $1 FOR UPDATE",
+            guest.invitation.org_id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        if let Some(capacity) = capacity {
+            let remaining = i64::from(capacity) - org::attending(&mut *tx, guest.invitation.org_id, Some(invitation_id)).await?;
+            if i64::from(guest_count) > remaining {
+                return Err(AppError::OrgCapacityReached(remaining.max(0)));
+            }
+        }
+    }
+
+    let existing_user = sqlx::query_scalar!(
+        "SELECT id FROM users WHERE email_index = $1",
+        guest.invitation.email_index
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    // a guest without an account joins when they sign up
+    let joins = response == RsvpResponse::Accepted && existing_user.is_some();
+
+    let invitation = sqlx::query_as!(
+        OrgInvitationModel,
+        "UPDATE org_invitations SET rsvp = $2, rsvp_at = $4, accepted_at = CASE WHEN $3 THEN $4::timestamptz END, guest_count = $5, guest_names = $6 WHERE id = $1 AND accepted_at IS NULL RETURNING *",
+        invitation_id,
+        response.as_str(),
+        joins,
+        data.clock.now(),
+        guest_count,
+        guest_names
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(AppError::OrgInvitationAnswered(invitation_id))?;
+
+    let mut member = None;
+    if let (true, Some(user_id)) = (joins, existing_user) {
+        member = sqlx::query_as!(
+            OrgMemberModel,
+            "INSERT INTO org_members (org_id, user_id, role) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING RETURNING *",
+            invitation.org_id,
+            user_id,
+            invitation.role
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+    }
+
+    audit::record(
+        &mut *tx,
+        "org.invitation_answered",
+        json!({"org_id": invitation.org_id, "invitation_id": invitation.id, "rsvp": invitation.rsvp, "guest_count": invitation.guest_count, "via": via}),
+    )
+    .await?;
+    notify_org_admins(&mut tx, invitation.org_id, "org_invitation_answered", org::invitation_event(&invitation)).await?;
+    if let Some(member) = &member {
+        notify_org_admins(&mut tx, invitation.org_id, "org_member_added", json!(member)).await?;
+    }
+    tx.commit().await?;
+
+    Ok((invitation, member))
+}
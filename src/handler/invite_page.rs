@@ -0,0 +1,105 @@
+use std::sync::Arc;
+
+use askama::Template;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+    Form,
+};
+
+use crate::{
+    error::AppError,
+    handler::invitation::{answer, inviters},
+    i18n,
+    invite_token::InvitationGuest,
+    model::OrgInvitationModel,
+    sanitize::Sanitize,
+    schema::RsvpSchema,
+    AppState,
+};
+
+#[derive(Template)]
+#[template(path = "invitation.html")]
+struct InvitationPage<'a> {
+    lang: &'static str,
+    token: &'a str,
+    org_name: String,
+    invited_by: Option<String>,
+    email: String,
+    role: String,
+    rsvp: Option<String>,
+    joined: bool,
+    max_party_size: i32,
+    guest_count: Option<i32>,
+}
+
+#[derive(Template)]
+#[template(path = "invitation_error.html")]
+struct ErrorPage {
+    lang: &'static str,
+    message: String,
+}
+
+/// The page an invite link opens, with the invitation and a form to answer it.
+pub async fn invite_page_handler(
+    State(data): State<Arc<AppState>>,
+    Path(token): Path<String>,
+) -> Response {
+    let page = async {
+        let guest = InvitationGuest::from_token(&data, &token).await?;
+        render(&data, &token, &guest.invitation).await
+    };
+    page.await.unwrap_or_else(error_page)
+}
+
+/// Takes the form on the invite page and shows the page again with the answer
+/// in. A post without a valid answer just shows the page.
+pub async fn invite_page_rsvp_handler(
+    State(data): State<Arc<AppState>>,
+    Path(token): Path<String>,
+    form: Option<Form<RsvpSchema>>,
+) -> Response {
+    let page = async {
+        let guest = InvitationGuest::from_token(&data, &token).await?;
+        let invitation = match form {
+            Some(Form(mut body)) => {
+                body.sanitize()?;
+                answer(&data, &guest, &body, "invite_page").await?.0
+            }
+            None => guest.invitation,
+        };
+        render(&data, &token, &invitation).await
+    };
+    page.await.unwrap_or_else(error_page)
+}
+
+async fn render(data: &AppState, token: &str, invitation: &OrgInvitationModel) -> Result<Response, AppError> {
+    let (org_name, invited_by) = inviters(data, invitation).await?;
+    let page = InvitationPage {
+        lang: i18n::current(),
+        token,
+        org_name,
+        invited_by,
+        email: invitation.email.to_string(),
+        role: invitation.role.clone(),
+        rsvp: invitation.rsvp.clone(),
+        joined: invitation.accepted_at.is_some(),
+        max_party_size: invitation.max_party_size,
+        guest_count: invitation.guest_count,
+    };
+    let html = page.render().map_err(|_| AppError::Unexpected)?;
+    Ok(Html(html).into_response())
+}
+
+fn error_page(err: AppError) -> Response {
+    let status = err.status();
+    if status.is_server_error() {
+        return err.into_response();
+    }
+    let page = ErrorPage { lang: i18n::current(), message: err.message() };
+    match page.render() {
+        Ok(html) => (status, Html(html)).into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
@@ -0,0 +1,71 @@
+use std::sync::Arc;
+
+use axum::{extract::State, response::IntoResponse};
+use serde_json::json;
+
+use crate::{
+    error::AppError,
+    extract::Json,
+    leaderboard,
+    pagination::Pagination,
+    scheduler, AppState,
+};
+
+/// Referrers by credited referrals, ties sharing a rank. `stale_as_of` is
+/// when the ranking was last computed. Requests for the same page at the
+/// same time share one read.
+pub async fn leaderboard_handler(
+    pagination: Pagination,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let limit = pagination.limit(10);
+    let offset = pagination.offset(10);
+    let sort = pagination.sort(&["rank"], "rank")?;
+
+    let key = format!("{}:{}:{}", limit, offset, sort);
+    let page = data
+        .coalescer
+        .run("leaderboard", key, || leaderboard_page(&data, &pagination, limit, offset, &sort))
+        .await?;
+    Ok(Json(page))
+}
+
+async fn leaderboard_page(
+    data: &AppState,
+    pagination: &Pagination,
+    limit: usize,
+    offset: usize,
+    sort: &str,
+) -> Result<serde_json::Value, AppError> {
+    let entries: Vec<serde_json::Value> = sqlx::query!(
+        r#"SELECT rank AS "rank!", user_id AS "user_id!", user_name AS "user_name!", referrals AS "referrals!" FROM leaderboard ORDER BY CASE WHEN $3 = '-rank' THEN rank END DESC, rank, user_name LIMIT $1 OFFSET $2"#,
+        limit as i64,
+        offset as i64,
+        sort
+    )
+    .fetch_all(&data.db)
+    .await?
+    .into_iter()
+    .map(|row| json!({"rank": row.rank, "user_id": row.user_id, "user_name": row.user_name, "referrals": row.referrals}))
+    .collect();
+
+    Ok(json!({
+        "status": "success",
+        "stale_as_of": scheduler::refreshed_at(&data.db, &[leaderboard::VIEW]).await?,
+        "results": entries.len(),
+        "leaderboard": entries,
+        "next_cursor": pagination.next_cursor(10, entries.len())
+    }))
+}
+
+/// Recomputes the leaderboard now instead of at the next scheduled
+/// refresh. `refreshed` is false when another instance was already at it.
+pub async fn refresh_leaderboard_handler(State(data): State<Arc<AppState>>) -> Result<impl IntoResponse, AppError> {
+    let refreshed = scheduler::refresh_view(&data.db, leaderboard::VIEW, data.clock.now()).await?;
+    let stale_as_of = scheduler::refreshed_at(&data.db, &[leaderboard::VIEW]).await?;
+    Ok(Json(json!({
+        "status": "success",
+        "refreshed": refreshed,
+        "stale_as_of": stale_as_of
+    })))
+}
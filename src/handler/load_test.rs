@@ -0,0 +1,81 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::{
+    audit,
+    error::AppError,
+    events,
+    extract::{Json, Query},
+    schema::EmitEventsOptions,
+    timestamp, AppState,
+};
+
+const MAX_RATE: u32 = 10_000;
+const MAX_COUNT: u32 = 1_000_000;
+// events go out in a batch per tick, so high rates don't need a timer per event
+const TICK: Duration = Duration::from_millis(100);
+
+/// Starts pushing `count` synthetic events through the outbox at `rate` a
+/// second, and answers right away. They take the same path as real ones,
+/// relay, fanout, bus and every SSE client, so fan-out can be benchmarked
+/// without creating users. Each carries the run id, its sequence number and
+/// when it was enqueued, for clients to measure delivery latency.
+pub async fn emit_events_handler(
+    opts: Option<Query<EmitEventsOptions>>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let Query(opts) = opts.unwrap_or_default();
+    let rate = opts.rate.unwrap_or(10).clamp(1, MAX_RATE);
+    let count = opts.count.unwrap_or(100).clamp(1, MAX_COUNT);
+    let run_id = Uuid::new_v4();
+
+    audit::record(
+        &data.db,
+        "events.synthetic_emitted",
+        json!({"run_id": run_id, "rate": rate, "count": count}),
+    )
+    .await?;
+    tokio::spawn(emit(data, run_id, rate, count));
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(json!({
+            "status": "success",
+            "data": {"run_id": run_id, "rate": rate, "count": count, "duration_secs": count as f64 / rate as f64}
+        })),
+    ))
+}
+
+async fn emit(data: Arc<AppState>, run_id: Uuid, rate: u32, count: u32) {
+    // slow rates send one event a tick and space the ticks out instead
+    let per_tick = (rate as f64 * TICK.as_secs_f64()).max(1.0) as u32;
+    let mut ticks = tokio::time::interval(Duration::from_secs_f64(per_tick as f64 / rate as f64));
+    let mut seq = 0;
+
+    while seq < count {
+        ticks.tick().await;
+        let batch = per_tick.min(count - seq);
+        if let Err(e) = emit_batch(&data, run_id, seq, batch).await {
+            println!("🔥 Synthetic event run {} stopped after {} events: {:?}", run_id, seq, e);
+            return;
+        }
+        seq += batch;
+    }
+    println!("✅ Synthetic event run {} emitted {} events", run_id, count);
+}
+
+async fn emit_batch(data: &AppState, run_id: Uuid, first: u32, batch: u32) -> Result<(), sqlx::Error> {
+    let mut tx = data.db.begin().await?;
+    for seq in first..first + batch {
+        let event = json!({
+            "status": "success",
+            "event_type": "synthetic",
+            "event_data": {"run_id": run_id, "seq": seq, "enqueued_at": timestamp::json(&data.clock.now())}
+        });
+        events::enqueue(&mut tx, event).await?;
+    }
+    tx.commit().await
+}
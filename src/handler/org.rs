@@ -0,0 +1,1246 @@
+use std::{collections::HashSet, sync::Arc};
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse};
+use serde_json::json;
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use super::contact::insert_contact;
+use crate::{
+    audit, blocks, email_domain, email_log,
+    error::AppError,
+    events,
+    guest_list::GuestList,
+    extract::{Json, Path, Query, Sanitized},
+    ids,
+    model::{ContactModel, EmailLogModel, OrgInvitationModel, OrgMemberModel, OrganizationModel, UserModel},
+    pagination::Pagination,
+    pii,
+    schema::{
+        AddOrgMemberSchema, ContactFilterOptions, CreateContactSchema, CreateOrganizationSchema,
+        ImportInvitationsSchema, InvitationFilterOptions, InviteOrgMemberSchema, InviteTagGroupSchema,
+        OrgCapacitySchema, TagInvitationSchema, UpdateOrgMemberSchema,
+    },
+    quota::Metric,
+    session::Caller,
+    suppression,
+    tenant::Tenant,
+    AppState,
+};
+
+pub(crate) const ORG_ROLES: [&str; 3] = ["owner", "admin", "member"];
+pub(crate) const ORG_ADMIN_ROLES: [&str; 2] = ["owner", "admin"];
+const MAX_PARTY_SIZE: i32 = 20;
+
+fn check_role(role: &str, allowed: &[&'static str]) -> Result<(), AppError> {
+    if !allowed.contains(&role) {
+        return Err(AppError::OrgRoleInvalid(allowed.to_vec()));
+    }
+    Ok(())
+}
+
+// 1 unless given
+fn max_party_size(size: Option<i32>) -> Result<i32, AppError> {
+    let size = size.unwrap_or(1);
+    if !(1..=MAX_PARTY_SIZE).contains(&size) {
+        return Err(AppError::PartySizeInvalid(MAX_PARTY_SIZE));
+    }
+    Ok(size)
+}
+
+/// Queues an event that only the organization's owners and admins receive
+/// over SSE, published once the surrounding transaction commits.
+pub(super) async fn notify_org_admins(
+    conn: &mut PgConnection,
+    org_id: Uuid,
+    event_type: &str,
+    event_data: serde_json::Value,
+) -> Result<(), sqlx::Error> {
+    let event_to_send = json!({
+        "status": "success",
+        "event_type": event_type,
+        "audience": {"org_id": org_id, "roles": ORG_ADMIN_ROLES},
+        "event_data": event_data
+    });
+    events::enqueue(conn, event_to_send).await
+}
+
+/// An invitation as its events carry it. Events are kept and replayed, so
+/// they leave the address out, admins look it up in the invitations list.
+pub(super) fn invitation_event(invitation: &OrgInvitationModel) -> serde_json::Value {
+    let mut event = json!(invitation);
+    if let Some(fields) = event.as_object_mut() {
+        fields.remove("email");
+    }
+    event
+}
+
+pub(super) async fn member_role(data: &AppState, tenant: Tenant, user_id: Uuid) -> Result<Option<String>, AppError> {
+    sqlx::query_scalar!(
+        "SELECT role FROM org_members WHERE org_id = $1 AND user_id = $2",
+        tenant.org_id,
+        user_id
+    )
+    .fetch_optional(tenant.db(data))
+    .await
+    .map_err(AppError::from)
+}
+
+/// Creates an organization owned by the user creating it.
+pub async fn create_org_handler(
+    Caller(owner_id): Caller,
+    State(data): State<Arc<AppState>>,
+    Sanitized(body): Sanitized<CreateOrganizationSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    // the id decides which database the organization lives on, so it's picked here
+    let org_id = ids::new();
+    let mut tx = data.shards.pool(org_id).begin().await?;
+    let org = sqlx::query_as!(
+        OrganizationModel,
+        "INSERT INTO organizations (id, name) VALUES ($1, $2) RETURNING *",
+        org_id,
+        body.name
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "INSERT INTO org_members (org_id, user_id, role) VALUES ($1, $2, 'owner')",
+        org.id,
+        owner_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({"status": "success","data": json!({ "organization": org })})),
+    ))
+}
+
+pub async fn get_org_handler(
+    tenant: Tenant,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let org = sqlx::query_as!(
+        OrganizationModel,
+        "SELECT * FROM organizations WHERE id = $1",
+        tenant.org_id
+    )
+    .fetch_one(tenant.db(&data))
+    .await?;
+
+    Ok(Json(json!({"status": "success","data": json!({ "organization": org })})))
+}
+
+/// Sets how many guests the organization has room for, whole parties
+/// counted, or lifts the limit with `null`. Guests already coming stay
+/// when it's set below them, only new acceptances are turned away.
+pub async fn set_org_capacity_handler(
+    tenant: Tenant,
+    State(data): State<Arc<AppState>>,
+    Json(body): Json<OrgCapacitySchema>,
+) -> Result<impl IntoResponse, AppError> {
+    tenant.require_admin()?;
+    if body.capacity.is_some_and(|capacity| capacity < 1) {
+        return Err(AppError::OrgCapacityInvalid);
+    }
+
+    let mut tx = tenant.db(&data).begin().await?;
+    let org = sqlx::query_as!(
+        OrganizationModel,
+        "UPDATE organizations SET capacity = $2, updated_at = $3 WHERE id = $1 RETURNING *",
+        tenant.org_id,
+        body.capacity,
+        data.clock.now()
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    audit::record(&mut *tx, "org.capacity_set", json!({"org_id": tenant.org_id, "capacity": org.capacity, "set_by": tenant.user_id}))
+        .await?;
+    tx.commit().await?;
+
+    Ok(Json(json!({"status": "success","data": json!({ "organization": org })})))
+}
+
+/// Who's coming, counting whole parties against the capacity, for the
+/// organization's owners and admins. With `tag`, only invitations tagged
+/// with it are counted, the capacity is the whole organization's.
+pub async fn attendance_handler(
+    tenant: Tenant,
+    opts: Option<Query<InvitationFilterOptions>>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    tenant.require_admin()?;
+    let Query(opts) = opts.unwrap_or_default();
+
+    let capacity = sqlx::query_scalar!("SELECT capacity FROM organizations WHERE id = $1", tenant.org_id)
+        .fetch_one(tenant.db(&data))
+        .await?;
+    let counts = sqlx::query!(
+        r#"SELECT COUNT(*) FILTER (WHERE rsvp = 'accepted') AS "accepted!", COUNT(*) FILTER (WHERE rsvp = 'declined') AS "declined!", COUNT(*) FILTER (WHERE rsvp IS NULL AND accepted_at IS NULL) AS "pending!", COALESCE(SUM(guest_count) FILTER (WHERE rsvp = 'accepted'), 0) AS "guests!" FROM org_invitations WHERE org_id = $1 AND ($2::text IS NULL OR $2 = ANY(tags))"#,
+        tenant.org_id,
+        opts.tag
+    )
+    .fetch_one(tenant.db(&data))
+    .await?;
+    let coming = attending(tenant.db(&data), tenant.org_id, None).await?;
+
+    Ok(Json(json!({
+        "status": "success",
+        "data": json!({
+            "attendance": {
+                "accepted": counts.accepted,
+                "declined": counts.declined,
+                "pending": counts.pending,
+                "guests": counts.guests,
+                "capacity": capacity,
+                "remaining": capacity.map(|capacity| (i64::from(capacity) - coming).max(0)),
+            }
+        })
+    })))
+}
+
+/// How many are coming to an organization in all, whole parties counted,
+/// leaving out the guests of `except`.
+pub(super) async fn attending<'e, E: sqlx::PgExecutor<'e>>(
+    db: E,
+    org_id: Uuid,
+    except: Option<Uuid>,
+) -> Result<i64, sqlx::Error> {
+    let coming = sqlx::query_scalar!(
+        r#"SELECT COALESCE(SUM(guest_count), 0) AS "coming!" FROM org_invitations WHERE org_id = $1 AND rsvp = 'accepted' AND id IS DISTINCT FROM $2"#,
+        org_id,
+        except
+    )
+    .fetch_one(db)
+    .await?;
+    Ok(coming)
+}
+
+pub async fn org_members_list_handler(
+    tenant: Tenant,
+    pagination: Pagination,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let limit = pagination.limit(50);
+    let offset = pagination.offset(50);
+    let sort = pagination.sort(&["created_at", "role"], "created_at")?;
+
+    let members = sqlx::query_as!(
+        OrgMemberModel,
+        "SELECT * FROM org_members WHERE org_id = $1 ORDER BY CASE WHEN $4 = 'role' THEN role END, CASE WHEN $4 = '-role' THEN role END DESC, CASE WHEN $4 = '-created_at' THEN created_at END DESC, created_at, user_id LIMIT $2 OFFSET $3",
+        tenant.org_id,
+        limit as i32,
+        offset as i32,
+        sort
+    )
+    .fetch_all(tenant.db(&data))
+    .await?;
+
+    Ok(Json(json!({
+        "status": "success",
+        "results": members.len(),
+        "members": members,
+        "next_cursor": pagination.next_cursor(50, members.len())
+    })))
+}
+
+pub async fn add_org_member_handler(
+    tenant: Tenant,
+    State(data): State<Arc<AppState>>,
+    Json(body): Json<AddOrgMemberSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    tenant.require_admin()?;
+    let role = body.role.as_deref().unwrap_or("member");
+    check_role(role, &["admin", "member"])?;
+
+    let mut tx = tenant.db(&data).begin().await?;
+
+    let query_result = sqlx::query_as!(
+        OrgMemberModel,
+        "INSERT INTO org_members (org_id, user_id, role) SELECT $1, id, $3 FROM users WHERE id = $2 ON CONFLICT DO NOTHING RETURNING *",
+        tenant.org_id,
+        body.user_id,
+        role
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let member = match query_result {
+        Some(member) => member,
+        None if member_role(&data, tenant, body.user_id).await?.is_some() => {
+            return Err(AppError::OrgAlreadyMember(body.user_id));
+        }
+        None => return Err(AppError::UserNotFound(body.user_id.to_string())),
+    };
+
+    audit::record(&mut *tx, "org.member_added", json!({"org_id": tenant.org_id, "user_id": member.user_id, "role": member.role}))
+        .await?;
+    notify_org_admins(&mut tx, tenant.org_id, "org_member_added", json!(member)).await?;
+    tx.commit().await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({"status": "success","data": json!({ "member": member })})),
+    ))
+}
+
+pub async fn update_org_member_handler(
+    tenant: Tenant,
+    Path((_, user_id)): Path<(Uuid, Uuid)>,
+    State(data): State<Arc<AppState>>,
+    Json(body): Json<UpdateOrgMemberSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    tenant.require_admin()?;
+    check_role(&body.role, &ORG_ROLES)?;
+
+    let mut tx = tenant.db(&data).begin().await?;
+    let current = lock_member(&mut tx, tenant, user_id).await?;
+    if current.role == "owner" || body.role == "owner" {
+        tenant.require_owner()?;
+    }
+
+    if current.role == "owner" && body.role != "owner" {
+        check_not_last_owner(&mut tx, tenant).await?;
+    }
+
+    let member = sqlx::query_as!(
+        OrgMemberModel,
+        "UPDATE org_members SET role = $1 WHERE org_id = $2 AND user_id = $3 RETURNING *",
+        body.role,
+        tenant.org_id,
+        user_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    audit::record(
+        &mut *tx,
+        "org.member_role_changed",
+        json!({"org_id": tenant.org_id, "user_id": user_id, "from": current.role, "to": member.role}),
+    )
+    .await?;
+    notify_org_admins(&mut tx, tenant.org_id, "org_member_role_changed", json!(member)).await?;
+    tx.commit().await?;
+
+    Ok(Json(json!({"status": "success","data": json!({ "member": member })})))
+}
+
+pub async fn remove_org_member_handler(
+    tenant: Tenant,
+    Path((_, user_id)): Path<(Uuid, Uuid)>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    tenant.require_admin()?;
+    let mut tx = tenant.db(&data).begin().await?;
+    let current = lock_member(&mut tx, tenant, user_id).await?;
+
+    if current.role == "owner" {
+        tenant.require_owner()?;
+        check_not_last_owner(&mut tx, tenant).await?;
+    }
+
+    sqlx::query!(
+        "DELETE FROM org_members WHERE org_id = $1 AND user_id = $2",
+        tenant.org_id,
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    audit::record(&mut *tx, "org.member_removed", json!({"org_id": tenant.org_id, "user_id": user_id, "role": current.role}))
+        .await?;
+    notify_org_admins(&mut tx, tenant.org_id, "org_member_removed", json!(current)).await?;
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn lock_member(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    tenant: Tenant,
+    user_id: Uuid,
+) -> Result<OrgMemberModel, AppError> {
+    sqlx::query_as!(
+        OrgMemberModel,
+        "SELECT * FROM org_members WHERE org_id = $1 AND user_id = $2 FOR UPDATE",
+        tenant.org_id,
+        user_id
+    )
+    .fetch_optional(&mut **tx)
+    .await?
+    .ok_or(AppError::OrgMemberNotFound(user_id))
+}
+
+// an organization always keeps at least one owner
+async fn check_not_last_owner(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    tenant: Tenant,
+) -> Result<(), AppError> {
+    let owners = sqlx::query_scalar!(
+        "SELECT user_id FROM org_members WHERE org_id = $1 AND role = 'owner' FOR UPDATE",
+        tenant.org_id
+    )
+    .fetch_all(&mut **tx)
+    .await?;
+
+    if owners.len() <= 1 {
+        return Err(AppError::OrgLastOwner);
+    }
+    Ok(())
+}
+
+/// Invites someone by email. Registered users become members right away,
+/// everyone else gets a pending invitation, with the tags given, that is
+/// claimed when they sign up.
+pub async fn invite_org_member_handler(
+    tenant: Tenant,
+    State(data): State<Arc<AppState>>,
+    Sanitized(body): Sanitized<InviteOrgMemberSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    let role = body.role.as_deref().unwrap_or("member");
+    check_role(role, &["admin", "member"])?;
+    let details = InvitationDetails { tags: &body.tags, max_party_size: max_party_size(body.max_party_size)? };
+    tenant.require_admin()?;
+
+    let created = match invite(&data, tenant, &body.email, role, details).await? {
+        Invited::Member(member) => json!({ "member": member }),
+        Invited::Invitation(invitation, invite_email) => {
+            // for the invite email, it is never stored
+            let invite_token = data.invite_tokens.to_json(&invitation, data.clock.now());
+            json!({ "invitation": invitation, "invite_token": invite_token, "email": invite_email })
+        }
+    };
+    Ok((StatusCode::CREATED, Json(json!({"status": "success", "data": created}))))
+}
+
+/// What a new invitation carries besides who it's for and their role.
+#[derive(Clone, Copy)]
+pub(super) struct InvitationDetails<'a> {
+    pub tags: &'a [String],
+    pub max_party_size: i32,
+}
+
+pub(super) enum Invited {
+    Member(OrgMemberModel),
+    // with what the invite email needs, see `queue_invite_email`
+    Invitation(Box<OrgInvitationModel>, serde_json::Value),
+}
+
+/// Adds a registered user as a member, or invites an email that isn't one
+/// yet, once the role is known to be valid and the inviter an admin. The
+/// member making the request is the one inviting. The details only go on a
+/// new invitation, members have no tags or party.
+pub(super) async fn invite(
+    data: &AppState,
+    tenant: Tenant,
+    email: &str,
+    role: &str,
+    details: InvitationDetails<'_>,
+) -> Result<Invited, AppError> {
+    let invited_by = tenant.user_id;
+    let email = email.trim();
+    email_domain::check(&data.db, email, Some(tenant.org_id)).await?;
+    if blocks::email_has_blocked(&data.db, email, invited_by).await? {
+        return Err(AppError::InviteeBlocked);
+    }
+
+    let mut tx = tenant.db(data).begin().await?;
+    data.quotas
+        .consume(tenant.db(data), &mut tx, tenant.org_id, Metric::InvitesPerMonth, data.clock.now())
+        .await?;
+
+    let existing_user = sqlx::query_scalar!("SELECT id FROM users WHERE email_index = $1", pii::blind_index(email))
+        .fetch_optional(&mut *tx)
+        .await?;
+
+    if let Some(user_id) = existing_user {
+        let member = sqlx::query_as!(
+            OrgMemberModel,
+            "INSERT INTO org_members (org_id, user_id, role) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING RETURNING *",
+            tenant.org_id,
+            user_id,
+            role
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(AppError::OrgAlreadyMember(user_id))?;
+
+        audit::record(
+            &mut *tx,
+            "org.member_added",
+            json!({"org_id": tenant.org_id, "user_id": user_id, "role": role, "invited_by": invited_by}),
+        )
+        .await?;
+        notify_org_admins(&mut tx, tenant.org_id, "org_member_added", json!(member)).await?;
+        tx.commit().await?;
+
+        return Ok(Invited::Member(member));
+    }
+
+    let invitation = sqlx::query_as!(
+        OrgInvitationModel,
+        "INSERT INTO org_invitations (id, org_id, email, email_index, role, invited_by, tags, max_party_size) VALUES ($1, $2, $3, $4, $5, $6, $7, $8) ON CONFLICT (org_id, email_index) WHERE accepted_at IS NULL DO NOTHING RETURNING *",
+        ids::new(),
+        tenant.org_id,
+        pii::seal(email),
+        pii::blind_index(email),
+        role,
+        invited_by,
+        details.tags,
+        details.max_party_size
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let Some(invitation) = invitation else {
+        return Err(AppError::OrgInvitationPending(email.to_string()));
+    };
+
+    audit::record(
+        &mut *tx,
+        "org.member_invited",
+        json!({"org_id": tenant.org_id, "invitation_id": invitation.id, "role": role, "invited_by": invited_by, "tags": details.tags, "max_party_size": details.max_party_size}),
+    )
+    .await?;
+    notify_org_admins(&mut tx, tenant.org_id, "org_member_invited", invitation_event(&invitation)).await?;
+    let invite_email = queue_invite_email(data, &mut tx, &invitation).await?;
+    tx.commit().await?;
+
+    Ok(Invited::Invitation(Box::new(invitation), invite_email))
+}
+
+const PREVIEW_ROWS: usize = 5;
+
+/// How inviting a batch of guests went, guest by guest. Each result names
+/// its guest by `at`, the line of a file or the contact it came from.
+#[derive(Default)]
+struct Batch {
+    invited: usize,
+    added: usize,
+    duplicates: usize,
+    invalid: usize,
+    results: Vec<serde_json::Value>,
+}
+
+impl Batch {
+    fn push(&mut self, at: &serde_json::Value, status: &str, fields: serde_json::Value) {
+        let mut result = at.clone();
+        if let (Some(result), serde_json::Value::Object(fields)) = (result.as_object_mut(), fields) {
+            result.insert("status".to_string(), json!(status));
+            result.extend(fields);
+        }
+        self.results.push(result);
+    }
+
+    fn invalid(&mut self, at: &serde_json::Value, fields: serde_json::Value) {
+        self.invalid += 1;
+        self.push(at, "invalid", fields);
+    }
+
+    fn duplicate(&mut self, at: &serde_json::Value, email: &str) {
+        self.duplicates += 1;
+        self.push(at, "duplicate", json!({ "email": email }));
+    }
+
+    /// Invites one guest as `invite_org_member_handler` would. Guests who
+    /// can't be invited are reported, only a failure on our side stops the
+    /// batch.
+    async fn invite(
+        &mut self,
+        data: &AppState,
+        tenant: Tenant,
+        at: &serde_json::Value,
+        email: &str,
+        role: &str,
+        details: InvitationDetails<'_>,
+    ) -> Result<(), AppError> {
+        match invite(data, tenant, email, role, details).await {
+            Ok(Invited::Member(member)) => {
+                self.added += 1;
+                self.push(at, "added", json!({ "member": member }));
+            }
+            Ok(Invited::Invitation(invitation, invite_email)) => {
+                self.invited += 1;
+                let invite_token = data.invite_tokens.to_json(&invitation, data.clock.now());
+                self.push(
+                    at,
+                    "invited",
+                    json!({ "invitation": invitation, "invite_token": invite_token, "email": invite_email }),
+                );
+            }
+            Err(AppError::OrgAlreadyMember(_) | AppError::OrgInvitationPending(_)) => self.duplicate(at, email),
+            // blocked domains, quota and the like, the guest stays out
+            Err(e) if e.status().is_client_error() => {
+                self.invalid(at, json!({"code": e.code(), "message": e.message()}));
+            }
+            Err(e) => return Err(e),
+        }
+        Ok(())
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        json!({
+            "invited": self.invited,
+            "added": self.added,
+            "duplicates": self.duplicates,
+            "invalid": self.invalid,
+            "results": self.results
+        })
+    }
+}
+
+/// Invites everyone on a guest list exported from elsewhere, a CSV or a
+/// Google Contacts export, each row as `invite_org_member_handler` would.
+/// Without a column mapping nothing is imported: the answer lists the
+/// columns, a suggested mapping and the first rows, to send back with the
+/// mapping once it's right. Emails already invited, already members or
+/// listed twice are skipped, and rows that can't be invited are reported
+/// by line with the reason.
+pub async fn import_invitations_handler(
+    tenant: Tenant,
+    State(data): State<Arc<AppState>>,
+    Sanitized(body): Sanitized<ImportInvitationsSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    let default_role = body.role.as_deref().unwrap_or("member");
+    check_role(default_role, &["admin", "member"])?;
+    let details = InvitationDetails { tags: &body.tags, max_party_size: max_party_size(body.max_party_size)? };
+    tenant.require_admin()?;
+
+    let list = GuestList::parse(body.format, &body.data)?;
+    let Some(mapping) = body.mapping else {
+        return Ok(Json(json!({
+            "status": "success",
+            "imported": false,
+            "rows": list.row_count(),
+            "columns": list.columns(),
+            "suggested_mapping": list.suggest(),
+            "preview": list.preview(PREVIEW_ROWS)
+        })));
+    };
+
+    let mut batch = Batch::default();
+    let mut seen = HashSet::new();
+
+    for guest in list.guests(&mapping)? {
+        let guest = match guest {
+            Ok(guest) => guest,
+            Err(row) => {
+                batch.invalid(&json!({ "line": row.line }), json!({ "message": row.message }));
+                continue;
+            }
+        };
+        let at = json!({ "line": guest.line });
+        let Some(email) = guest.email else {
+            batch.invalid(&at, json!({"message": "email is required"}));
+            continue;
+        };
+        if !email.contains('@') {
+            batch.invalid(&at, json!({"message": format!("{} is not an email", email)}));
+            continue;
+        }
+        let role = guest.role.as_deref().unwrap_or(default_role);
+        if let Err(e) = check_role(role, &["admin", "member"]) {
+            batch.invalid(&at, json!({"code": e.code(), "message": e.message()}));
+            continue;
+        }
+        if !seen.insert(pii::blind_index(&email)) {
+            batch.duplicate(&at, &email);
+            continue;
+        }
+
+        batch.invite(&data, tenant, &at, &email, role, details).await?;
+    }
+
+    let mut imported = batch.to_json();
+    imported["status"] = json!("success");
+    imported["imported"] = json!(true);
+    Ok(Json(imported))
+}
+
+/// Invites everyone in the organization's address book tagged `tag`, their
+/// invitations tagged the same, each as `invite_org_member_handler` would.
+/// Contacts already invited or already members are skipped, and those that
+/// can't be invited are reported with the reason.
+pub async fn invite_tag_group_handler(
+    tenant: Tenant,
+    State(data): State<Arc<AppState>>,
+    Sanitized(body): Sanitized<InviteTagGroupSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    let role = body.role.as_deref().unwrap_or("member");
+    check_role(role, &["admin", "member"])?;
+    let tags = [body.tag.clone()];
+    let details = InvitationDetails { tags: &tags, max_party_size: max_party_size(body.max_party_size)? };
+    tenant.require_admin()?;
+
+    let contacts = sqlx::query_as!(
+        ContactModel,
+        "SELECT * FROM contacts WHERE org_id = $1 AND $2 = ANY(tags) ORDER BY name, id",
+        tenant.org_id,
+        body.tag
+    )
+    .fetch_all(tenant.db(&data))
+    .await?;
+
+    let mut batch = Batch::default();
+    for contact in &contacts {
+        batch
+            .invite(&data, tenant, &json!({ "contact_id": contact.id }), &contact.email, role, details)
+            .await?;
+    }
+
+    let mut invited = batch.to_json();
+    invited["status"] = json!("success");
+    invited["tag"] = json!(body.tag);
+    Ok(Json(invited))
+}
+
+/// What whoever sends the invite email needs besides the invite link: whether
+/// to send it at all, the id to tag it with so delivery can be tracked, and
+/// the unsubscribe headers.
+async fn queue_invite_email(
+    data: &AppState,
+    conn: &mut PgConnection,
+    invitation: &OrgInvitationModel,
+) -> Result<serde_json::Value, sqlx::Error> {
+    if suppression::is_suppressed(&mut *conn, &invitation.email).await? {
+        return Ok(json!({"send": false, "message_id": null, "headers": null}));
+    }
+    let message_id = email_log::queue(&mut *conn, Some(invitation.id), &invitation.email).await?;
+    let headers = data.suppressions.headers(&mut *conn, &invitation.email, data.clock.now()).await?;
+    Ok(json!({"send": true, "message_id": message_id, "headers": headers}))
+}
+
+/// The pending invitations with the addresses they went to, for the
+/// organization's owners and admins, only those tagged `tag` when given.
+pub async fn org_invitations_list_handler(
+    tenant: Tenant,
+    pagination: Pagination,
+    opts: Option<Query<InvitationFilterOptions>>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    tenant.require_admin()?;
+    let Query(opts) = opts.unwrap_or_default();
+    let limit = pagination.limit(50);
+    let offset = pagination.offset(50);
+    // email is stored encrypted, so there is no sorting by it
+    let sort = pagination.sort(&["created_at"], "created_at")?;
+
+    let invitations = sqlx::query_as!(
+        OrgInvitationModel,
+        "SELECT * FROM org_invitations WHERE org_id = $1 AND accepted_at IS NULL AND ($5::text IS NULL OR $5 = ANY(tags)) ORDER BY CASE WHEN $4 = '-created_at' THEN created_at END DESC, created_at, id LIMIT $2 OFFSET $3",
+        tenant.org_id,
+        limit as i32,
+        offset as i32,
+        sort,
+        opts.tag
+    )
+    .fetch_all(tenant.db(&data))
+    .await?;
+
+    Ok(Json(json!({
+        "status": "success",
+        "results": invitations.len(),
+        "invitations": invitations,
+        "next_cursor": pagination.next_cursor(50, invitations.len())
+    })))
+}
+
+pub async fn revoke_org_invitation_handler(
+    tenant: Tenant,
+    Path((_, invitation_id)): Path<(Uuid, Uuid)>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    tenant.require_admin()?;
+    let mut tx = tenant.db(&data).begin().await?;
+
+    let revoked = sqlx::query_as!(
+        OrgInvitationModel,
+        "DELETE FROM org_invitations WHERE id = $1 AND org_id = $2 AND accepted_at IS NULL RETURNING *",
+        invitation_id,
+        tenant.org_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let Some(revoked) = revoked else {
+        return Err(AppError::OrgInvitationNotFound(invitation_id));
+    };
+
+    audit::record(&mut *tx, "org.invitation_revoked", json!({"org_id": tenant.org_id, "invitation_id": revoked.id}))
+        .await?;
+    notify_org_admins(&mut tx, tenant.org_id, "org_invitation_revoked", invitation_event(&revoked)).await?;
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Adds tags to an invitation, keeping the ones it has.
+pub async fn tag_invitation_handler(
+    tenant: Tenant,
+    Path((_, invitation_id)): Path<(Uuid, Uuid)>,
+    State(data): State<Arc<AppState>>,
+    Sanitized(body): Sanitized<TagInvitationSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    tenant.require_admin()?;
+    let mut tx = tenant.db(&data).begin().await?;
+
+    let invitation = sqlx::query_as!(
+        OrgInvitationModel,
+        "UPDATE org_invitations SET tags = tags || ARRAY(SELECT tag FROM unnest($3::text[]) WITH ORDINALITY AS added(tag, n) WHERE tag <> ALL(tags) ORDER BY n) WHERE id = $1 AND org_id = $2 RETURNING *",
+        invitation_id,
+        tenant.org_id,
+        &body.tags
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(AppError::OrgInvitationNotFound(invitation_id))?;
+
+    audit::record(
+        &mut *tx,
+        "org.invitation_tagged",
+        json!({"org_id": tenant.org_id, "invitation_id": invitation.id, "tags": body.tags}),
+    )
+    .await?;
+    notify_org_admins(&mut tx, tenant.org_id, "org_invitation_tagged", invitation_event(&invitation)).await?;
+    tx.commit().await?;
+
+    Ok(Json(json!({"status": "success","data": json!({ "invitation": invitation })})))
+}
+
+pub async fn untag_invitation_handler(
+    tenant: Tenant,
+    Path((_, invitation_id, tag)): Path<(Uuid, Uuid, String)>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    tenant.require_admin()?;
+    let mut tx = tenant.db(&data).begin().await?;
+
+    let invitation = sqlx::query_as!(
+        OrgInvitationModel,
+        "UPDATE org_invitations SET tags = array_remove(tags, $3) WHERE id = $1 AND org_id = $2 RETURNING *",
+        invitation_id,
+        tenant.org_id,
+        tag
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(AppError::OrgInvitationNotFound(invitation_id))?;
+
+    audit::record(
+        &mut *tx,
+        "org.invitation_untagged",
+        json!({"org_id": tenant.org_id, "invitation_id": invitation.id, "tag": tag}),
+    )
+    .await?;
+    notify_org_admins(&mut tx, tenant.org_id, "org_invitation_untagged", invitation_event(&invitation)).await?;
+    tx.commit().await?;
+
+    Ok(Json(json!({"status": "success","data": json!({ "invitation": invitation })})))
+}
+
+/// Every invite email sent for an invitation and how far each got, the
+/// latest first.
+pub async fn invitation_delivery_handler(
+    tenant: Tenant,
+    Path((_, invitation_id)): Path<(Uuid, Uuid)>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    tenant.require_admin()?;
+    let exists = sqlx::query_scalar!(
+        "SELECT EXISTS(SELECT 1 FROM org_invitations WHERE id = $1 AND org_id = $2)",
+        invitation_id,
+        tenant.org_id
+    )
+    .fetch_one(tenant.db(&data))
+    .await?;
+    if exists != Some(true) {
+        return Err(AppError::OrgInvitationNotFound(invitation_id));
+    }
+
+    let emails = sqlx::query_as!(
+        EmailLogModel,
+        "SELECT * FROM email_log WHERE invitation_id = $1 ORDER by created_at DESC",
+        invitation_id
+    )
+    .fetch_all(tenant.db(&data))
+    .await?;
+
+    Ok(Json(json!({
+        "status": "success",
+        "data": json!({
+            "status": emails.first().map(|email| email.status.as_str()),
+            "results": emails.len(),
+            "emails": emails
+        })
+    })))
+}
+
+/// Replaces the invite link of a pending invitation, for a guest whose link
+/// expired or to send a new one after revoking it. Links handed out before
+/// stop working.
+pub async fn reissue_invite_token_handler(
+    tenant: Tenant,
+    Path((_, invitation_id)): Path<(Uuid, Uuid)>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    if !data.invite_tokens.is_configured() {
+        return Err(AppError::InviteTokensNotConfigured);
+    }
+    let invitation = bump_token_version(&data, tenant, invitation_id).await?;
+
+    let mut conn = tenant.db(&data).acquire().await?;
+    let invite_email = queue_invite_email(&data, &mut conn, &invitation).await?;
+    Ok(Json(json!({
+        "status": "success",
+        "data": json!({
+            "invitation": invitation,
+            "invite_token": data.invite_tokens.to_json(&invitation, data.clock.now()),
+            "email": invite_email
+        })
+    })))
+}
+
+/// Revokes the invite link of a pending invitation, the invitation itself
+/// stays and can still be claimed by signing up.
+pub async fn revoke_invite_token_handler(
+    tenant: Tenant,
+    Path((_, invitation_id)): Path<(Uuid, Uuid)>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    bump_token_version(&data, tenant, invitation_id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn bump_token_version(data: &AppState, tenant: Tenant, invitation_id: Uuid) -> Result<OrgInvitationModel, AppError> {
+    tenant.require_admin()?;
+
+    let mut tx = tenant.db(data).begin().await?;
+    let invitation = sqlx::query_as!(
+        OrgInvitationModel,
+        "UPDATE org_invitations SET token_version = token_version + 1 WHERE id = $1 AND org_id = $2 AND accepted_at IS NULL RETURNING *",
+        invitation_id,
+        tenant.org_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(AppError::OrgInvitationNotFound(invitation_id))?;
+
+    audit::record(
+        &mut *tx,
+        "org.invite_token_revoked",
+        json!({"org_id": tenant.org_id, "invitation_id": invitation.id, "revoked_by": tenant.user_id}),
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(invitation)
+}
+
+/// Turns the pending invitations for a newly registered user's email into
+/// memberships, leaving out the ones they declined through their invite link.
+pub(crate) async fn claim_pending_invitations(data: &AppState, user: &UserModel) -> Result<(), sqlx::Error> {
+    let mut tx = data.db.begin().await?;
+
+    let claimed = sqlx::query_as!(
+        OrgMemberModel,
+        "WITH claimed AS (UPDATE org_invitations SET accepted_at = $3 WHERE email_index = $2 AND accepted_at IS NULL AND rsvp IS DISTINCT FROM 'declined' RETURNING org_id, role) INSERT INTO org_members (org_id, user_id, role) SELECT org_id, $1, role FROM claimed ON CONFLICT DO NOTHING RETURNING *",
+        user.id,
+        user.email_index,
+        data.clock.now()
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    for member in claimed {
+        audit::record(
+            &mut *tx,
+            "org.invitation_claimed",
+            json!({"org_id": member.org_id, "user_id": member.user_id, "role": member.role}),
+        )
+        .await?;
+        notify_org_admins(&mut tx, member.org_id, "org_member_added", json!(member)).await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn org_contacts_list_handler(
+    tenant: Tenant,
+    pagination: Pagination,
+    opts: Option<Query<ContactFilterOptions>>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let Query(opts) = opts.unwrap_or_default();
+
+    let limit = pagination.limit(10);
+    let offset = pagination.offset(10);
+    let sort = pagination.sort(&["name", "created_at"], "name")?;
+
+    let contacts = sqlx::query_as!(
+        ContactModel,
+        "SELECT * FROM contacts WHERE org_id = $1 AND ($2::text IS NULL OR $2 = ANY(tags)) ORDER BY CASE WHEN $5 = '-name' THEN name END DESC, CASE WHEN $5 = 'created_at' THEN created_at END, CASE WHEN $5 = '-created_at' THEN created_at END DESC, name, id LIMIT $3 OFFSET $4",
+        tenant.org_id,
+        opts.tag,
+        limit as i32,
+        offset as i32,
+        sort
+    )
+    .fetch_all(tenant.db(&data))
+    .await?;
+
+    Ok(Json(json!({
+        "status": "success",
+        "results": contacts.len(),
+        "contacts": contacts,
+        "next_cursor": pagination.next_cursor(10, contacts.len())
+    })))
+}
+
+pub async fn create_org_contact_handler(
+    tenant: Tenant,
+    State(data): State<Arc<AppState>>,
+    Sanitized(body): Sanitized<CreateContactSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    // any member can add to the shared address book, it's kept as theirs
+    let contact = insert_contact(&data, tenant.user_id, Some(tenant.org_id), &body)
+        .await?
+        .ok_or_else(|| AppError::ContactEmailTaken(body.email.clone()))?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({"status": "success","data": json!({ "contact": contact })})),
+    ))
+}
+
+pub async fn get_org_contact_handler(
+    tenant: Tenant,
+    Path((_, contact_id)): Path<(Uuid, Uuid)>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let contact = sqlx::query_as!(
+        ContactModel,
+        "SELECT * FROM contacts WHERE id = $1 AND org_id = $2",
+        contact_id,
+        tenant.org_id
+    )
+    .fetch_optional(tenant.db(&data))
+    .await?
+    .ok_or(AppError::ContactNotFound(contact_id))?;
+
+    Ok(Json(json!({"status": "success","data": json!({ "contact": contact })})))
+}
+
+pub async fn delete_org_contact_handler(
+    tenant: Tenant,
+    Path((_, contact_id)): Path<(Uuid, Uuid)>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    tenant.require_admin()?;
+    let rows_affected = sqlx::query!(
+        "DELETE FROM contacts WHERE id = $1 AND org_id = $2",
+        contact_id,
+        tenant.org_id
+    )
+    .execute(tenant.db(&data))
+    .await?
+    .rows_affected();
+
+    if rows_affected == 0 {
+        return Err(AppError::ContactNotFound(contact_id));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn org_usage_handler(
+    tenant: Tenant,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    tenant.require_admin()?;
+
+    let usage = data.quotas.usage(tenant.db(&data), tenant.org_id, data.clock.now()).await?;
+
+    Ok(Json(json!({"status": "success","data": json!({ "usage": usage })})))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+    use serde_json::{json, Value};
+
+    use crate::testing::TestApp;
+
+    async fn join(app: &TestApp, owner: &Value, org: &str, user: &Value, role: &str) {
+        let body = json!({"user_id": user["id"], "role": role});
+        let (status, body) = app.as_user(owner, Method::POST, &format!("{}/members", org), Some(body)).await;
+        assert_eq!(status, StatusCode::CREATED, "{}", body);
+    }
+
+    #[tokio::test]
+    async fn only_admins_run_an_organization_and_only_owners_hand_it_over() {
+        let app = TestApp::new().await;
+        let ada = app.create_user("ada", "ada@example.com").await;
+        let (status, created) = app.as_user(&ada, Method::POST, "/api/orgs", Some(json!({"name": "Acme"}))).await;
+        assert_eq!(status, StatusCode::CREATED, "{}", created);
+        let org = format!("/api/orgs/{}", created["data"]["organization"]["id"].as_str().unwrap());
+        let (_, members) = app.as_user(&ada, Method::GET, &format!("{}/members", org), None).await;
+        assert_eq!(members["members"][0]["user_id"], ada["id"]);
+        assert_eq!(members["members"][0]["role"], "owner");
+
+        let (status, invited) = app
+            .as_user(&ada, Method::POST, &format!("{}/invitations", org), Some(json!({"email": "guest@example.com"})))
+            .await;
+        assert_eq!(status, StatusCode::CREATED, "{}", invited);
+        assert_eq!(invited["data"]["invitation"]["invited_by"], ada["id"]);
+        let invitation = format!("{}/invitations/{}", org, invited["data"]["invitation"]["id"].as_str().unwrap());
+
+        let mallory = app.create_user("mallory", "mallory@example.com").await;
+        let carl = app.create_user("carl", "carl@example.com").await;
+        let dan = app.create_user("dan", "dan@example.com").await;
+        join(&app, &ada, &org, &carl, "member").await;
+        join(&app, &ada, &org, &dan, "admin").await;
+        let ada_member = format!("{}/members/{}", org, ada["id"].as_str().unwrap());
+        let writes = [
+            (Method::POST, format!("{}/members", org), Some(json!({"user_id": mallory["id"], "role": "admin"}))),
+            (Method::PATCH, ada_member.clone(), Some(json!({"role": "member"}))),
+            (Method::DELETE, ada_member.clone(), None),
+            (Method::POST, format!("{}/invitations", org), Some(json!({"email": "else@example.com"}))),
+            (Method::GET, format!("{}/invitations", org), None),
+            (Method::DELETE, invitation.clone(), None),
+            (Method::DELETE, format!("{}/token", invitation), None),
+            (Method::GET, format!("{}/usage", org), None),
+            (Method::PUT, format!("{}/capacity", org), Some(json!({"capacity": 10}))),
+            (Method::GET, format!("{}/attendance", org), None),
+        ];
+
+        // strangers aren't let in at all, members can't run the organization
+        for (user, code) in [(&mallory, "ORG_MEMBERSHIP_REQUIRED"), (&carl, "ORG_ADMIN_REQUIRED")] {
+            for (method, uri, body) in &writes {
+                let (status, denied) = app.as_user(user, method.clone(), uri, body.clone()).await;
+                assert_eq!(status, StatusCode::FORBIDDEN, "{} {}", method, uri);
+                assert_eq!(denied["code"], code, "{} {}", method, uri);
+            }
+        }
+
+        // admins can, except for making or unmaking owners
+        let dan_member = format!("{}/members/{}", org, dan["id"].as_str().unwrap());
+        for (method, uri, body) in [
+            (Method::PATCH, dan_member, Some(json!({"role": "owner"}))),
+            (Method::PATCH, ada_member.clone(), Some(json!({"role": "member"}))),
+            (Method::DELETE, ada_member, None),
+        ] {
+            let (status, denied) = app.as_user(&dan, method.clone(), &uri, body).await;
+            assert_eq!(status, StatusCode::FORBIDDEN, "{} {}", method, uri);
+            assert_eq!(denied["code"], "ORG_OWNER_REQUIRED");
+        }
+        let (status, _) = app.as_user(&dan, Method::GET, &format!("{}/usage", org), None).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = app.as_user(&dan, Method::DELETE, &invitation, None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+    }
+
+    async fn tagged_count(app: &TestApp, admin: &Value, org: &str, tag: &str) -> u64 {
+        let (_, list) = app.as_user(admin, Method::GET, &format!("{}/invitations?tag={}", org, tag), None).await;
+        list["results"].as_u64().unwrap()
+    }
+
+    #[tokio::test]
+    async fn tag_groups_from_the_address_book_are_invited_and_listed_together() {
+        let app = TestApp::new().await;
+        let ada = app.create_user("ada", "ada@example.com").await;
+        let (_, created) = app.as_user(&ada, Method::POST, "/api/orgs", Some(json!({"name": "Acme"}))).await;
+        let org = format!("/api/orgs/{}", created["data"]["organization"]["id"].as_str().unwrap());
+        for (name, tags) in [("aunt", vec!["family"]), ("uncle", vec!["family", "vip"]), ("boss", vec!["work"])] {
+            let contact = json!({"name": name, "email": format!("{}@example.com", name), "tags": tags});
+            let (status, body) = app.as_user(&ada, Method::POST, &format!("{}/contacts", org), Some(contact)).await;
+            assert_eq!(status, StatusCode::CREATED, "{}", body);
+        }
+
+        let family = Some(json!({"tag": "family"}));
+        let (status, invited) = app.as_user(&ada, Method::POST, &format!("{}/invitations/from-contacts", org), family.clone()).await;
+        assert_eq!(status, StatusCode::OK, "{}", invited);
+        assert_eq!(invited["invited"], 2, "{}", invited);
+        assert_eq!(invited["results"][0]["invitation"]["tags"], json!(["family"]));
+        // asking again invites no one twice
+        let (_, again) = app.as_user(&ada, Method::POST, &format!("{}/invitations/from-contacts", org), family).await;
+        assert_eq!((again["invited"].clone(), again["duplicates"].clone()), (json!(0), json!(2)));
+
+        let (_, boss) = app
+            .as_user(&ada, Method::POST, &format!("{}/invitations", org), Some(json!({"email": "boss@example.com", "tags": ["work", "work"]})))
+            .await;
+        assert_eq!(boss["data"]["invitation"]["tags"], json!(["work"]));
+        let tags = format!("{}/invitations/{}/tags", org, boss["data"]["invitation"]["id"].as_str().unwrap());
+        let (status, tagged) = app.as_user(&ada, Method::POST, &tags, Some(json!({"tags": ["family", "work"]}))).await;
+        assert_eq!(status, StatusCode::OK, "{}", tagged);
+        assert_eq!(tagged["data"]["invitation"]["tags"], json!(["work", "family"]));
+
+        assert_eq!(tagged_count(&app, &ada, &org, "family").await, 3);
+        let (status, _) = app.as_user(&ada, Method::DELETE, &format!("{}/family", tags), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(tagged_count(&app, &ada, &org, "family").await, 2);
+        assert_eq!(tagged_count(&app, &ada, &org, "work").await, 1);
+
+        // tags are the admins' to hand out
+        let carl = app.create_user("carl", "carl@example.com").await;
+        join(&app, &ada, &org, &carl, "member").await;
+        let (status, denied) = app.as_user(&carl, Method::POST, &tags, Some(json!({"tags": ["vip"]}))).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(denied["code"], "ORG_ADMIN_REQUIRED");
+    }
+
+    #[tokio::test]
+    async fn whole_parties_count_against_the_capacity() {
+        std::env::set_var("INVITE_TOKEN_SECRET", "test secret");
+        let app = TestApp::new().await;
+        let ada = app.create_user("ada", "ada@example.com").await;
+        let (_, created) = app.as_user(&ada, Method::POST, "/api/orgs", Some(json!({"name": "Acme"}))).await;
+        let org = format!("/api/orgs/{}", created["data"]["organization"]["id"].as_str().unwrap());
+        let (status, body) = app.as_user(&ada, Method::PUT, &format!("{}/capacity", org), Some(json!({"capacity": 4}))).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["data"]["organization"]["capacity"], 4);
+
+        let mut rsvps = Vec::new();
+        for (email, max_party_size) in [("family@example.com", 3), ("friend@example.com", 2), ("solo@example.com", 1)] {
+            let invitation = json!({"email": email, "max_party_size": max_party_size});
+            let (status, body) = app.as_user(&ada, Method::POST, &format!("{}/invitations", org), Some(invitation)).await;
+            assert_eq!(status, StatusCode::CREATED, "{}", body);
+            rsvps.push(format!("/api/invitations/guest/rsvp?token={}", body["data"]["invite_token"]["token"].as_str().unwrap()));
+        }
+
+        let (status, body) = app.post(&rsvps[0], json!({"response": "accepted", "guest_count": 4})).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "PARTY_SIZE_INVALID");
+        let too_many_names = json!({"response": "accepted", "guest_count": 2, "guest_names": ["Bo", "Cy"]});
+        let (_, body) = app.post(&rsvps[0], too_many_names).await;
+        assert_eq!(body["code"], "GUEST_NAMES_TOO_MANY");
+        let (status, body) = app.post(&rsvps[0], json!({"response": "accepted", "guest_count": 3, "guest_names": ["Bo", "Cy"]})).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["data"]["invitation"]["guest_names"], json!(["Bo", "Cy"]));
+
+        // a party of two doesn't fit in the last place, one guest does
+        let (status, body) = app.post(&rsvps[1], json!({"response": "accepted", "guest_count": 2})).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["code"], "ORG_CAPACITY_REACHED");
+        let (status, _) = app.post(&rsvps[1], json!({"response": "accepted"})).await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, body) = app.post(&rsvps[2], json!({"response": "accepted"})).await;
+        assert_eq!(body["code"], "ORG_CAPACITY_REACHED");
+        // a guest changing their answer frees their places
+        let (_, body) = app.post(&rsvps[0], json!({"response": "declined"})).await;
+        assert_eq!(body["data"]["invitation"]["guest_count"], Value::Null);
+
+        let (status, body) = app.as_user(&ada, Method::GET, &format!("{}/attendance", org), None).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let attendance = &body["data"]["attendance"];
+        assert_eq!(
+            (&attendance["accepted"], &attendance["declined"], &attendance["pending"]),
+            (&json!(1), &json!(1), &json!(1))
+        );
+        assert_eq!((&attendance["guests"], &attendance["remaining"]), (&json!(1), &json!(3)));
+    }
+}
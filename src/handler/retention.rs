@@ -0,0 +1,17 @@
+use std::sync::Arc;
+
+use axum::{extract::State, response::IntoResponse};
+use serde_json::json;
+
+use crate::{error::AppError, extract::Json, AppState};
+
+/// The retention policies and how many rows pruning has removed under each.
+pub async fn retention_handler(State(data): State<Arc<AppState>>) -> impl IntoResponse {
+    Json(json!({"status": "success", "retention": data.retention.to_json()}))
+}
+
+/// A dry run: how many rows each policy would remove now, without removing any.
+pub async fn retention_preview_handler(State(data): State<Arc<AppState>>) -> Result<impl IntoResponse, AppError> {
+    let preview = data.retention.preview(&data).await?;
+    Ok(Json(json!({"status": "success", "preview": preview})))
+}
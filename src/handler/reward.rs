@@ -0,0 +1,80 @@
+use std::sync::Arc;
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::{
+    audit,
+    error::AppError,
+    extract::{Json, Path, Sanitized},
+    model::RewardRuleModel,
+    rewards,
+    schema::CreateRewardRuleSchema,
+    AppState,
+};
+
+pub async fn reward_rules_list_handler(State(data): State<Arc<AppState>>) -> Result<impl IntoResponse, AppError> {
+    let rules = sqlx::query_as!(RewardRuleModel, "SELECT * FROM reward_rules ORDER by created_at, id")
+        .fetch_all(&data.db)
+        .await?;
+
+    Ok(Json(json!({
+        "status": "success",
+        "results": rules.len(),
+        "rules": rules
+    })))
+}
+
+/// Adds a rule, its winners are awarded at the next evaluation.
+pub async fn create_reward_rule_handler(
+    State(data): State<Arc<AppState>>,
+    Sanitized(body): Sanitized<CreateRewardRuleSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    if body.size < 1 || (body.badge.is_none() && body.points.is_none()) {
+        return Err(AppError::RewardRuleInvalid);
+    }
+    let mut tx = data.db.begin().await?;
+
+    let rule = sqlx::query_as!(
+        RewardRuleModel,
+        "INSERT INTO reward_rules (name, kind, size, badge, points) VALUES ($1, $2, $3, $4, $5) RETURNING *",
+        body.name,
+        body.kind.as_str(),
+        body.size,
+        body.badge,
+        body.points
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    audit::record(&mut *tx, "reward.rule_added", json!(rule)).await?;
+    tx.commit().await?;
+
+    Ok((StatusCode::CREATED, Json(json!({"status": "success","data": json!({ "rule": rule })}))))
+}
+
+/// Removes a rule. What it already awarded is kept.
+pub async fn delete_reward_rule_handler(
+    Path(id): Path<Uuid>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let mut tx = data.db.begin().await?;
+
+    let removed = sqlx::query_as!(RewardRuleModel, "DELETE FROM reward_rules WHERE id = $1 RETURNING *", id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(AppError::RewardRuleNotFound(id))?;
+
+    audit::record(&mut *tx, "reward.rule_removed", json!(removed)).await?;
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Evaluates the rules now instead of waiting for the scheduled run.
+pub async fn evaluate_rewards_handler(State(data): State<Arc<AppState>>) -> Result<impl IntoResponse, AppError> {
+    let awarded = rewards::evaluate(&data).await?;
+
+    Ok(Json(json!({"status": "success", "awarded": awarded})))
+}
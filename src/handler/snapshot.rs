@@ -0,0 +1,31 @@
+use std::sync::Arc;
+
+use axum::{extract::State, http::header, response::IntoResponse};
+use serde_json::json;
+
+use crate::{audit, error::AppError, snapshot, stream, AppState};
+
+/// Streams an encrypted snapshot of the core tables as it's read, see
+/// `snapshot::write`. A failure partway through ends the download early,
+/// without the last frame a complete snapshot has.
+pub async fn snapshot_handler(State(data): State<Arc<AppState>>) -> Result<impl IntoResponse, AppError> {
+    let cipher = snapshot::key().ok_or(AppError::SnapshotKeyMissing)?;
+    let taken_at = data.clock.now();
+    let file_name = snapshot::file_name(taken_at);
+    audit::record(&data.db, "snapshot.exported", json!({"file": file_name, "via": "api"})).await?;
+
+    let (mut writer, reader) = tokio::io::duplex(64 * 1024);
+    tokio::spawn(async move {
+        if let Err(e) = snapshot::write(&data.db, cipher, taken_at, &mut writer).await {
+            println!("🔥 Snapshot failed: {}", e);
+        }
+    });
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", file_name)),
+        ],
+        stream::read(reader),
+    ))
+}
@@ -0,0 +1,143 @@
+use std::{collections::BTreeSet, sync::Arc};
+
+use axum::{extract::State, response::IntoResponse};
+use serde_json::json;
+use uuid::Uuid;
+
+use super::{
+    audience_allows,
+    org::{invitation_event, ORG_ADMIN_ROLES},
+    user_memberships,
+};
+use crate::{
+    error::AppError,
+    events,
+    extract::{Json, Query},
+    fields::Fields,
+    model::{OrgInvitationModel, TombstoneModel, UserModel},
+    privacy,
+    schema::SyncOptions,
+    session::Viewer,
+    AppState,
+};
+
+const MAX_SYNC_CHANGES: i64 = 500;
+
+/// Everything that changed since `since`, the `cursor` of the previous sync,
+/// for clients that keep a local copy, as the signed-in user sees it. Users
+/// and invitations come back as they are now, whatever was written to them
+/// in between, invitations without the address they went to. The tombstones
+/// of the ones deleted, and the users no longer visible to the user, are
+/// listed under `deleted`.
+/// Without `since` only the cursor to start from is returned, the client
+/// fetches the lists once and syncs from there.
+pub async fn sync_handler(
+    Query(opts): Query<SyncOptions>,
+    Viewer(user_id): Viewer,
+    fields: Fields,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let Some(since) = opts.since else {
+        let cursor = sqlx::query_scalar!("SELECT MAX(id) FROM events").fetch_one(&data.db).await?;
+        return Ok(Json(json!({"status": "success", "cursor": cursor.unwrap_or(0), "has_more": false})));
+    };
+
+    let rows = sqlx::query!(
+        "SELECT id, event_type, payload, created_at FROM events WHERE id > $1 ORDER BY id LIMIT $2",
+        since,
+        MAX_SYNC_CHANGES
+    )
+    .fetch_all(&data.db)
+    .await?;
+    let has_more = rows.len() as i64 == MAX_SYNC_CHANGES;
+    let cursor = rows.last().map_or(since, |row| row.id);
+
+    let memberships = user_memberships(&data, user_id).await;
+    let schema_version = opts.schema_version.unwrap_or(data.sse.schema_version);
+    let mut user_ids = BTreeSet::new();
+    let mut invitation_ids = BTreeSet::new();
+    let mut feed = Vec::new();
+    for row in rows {
+        if !audience_allows(&row.payload, user_id, &memberships) {
+            continue;
+        }
+        // deletions come from the tombstones below
+        if row.event_type == "entity_deleted" {
+            continue;
+        }
+        if row.event_type == "row_changed" {
+            let changed = &row.payload["event_data"];
+            let Some(id) = changed["id"].as_str().and_then(|id| id.parse::<Uuid>().ok()) else {
+                continue;
+            };
+            match changed["table"].as_str() {
+                Some("users") => {
+                    user_ids.insert(id);
+                }
+                Some("org_invitations") => {
+                    invitation_ids.insert(id);
+                }
+                _ => {}
+            }
+            continue;
+        }
+        let mut event = row.payload;
+        event["event_id"] = json!(row.id);
+        event["created_at"] = json!(row.created_at);
+        feed.push(fields.select(events::render(&event, schema_version)));
+    }
+
+    let user_ids: Vec<Uuid> = user_ids.into_iter().collect();
+    let users = sqlx::query_as!(UserModel, "SELECT * FROM users WHERE id = ANY($1) ORDER BY id", &user_ids)
+        .fetch_all(&data.db)
+        .await?;
+    let users = privacy::present_all(&data.db, &users, user_id).await?;
+
+    let invitation_ids: Vec<Uuid> = invitation_ids.into_iter().collect();
+    let invitations = sqlx::query_as!(
+        OrgInvitationModel,
+        "SELECT * FROM org_invitations WHERE id = ANY($1) ORDER BY id",
+        &invitation_ids
+    )
+    .fetch_all(&data.db)
+    .await?;
+    let invitations: Vec<serde_json::Value> = invitations.iter().map(invitation_event).collect();
+
+    let tombstones = sqlx::query_as!(
+        TombstoneModel,
+        "SELECT entity_type, entity_id, org_id, deleted_at FROM tombstones WHERE event_id > $1 AND event_id <= $2 ORDER BY event_id",
+        since,
+        cursor
+    )
+    .fetch_all(&data.db)
+    .await?;
+    let mut deleted: Vec<serde_json::Value> = tombstones
+        .iter()
+        .filter(|tombstone| {
+            tombstone.org_id.is_none_or(|org_id| {
+                memberships
+                    .iter()
+                    .any(|(member_org, role)| *member_org == org_id && ORG_ADMIN_ROLES.contains(&role.as_str()))
+            })
+        })
+        .map(|tombstone| json!(tombstone))
+        .collect();
+    // users who went private since are gone as far as this client goes
+    for id in &user_ids {
+        let exists = users.iter().any(|user| user["id"] == json!(id));
+        let tombstoned = tombstones.iter().any(|tombstone| tombstone.entity_type == "user" && tombstone.entity_id == *id);
+        if !exists && !tombstoned {
+            deleted.push(json!({"type": "user", "id": id}));
+        }
+    }
+
+    Ok(Json(json!({
+        "status": "success",
+        "users": fields.select(json!(users)),
+        "invitations": invitations,
+        "events": feed,
+        "deleted": deleted,
+        "cursor": cursor,
+        "has_more": has_more
+    })))
+}
@@ -0,0 +1,181 @@
+use std::{sync::Arc, time::Instant};
+
+use axum::{
+    body::{self, Body, Full},
+    extract::State,
+    http::Request,
+    middleware::Next,
+    response::Response,
+};
+use serde_json::Value;
+use tokio::sync::mpsc;
+
+use crate::{config, report::REQUEST_ID_HEADER, AppState};
+
+const REDACTED: &str = "[REDACTED]";
+
+enum Sink {
+    Off,
+    Stdout,
+    // entries are posted one by one from a background task
+    Http(mpsc::Sender<Value>),
+}
+
+/// Access log for the API: one JSON entry per request with its method, path,
+/// status and latency. Failed requests also get their request and response
+/// bodies, truncated and with the configured fields redacted.
+pub struct HttpLog {
+    sink: Sink,
+    redact_fields: Vec<String>,
+    body_limit: usize,
+}
+
+impl HttpLog {
+    /// `HTTP_LOG_SINK` is `stdout` (the default), `off`, or an http(s) URL
+    /// that entries are POSTed to. `HTTP_LOG_REDACT` is a comma separated list
+    /// of body fields to hide, `email,phone` unless set, and
+    /// `HTTP_LOG_BODY_LIMIT` caps logged bodies at that many characters. All
+    /// three take effect on a configuration reload.
+    pub fn from_env() -> Self {
+        let sink = match config::var("HTTP_LOG_SINK").as_deref() {
+            None | Some("stdout") => Sink::Stdout,
+            Some("off") => Sink::Off,
+            Some(url) => Sink::Http(spawn_http_sink(url.to_string())),
+        };
+        let redact_fields = config::var("HTTP_LOG_REDACT")
+            .unwrap_or_else(|| "email,phone".to_string())
+            .split(',')
+            .map(|field| field.trim().to_lowercase())
+            .filter(|field| !field.is_empty())
+            .collect();
+        let body_limit = config::var("HTTP_LOG_BODY_LIMIT")
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(1024);
+
+        HttpLog {
+            sink,
+            redact_fields,
+            body_limit,
+        }
+    }
+
+    fn enabled(&self) -> bool {
+        !matches!(self.sink, Sink::Off)
+    }
+
+    fn write(&self, entry: Value) {
+        match &self.sink {
+            Sink::Off => {}
+            Sink::Stdout => println!("{}", entry),
+            Sink::Http(sender) => {
+                // a slow sink loses entries rather than holding up requests
+                let _ = sender.try_send(entry);
+            }
+        }
+    }
+
+    fn redact(&self, value: &mut Value) {
+        match value {
+            Value::Object(map) => {
+                for (key, field) in map.iter_mut() {
+                    if self.redact_fields.contains(&key.to_lowercase()) {
+                        *field = Value::String(REDACTED.to_string());
+                    } else {
+                        self.redact(field);
+                    }
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(|item| self.redact(item)),
+            // error messages can quote the address that was rejected
+            Value::String(text) if self.redact_fields.iter().any(|f| f == "email") && text.contains('@') => {
+                *text = text
+                    .split(' ')
+                    .map(|word| if word.contains('@') { REDACTED } else { word })
+                    .collect::<Vec<_>>()
+                    .join(" ");
+            }
+            _ => {}
+        }
+    }
+
+    // bodies that aren't JSON can't be redacted field by field, so only their size is kept
+    fn excerpt(&self, bytes: &[u8]) -> Option<Value> {
+        if bytes.is_empty() {
+            return None;
+        }
+        let Ok(mut value) = serde_json::from_slice::<Value>(bytes) else {
+            return Some(Value::String(format!("<{} bytes, not JSON>", bytes.len())));
+        };
+        self.redact(&mut value);
+
+        let text = value.to_string();
+        Some(Value::String(match text.char_indices().nth(self.body_limit) {
+            Some((end, _)) => format!("{}…", &text[..end]),
+            None => text,
+        }))
+    }
+}
+
+fn spawn_http_sink(url: String) -> mpsc::Sender<Value> {
+    let (sender, mut receiver) = mpsc::channel::<Value>(1024);
+    tokio::spawn(async move {
+        let client = reqwest::Client::new();
+        while let Some(entry) = receiver.recv().await {
+            if let Err(e) = client.post(&url).json(&entry).send().await {
+                println!("🔥 Failed to ship request log: {}", e);
+            }
+        }
+    });
+    sender
+}
+
+pub async fn log_requests(
+    State(data): State<Arc<AppState>>,
+    req: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    let log = data.http_log.get();
+    if !log.enabled() {
+        return next.run(req).await;
+    }
+
+    let started = Instant::now();
+    let method = req.method().to_string();
+    let path = req.uri().path().to_string();
+    let request_id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+
+    // the body is kept around in case the request fails and it gets logged
+    let (parts, req_body) = req.into_parts();
+    let req_bytes = hyper::body::to_bytes(req_body).await.unwrap_or_default();
+    let response = next
+        .run(Request::from_parts(parts, Body::from(req_bytes.clone())))
+        .await;
+
+    let status = response.status();
+    let mut entry = serde_json::json!({
+        "timestamp": chrono::Utc::now(),
+        "request_id": request_id,
+        "method": method,
+        "path": path,
+        "status": status.as_u16(),
+        "latency_ms": started.elapsed().as_secs_f64() * 1000.0,
+    });
+
+    // only error responses are buffered, successful ones may be SSE streams
+    let response = if status.is_client_error() || status.is_server_error() {
+        let (parts, res_body) = response.into_parts();
+        let res_bytes = hyper::body::to_bytes(res_body).await.unwrap_or_default();
+        entry["request_body"] = log.excerpt(&req_bytes).into();
+        entry["response_body"] = log.excerpt(&res_bytes).into();
+        Response::from_parts(parts, body::boxed(Full::from(res_bytes)))
+    } else {
+        response
+    };
+
+    log.write(entry);
+    response
+}
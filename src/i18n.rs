@@ -0,0 +1,107 @@
+use std::{collections::HashMap, sync::OnceLock};
+
+use axum::{
+    http::{header, HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+};
+
+pub const DEFAULT_LOCALE: &str = "en";
+
+// every locale file maps message codes to templates with `{name}` placeholders
+const CATALOG_FILES: [(&str, &str); 3] = [
+    ("en", include_str!("../locales/en.json")),
+    ("fr", include_str!("../locales/fr.json")),
+    ("es", include_str!("../locales/es.json")),
+];
+
+tokio::task_local! {
+    static LOCALE: &'static str;
+}
+
+fn catalogs() -> &'static HashMap<&'static str, HashMap<String, String>> {
+    static CATALOGS: OnceLock<HashMap<&'static str, HashMap<String, String>>> = OnceLock::new();
+    CATALOGS.get_or_init(|| {
+        CATALOG_FILES
+            .iter()
+            .map(|(locale, file)| {
+                let messages = serde_json::from_str(file)
+                    .unwrap_or_else(|e| panic!("locales/{}.json is not valid: {}", locale, e));
+                (*locale, messages)
+            })
+            .collect()
+    })
+}
+
+/// Picks the best supported locale from an `Accept-Language` header, honoring
+/// q-values and falling back from `fr-CA` to `fr`.
+pub fn negotiate(accept_language: Option<&str>) -> &'static str {
+    let Some(accept_language) = accept_language else {
+        return DEFAULT_LOCALE;
+    };
+
+    let mut ranges: Vec<(&str, f32)> = accept_language
+        .split(',')
+        .filter_map(|range| {
+            let mut parts = range.trim().split(';');
+            let tag = parts.next()?.trim();
+            let quality = parts
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .and_then(|q| q.parse().ok())
+                .unwrap_or(1.0);
+            (!tag.is_empty() && quality > 0.0).then_some((tag, quality))
+        })
+        .collect();
+    // stable, so equally weighted ranges keep the client's order
+    ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    ranges
+        .iter()
+        .find_map(|(tag, _)| {
+            let primary = tag.split('-').next().unwrap_or(tag);
+            CATALOG_FILES
+                .iter()
+                .map(|(locale, _)| *locale)
+                .find(|locale| locale.eq_ignore_ascii_case(primary))
+        })
+        .unwrap_or(DEFAULT_LOCALE)
+}
+
+/// The locale negotiated for the request being handled.
+pub fn current() -> &'static str {
+    LOCALE.try_with(|locale| *locale).unwrap_or(DEFAULT_LOCALE)
+}
+
+/// Renders the message for `code` in the current locale, falling back to
+/// English and then to the code itself.
+pub fn t(code: &str, args: &[(&str, String)]) -> String {
+    let catalogs = catalogs();
+    let template = catalogs
+        .get(current())
+        .and_then(|messages| messages.get(code))
+        .or_else(|| catalogs.get(DEFAULT_LOCALE).and_then(|messages| messages.get(code)));
+
+    let Some(template) = template else {
+        return code.to_string();
+    };
+
+    args.iter().fold(template.clone(), |message, (name, value)| {
+        message.replace(&format!("{{{}}}", name), value)
+    })
+}
+
+/// Negotiates the locale from `Accept-Language` and makes it available to
+/// everything that runs while handling the request.
+pub async fn locale_layer<B>(req: Request<B>, next: Next<B>) -> Response {
+    let locale = negotiate(
+        req.headers()
+            .get(header::ACCEPT_LANGUAGE)
+            .and_then(|value| value.to_str().ok()),
+    );
+
+    let mut response = LOCALE.scope(locale, next.run(req)).await;
+    response
+        .headers_mut()
+        .insert(header::CONTENT_LANGUAGE, HeaderValue::from_static(locale));
+    response
+}
@@ -0,0 +1,9 @@
+use uuid::Uuid;
+
+/// An id for a new row: a UUIDv7, which starts with the time it was made,
+/// so rows inserted together sit together in the primary key index and
+/// sorting by id is sorting by creation. Tables that use these have no id
+/// default in the database, the app always hands one over.
+pub fn new() -> Uuid {
+    Uuid::now_v7()
+}
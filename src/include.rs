@@ -0,0 +1,53 @@
+use crate::error::AppError;
+
+const DEFAULT_LIMIT: usize = 10;
+const MAX_LIMIT: usize = 50;
+
+/// Parses an `?include=` like `referrals,events:5`, the related collections
+/// to embed in a response, each with how many items of it to embed. Only
+/// `relations` may be asked for, each at most `MAX_LIMIT` items.
+pub fn parse(include: Option<&str>, relations: &[&'static str]) -> Result<Vec<(&'static str, usize)>, AppError> {
+    let Some(include) = include else {
+        return Ok(Vec::new());
+    };
+
+    let invalid = || AppError::IncludeInvalid(relations.to_vec());
+    let mut included: Vec<(&'static str, usize)> = Vec::new();
+    for item in include.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+        let (name, limit) = match item.split_once(':') {
+            Some((name, limit)) => (name, limit.parse().map_err(|_| invalid())?),
+            None => (item, DEFAULT_LIMIT),
+        };
+        let relation = relations.iter().find(|relation| **relation == name).ok_or_else(invalid)?;
+        if limit == 0 || limit > MAX_LIMIT {
+            return Err(invalid());
+        }
+        included.retain(|(other, _)| other != relation);
+        included.push((relation, limit));
+    }
+    Ok(included)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RELATIONS: [&str; 2] = ["referrals", "events"];
+
+    #[test]
+    fn parses_relations_with_limits() {
+        assert_eq!(parse(None, &RELATIONS).unwrap(), vec![]);
+        assert_eq!(
+            parse(Some("referrals, events:5"), &RELATIONS).unwrap(),
+            vec![("referrals", DEFAULT_LIMIT), ("events", 5)]
+        );
+        assert_eq!(parse(Some("events,events:3"), &RELATIONS).unwrap(), vec![("events", 3)]);
+    }
+
+    #[test]
+    fn rejects_unknown_relations_and_limits() {
+        for include in ["contacts", "events:0", "events:1000", "events:many"] {
+            assert!(matches!(parse(Some(include), &RELATIONS), Err(AppError::IncludeInvalid(_))));
+        }
+    }
+}
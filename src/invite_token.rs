@@ -0,0 +1,251 @@
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Query},
+    http::request::Parts,
+};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use hmac::{Hmac, Mac};
+use serde_json::json;
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::{error::AppError, model::OrgInvitationModel, secrets, timestamp, AppState};
+
+// a week, long enough for an invite email to sit unread for a few days
+const DEFAULT_TTL_HOURS: i64 = 168;
+
+/// Issues and checks the signed links in invite emails, which let the
+/// invited guest see and answer their invitation without an account.
+pub struct InviteTokens {
+    ttl: Duration,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TokenError {
+    Malformed,
+    Expired,
+    Mismatch,
+}
+
+impl InviteTokens {
+    /// Tokens are signed with `INVITE_TOKEN_SECRET`, none are issued while
+    /// it is unset. They stay valid for `INVITE_TOKEN_TTL_HOURS`, a week
+    /// unless set. The secret is looked up on every use, so a rotated one
+    /// takes over without a restart; put the old one in
+    /// `INVITE_TOKEN_SECRET_PREVIOUS` to keep links signed with it working.
+    pub fn from_env() -> Self {
+        let ttl_hours = std::env::var("INVITE_TOKEN_TTL_HOURS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(DEFAULT_TTL_HOURS);
+
+        InviteTokens {
+            ttl: Duration::hours(ttl_hours),
+        }
+    }
+
+    pub fn is_configured(&self) -> bool {
+        secrets::var("INVITE_TOKEN_SECRET").is_some()
+    }
+
+    /// A token for the current version of an invitation, as
+    /// `<invitation id>.<version>.<expiry unix time>.<hex hmac>`, with the
+    /// HMAC-SHA256 taken over everything before the last dot.
+    pub fn issue(&self, invitation: &OrgInvitationModel, now: DateTime<Utc>) -> Option<(String, DateTime<Utc>)> {
+        let expires_at = now + self.ttl;
+        let claims = format!("{}.{}.{}", invitation.id, invitation.token_version, expires_at.timestamp());
+        let secret = secrets::var("INVITE_TOKEN_SECRET")?;
+        let signature = hex::encode(mac(&secret, &claims).finalize().into_bytes());
+        Some((format!("{}.{}", claims, signature), expires_at))
+    }
+
+    /// The response field carrying a fresh token, `null` while tokens are
+    /// not configured.
+    pub fn to_json(&self, invitation: &OrgInvitationModel, now: DateTime<Utc>) -> serde_json::Value {
+        match self.issue(invitation, now) {
+            Some((token, expires_at)) => json!({"token": token, "expires_at": timestamp::json(&expires_at)}),
+            None => serde_json::Value::Null,
+        }
+    }
+
+    /// The invitation id and version a token was issued for, once its
+    /// signature and expiry check out.
+    pub fn verify(&self, token: &str, now: DateTime<Utc>) -> Result<(Uuid, i32), TokenError> {
+        let (claims, signature) = token.rsplit_once('.').ok_or(TokenError::Malformed)?;
+        let mut parts = claims.split('.');
+        let (Some(id), Some(version), Some(expires_at), None) = (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(TokenError::Malformed);
+        };
+        let id = id.parse::<Uuid>().map_err(|_| TokenError::Malformed)?;
+        let version = version.parse::<i32>().map_err(|_| TokenError::Malformed)?;
+        let expires_at = expires_at
+            .parse::<i64>()
+            .ok()
+            .and_then(|secs| Utc.timestamp_opt(secs, 0).single())
+            .ok_or(TokenError::Malformed)?;
+        let signature = hex::decode(signature).map_err(|_| TokenError::Malformed)?;
+
+        let signed = [secrets::var("INVITE_TOKEN_SECRET"), secrets::var("INVITE_TOKEN_SECRET_PREVIOUS")]
+            .into_iter()
+            .flatten()
+            .any(|secret| mac(&secret, claims).verify_slice(&signature).is_ok());
+        if !signed {
+            return Err(TokenError::Mismatch);
+        }
+        // only trusted once the signature matched
+        if expires_at <= now {
+            return Err(TokenError::Expired);
+        }
+        Ok((id, version))
+    }
+}
+
+fn mac(secret: &str, claims: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(claims.as_bytes());
+    mac
+}
+
+/// The holder of an invite link, taken from the `token` query parameter.
+/// Only resolves while the token is signed, unexpired and not revoked, and
+/// only ever grants access to the one invitation it was issued for.
+#[derive(Debug)]
+pub struct InvitationGuest {
+    pub invitation: OrgInvitationModel,
+}
+
+impl InvitationGuest {
+    /// The guest holding `token`, for links that carry it somewhere other
+    /// than the query.
+    pub async fn from_token(state: &AppState, token: &str) -> Result<Self, AppError> {
+        if !state.invite_tokens.is_configured() {
+            return Err(AppError::InviteTokensNotConfigured);
+        }
+
+        let (invitation_id, version) = state
+            .invite_tokens
+            .verify(token, state.clock.now())
+            .map_err(AppError::InviteToken)?;
+
+        let invitation = sqlx::query_as!(
+            OrgInvitationModel,
+            "SELECT * FROM org_invitations WHERE id = $1",
+            invitation_id
+        )
+        .fetch_optional(&state.db)
+        .await?;
+
+        // a revoked invitation, or a link the host has since replaced, reads as a bad signature
+        match invitation {
+            Some(invitation) if invitation.token_version == version => Ok(InvitationGuest { invitation }),
+            _ => Err(AppError::InviteToken(TokenError::Mismatch)),
+        }
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for InvitationGuest {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &Arc<AppState>) -> Result<Self, Self::Rejection> {
+        if !state.invite_tokens.is_configured() {
+            return Err(AppError::InviteTokensNotConfigured);
+        }
+
+        let token = Query::<HashMap<String, String>>::from_request_parts(parts, state)
+            .await
+            .ok()
+            .and_then(|Query(params)| params.get("token").cloned())
+            .ok_or(AppError::InviteToken(TokenError::Malformed))?;
+        InvitationGuest::from_token(state, &token).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+
+    use super::*;
+    use crate::testing::TestApp;
+
+    #[test]
+    fn tokens_expire_after_the_ttl() {
+        std::env::set_var("INVITE_TOKEN_SECRET", "test secret");
+        let tokens = InviteTokens { ttl: Duration::hours(168) };
+        let invitation = OrgInvitationModel {
+            id: Uuid::new_v4(),
+            org_id: Uuid::new_v4(),
+            email: String::from("guest@example.com").into(),
+            role: "member".to_string(),
+            invited_by: None,
+            accepted_at: None,
+            created_at: None,
+            token_version: 3,
+            rsvp: None,
+            rsvp_at: None,
+            email_index: String::new(),
+            tags: Vec::new(),
+            max_party_size: 1,
+            guest_count: None,
+            guest_names: Vec::new(),
+        };
+        let issued_at = Utc.timestamp_opt(1_760_520_600, 0).unwrap();
+
+        let (token, expires_at) = tokens.issue(&invitation, issued_at).unwrap();
+        assert_eq!(expires_at, issued_at + Duration::hours(168));
+        assert_eq!(tokens.verify(&token, expires_at - Duration::seconds(1)), Ok((invitation.id, 3)));
+        assert_eq!(tokens.verify(&token, expires_at), Err(TokenError::Expired));
+        assert_eq!(tokens.verify(&format!("{}0", token), issued_at), Err(TokenError::Malformed));
+    }
+
+    #[tokio::test]
+    async fn invite_links_open_only_their_own_invitation_until_revoked() {
+        std::env::set_var("INVITE_TOKEN_SECRET", "test secret");
+        let app = TestApp::new().await;
+        let ada = app.create_user("ada", "ada@example.com").await;
+        let (_, created) = app.as_user(&ada, Method::POST, "/api/orgs", Some(json!({"name": "Acme"}))).await;
+        let org = format!("/api/orgs/{}", created["data"]["organization"]["id"].as_str().unwrap());
+        let mut invited = Vec::new();
+        for email in ["guest@example.com", "other@example.com"] {
+            let (status, body) = app
+                .as_user(&ada, Method::POST, &format!("{}/invitations", org), Some(json!({"email": email})))
+                .await;
+            assert_eq!(status, StatusCode::CREATED, "{}", body);
+            invited.push(body["data"].clone());
+        }
+        let (guest, other) = (&invited[0], &invited[1]);
+        let token = guest["invite_token"]["token"].as_str().unwrap();
+
+        let (status, body) = app.get(&format!("/api/invitations/guest?token={}", token)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["invitation"]["id"], guest["invitation"]["id"]);
+        assert_eq!(body["data"]["organization"]["name"], "Acme");
+
+        let rsvp = format!("/api/invitations/guest/rsvp?token={}", token);
+        let (status, body) = app.post(&rsvp, json!({"response": "declined"})).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["invitation"]["rsvp"], "declined");
+        let answered = sqlx::query_scalar!("SELECT rsvp FROM org_invitations WHERE rsvp IS NOT NULL")
+            .fetch_all(app.db())
+            .await
+            .unwrap();
+        assert_eq!(answered, vec![Some("declined".to_string())]);
+
+        // another invitation's id under this token's signature
+        let other_id = other["invitation"]["id"].as_str().unwrap();
+        let forged = format!("{}{}", other_id, &token[other_id.len()..]);
+        let (status, body) = app.get(&format!("/api/invitations/guest?token={}", forged)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["code"], "INVITE_TOKEN_INVALID");
+
+        let revoke = format!("{}/invitations/{}/token", org, guest["invitation"]["id"].as_str().unwrap());
+        let (status, _) = app.as_user(&ada, Method::DELETE, &revoke, None).await;
+        assert!(status.is_success(), "{}", status);
+        let (status, body) = app.post(&rsvp, json!({"response": "accepted"})).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["code"], "INVITE_TOKEN_INVALID");
+    }
+}
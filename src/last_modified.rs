@@ -0,0 +1,43 @@
+use std::time::SystemTime;
+
+use axum::{
+    headers::{HeaderMapExt, IfModifiedSince, LastModified},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use sqlx::{Pool, Postgres};
+
+pub const USERS: &str = "users";
+pub const EVENTS: &str = "events";
+
+/// When a collection last changed, kept in `collection_changes` by triggers
+/// on its tables. Lets polling clients send `If-Modified-Since` and get a
+/// 304 instead of the same page again.
+pub struct Changed(Option<SystemTime>);
+
+impl Changed {
+    pub async fn of(db: &Pool<Postgres>, collection: &str) -> Result<Self, sqlx::Error> {
+        let changed_at = sqlx::query_scalar!(
+            "SELECT changed_at FROM collection_changes WHERE collection = $1",
+            collection
+        )
+        .fetch_optional(db)
+        .await?;
+        Ok(Changed(changed_at.map(SystemTime::from)))
+    }
+
+    /// A 304 if nothing changed since the client's `If-Modified-Since`.
+    pub fn not_modified(&self, headers: &HeaderMap) -> Option<Response> {
+        let changed_at = self.0?;
+        let since = headers.typed_get::<IfModifiedSince>()?;
+        (!since.is_modified(changed_at)).then(|| self.stamp(StatusCode::NOT_MODIFIED.into_response()))
+    }
+
+    /// Adds `Last-Modified` to a response.
+    pub fn stamp(&self, mut response: Response) -> Response {
+        if let Some(changed_at) = self.0 {
+            response.headers_mut().typed_insert(LastModified::from(changed_at));
+        }
+        response
+    }
+}
@@ -0,0 +1,34 @@
+use std::{sync::Arc, time::Duration};
+
+use crate::{scheduler, AppState};
+
+pub const VIEW: &str = "leaderboard";
+
+/// The referral leaderboard is served from a materialized view rather than
+/// ranked on every request, so it lags behind by up to `refresh_every`.
+pub struct Leaderboard {
+    refresh_every: Duration,
+}
+
+impl Leaderboard {
+    /// Refreshed every `LEADERBOARD_REFRESH_MINUTES`, 5 unless set.
+    pub fn from_env() -> Self {
+        let minutes = std::env::var("LEADERBOARD_REFRESH_MINUTES")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .filter(|minutes| *minutes > 0)
+            .unwrap_or(5);
+
+        Leaderboard {
+            refresh_every: Duration::from_secs(minutes * 60),
+        }
+    }
+}
+
+pub fn spawn_job(data: Arc<AppState>) {
+    let period = data.leaderboard.refresh_every;
+    scheduler::every("leaderboard_refresh", period, data, |data| async move {
+        scheduler::refresh_view(&data.db, VIEW, data.clock.now()).await?;
+        Ok(())
+    });
+}
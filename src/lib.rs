@@ -0,0 +1,248 @@
+mod achievements;
+#[cfg(feature = "bench")]
+pub mod bench;
+mod analytics;
+mod attachments;
+mod audit;
+mod billing;
+mod blocks;
+mod build_info;
+mod challenge;
+mod bus;
+mod chaos;
+mod cli;
+mod client_ip;
+mod clock;
+mod coalesce;
+mod concurrency;
+mod config;
+mod cors;
+mod email_domain;
+mod email_log;
+mod email_validation;
+mod error;
+mod events;
+mod exports;
+mod extract;
+mod fields;
+mod format;
+mod fraud;
+#[cfg(feature = "fuzz")]
+pub mod fuzz;
+mod geo;
+mod guest_list;
+mod handler;
+mod http_log;
+mod i18n;
+mod ids;
+mod include;
+mod invite_token;
+mod last_modified;
+mod leaderboard;
+mod maintenance;
+mod model;
+mod pagination;
+mod pii;
+mod privacy;
+mod query_metrics;
+mod quota;
+mod rate_limit;
+mod readiness;
+mod ref_code;
+mod report;
+mod request_signing;
+mod retention;
+mod rewards;
+mod route;
+mod sanitize;
+mod scheduler;
+mod schema;
+mod schema_check;
+mod secrets;
+mod server;
+mod session;
+mod shards;
+mod security_headers;
+mod snapshot;
+mod sse;
+mod storage;
+mod stream;
+mod suppression;
+mod tenant;
+#[cfg(test)]
+mod testing;
+mod timestamp;
+#[cfg(feature = "tls")]
+mod tls;
+mod tombstones;
+mod user_ref;
+
+use std::{net::SocketAddr, sync::Arc};
+
+use clap::Parser;
+use route::create_router;
+use tokio::sync::broadcast;
+
+use sqlx::{postgres::PgPoolOptions, Pool, Postgres};
+
+pub struct AppState {
+    db: Pool<Postgres>,
+    tx: broadcast::Sender<String>,
+    quotas: quota::Quotas,
+    billing: billing::Billing,
+    invite_tokens: invite_token::InviteTokens,
+    sessions: session::Sessions,
+    suppressions: suppression::Suppressions,
+    email_validation: email_validation::EmailValidation,
+    fraud: fraud::FraudRules,
+    geo: geo::Geo,
+    http_log: config::Reloadable<http_log::HttpLog>,
+    rate_limit: rate_limit::RateLimit,
+    challenges: challenge::Challenges,
+    maintenance: maintenance::Maintenance,
+    pagination: pagination::PageLimits,
+    query_metrics: Arc<query_metrics::QueryMetrics>,
+    sse: sse::SseRegistry,
+    analytics: analytics::Analytics,
+    leaderboard: leaderboard::Leaderboard,
+    rewards: rewards::Rewards,
+    tombstones: tombstones::Tombstones,
+    retention: retention::Retention,
+    security_headers: security_headers::SecurityHeaders,
+    trusted_proxies: client_ip::TrustedProxies,
+    request_signing: request_signing::RequestSigning,
+    bus: Option<Box<dyn bus::EventBus>>,
+    readiness: readiness::Readiness,
+    shards: shards::Shards,
+    coalescer: coalesce::Coalescer,
+    concurrency: concurrency::ConcurrencyLimits,
+    cors: Arc<config::Reloadable<cors::CorsOrigins>>,
+    storage: Box<dyn storage::StorageBackend>,
+    exports: exports::Exports,
+    attachments: attachments::Attachments,
+    clock: Arc<dyn clock::Clock>,
+}
+
+impl AppState {
+    /// Everything the handlers and jobs share, each part configured from the
+    /// environment.
+    pub(crate) async fn from_env(db: Pool<Postgres>, query_metrics: Arc<query_metrics::QueryMetrics>) -> Self {
+        let (tx, _rx) = broadcast::channel(100);
+
+        AppState {
+            shards: shards::Shards::new(db.clone()),
+            db,
+            tx,
+            quotas: quota::Quotas::from_env(),
+            billing: billing::Billing::from_env(),
+            invite_tokens: invite_token::InviteTokens::from_env(),
+            sessions: session::Sessions::from_env(),
+            suppressions: suppression::Suppressions::from_env(),
+            email_validation: email_validation::EmailValidation::from_env(),
+            fraud: fraud::FraudRules::from_env(),
+            geo: geo::Geo::from_env(),
+            http_log: config::Reloadable::new(http_log::HttpLog::from_env()),
+            rate_limit: rate_limit::RateLimit::from_env().await,
+            challenges: challenge::Challenges::from_env(),
+            query_metrics,
+            maintenance: maintenance::Maintenance::from_env(),
+            pagination: pagination::PageLimits::from_env(),
+            sse: sse::SseRegistry::from_env(),
+            analytics: analytics::Analytics::from_env(),
+            leaderboard: leaderboard::Leaderboard::from_env(),
+            rewards: rewards::Rewards::from_env(),
+            tombstones: tombstones::Tombstones::from_env(),
+            retention: retention::Retention::from_env(),
+            security_headers: security_headers::SecurityHeaders::from_env(),
+            trusted_proxies: client_ip::TrustedProxies::from_env(),
+            request_signing: request_signing::RequestSigning::from_env(),
+            bus: bus::from_env().await,
+            readiness: readiness::Readiness::from_env(),
+            coalescer: coalesce::Coalescer::default(),
+            concurrency: concurrency::ConcurrencyLimits::from_env(),
+            cors: Arc::new(config::Reloadable::new(cors::CorsOrigins::from_env())),
+            storage: storage::from_env(),
+            exports: exports::Exports::from_env(),
+            attachments: attachments::Attachments::from_env(),
+            clock: Arc::new(clock::SystemClock),
+        }
+    }
+}
+
+/// Runs the API server, or the maintenance command given on the command line.
+pub async fn run() {
+    config::init();
+    let cli = cli::Cli::parse();
+    secrets::init().await;
+    pii::check_keys();
+    session::Sessions::check_secret();
+    report::init();
+    let query_metrics = query_metrics::QueryMetrics::from_env();
+    query_metrics.install();
+
+    let database_url = secrets::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let pool = match chaos::install(PgPoolOptions::new().max_connections(10))
+        .connect(&database_url)
+        .await
+    {
+        Ok(pool) => {
+            println!("✅Connection to the database is successful!");
+            pool
+        }
+        Err(err) => {
+            println!("🔥 Failed to connect to the database: {:?}", err);
+            std::process::exit(1);
+        }
+    };
+
+    schema_check::run(&pool).await;
+
+    if let Some(command) = cli.command {
+        if let Err(err) = cli::run(command, &pool).await {
+            println!("🔥 {}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    build_info::print_banner();
+    let app_state = Arc::new(AppState::from_env(pool.clone(), query_metrics).await);
+    events::spawn_relay(app_state.clone());
+    maintenance::spawn_sync(app_state.clone());
+    analytics::spawn_jobs(app_state.clone());
+    leaderboard::spawn_job(app_state.clone());
+    rewards::spawn_job(app_state.clone());
+    achievements::spawn_engine(app_state.clone());
+    tombstones::spawn_pruning(app_state.clone());
+    retention::spawn_pruning(app_state.clone());
+    secrets::spawn_refresh();
+    request_signing::spawn_pruning(app_state.clone());
+    config::spawn_reload_on_hangup(app_state.clone());
+    exports::spawn_worker(app_state.clone());
+    exports::spawn_pruning(app_state.clone());
+    sse::spawn_reaper(app_state.clone());
+
+    let cors = cors::layer(app_state.cors.clone());
+    let app = create_router(app_state)
+        .layer(cors)
+        .into_make_service_with_connect_info::<SocketAddr>();
+
+    let tuning = server::Tuning::from_env();
+
+    #[cfg(feature = "tls")]
+    if let Some(tls) = tls::Tls::from_env() {
+        println!("🚀 Server started successfully, over HTTPS");
+        tls.serve(app, &tuning).await;
+        return;
+    }
+
+    let listener = match server::Listener::from_env() {
+        Ok(listener) => listener,
+        Err(err) => {
+            println!("🔥 Failed to open the listening socket: {}", err);
+            std::process::exit(1);
+        }
+    };
+    println!("🚀 Server started successfully");
+    server::serve(listener, &tuning, app).await.unwrap();
+}
@@ -1,63 +1,4 @@
-mod handler;
-mod model;
-mod route;
-mod schema;
-
-use std::sync::Arc;
-
-use axum::http::{
-    header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE},
-    HeaderValue, Method,
-};
-use dotenv::dotenv;
-use route::create_router;
-use tokio::sync::broadcast;
-use tower_http::cors::CorsLayer;
-
-use sqlx::{postgres::PgPoolOptions, Pool, Postgres};
-
-pub struct AppState {
-    db: Pool<Postgres>,
-    tx: broadcast::Sender<String>,
-}
-
 #[tokio::main]
 async fn main() {
-    dotenv().ok();
-    
-    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    let pool = match PgPoolOptions::new()
-        .max_connections(10)
-        .connect(&database_url)
-        .await
-    {
-        Ok(pool) => {
-            println!("✅Connection to the database is successful!");
-            pool
-        }
-        Err(err) => {
-            println!("🔥 Failed to connect to the database: {:?}", err);
-            std::process::exit(1);
-        }
-    };
-
-    let cors = CorsLayer::new()
-        .allow_origin("http://localhost:4000".parse::<HeaderValue>().unwrap())
-        .allow_methods([Method::GET, Method::POST, Method::PATCH, Method::DELETE])
-        .allow_credentials(true)
-        .allow_headers([AUTHORIZATION, ACCEPT, CONTENT_TYPE]);
-
-    let (tx, _rx) = broadcast::channel(100);
-
-    let app = create_router(Arc::new(AppState {
-        db: pool.clone(),
-        tx,
-    }))
-    .layer(cors);
-
-    println!("🚀 Server started successfully");
-    axum::Server::bind(&"0.0.0.0:3000".parse().unwrap())
-        .serve(app.into_make_service())
-        .await
-        .unwrap();
+    rust_axum_postgres_api::run().await;
 }
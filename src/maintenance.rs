@@ -0,0 +1,134 @@
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    Arc,
+};
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{Method, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde_json::json;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{error::AppError, AppState};
+
+pub const EVENT_TYPE: &str = "maintenance_mode";
+// writes that keep working: the toggle itself, a read that takes its ids in
+// a body, and what providers and mail clients send, which they wouldn't
+// retry or, for unsubscribes, must be honored
+const ALLOWED_WRITES: [&str; 5] = [
+    "/api/admin/maintenance",
+    "/api/users/batch-get",
+    "/api/billing/stripe/webhook",
+    "/api/email/webhook",
+    "/api/email/unsubscribe",
+];
+
+/// While on, the API only serves reads and answers writes with 503.
+pub struct Maintenance {
+    enabled: AtomicBool,
+    retry_after: AtomicU64,
+}
+
+impl Maintenance {
+    /// Starts on when `MAINTENANCE_MODE` is `true`. Writes are told to retry
+    /// after `MAINTENANCE_RETRY_AFTER` seconds, 300 unless set.
+    pub fn from_env() -> Self {
+        let enabled = std::env::var("MAINTENANCE_MODE").is_ok_and(|value| value == "true");
+        let retry_after = std::env::var("MAINTENANCE_RETRY_AFTER")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(300);
+
+        Maintenance {
+            enabled: AtomicBool::new(enabled),
+            retry_after: AtomicU64::new(retry_after),
+        }
+    }
+
+    pub fn set(&self, enabled: bool, retry_after: Option<u64>) {
+        if let Some(retry_after) = retry_after {
+            self.retry_after.store(retry_after, Ordering::Relaxed);
+        }
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "enabled": self.enabled.load(Ordering::Relaxed),
+            "retry_after": self.retry_after.load(Ordering::Relaxed),
+        })
+    }
+}
+
+/// Applies maintenance mode changes made on any instance, they reach every
+/// instance as events.
+pub fn spawn_sync(data: Arc<AppState>) {
+    let mut events = data.tx.subscribe();
+    tokio::spawn(async move {
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return,
+            };
+            let Ok(event) = serde_json::from_str::<serde_json::Value>(&event) else {
+                continue;
+            };
+            if event["event_type"] == EVENT_TYPE {
+                let state = &event["event_data"];
+                data.maintenance
+                    .set(state["enabled"].as_bool().unwrap_or(false), state["retry_after"].as_u64());
+            }
+        }
+    });
+}
+
+/// Turns away anything but reads, and the writes in `ALLOWED_WRITES`,
+/// while maintenance mode is on.
+pub async fn reject_writes(State(data): State<Arc<AppState>>, req: Request<Body>, next: Next<Body>) -> Response {
+    let maintenance = &data.maintenance;
+    let read = matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS)
+        || (*req.method() == Method::POST && ALLOWED_WRITES.contains(&req.uri().path()));
+    if read || !maintenance.enabled.load(Ordering::Relaxed) {
+        return next.run(req).await;
+    }
+
+    AppError::MaintenanceMode(maintenance.retry_after.load(Ordering::Relaxed)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+
+    use super::*;
+    use crate::testing::TestApp;
+
+    #[tokio::test]
+    async fn maintenance_turns_away_writes_but_not_the_ones_that_must_land() {
+        let app = TestApp::new().await;
+        let ada = app.create_user("ada", "ada@example.com").await;
+        let (status, _) = app
+            .signed(Method::POST, "/api/admin/maintenance", Some(json!({"enabled": true, "retry_after": 60})))
+            .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, body) = app.post("/api/users", json!({"user_name": "bob", "email": "bob@example.com"})).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["code"], "MAINTENANCE_MODE");
+        let (status, _) = app.get("/api/users").await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, _) = app
+            .as_user(&ada, Method::POST, "/api/users/batch-get", Some(json!({"ids": [ada["id"]]})))
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        for uri in ["/api/billing/stripe/webhook", "/api/email/webhook", "/api/email/unsubscribe?token=nope"] {
+            let (_, body) = app.post(uri, json!({})).await;
+            assert_ne!(body["code"], "MAINTENANCE_MODE", "{}", uri);
+        }
+    }
+}
@@ -2,16 +2,258 @@ use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use uuid::Uuid;
 
+use crate::pii::Pii;
+
 #[derive(Debug, FromRow, Deserialize, Serialize)]
 #[allow(non_snake_case)]
 pub struct UserModel {
     pub id: Uuid,
     pub user_name: String,
-    pub email: String,
+    pub email: Pii,
     pub ref_code: String,
     pub added_by_ref_code: i32,
-    #[serde(rename = "createdAt")]
+    pub version: i32,
+    #[serde(rename = "createdAt", default, with = "crate::timestamp::option")]
+    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(rename = "updatedAt", default, with = "crate::timestamp::option")]
+    pub updated_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(skip_serializing, default)]
+    pub email_index: String,
+}
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct ContactModel {
+    pub id: Uuid,
+    pub owner_id: Uuid,
+    pub org_id: Option<Uuid>,
+    pub name: String,
+    pub email: Pii,
+    pub phone: Pii<Option<String>>,
+    pub tags: Vec<String>,
+    // the registered user this contact's email belongs to, if any
+    pub user_id: Option<Uuid>,
+    #[serde(rename = "createdAt", default, with = "crate::timestamp::option")]
+    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(rename = "updatedAt", default, with = "crate::timestamp::option")]
+    pub updated_at: Option<chrono::DateTime<chrono::Utc>>,
+    // comes along with `SELECT *`, contacts are only looked up by it in SQL
+    #[allow(dead_code)]
+    #[serde(skip_serializing, default)]
+    pub email_index: String,
+}
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct OrganizationModel {
+    pub id: Uuid,
+    pub name: String,
+    pub plan_id: String,
+    pub stripe_customer_id: Option<String>,
+    pub stripe_subscription_id: Option<String>,
+    pub subscription_status: Option<String>,
+    // how many guests fit, counting whole parties, no limit when `None`
+    pub capacity: Option<i32>,
+    #[serde(rename = "createdAt", default, with = "crate::timestamp::option")]
+    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(rename = "updatedAt", default, with = "crate::timestamp::option")]
+    pub updated_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct PlanModel {
+    pub id: String,
+    pub name: String,
+    pub stripe_price_id: Option<String>,
+    pub invites_per_month: Option<i32>,
+    pub sse_connections: Option<i32>,
+    pub features: Vec<String>,
+    #[serde(rename = "createdAt", default, with = "crate::timestamp::option")]
+    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct OrgMemberModel {
+    pub org_id: Uuid,
+    pub user_id: Uuid,
+    pub role: String,
+    #[serde(rename = "createdAt", default, with = "crate::timestamp::option")]
+    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct OrgInvitationModel {
+    pub id: Uuid,
+    pub org_id: Uuid,
+    pub email: Pii,
+    pub role: String,
+    pub invited_by: Option<Uuid>,
+    #[serde(rename = "acceptedAt", default, with = "crate::timestamp::option")]
+    pub accepted_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(rename = "createdAt", default, with = "crate::timestamp::option")]
+    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(skip_serializing, default)]
+    pub token_version: i32,
+    pub rsvp: Option<String>,
+    #[serde(rename = "rsvpAt", default, with = "crate::timestamp::option")]
+    pub rsvp_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(skip_serializing, default)]
+    pub email_index: String,
+    pub tags: Vec<String>,
+    pub max_party_size: i32,
+    // once accepted, how many are coming and the names of those along
+    pub guest_count: Option<i32>,
+    pub guest_names: Vec<String>,
+}
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct OrgAttachmentModel {
+    pub id: Uuid,
+    pub org_id: Uuid,
+    pub file_name: String,
+    pub content_type: String,
+    pub size: i64,
+    #[serde(skip_serializing, default)]
+    pub storage_key: String,
+    pub uploaded_by: Option<Uuid>,
+    #[serde(rename = "createdAt", with = "crate::timestamp")]
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct EmailSuppressionModel {
+    pub id: Uuid,
+    pub email: Pii,
+    pub reason: String,
+    pub details: Option<String>,
+    #[serde(rename = "createdAt", default, with = "crate::timestamp::option")]
     pub created_at: Option<chrono::DateTime<chrono::Utc>>,
-    #[serde(rename = "updatedAt")]
+    #[serde(skip_serializing, default)]
+    pub email_index: String,
+}
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct EmailLogModel {
+    pub id: Uuid,
+    pub invitation_id: Option<Uuid>,
+    pub email: Pii,
+    pub status: String,
+    pub details: Option<String>,
+    #[serde(rename = "sentAt", default, with = "crate::timestamp::option")]
+    pub sent_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(rename = "deliveredAt", default, with = "crate::timestamp::option")]
+    pub delivered_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(rename = "openedAt", default, with = "crate::timestamp::option")]
+    pub opened_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(rename = "bouncedAt", default, with = "crate::timestamp::option")]
+    pub bounced_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(rename = "createdAt", default, with = "crate::timestamp::option")]
+    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(rename = "updatedAt", default, with = "crate::timestamp::option")]
+    pub updated_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct EmailDomainRuleModel {
+    pub id: Uuid,
+    pub domain: String,
+    pub rule: String,
+    pub org_id: Option<Uuid>,
+    #[serde(rename = "createdAt", default, with = "crate::timestamp::option")]
+    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct FraudFlagModel {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub referrer_id: Option<Uuid>,
+    pub reasons: Vec<String>,
+    pub details: serde_json::Value,
+    pub status: String,
+    #[serde(rename = "reviewedAt", default, with = "crate::timestamp::option")]
+    pub reviewed_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(rename = "createdAt", default, with = "crate::timestamp::option")]
+    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct ExportJobModel {
+    pub id: Uuid,
+    pub status: String,
+    pub params: serde_json::Value,
+    pub total_rows: Option<i64>,
+    pub rows_read: i64,
+    pub rows_written: i64,
+    #[serde(skip_serializing)]
+    pub artifact: Option<String>,
+    pub error: Option<String>,
+    #[serde(rename = "createdAt", with = "crate::timestamp")]
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    #[serde(rename = "startedAt", default, with = "crate::timestamp::option")]
+    pub started_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(rename = "finishedAt", default, with = "crate::timestamp::option")]
+    pub finished_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(rename = "updatedAt", with = "crate::timestamp")]
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct RewardRuleModel {
+    pub id: Uuid,
+    pub name: String,
+    pub kind: String,
+    pub size: i32,
+    pub badge: Option<String>,
+    pub points: Option<i32>,
+    #[serde(rename = "createdAt", default, with = "crate::timestamp::option")]
+    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct RewardModel {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub rule_id: Option<Uuid>,
+    pub period: String,
+    pub badge: Option<String>,
+    pub points: Option<i32>,
+    #[serde(rename = "createdAt", default, with = "crate::timestamp::option")]
+    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct BadgeModel {
+    pub user_id: Uuid,
+    pub badge: String,
+    #[serde(rename = "earnedAt", default, with = "crate::timestamp::option")]
+    pub earned_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct BlockModel {
+    pub blocker_id: Uuid,
+    pub blocked_id: Uuid,
+    #[serde(rename = "createdAt", default, with = "crate::timestamp::option")]
+    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct TombstoneModel {
+    #[serde(rename = "type")]
+    pub entity_type: String,
+    #[serde(rename = "id")]
+    pub entity_id: Uuid,
+    #[serde(skip_serializing)]
+    pub org_id: Option<Uuid>,
+    #[serde(rename = "deletedAt", with = "crate::timestamp")]
+    pub deleted_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, FromRow, Deserialize, Serialize)]
+pub struct PrivacySettingsModel {
+    pub user_id: Uuid,
+    pub profile_visibility: String,
+    pub hide_email: bool,
+    pub hide_referral_stats: bool,
+    #[serde(rename = "updatedAt", default, with = "crate::timestamp::option")]
     pub updated_at: Option<chrono::DateTime<chrono::Utc>>,
 }
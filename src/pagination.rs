@@ -0,0 +1,204 @@
+use std::sync::Arc;
+
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Query},
+    http::{request::Parts, Uri},
+};
+use serde::Deserialize;
+
+use crate::{error::AppError, AppState};
+
+/// How large a page of a list endpoint clients may ask for.
+pub struct PageLimits {
+    max: usize,
+}
+
+impl PageLimits {
+    /// At most `PAGE_SIZE_MAX` items a page, 100 unless set.
+    pub fn from_env() -> Self {
+        let max = std::env::var("PAGE_SIZE_MAX")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .filter(|max| *max > 0)
+            .unwrap_or(100);
+
+        PageLimits { max }
+    }
+}
+
+// how far into a listing a page may start, queries bind the offset as an i32
+const OFFSET_MAX: usize = i32::MAX as usize;
+
+#[derive(Deserialize, Debug, Default)]
+struct PageParams {
+    page: Option<usize>,
+    limit: Option<usize>,
+    cursor: Option<String>,
+    sort: Option<String>,
+}
+
+/// The `?page=`, `?limit=`, `?cursor=` and `?sort=` of a list endpoint, so
+/// every listing pages the same way. Pages start at 1, and a limit outside
+/// 1..=`PAGE_SIZE_MAX` is turned away rather than clamped so clients notice
+/// they got less than they asked for. A cursor is the `next_cursor` of the
+/// previous page and wins over `page`.
+#[derive(Debug, Clone)]
+pub struct Pagination {
+    page: usize,
+    limit: Option<usize>,
+    max: usize,
+    // where the page a cursor was handed out for ended
+    after: Option<usize>,
+    sort: Option<String>,
+}
+
+impl Pagination {
+    /// The page size, `default` when the client didn't ask for one.
+    pub fn limit(&self, default: usize) -> usize {
+        self.limit.unwrap_or(default).min(self.max)
+    }
+
+    pub fn offset(&self, default: usize) -> usize {
+        self.after
+            .unwrap_or_else(|| self.page.saturating_sub(1).saturating_mul(self.limit(default)))
+            .min(OFFSET_MAX)
+    }
+
+    /// The cursor for the page after this one, `None` once a page comes back
+    /// short.
+    pub fn next_cursor(&self, default: usize, results: usize) -> Option<String> {
+        let limit = self.limit(default);
+        (results >= limit).then(|| hex::encode(self.offset(default).saturating_add(limit).min(OFFSET_MAX).to_string()))
+    }
+
+    /// The order asked for, one of `keys`, or `-` and one of them for
+    /// descending order. `default` when the client didn't ask for one.
+    pub fn sort(&self, keys: &[&'static str], default: &'static str) -> Result<String, AppError> {
+        let Some(sort) = &self.sort else {
+            return Ok(default.to_string());
+        };
+        if !keys.contains(&sort.strip_prefix('-').unwrap_or(sort)) {
+            return Err(AppError::SortInvalid(keys.to_vec()));
+        }
+        Ok(sort.clone())
+    }
+}
+
+fn decode_cursor(cursor: &str) -> Option<usize> {
+    String::from_utf8(hex::decode(cursor).ok()?).ok()?.parse().ok()
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for Pagination {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        Pagination::from_uri(&parts.uri, state.pagination.max)
+    }
+}
+
+impl Pagination {
+    /// Reads the paging parameters from a request's query string.
+    pub fn from_uri(uri: &Uri, max: usize) -> Result<Self, AppError> {
+        let Query(params) = Query::<PageParams>::try_from_uri(uri).map_err(AppError::QueryInvalid)?;
+
+        let page = params.page.unwrap_or(1);
+        if page == 0 {
+            return Err(AppError::PageInvalid);
+        }
+        if params.limit.is_some_and(|limit| limit == 0 || limit > max) {
+            return Err(AppError::PageLimitInvalid(max));
+        }
+
+        let after = match params.cursor.as_deref() {
+            Some(cursor) => Some(decode_cursor(cursor).ok_or(AppError::CursorInvalid)?),
+            None => None,
+        };
+
+        Ok(Pagination {
+            page,
+            limit: params.limit,
+            max,
+            after,
+            sort: params.sort.filter(|sort| !sort.is_empty()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    fn pagination(page: usize, limit: Option<usize>, sort: Option<&str>) -> Pagination {
+        Pagination {
+            page,
+            limit,
+            max: 100,
+            after: None,
+            sort: sort.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn next_cursor_continues_where_the_page_ended() {
+        let first = pagination(3, Some(20), None);
+        let cursor = first.next_cursor(10, 20).unwrap();
+        assert_eq!(decode_cursor(&cursor), Some(60));
+        assert_eq!(first.next_cursor(10, 7), None);
+    }
+
+    #[test]
+    fn cursor_wins_over_page() {
+        let mut next = pagination(5, None, None);
+        next.after = Some(30);
+        assert_eq!(next.offset(10), 30);
+        assert_eq!(decode_cursor("not a cursor"), None);
+    }
+
+    #[test]
+    fn sort_takes_known_keys_either_way() {
+        let keys = ["created_at", "email"];
+        assert_eq!(pagination(1, None, None).sort(&keys, "-created_at").unwrap(), "-created_at");
+        assert_eq!(pagination(1, None, Some("-email")).sort(&keys, "created_at").unwrap(), "-email");
+        assert!(matches!(
+            pagination(1, None, Some("password")).sort(&keys, "created_at"),
+            Err(AppError::SortInvalid(_))
+        ));
+    }
+
+    proptest! {
+        #[test]
+        fn any_query_parses_or_is_turned_away(query in "((page|limit|cursor|sort)=[-0-9a-z_]{0,24}&?){0,4}", max in 1usize..1000) {
+            let uri: Uri = format!("/api/users?{}", query).parse().unwrap();
+            if let Ok(pagination) = Pagination::from_uri(&uri, max) {
+                prop_assert!(pagination.page >= 1);
+                prop_assert!(pagination.limit(max) <= max);
+            }
+        }
+
+        #[test]
+        fn window_math_stays_in_range(
+            page in 1usize..=usize::MAX,
+            limit in proptest::option::of(1usize..=100),
+            after in proptest::option::of(any::<usize>()),
+            default in 1usize..=100,
+            results in any::<usize>(),
+        ) {
+            let pagination = Pagination { after, ..pagination(page, limit, None) };
+            let limit = pagination.limit(default);
+            prop_assert!((1..=100).contains(&limit));
+            prop_assert!(pagination.offset(default) <= OFFSET_MAX);
+            if let Some(cursor) = pagination.next_cursor(default, results) {
+                let next = decode_cursor(&cursor).unwrap();
+                prop_assert!(next <= OFFSET_MAX);
+                prop_assert!(next >= pagination.offset(default));
+            }
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use std::{fmt, ops::Deref};
+
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Key, Nonce,
+};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{
+    error::BoxDynError,
+    postgres::{PgTypeInfo, PgValueRef},
+    Decode, Postgres, Type,
+};
+
+use crate::secrets;
+
+// marks a stored value as sealed, anything else is plaintext written before
+// encryption was turned on
+const PREFIX: &str = "enc:v1:";
+const NONCE_LEN: usize = 12;
+
+/// Personal data that is encrypted at rest: emails and phone numbers.
+/// Values are sealed with AES-256-GCM under `PII_ENCRYPTION_KEY` before
+/// they're written, and opened as rows are read, so the rest of the code
+/// only sees plaintext. Rows sealed under a key since replaced still open
+/// while it's kept as `PII_ENCRYPTION_KEY_PREVIOUS`. Both are 32 bytes in
+/// hex, and the key is required at startup, see `check_keys`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Pii<T = String>(T);
+
+impl From<String> for Pii {
+    fn from(stored: String) -> Self {
+        Pii(open(&stored).unwrap_or(stored))
+    }
+}
+
+impl From<Option<String>> for Pii<Option<String>> {
+    fn from(stored: Option<String>) -> Self {
+        Pii(stored.map(|stored| open(&stored).unwrap_or(stored)))
+    }
+}
+
+impl<T> Deref for Pii<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl fmt::Display for Pii {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+// for rows read with `FromRow`, the same as with the query macros
+impl<T: Type<Postgres>> Type<Postgres> for Pii<T> {
+    fn type_info() -> PgTypeInfo {
+        T::type_info()
+    }
+
+    fn compatible(ty: &PgTypeInfo) -> bool {
+        T::compatible(ty)
+    }
+}
+
+impl<'r, T: Decode<'r, Postgres>> Decode<'r, Postgres> for Pii<T>
+where
+    Pii<T>: From<T>,
+{
+    fn decode(value: PgValueRef<'r>) -> Result<Self, BoxDynError> {
+        Ok(T::decode(value)?.into())
+    }
+}
+
+fn key(name: &str) -> Option<Aes256Gcm> {
+    let bytes = hex::decode(secrets::var(name)?).ok().filter(|bytes| bytes.len() == 32)?;
+    Some(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&bytes)))
+}
+
+/// Stops startup without `PII_ENCRYPTION_KEY` and `PII_INDEX_KEY`, or when
+/// a key is set but isn't 32 bytes of hex, rather than storing personal data
+/// in plaintext behind an unkeyed index. `PII_KEYS_OPTIONAL=true` lets a
+/// development setup run without them, and says so at every start.
+pub fn check_keys() {
+    let optional = std::env::var("PII_KEYS_OPTIONAL").is_ok_and(|value| value == "true");
+    for name in ["PII_ENCRYPTION_KEY", "PII_INDEX_KEY"] {
+        if secrets::var(name).is_some() {
+            continue;
+        }
+        if !optional {
+            println!("🔥 {} must be set, personal data would be stored unprotected without it", name);
+            std::process::exit(1);
+        }
+        println!("🔥 PII_KEYS_OPTIONAL is set and {} isn't, personal data is stored unprotected", name);
+    }
+    for name in ["PII_ENCRYPTION_KEY", "PII_ENCRYPTION_KEY_PREVIOUS", "PII_INDEX_KEY", "SNAPSHOT_KEY"] {
+        let valid = secrets::var(name).is_none_or(|value| hex::decode(value).is_ok_and(|bytes| bytes.len() == 32));
+        if !valid {
+            println!("🔥 {} must be 32 bytes in hex", name);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// The value to store for `plain`.
+pub fn seal(plain: &str) -> String {
+    let Some(cipher) = key("PII_ENCRYPTION_KEY") else {
+        return plain.to_string();
+    };
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let sealed = cipher
+        .encrypt(&nonce, plain.as_bytes())
+        .expect("AES-GCM encrypts any length an email or phone number can have");
+    format!("{}{}{}", PREFIX, hex::encode(nonce), hex::encode(sealed))
+}
+
+pub fn seal_opt(plain: Option<&str>) -> Option<String> {
+    plain.map(seal)
+}
+
+// `None` when sealed under neither key
+fn open(stored: &str) -> Option<String> {
+    let Some(sealed) = stored.strip_prefix(PREFIX) else {
+        return Some(stored.to_string());
+    };
+    let bytes = hex::decode(sealed).ok().filter(|bytes| bytes.len() > NONCE_LEN)?;
+    let (nonce, sealed) = bytes.split_at(NONCE_LEN);
+    let opened = [key("PII_ENCRYPTION_KEY"), key("PII_ENCRYPTION_KEY_PREVIOUS")]
+        .into_iter()
+        .flatten()
+        .find_map(|cipher| cipher.decrypt(Nonce::from_slice(nonce), sealed).ok());
+    if opened.is_none() {
+        println!("🔥 Stored personal data could not be decrypted with the configured keys");
+    }
+    String::from_utf8(opened?).ok()
+}
+
+/// What equality lookups on an email go through, since the sealed value is
+/// different every time: an HMAC-SHA256 of the trimmed, lower-cased email
+/// under `PII_INDEX_KEY`. Only a development setup running with
+/// `PII_KEYS_OPTIONAL` goes without that key, and gets a plain SHA-256, which
+/// is also what the migration filled in for existing rows; setting or
+/// changing the key needs a `reseal-pii` run before lookups find those rows
+/// again.
+pub fn blind_index(email: &str) -> String {
+    let normalized = email.trim().to_lowercase();
+    match secrets::var("PII_INDEX_KEY").and_then(|key| hex::decode(key).ok()) {
+        Some(key) => {
+            let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&key).unwrap();
+            mac.update(normalized.as_bytes());
+            hex::encode(mac.finalize().into_bytes())
+        }
+        None => hex::encode(Sha256::digest(normalized.as_bytes())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Method;
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing::TestApp;
+
+    #[tokio::test]
+    async fn personal_data_is_sealed_at_rest() {
+        let app = TestApp::new().await;
+        let ada = app.create_user("ada", "Ada@Example.com").await;
+        let contacts = format!("/api/user/{}/contacts", ada["id"].as_str().unwrap());
+        let contact = json!({"name": "Carl", "email": "carl@example.com", "phone": "+1 555 0100"});
+        app.as_user(&ada, Method::POST, &contacts, Some(contact)).await;
+
+        let user = sqlx::query!("SELECT email, email_index FROM users").fetch_one(app.db()).await.unwrap();
+        assert!(user.email.starts_with(PREFIX), "{}", user.email);
+        assert!(!user.email.to_lowercase().contains("ada@"));
+        // keyed, so the index of a known address can't be computed without the key
+        assert_eq!(user.email_index, blind_index(" ada@example.com"));
+        assert_ne!(user.email_index, hex::encode(Sha256::digest(b"ada@example.com")));
+        let stored = sqlx::query!("SELECT email, phone FROM contacts").fetch_one(app.db()).await.unwrap();
+        assert!(stored.email.starts_with(PREFIX));
+        assert!(stored.phone.unwrap().starts_with(PREFIX));
+
+        // and opened again on the way out
+        let (_, listed) = app.as_user(&ada, Method::GET, &contacts, None).await;
+        assert_eq!(listed["contacts"][0]["email"], "carl@example.com");
+        assert_eq!(listed["contacts"][0]["phone"], "+1 555 0100");
+    }
+}
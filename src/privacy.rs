@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+
+use serde_json::json;
+use sqlx::{Executor, Postgres};
+use uuid::Uuid;
+
+use crate::{
+    blocks,
+    model::{PrivacySettingsModel, UserModel},
+};
+
+/// What a user shows of themselves to everyone else. They always see their
+/// whole profile themselves.
+#[derive(Debug, Clone)]
+pub struct Privacy {
+    pub profile_visibility: String,
+    pub hide_email: bool,
+    pub hide_referral_stats: bool,
+}
+
+impl Default for Privacy {
+    // emails stay hidden unless a user chooses to show theirs
+    fn default() -> Self {
+        Privacy {
+            profile_visibility: "public".to_string(),
+            hide_email: true,
+            hide_referral_stats: false,
+        }
+    }
+}
+
+impl From<PrivacySettingsModel> for Privacy {
+    fn from(settings: PrivacySettingsModel) -> Self {
+        Privacy {
+            profile_visibility: settings.profile_visibility,
+            hide_email: settings.hide_email,
+            hide_referral_stats: settings.hide_referral_stats,
+        }
+    }
+}
+
+impl Privacy {
+    /// Whether `viewer` gets to see the user's referral count, rewards and
+    /// referral stats.
+    pub fn shows_referral_stats(&self, user_id: Uuid, viewer: Option<Uuid>) -> bool {
+        viewer == Some(user_id) || (self.profile_visibility == "public" && !self.hide_referral_stats)
+    }
+
+    /// The user as `viewer` gets to see them, `None` when their profile is
+    /// private to them.
+    pub fn present(&self, user: &UserModel, viewer: Option<Uuid>) -> Option<serde_json::Value> {
+        if viewer == Some(user.id) {
+            return Some(json!(user));
+        }
+        match self.profile_visibility.as_str() {
+            "public" => {
+                let mut profile = json!(user);
+                if self.hide_email {
+                    profile.as_object_mut()?.remove("email");
+                }
+                if self.hide_referral_stats {
+                    profile.as_object_mut()?.remove("added_by_ref_code");
+                }
+                Some(profile)
+            }
+            "ref_code_only" => Some(json!({"id": user.id, "user_name": user.user_name, "ref_code": user.ref_code})),
+            _ => None,
+        }
+    }
+}
+
+pub async fn load<'e>(db: impl Executor<'e, Database = Postgres>, user_id: Uuid) -> Result<Privacy, sqlx::Error> {
+    let settings = sqlx::query_as!(PrivacySettingsModel, "SELECT * FROM privacy_settings WHERE user_id = $1", user_id)
+        .fetch_optional(db)
+        .await?;
+    Ok(settings.map(Privacy::from).unwrap_or_default())
+}
+
+/// The settings of several users at once, for lists of them.
+pub async fn load_many<'e>(
+    db: impl Executor<'e, Database = Postgres>,
+    user_ids: &[Uuid],
+) -> Result<HashMap<Uuid, Privacy>, sqlx::Error> {
+    let settings = sqlx::query_as!(
+        PrivacySettingsModel,
+        "SELECT * FROM privacy_settings WHERE user_id = ANY($1)",
+        user_ids
+    )
+    .fetch_all(db)
+    .await?;
+    Ok(settings
+        .into_iter()
+        .map(|settings| (settings.user_id, Privacy::from(settings)))
+        .collect())
+}
+
+/// The users `viewer` may see, each as they get to see them. Those who
+/// blocked the viewer are left out, as if their profile were private.
+pub async fn present_all<'e>(
+    db: impl Executor<'e, Database = Postgres> + Copy,
+    users: &[UserModel],
+    viewer: Option<Uuid>,
+) -> Result<Vec<serde_json::Value>, sqlx::Error> {
+    let ids: Vec<Uuid> = users.iter().map(|user| user.id).collect();
+    let settings = load_many(db, &ids).await?;
+    let blockers = blocks::blockers_of(db, &ids, viewer).await?;
+    Ok(users
+        .iter()
+        .filter(|user| !blockers.contains(&user.id))
+        .filter_map(|user| settings.get(&user.id).cloned().unwrap_or_default().present(user, viewer))
+        .collect())
+}
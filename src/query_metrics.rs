@@ -0,0 +1,221 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tracing::{
+    field::{Field, Visit},
+    level_filters::LevelFilter,
+    Event, Subscriber,
+};
+use tracing_subscriber::{
+    filter::Targets,
+    layer::{Context, SubscriberExt},
+    Layer,
+};
+
+use crate::report;
+
+// sqlx reports every statement it runs as an event on this target
+const SQLX_TARGET: &str = "sqlx::query";
+// upper bounds of the latency histogram buckets, anything slower goes in a last one
+const BUCKETS_MS: [f64; 12] = [1.0, 2.0, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0];
+// statements built at runtime could otherwise grow the table without bound
+const MAX_STATEMENTS: usize = 1000;
+
+struct Stats {
+    sql: String,
+    count: u64,
+    total: Duration,
+    max: Duration,
+    buckets: [u64; BUCKETS_MS.len() + 1],
+}
+
+impl Stats {
+    // the upper bound of the bucket the `p`th fastest run fell in, `None` past the last bound
+    fn percentile(&self, p: f64) -> Option<f64> {
+        let target = (self.count as f64 * p).ceil() as u64;
+        let mut seen = 0;
+        for (i, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= target {
+                return BUCKETS_MS.get(i).copied();
+            }
+        }
+        None
+    }
+
+    fn to_json(&self, fingerprint: &str) -> Value {
+        let histogram: Vec<Value> = self
+            .buckets
+            .iter()
+            .enumerate()
+            .map(|(i, count)| json!({"le_ms": BUCKETS_MS.get(i), "count": count}))
+            .collect();
+        json!({
+            "fingerprint": fingerprint,
+            "sql": self.sql,
+            "count": self.count,
+            "total_ms": millis(self.total),
+            "mean_ms": millis(self.total) / self.count as f64,
+            "max_ms": millis(self.max),
+            "p50_ms": self.percentile(0.5),
+            "p95_ms": self.percentile(0.95),
+            "p99_ms": self.percentile(0.99),
+            "histogram": histogram,
+        })
+    }
+}
+
+/// Latency of every SQL statement the app runs, grouped by statement, from
+/// the events sqlx emits for each query. Statements slower than the
+/// threshold are also logged.
+pub struct QueryMetrics {
+    slow_threshold: Duration,
+    statements: Mutex<HashMap<String, Stats>>,
+}
+
+impl QueryMetrics {
+    /// Statements taking longer than `DB_SLOW_QUERY_MS` (500 by default) are
+    /// logged. Recording starts once `install` is called.
+    pub fn from_env() -> Arc<Self> {
+        let slow_ms = std::env::var("DB_SLOW_QUERY_MS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(500);
+
+        Arc::new(QueryMetrics {
+            slow_threshold: Duration::from_millis(slow_ms),
+            statements: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Starts collecting sqlx's query events. Call it before the pool
+    /// connects.
+    pub fn install(self: &Arc<Self>) {
+        let layer = QueryLayer { metrics: self.clone() }
+            .with_filter(Targets::new().with_target(SQLX_TARGET, LevelFilter::TRACE));
+        if tracing::subscriber::set_global_default(tracing_subscriber::registry().with(layer)).is_err() {
+            println!("🔥 Query metrics not recorded, a tracing subscriber is already installed");
+        }
+    }
+
+    fn record(&self, sql: &str, elapsed: Duration) {
+        let sql = sql.split_whitespace().collect::<Vec<_>>().join(" ");
+        let fingerprint = fingerprint(&sql);
+
+        if elapsed >= self.slow_threshold {
+            println!(
+                "{}",
+                json!({
+                    "timestamp": chrono::Utc::now(),
+                    "request_id": report::current().request_id,
+                    "slow_query": fingerprint,
+                    "sql": sql,
+                    "elapsed_ms": millis(elapsed),
+                })
+            );
+        }
+
+        let mut statements = self.statements.lock().unwrap();
+        if statements.len() >= MAX_STATEMENTS && !statements.contains_key(&fingerprint) {
+            return;
+        }
+        let stats = statements.entry(fingerprint).or_insert_with(|| Stats {
+            sql,
+            count: 0,
+            total: Duration::ZERO,
+            max: Duration::ZERO,
+            buckets: [0; BUCKETS_MS.len() + 1],
+        });
+        stats.count += 1;
+        stats.total += elapsed;
+        stats.max = stats.max.max(elapsed);
+        let bucket = BUCKETS_MS
+            .iter()
+            .position(|bound| millis(elapsed) <= *bound)
+            .unwrap_or(BUCKETS_MS.len());
+        stats.buckets[bucket] += 1;
+    }
+
+    /// Every statement seen so far, the ones with the most total time first.
+    pub fn snapshot(&self) -> Vec<Value> {
+        let statements = self.statements.lock().unwrap();
+        let mut sorted: Vec<(&String, &Stats)> = statements.iter().collect();
+        sorted.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.total));
+        sorted
+            .into_iter()
+            .map(|(fingerprint, stats)| stats.to_json(fingerprint))
+            .collect()
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+// a short stable id for a statement, to find it across logs and metrics
+fn fingerprint(sql: &str) -> String {
+    hex::encode(&Sha256::digest(sql.as_bytes())[..8])
+}
+
+struct QueryLayer {
+    metrics: Arc<QueryMetrics>,
+}
+
+impl<S: Subscriber> Layer<S> for QueryLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut fields = QueryFields::default();
+        event.record(&mut fields);
+        // the full statement is only attached when it's longer than the summary
+        let sql = if fields.statement.trim().is_empty() {
+            fields.summary
+        } else {
+            fields.statement
+        };
+        if let Some(elapsed) = fields.elapsed {
+            self.metrics.record(&sql, elapsed);
+        }
+    }
+}
+
+#[derive(Default)]
+struct QueryFields {
+    summary: String,
+    statement: String,
+    elapsed: Option<Duration>,
+}
+
+impl Visit for QueryFields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "summary" => self.summary = value.to_string(),
+            "db.statement" => self.statement = value.to_string(),
+            _ => {}
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "elapsed" {
+            self.elapsed = parse_duration(&format!("{:?}", value));
+        }
+    }
+}
+
+// sqlx only hands out the elapsed time as `Duration`'s Debug output, e.g. `1.2ms`
+fn parse_duration(text: &str) -> Option<Duration> {
+    let split = text.find(|c: char| !(c.is_ascii_digit() || c == '.'))?;
+    let (value, unit) = text.split_at(split);
+    let value: f64 = value.parse().ok()?;
+    let secs = match unit {
+        "s" => value,
+        "ms" => value / 1e3,
+        "µs" => value / 1e6,
+        "ns" => value / 1e9,
+        _ => return None,
+    };
+    Some(Duration::from_secs_f64(secs))
+}
@@ -0,0 +1,275 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Datelike, NaiveDate, TimeZone, Utc};
+use sqlx::{PgConnection, Pool, Postgres};
+use uuid::Uuid;
+
+use crate::{error::AppError, model::PlanModel};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    /// Invitations sent in the current calendar month.
+    InvitesPerMonth,
+    /// Open SSE connections right now.
+    SseConnections,
+}
+
+impl Metric {
+    pub const ALL: [Metric; 2] = [Metric::InvitesPerMonth, Metric::SseConnections];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Metric::InvitesPerMonth => "invites_per_month",
+            Metric::SseConnections => "sse_connections",
+        }
+    }
+}
+
+/// Per-organization limits. Monthly counters live in the `org_usage` table,
+/// live connection counts are kept in memory.
+pub struct Quotas {
+    invites_per_month: i32,
+    sse_connections: i32,
+    connections: Arc<Mutex<HashMap<Uuid, i32>>>,
+}
+
+fn env_limit(name: &str, default: i32) -> i32 {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+/// The month `now` falls in, by its first day.
+pub fn current_period(now: DateTime<Utc>) -> NaiveDate {
+    now.date_naive().with_day(1).unwrap()
+}
+
+fn next_period(now: DateTime<Utc>) -> DateTime<Utc> {
+    let period = current_period(now);
+    let next = if period.month() == 12 {
+        NaiveDate::from_ymd_opt(period.year() + 1, 1, 1)
+    } else {
+        NaiveDate::from_ymd_opt(period.year(), period.month() + 1, 1)
+    };
+    Utc.from_utc_datetime(&next.unwrap().and_hms_opt(0, 0, 0).unwrap())
+}
+
+impl Quotas {
+    pub fn from_env() -> Self {
+        Quotas {
+            invites_per_month: env_limit("QUOTA_INVITES_PER_MONTH", 100),
+            sse_connections: env_limit("QUOTA_SSE_CONNECTIONS", 50),
+            connections: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    fn default_limit(&self, metric: Metric) -> i32 {
+        match metric {
+            Metric::InvitesPerMonth => self.invites_per_month,
+            Metric::SseConnections => self.sse_connections,
+        }
+    }
+
+    /// The limit that applies to an organization: its own override if it has
+    /// one, otherwise what its plan allows. Organizations without an active
+    /// subscription get the free plan, and plans without a limit for the
+    /// metric fall back to the configured default.
+    pub async fn limit(&self, db: &Pool<Postgres>, org_id: Uuid, metric: Metric) -> Result<i32, AppError> {
+        let row = sqlx::query!(
+            r#"SELECT q.max_value AS "max_value?", p.invites_per_month, p.sse_connections FROM organizations o JOIN plans p ON p.id = CASE WHEN o.subscription_status IN ('active', 'trialing') THEN o.plan_id ELSE 'free' END LEFT JOIN org_quotas q ON q.org_id = o.id AND q.metric = $2 WHERE o.id = $1"#,
+            org_id,
+            metric.as_str()
+        )
+        .fetch_optional(db)
+        .await?;
+
+        let limit = row.and_then(|row| {
+            row.max_value.or(match metric {
+                Metric::InvitesPerMonth => row.invites_per_month,
+                Metric::SseConnections => row.sse_connections,
+            })
+        });
+
+        Ok(limit.unwrap_or_else(|| self.default_limit(metric)))
+    }
+
+    /// The plan currently in effect for an organization.
+    pub async fn plan(&self, db: &Pool<Postgres>, org_id: Uuid) -> Result<PlanModel, AppError> {
+        sqlx::query_as!(
+            PlanModel,
+            "SELECT p.* FROM organizations o JOIN plans p ON p.id = CASE WHEN o.subscription_status IN ('active', 'trialing') THEN o.plan_id ELSE 'free' END WHERE o.id = $1",
+            org_id
+        )
+        .fetch_one(db)
+        .await
+        .map_err(AppError::from)
+    }
+
+    /// Counts one use of a monthly metric, failing with 402 once the month's
+    /// limit is reached: the plan's allowance is used up, and a bigger plan
+    /// is what lifts it before `reset_at`. Run it inside the transaction of the action being
+    /// counted so a rollback gives the use back.
+    pub async fn consume(
+        &self,
+        db: &Pool<Postgres>,
+        conn: &mut PgConnection,
+        org_id: Uuid,
+        metric: Metric,
+        now: DateTime<Utc>,
+    ) -> Result<(), AppError> {
+        let limit = self.limit(db, org_id, metric).await?;
+
+        let used = if limit > 0 {
+            sqlx::query_scalar!(
+                "INSERT INTO org_usage (org_id, metric, period, used) VALUES ($1, $2, $3, 1) ON CONFLICT (org_id, metric, period) DO UPDATE SET used = org_usage.used + 1 WHERE org_usage.used < $4 RETURNING used",
+                org_id,
+                metric.as_str(),
+                current_period(now),
+                limit
+            )
+            .fetch_optional(&mut *conn)
+            .await?
+        } else {
+            None
+        };
+
+        if used.is_none() {
+            return Err(AppError::QuotaExceeded {
+                metric: metric.as_str(),
+                limit,
+                reset_at: next_period(now),
+            });
+        }
+        Ok(())
+    }
+
+    /// Registers an SSE connection for the organization, failing with 429 when
+    /// it already has as many as its plan allows. That passes as soon as one
+    /// of them closes, which releases it when its guard is dropped.
+    pub async fn acquire_connection(
+        &self,
+        db: &Pool<Postgres>,
+        org_id: Uuid,
+    ) -> Result<ConnectionGuard, AppError> {
+        let limit = self.limit(db, org_id, Metric::SseConnections).await?;
+
+        let mut connections = self.connections.lock().unwrap();
+        let open = connections.entry(org_id).or_insert(0);
+        if *open >= limit {
+            return Err(AppError::ConnectionLimitReached(limit));
+        }
+        *open += 1;
+
+        Ok(ConnectionGuard {
+            org_id,
+            connections: self.connections.clone(),
+        })
+    }
+
+    pub fn open_connections(&self, org_id: Uuid) -> i32 {
+        self.connections.lock().unwrap().get(&org_id).copied().unwrap_or(0)
+    }
+
+    pub async fn usage(&self, db: &Pool<Postgres>, org_id: Uuid, now: DateTime<Utc>) -> Result<serde_json::Value, AppError> {
+        let mut usage = Vec::new();
+
+        for metric in Metric::ALL {
+            let limit = self.limit(db, org_id, metric).await?;
+            let entry = match metric {
+                Metric::SseConnections => serde_json::json!({
+                    "metric": metric.as_str(),
+                    "used": self.open_connections(org_id),
+                    "limit": limit
+                }),
+                Metric::InvitesPerMonth => {
+                    let used = sqlx::query_scalar!(
+                        "SELECT used FROM org_usage WHERE org_id = $1 AND metric = $2 AND period = $3",
+                        org_id,
+                        metric.as_str(),
+                        current_period(now)
+                    )
+                    .fetch_optional(db)
+                    .await?;
+
+                    serde_json::json!({
+                        "metric": metric.as_str(),
+                        "used": used.unwrap_or(0),
+                        "limit": limit,
+                        "period": current_period(now),
+                        "reset_at": next_period(now)
+                    })
+                }
+            };
+            usage.push(entry);
+        }
+
+        let plan = self.plan(db, org_id).await?;
+        Ok(serde_json::json!({"plan": plan.id, "features": plan.features, "metrics": usage}))
+    }
+}
+
+pub struct ConnectionGuard {
+    org_id: Uuid,
+    connections: Arc<Mutex<HashMap<Uuid, i32>>>,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        let mut connections = self.connections.lock().unwrap();
+        if let Some(open) = connections.get_mut(&self.org_id) {
+            *open -= 1;
+            if *open <= 0 {
+                connections.remove(&self.org_id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{header, Method, StatusCode};
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing::{builder, TestApp};
+
+    #[tokio::test]
+    async fn a_spent_allowance_asks_for_a_plan_and_a_full_house_for_patience() {
+        let app = TestApp::new().await;
+        let ada = app.create_user("ada", "ada@example.com").await;
+        let (_, created) = app.as_user(&ada, Method::POST, "/api/orgs", Some(json!({"name": "Acme"}))).await;
+        let org_id: Uuid = created["data"]["organization"]["id"].as_str().unwrap().parse().unwrap();
+        sqlx::query!(
+            "INSERT INTO org_quotas (org_id, metric, max_value) VALUES ($1, 'invites_per_month', 1), ($1, 'sse_connections', 0)",
+            org_id
+        )
+        .execute(app.db())
+        .await
+        .unwrap();
+
+        let invitations = format!("/api/orgs/{}/invitations", org_id);
+        let (status, _) = app
+            .as_user(&ada, Method::POST, &invitations, Some(json!({"email": "bob@example.com"})))
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+        let (status, body) = app
+            .as_user(&ada, Method::POST, &invitations, Some(json!({"email": "carl@example.com"})))
+            .await;
+        assert_eq!(status, StatusCode::PAYMENT_REQUIRED);
+        assert_eq!(body["code"], "QUOTA_EXCEEDED");
+        assert_eq!(body["limit"], 1);
+        assert!(body["reset_at"].is_string(), "{}", body);
+
+        let stream = builder(Method::GET, &format!("/api/user-events?org_id={}", org_id))
+            .header(header::USER_AGENT, "tests")
+            .header(header::AUTHORIZATION, app.bearer(&ada));
+        let (status, body) = app.send(stream, None).await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(body["code"], "CONNECTION_LIMIT_REACHED");
+        assert_eq!(body["limit"], 0);
+    }
+}
@@ -0,0 +1,310 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    async_trait,
+    body::Body,
+    extract::State,
+    http::{HeaderValue, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::{config, error::AppError, AppState};
+
+pub type BackendError = Box<dyn std::error::Error + Send + Sync>;
+
+// the memory backend forgets full buckets once it tracks this many clients
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// The outcome of taking a token from a client's bucket.
+pub struct Decision {
+    pub allowed: bool,
+    pub remaining: u32,
+    pub retry_after: Duration,
+}
+
+/// Where the token buckets are kept. Every request takes one token from its
+/// client's bucket, which holds up to `capacity` tokens and refills at
+/// `per_sec` tokens a second.
+#[async_trait]
+pub trait RateLimitBackend: Send + Sync {
+    async fn take(&self, key: &str, capacity: u32, per_sec: f64) -> Result<Decision, BackendError>;
+
+    /// The service the buckets are kept in, for backends that use one.
+    fn service(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// A round trip to that service, for the readiness check.
+    async fn ping(&self) -> Result<(), BackendError> {
+        Ok(())
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Buckets kept in process, for a single instance.
+#[derive(Default)]
+pub struct MemoryBackend {
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+#[async_trait]
+impl RateLimitBackend for MemoryBackend {
+    async fn take(&self, key: &str, capacity: u32, per_sec: f64) -> Result<Decision, BackendError> {
+        let now = Instant::now();
+        let capacity = capacity as f64;
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() >= MAX_TRACKED_CLIENTS {
+            buckets.retain(|_, bucket| {
+                bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * per_sec < capacity
+            });
+        }
+
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        bucket.tokens = (bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * per_sec).min(capacity);
+        bucket.updated = now;
+
+        let allowed = bucket.tokens >= 1.0;
+        if allowed {
+            bucket.tokens -= 1.0;
+        }
+        Ok(Decision {
+            allowed,
+            remaining: bucket.tokens as u32,
+            retry_after: Duration::from_secs_f64(((1.0 - bucket.tokens) / per_sec).max(0.0)),
+        })
+    }
+}
+
+#[cfg(feature = "redis")]
+mod redis_backend {
+    use super::*;
+
+    // Refills and takes in one step so instances sharing a bucket can't race.
+    // Redis' own clock is used so instances don't need to agree on the time.
+    const TOKEN_BUCKET: &str = r#"
+local capacity = tonumber(ARGV[1])
+local per_ms = tonumber(ARGV[2]) / 1000
+local time = redis.call('TIME')
+local now = time[1] * 1000 + math.floor(time[2] / 1000)
+
+local bucket = redis.call('HMGET', KEYS[1], 'tokens', 'updated')
+local tokens = tonumber(bucket[1]) or capacity
+local updated = tonumber(bucket[2]) or now
+tokens = math.min(capacity, tokens + (now - updated) * per_ms)
+
+local allowed = 0
+if tokens >= 1 then
+    tokens = tokens - 1
+    allowed = 1
+end
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'updated', now)
+redis.call('PEXPIRE', KEYS[1], math.ceil(capacity / per_ms))
+
+local retry_after = 0
+if allowed == 0 then
+    retry_after = math.ceil((1 - tokens) / per_ms)
+end
+return {allowed, math.floor(tokens), retry_after}
+"#;
+
+    /// Buckets shared by every instance through Redis.
+    pub struct RedisBackend {
+        connection: redis::aio::ConnectionManager,
+        script: redis::Script,
+    }
+
+    impl RedisBackend {
+        /// Connects to `REDIS_URL`, `redis://localhost:6379` unless set.
+        pub async fn connect() -> Result<Self, BackendError> {
+            let url = crate::secrets::var("REDIS_URL").unwrap_or_else(|| "redis://localhost:6379".to_string());
+            let connection = redis::Client::open(url)?.get_connection_manager().await?;
+            Ok(RedisBackend {
+                connection,
+                script: redis::Script::new(TOKEN_BUCKET),
+            })
+        }
+    }
+
+    #[async_trait]
+    impl RateLimitBackend for RedisBackend {
+        async fn take(&self, key: &str, capacity: u32, per_sec: f64) -> Result<Decision, BackendError> {
+            let (allowed, remaining, retry_after_ms): (i64, i64, i64) = self
+                .script
+                .key(format!("invito:rate_limit:{}", key))
+                .arg(capacity)
+                .arg(per_sec)
+                .invoke_async(&mut self.connection.clone())
+                .await?;
+            Ok(Decision {
+                allowed: allowed == 1,
+                remaining: remaining.max(0) as u32,
+                retry_after: Duration::from_millis(retry_after_ms.max(0) as u64),
+            })
+        }
+
+        fn service(&self) -> Option<&'static str> {
+            Some("redis")
+        }
+
+        async fn ping(&self) -> Result<(), BackendError> {
+            redis::cmd("PING").query_async::<_, String>(&mut self.connection.clone()).await?;
+            Ok(())
+        }
+    }
+}
+
+/// Limits how many requests each client can make.
+pub struct RateLimit {
+    backend: Arc<dyn RateLimitBackend>,
+    // none when limiting is off
+    limits: config::Reloadable<Option<Limits>>,
+}
+
+#[derive(Clone, Copy)]
+struct Limits {
+    capacity: u32,
+    per_sec: f64,
+}
+
+impl RateLimit {
+    /// Limiting is off unless `RATE_LIMIT_PER_MINUTE` is set. Clients can
+    /// burst up to `RATE_LIMIT_BURST` requests, as many as the per minute
+    /// limit unless set. Both take effect on a configuration reload.
+    /// `RATE_LIMIT_BACKEND` is `memory` (the default) or `redis`, which needs
+    /// the `redis` cargo feature and shares limits between instances.
+    /// Clients are told apart by their address, past any trusted proxies.
+    pub async fn from_env() -> Self {
+        RateLimit {
+            backend: backend().await,
+            limits: config::Reloadable::new(limits()),
+        }
+    }
+
+    pub fn reload(&self) {
+        self.limits.set(limits());
+    }
+
+    /// The backend, when it keeps the buckets in an outside service and
+    /// limiting is on.
+    pub fn remote_backend(&self) -> Option<(&'static str, &dyn RateLimitBackend)> {
+        (*self.limits.get())?;
+        Some((self.backend.service()?, self.backend.as_ref()))
+    }
+}
+
+fn limits() -> Option<Limits> {
+    let per_minute: u32 = config::var("RATE_LIMIT_PER_MINUTE")
+        .and_then(|value| value.parse().ok())
+        .filter(|limit| *limit > 0)?;
+    let capacity = config::var("RATE_LIMIT_BURST")
+        .and_then(|value| value.parse().ok())
+        .filter(|burst| *burst > 0)
+        .unwrap_or(per_minute);
+
+    Some(Limits {
+        capacity,
+        per_sec: per_minute as f64 / 60.0,
+    })
+}
+
+async fn backend() -> Arc<dyn RateLimitBackend> {
+    match std::env::var("RATE_LIMIT_BACKEND").as_deref() {
+        Err(_) | Ok("memory") => Arc::new(MemoryBackend::default()),
+        #[cfg(feature = "redis")]
+        Ok("redis") => match redis_backend::RedisBackend::connect().await {
+            Ok(backend) => Arc::new(backend),
+            Err(e) => {
+                println!("🔥 Failed to connect to Redis for rate limiting: {}", e);
+                std::process::exit(1);
+            }
+        },
+        Ok(other) => {
+            println!("🔥 `{}` is not a supported rate limit backend in this build", other);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// The client's address, `unknown` when there's no telling.
+pub fn client_key(data: &AppState, req: &Request<Body>) -> String {
+    data.trusted_proxies
+        .client_ip(req.headers(), req.extensions())
+        .map_or_else(|| "unknown".to_string(), |ip| ip.to_string())
+}
+
+/// Answers 429 with a `Retry-After` once a client's bucket is empty, and
+/// tells clients how many requests they have left otherwise. Requests are
+/// let through if the backend can't be reached.
+pub async fn limit_requests(State(data): State<Arc<AppState>>, req: Request<Body>, next: Next<Body>) -> Response {
+    let Some(limits) = *data.rate_limit.limits.get() else {
+        return next.run(req).await;
+    };
+
+    let key = client_key(&data, &req);
+    let decision = match data.rate_limit.backend.take(&key, limits.capacity, limits.per_sec).await {
+        Ok(decision) => decision,
+        Err(e) => {
+            println!("🔥 Rate limit backend failed, letting the request through: {}", e);
+            return next.run(req).await;
+        }
+    };
+    if !decision.allowed {
+        return AppError::RateLimited(decision.retry_after.as_secs_f64().ceil().max(1.0) as u64).into_response();
+    }
+
+    let mut response = next.run(req).await;
+    let headers = response.headers_mut();
+    headers.insert("x-ratelimit-limit", HeaderValue::from(limits.capacity));
+    headers.insert("x-ratelimit-remaining", HeaderValue::from(decision.remaining));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use axum::{
+        extract::ConnectInfo,
+        http::{Method, StatusCode},
+    };
+
+    use super::*;
+    use crate::testing::{builder, TestApp};
+
+    #[tokio::test]
+    async fn each_client_gets_its_own_burst() {
+        let app = TestApp::with(|state| {
+            state.rate_limit = RateLimit {
+                backend: Arc::new(MemoryBackend::default()),
+                limits: config::Reloadable::new(Some(Limits { capacity: 2, per_sec: 1.0 / 60.0 })),
+            };
+        })
+        .await;
+
+        for _ in 0..2 {
+            let (status, _) = app.get("/api/version").await;
+            assert_eq!(status, StatusCode::OK);
+        }
+        let (status, body) = app.get("/api/version").await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(body["code"], "RATE_LIMITED");
+        assert!(body["retry_after"].as_u64().is_some_and(|secs| secs > 0 && secs <= 60), "{}", body);
+
+        let elsewhere = builder(Method::GET, "/api/version").extension(ConnectInfo(SocketAddr::from(([10, 0, 0, 2], 40000))));
+        let (status, _) = app.send(elsewhere, None).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+}
@@ -0,0 +1,75 @@
+use std::{
+    fmt::Display,
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use serde_json::{json, Value};
+
+use crate::AppState;
+
+/// The dependencies a readiness check probes: the database always, Redis
+/// when rate limits are kept there and the event bus when one is set.
+/// `READY_PROBE_TIMEOUT_MS` (2000 unless set) is how long each gets to
+/// answer before it counts as down.
+pub struct Readiness {
+    timeout: Duration,
+}
+
+impl Readiness {
+    pub fn from_env() -> Self {
+        let timeout_ms = std::env::var("READY_PROBE_TIMEOUT_MS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .filter(|ms| *ms > 0)
+            .unwrap_or(2000);
+
+        Readiness {
+            timeout: Duration::from_millis(timeout_ms),
+        }
+    }
+
+    /// Probes every dependency at once. `true` when all of them are up.
+    pub async fn check(&self, data: &AppState) -> (bool, Vec<Value>) {
+        let database = self.probe("database", async {
+            sqlx::query("SELECT 1").execute(&data.db).await.map(|_| ())
+        });
+        let redis = async {
+            match data.rate_limit.remote_backend() {
+                Some((service, backend)) => Some(self.probe(service, backend.ping()).await),
+                None => None,
+            }
+        };
+        let bus = async {
+            match data.bus.as_deref() {
+                Some(bus) => Some(self.probe(bus.name(), bus.ping()).await),
+                None => None,
+            }
+        };
+
+        let (database, redis, bus) = tokio::join!(database, redis, bus);
+        let probes: Vec<(bool, Value)> = [Some(database), redis, bus].into_iter().flatten().collect();
+        let ready = probes.iter().all(|(up, _)| *up);
+        (ready, probes.into_iter().map(|(_, probe)| probe).collect())
+    }
+
+    async fn probe<E: Display>(&self, name: &str, check: impl Future<Output = Result<(), E>>) -> (bool, Value) {
+        let started = Instant::now();
+        let outcome = tokio::time::timeout(self.timeout, check).await;
+        let latency_ms = (started.elapsed().as_secs_f64() * 10_000.0).round() / 10.0;
+
+        let error = match outcome {
+            Ok(Ok(())) => None,
+            Ok(Err(e)) => Some(e.to_string()),
+            Err(_) => Some(format!("no answer within {}ms", self.timeout.as_millis())),
+        };
+        let up = error.is_none();
+        let probe = json!({
+            "name": name,
+            "status": if up { "up" } else { "down" },
+            "latency_ms": latency_ms,
+            "error": error,
+        });
+        (up, probe)
+    }
+}
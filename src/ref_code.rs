@@ -0,0 +1,27 @@
+use uuid::Uuid;
+
+/// A referral code: the first three characters of the user name and four
+/// random hex digits.
+pub fn generate(user_name: &str) -> String {
+    let ref_id = Uuid::new_v4().to_string();
+    format!("{}{}", user_name.chars().take(3).collect::<String>(), &ref_id[0..4])
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn takes_whole_characters_of_any_name(user_name in "\\PC{0,12}") {
+            let code = generate(&user_name);
+            let prefix: String = user_name.chars().take(3).collect();
+            prop_assert!(code.starts_with(&prefix));
+            let suffix = &code[prefix.len()..];
+            prop_assert_eq!(suffix.len(), 4);
+            prop_assert!(suffix.chars().all(|c| c.is_ascii_hexdigit()));
+        }
+    }
+}
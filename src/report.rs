@@ -0,0 +1,155 @@
+use std::{collections::HashMap, sync::OnceLock};
+
+use axum::{
+    extract::{FromRequestParts, MatchedPath, Path, Query},
+    http::Request,
+    middleware::Next,
+    response::Response,
+};
+use serde::Serialize;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// What is known about the request an error or panic happened in.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReportContext {
+    pub request_id: Option<String>,
+    pub route: Option<String>,
+    pub user_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report<'a> {
+    pub kind: &'static str,
+    pub message: &'a str,
+    #[serde(flatten)]
+    pub context: ReportContext,
+}
+
+/// Somewhere to send server errors and panics.
+pub trait Reporter: Send + Sync {
+    fn capture(&self, report: &Report);
+}
+
+/// Writes reports to stdout, used when no other backend is configured.
+struct StdoutReporter;
+
+impl Reporter for StdoutReporter {
+    fn capture(&self, report: &Report) {
+        println!("🔥 {}", serde_json::json!(report));
+    }
+}
+
+#[cfg(feature = "sentry")]
+struct SentryReporter {
+    _guard: sentry::ClientInitGuard,
+}
+
+#[cfg(feature = "sentry")]
+impl Reporter for SentryReporter {
+    fn capture(&self, report: &Report) {
+        sentry::with_scope(
+            |scope| {
+                scope.set_tag("kind", report.kind);
+                if let Some(request_id) = &report.context.request_id {
+                    scope.set_tag("request_id", request_id);
+                }
+                if let Some(route) = &report.context.route {
+                    scope.set_tag("route", route);
+                }
+                scope.set_user(report.context.user_id.clone().map(|id| sentry::User {
+                    id: Some(id),
+                    ..Default::default()
+                }));
+            },
+            || sentry::capture_message(report.message, sentry::Level::Error),
+        );
+    }
+}
+
+tokio::task_local! {
+    static CONTEXT: ReportContext;
+}
+
+static REPORTER: OnceLock<Box<dyn Reporter>> = OnceLock::new();
+
+/// Picks the reporting backend and reports panics through it. With the
+/// `sentry` feature and `SENTRY_DSN` set reports go to Sentry, otherwise to
+/// stdout. Panics are also logged with a full backtrace.
+pub fn init() {
+    REPORTER.get_or_init(backend);
+
+    std::panic::set_hook(Box::new(|info| {
+        let request_id = current().request_id.unwrap_or_else(|| "-".to_string());
+        println!(
+            "🔥 Panic while handling request {}: {}\n{}",
+            request_id,
+            info,
+            std::backtrace::Backtrace::force_capture()
+        );
+        capture("panic", &info.to_string());
+    }));
+}
+
+#[cfg(feature = "sentry")]
+fn backend() -> Box<dyn Reporter> {
+    match crate::secrets::var("SENTRY_DSN") {
+        Some(dsn) => Box::new(SentryReporter {
+            _guard: sentry::init((dsn, sentry::ClientOptions::default())),
+        }),
+        None => Box::new(StdoutReporter),
+    }
+}
+
+#[cfg(not(feature = "sentry"))]
+fn backend() -> Box<dyn Reporter> {
+    Box::new(StdoutReporter)
+}
+
+/// Reports an error together with the context of the request being handled.
+pub fn capture(kind: &'static str, message: &str) {
+    let report = Report {
+        kind,
+        message,
+        context: current(),
+    };
+    REPORTER.get_or_init(backend).capture(&report);
+}
+
+/// The context of the request being handled, empty outside of one.
+pub fn current() -> ReportContext {
+    CONTEXT.try_with(Clone::clone).unwrap_or_default()
+}
+
+/// Collects the request id, matched route and acting user so reports made
+/// while handling the request can carry them.
+pub async fn context_layer<B: Send>(req: Request<B>, next: Next<B>) -> Response {
+    let (mut parts, body) = req.into_parts();
+
+    let request_id = parts
+        .headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let route = parts.extensions.get::<MatchedPath>().map(|path| path.as_str().to_string());
+
+    // the acting user is passed as `?user_id=`, or is the user the route is about
+    let query = Query::<HashMap<String, String>>::from_request_parts(&mut parts, &())
+        .await
+        .map(|Query(query)| query)
+        .unwrap_or_default();
+    let params = Path::<HashMap<String, String>>::from_request_parts(&mut parts, &())
+        .await
+        .map(|Path(params)| params)
+        .unwrap_or_default();
+    let user_id = query.get("user_id").or(params.get("user_id")).or(params.get("id")).cloned();
+
+    let context = ReportContext {
+        request_id,
+        route,
+        user_id,
+    };
+    CONTEXT
+        .scope(context, next.run(Request::from_parts(parts, body)))
+        .await
+}
@@ -0,0 +1,221 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{request::Parts, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::{error::AppError, scheduler, AppState};
+
+pub const CLIENT_HEADER: &str = "x-client-id";
+pub const TIMESTAMP_HEADER: &str = "x-signature-timestamp";
+pub const BODY_HASH_HEADER: &str = "x-content-sha256";
+pub const SIGNATURE_HEADER: &str = "x-signature";
+
+const DEFAULT_TOLERANCE_SECS: i64 = 300;
+
+tokio::task_local! {
+    static CLIENT: Uuid;
+}
+
+#[derive(Debug)]
+pub enum SigningError {
+    Required,
+    Malformed,
+    Expired,
+    Mismatch,
+    Replayed,
+}
+
+/// Lets backend integrations sign their requests with a secret shared with
+/// this API. A signed request carries the client's id, a unix timestamp, the
+/// hex SHA-256 of its body and the hex HMAC-SHA256, keyed with the secret, of
+/// `"<timestamp>\n<METHOD>\n<path?query>\n<body hash>"`. The admin
+/// endpoints only take signed requests.
+///
+/// Clients and their secrets are kept in `api_clients` and managed from the
+/// command line only, with `create-api-client`, `list-api-clients` and
+/// `revoke-api-client`. There's no HTTP API for them, a leaked secret
+/// shouldn't be able to mint more.
+pub struct RequestSigning {
+    tolerance_secs: i64,
+    admin_optional: bool,
+}
+
+impl RequestSigning {
+    /// Timestamps may be `SIGNED_REQUEST_TOLERANCE_SECS` off, 300 unless set.
+    /// `SIGNED_ADMIN_OPTIONAL=true` opens the admin endpoints to unsigned
+    /// requests, for local development only.
+    pub fn from_env() -> Self {
+        let admin_optional = std::env::var("SIGNED_ADMIN_OPTIONAL").is_ok_and(|value| value == "true");
+        if admin_optional {
+            println!("🔥 SIGNED_ADMIN_OPTIONAL is set, the admin endpoints answer unsigned requests");
+        }
+
+        RequestSigning {
+            tolerance_secs: std::env::var("SIGNED_REQUEST_TOLERANCE_SECS")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .filter(|secs| *secs > 0)
+                .unwrap_or(DEFAULT_TOLERANCE_SECS),
+            admin_optional,
+        }
+    }
+}
+
+/// The signed string, see [`RequestSigning`].
+fn signing_payload(timestamp: i64, parts: &Parts, body_hash: &str) -> String {
+    let path = parts.uri.path_and_query().map(|path| path.as_str()).unwrap_or("/");
+    format!("{}\n{}\n{}\n{}", timestamp, parts.method, path, body_hash)
+}
+
+/// Checks the signature of every request that names a client. Each
+/// signature is only accepted once.
+pub async fn verify_signatures(State(data): State<Arc<AppState>>, req: Request<Body>, next: Next<Body>) -> Response {
+    if !req.headers().contains_key(CLIENT_HEADER) {
+        return next.run(req).await;
+    }
+
+    let (parts, body) = req.into_parts();
+    let Ok(bytes) = hyper::body::to_bytes(body).await else {
+        return AppError::RequestSignature(SigningError::Malformed).into_response();
+    };
+    match verify(&data, &parts, &bytes).await {
+        Ok(client_id) => {
+            CLIENT
+                .scope(client_id, next.run(Request::from_parts(parts, Body::from(bytes))))
+                .await
+        }
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn verify(data: &AppState, parts: &Parts, body: &[u8]) -> Result<Uuid, AppError> {
+    let malformed = || AppError::RequestSignature(SigningError::Malformed);
+    let header = |name| parts.headers.get(name).and_then(|value| value.to_str().ok()).ok_or_else(malformed);
+
+    let client_id: Uuid = header(CLIENT_HEADER)?.parse().map_err(|_| malformed())?;
+    let timestamp: i64 = header(TIMESTAMP_HEADER)?.parse().map_err(|_| malformed())?;
+    let body_hash = header(BODY_HASH_HEADER)?;
+    let signature = hex::decode(header(SIGNATURE_HEADER)?).map_err(|_| malformed())?;
+
+    if (data.clock.now().timestamp() - timestamp).abs() > data.request_signing.tolerance_secs {
+        return Err(AppError::RequestSignature(SigningError::Expired));
+    }
+    if !hex::encode(Sha256::digest(body)).eq_ignore_ascii_case(body_hash) {
+        return Err(AppError::RequestSignature(SigningError::Mismatch));
+    }
+
+    // unknown and revoked clients look the same as a wrong signature
+    let secret = sqlx::query_scalar!(
+        "SELECT secret FROM api_clients WHERE id = $1 AND revoked_at IS NULL",
+        client_id
+    )
+    .fetch_optional(&data.db)
+    .await?
+    .ok_or(AppError::RequestSignature(SigningError::Mismatch))?;
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(signing_payload(timestamp, parts, body_hash).as_bytes());
+    mac.verify_slice(&signature)
+        .map_err(|_| AppError::RequestSignature(SigningError::Mismatch))?;
+
+    let fresh = sqlx::query!(
+        "INSERT INTO request_signatures (signature, client_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        hex::encode(&signature),
+        client_id
+    )
+    .execute(&data.db)
+    .await?
+    .rows_affected()
+        == 1;
+    if !fresh {
+        return Err(AppError::RequestSignature(SigningError::Replayed));
+    }
+
+    Ok(client_id)
+}
+
+/// Turns away every request not signed by an API client, for the routes it
+/// is layered on, unless `SIGNED_ADMIN_OPTIONAL` says otherwise. Runs inside
+/// `verify_signatures`, which has checked the signature by then.
+pub async fn require_client<B>(State(data): State<Arc<AppState>>, req: Request<B>, next: Next<B>) -> Response {
+    if current().is_none() && !data.request_signing.admin_optional {
+        return AppError::RequestSignature(SigningError::Required).into_response();
+    }
+    next.run(req).await
+}
+
+/// The client that signed the request being handled, if it was signed.
+pub fn current() -> Option<Uuid> {
+    CLIENT.try_with(|client_id| *client_id).ok()
+}
+
+/// Forgets signatures old enough that their timestamp would be turned away
+/// anyway.
+pub fn spawn_pruning(data: Arc<AppState>) {
+    scheduler::every("request_signature_pruning", Duration::from_secs(60), data, |data| async move {
+        sqlx::query!(
+            "DELETE FROM request_signatures WHERE created_at < $1",
+            data.clock.now() - chrono::Duration::seconds(data.request_signing.tolerance_secs as i64 * 2)
+        )
+        .execute(&data.db)
+        .await?;
+        Ok(())
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+    use serde_json::json;
+
+    use crate::testing::TestApp;
+
+    #[tokio::test]
+    async fn admin_endpoints_only_take_signed_requests() {
+        let app = TestApp::new().await;
+        let user = app.create_user("ada", "ada@example.com").await;
+        let body = json!({"ids": [user["id"]]});
+
+        for (method, uri, body) in [
+            (Method::POST, "/api/admin/users/bulk-delete", Some(body.clone())),
+            (Method::POST, "/api/admin/users/bulk-update", Some(json!({"ids": [user["id"]], "patch": {}}))),
+            (Method::GET, "/api/admin/snapshot", None),
+            (Method::GET, "/api/admin/email-suppressions", None),
+        ] {
+            let (status, answer) = app.request(method, uri, body).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED, "{}", uri);
+            assert_eq!(answer["code"], "REQUEST_SIGNATURE_REQUIRED");
+        }
+        let (_, list) = app.get("/api/users").await;
+        assert_eq!(list["results"], 1);
+
+        let (status, answer) = app.signed(Method::POST, "/api/admin/users/bulk-delete", Some(body)).await;
+        assert_eq!(status, StatusCode::OK, "{}", answer);
+        let (_, list) = app.get("/api/users").await;
+        assert_eq!(list["results"], 0);
+    }
+
+    #[tokio::test]
+    async fn unsigned_admin_requests_only_pass_when_opted_in() {
+        let app = TestApp::with(|state| state.request_signing.admin_optional = true).await;
+
+        let (status, _) = app.get("/api/admin/email-suppressions").await;
+        assert_eq!(status, StatusCode::OK);
+        // a signature given is still checked
+        let (status, _) = app
+            .send(
+                crate::testing::builder(Method::GET, "/api/admin/email-suppressions")
+                    .header(super::CLIENT_HEADER, uuid::Uuid::nil().to_string()),
+                None,
+            )
+            .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,190 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use serde_json::{json, Value};
+
+use crate::{scheduler, timestamp, AppState};
+
+// rows deleted per statement, so pruning a large backlog doesn't hold locks for long
+const BATCH: i64 = 10_000;
+
+struct Policy {
+    name: &'static str,
+    days: Option<i32>,
+    pruned: AtomicU64,
+}
+
+impl Policy {
+    fn from_env(name: &'static str, var: &str, default_days: i32) -> Self {
+        let days = match std::env::var(var).ok().and_then(|value| value.parse::<i32>().ok()) {
+            Some(0) => None,
+            Some(days) if days > 0 => Some(days),
+            _ => Some(default_days),
+        };
+        Policy { name, days, pruned: AtomicU64::new(0) }
+    }
+}
+
+/// How long rows that only grow are kept before the hourly pruning removes
+/// them, and how many it has removed since this instance started. Each
+/// period is in days, `0` keeps the rows forever:
+/// - `audit_logs`: `AUDIT_LOG_RETENTION_DAYS`, 365 unless set
+/// - `outbox`: events every consumer has been handed,
+///   `OUTBOX_RETENTION_DAYS`, 7 unless set. Replay can't reach back further.
+pub struct Retention {
+    policies: [Policy; 2],
+    last_run_at: Mutex<Option<DateTime<Utc>>>,
+}
+
+impl Retention {
+    pub fn from_env() -> Self {
+        Retention {
+            policies: [
+                Policy::from_env("audit_logs", "AUDIT_LOG_RETENTION_DAYS", 365),
+                Policy::from_env("outbox", "OUTBOX_RETENTION_DAYS", 7),
+            ],
+            last_run_at: Mutex::new(None),
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let policies: Vec<Value> = self
+            .policies
+            .iter()
+            .map(|policy| {
+                json!({
+                    "name": policy.name,
+                    "retention_days": policy.days,
+                    "rows_pruned": policy.pruned.load(Ordering::Relaxed),
+                })
+            })
+            .collect();
+        let last_run_at = self.last_run_at.lock().unwrap().as_ref().map(timestamp::json);
+        json!({"policies": policies, "last_run_at": last_run_at})
+    }
+
+    /// How many rows each policy would remove if pruning ran now.
+    pub async fn preview(&self, data: &AppState) -> Result<Value, sqlx::Error> {
+        let mut policies = Vec::new();
+        for policy in &self.policies {
+            let expired = match policy.days {
+                Some(days) => Some(expired(data, policy.name, days).await?),
+                None => None,
+            };
+            policies.push(json!({"name": policy.name, "retention_days": policy.days, "rows_expired": expired}));
+        }
+        Ok(json!({"policies": policies}))
+    }
+}
+
+// rows from before this are past the policy's retention, by the app's clock
+fn cutoff(data: &AppState, days: i32) -> DateTime<Utc> {
+    data.clock.now() - ChronoDuration::days(days.into())
+}
+
+async fn expired(data: &AppState, policy: &str, days: i32) -> Result<i64, sqlx::Error> {
+    let cutoff = cutoff(data, days);
+    let count = match policy {
+        "audit_logs" => {
+            sqlx::query_scalar!(
+                r#"SELECT COUNT(*) AS "count!" FROM audit_logs WHERE created_at < $1"#,
+                cutoff
+            )
+            .fetch_one(&data.db)
+            .await?
+        }
+        _ => {
+            sqlx::query_scalar!(
+                r#"SELECT COUNT(*) AS "count!" FROM events WHERE published_at < $1 AND (bus_published_at IS NOT NULL OR NOT $2)"#,
+                cutoff,
+                data.bus.is_some()
+            )
+            .fetch_one(&data.db)
+            .await?
+        }
+    };
+    Ok(count)
+}
+
+// a batch at a time until none is left, an event still owed to the bus is kept
+async fn prune(data: &AppState, policy: &str, days: i32) -> Result<u64, sqlx::Error> {
+    let cutoff = cutoff(data, days);
+    let mut pruned = 0;
+    loop {
+        let deleted = match policy {
+            "audit_logs" => sqlx::query!(
+                "DELETE FROM audit_logs WHERE id IN (SELECT id FROM audit_logs WHERE created_at < $1 LIMIT $2)",
+                cutoff,
+                BATCH
+            )
+            .execute(&data.db)
+            .await?
+            .rows_affected(),
+            _ => sqlx::query!(
+                "DELETE FROM events WHERE id IN (SELECT id FROM events WHERE published_at < $1 AND (bus_published_at IS NOT NULL OR NOT $2) LIMIT $3)",
+                cutoff,
+                data.bus.is_some(),
+                BATCH
+            )
+            .execute(&data.db)
+            .await?
+            .rows_affected(),
+        };
+        pruned += deleted;
+        if deleted < BATCH as u64 {
+            return Ok(pruned);
+        }
+    }
+}
+
+pub fn spawn_pruning(data: Arc<AppState>) {
+    scheduler::every("retention_pruning", Duration::from_secs(60 * 60), data, |data| async move {
+        for policy in &data.retention.policies {
+            let Some(days) = policy.days else {
+                continue;
+            };
+            let pruned = prune(&data, policy.name, days).await?;
+            policy.pruned.fetch_add(pruned, Ordering::Relaxed);
+            if pruned > 0 {
+                println!("✅ Pruned {} rows past retention from {}", pruned, policy.name);
+            }
+        }
+        *data.retention.last_run_at.lock().unwrap() = Some(data.clock.now());
+        Ok(())
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::{clock::ManualClock, testing::TestApp};
+
+    #[tokio::test]
+    async fn rows_expire_by_the_app_clock() {
+        let start = Utc.timestamp_opt(1_893_456_000, 0).unwrap();
+        let clock = Arc::new(ManualClock::new(start));
+        let app = TestApp::with(|state| state.clock = clock.clone()).await;
+        let data = app.state();
+        sqlx::query!("INSERT INTO audit_logs (action, details, created_at) VALUES ('test', '{}', $1)", start)
+            .execute(app.db())
+            .await
+            .unwrap();
+
+        clock.advance(Duration::from_secs(365 * 24 * 60 * 60));
+        assert_eq!(expired(data, "audit_logs", 365).await.unwrap(), 0);
+        assert_eq!(prune(data, "audit_logs", 365).await.unwrap(), 0);
+
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(expired(data, "audit_logs", 365).await.unwrap(), 1);
+        assert_eq!(prune(data, "audit_logs", 365).await.unwrap(), 1);
+        assert_eq!(expired(data, "audit_logs", 365).await.unwrap(), 0);
+    }
+}
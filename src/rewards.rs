@@ -0,0 +1,105 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use sqlx::PgConnection;
+
+use crate::{
+    events,
+    model::{RewardModel, RewardRuleModel},
+    scheduler, AppState,
+};
+
+/// How often the reward rules are checked for new winners.
+pub struct Rewards {
+    evaluate_every: Duration,
+}
+
+impl Rewards {
+    /// Every `REWARDS_EVALUATE_MINUTES`, 60 unless set.
+    pub fn from_env() -> Self {
+        let minutes = std::env::var("REWARDS_EVALUATE_MINUTES")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .filter(|minutes| *minutes > 0)
+            .unwrap_or(60);
+
+        Rewards {
+            evaluate_every: Duration::from_secs(minutes * 60),
+        }
+    }
+}
+
+pub fn spawn_job(data: Arc<AppState>) {
+    let period = data.rewards.evaluate_every;
+    scheduler::every("rewards", period, data, |data| async move {
+        let awarded = evaluate(&data).await?;
+        if awarded > 0 {
+            println!("✅ Awarded {} rewards", awarded);
+        }
+        Ok(())
+    });
+}
+
+/// Awards every rule's winners that don't have their reward yet and tells
+/// each of them. Monthly rules look at the last full month, so a month's
+/// winners are settled once it's over. Returns how many were awarded.
+pub async fn evaluate(data: &AppState) -> Result<usize, sqlx::Error> {
+    let rules = sqlx::query_as!(RewardRuleModel, "SELECT * FROM reward_rules ORDER BY created_at, id")
+        .fetch_all(&data.db)
+        .await?;
+
+    let mut awarded = 0;
+    for rule in rules {
+        let mut tx = data.db.begin().await?;
+        for reward in award(&mut tx, &rule, data.clock.now()).await? {
+            let event_to_send = json!({
+                "status": "success",
+                "event_type": "reward_awarded",
+                "audience": {"user_id": reward.user_id},
+                "event_data": {"reward": reward, "rule": rule.name}
+            });
+            events::enqueue(&mut tx, event_to_send).await?;
+            awarded += 1;
+        }
+        tx.commit().await?;
+    }
+    Ok(awarded)
+}
+
+// A referral counts once it's credited: its signup has no fraud flag, or an
+// approved one. Ties go to whoever got there first.
+async fn award(conn: &mut PgConnection, rule: &RewardRuleModel, now: DateTime<Utc>) -> Result<Vec<RewardModel>, sqlx::Error> {
+    match rule.kind.as_str() {
+        "first_referrers" => {
+            sqlx::query_as!(
+                RewardModel,
+                r#"WITH winners AS (SELECT s.referrer_id AS user_id FROM signups s WHERE s.referrer_id IS NOT NULL AND NOT EXISTS (SELECT 1 FROM fraud_flags f WHERE f.user_id = s.user_id AND f.status <> 'approved') GROUP BY s.referrer_id ORDER BY MIN(s.created_at), s.referrer_id LIMIT $2)
+                INSERT INTO rewards (user_id, rule_id, period, badge, points, created_at) SELECT user_id, $1, 'all', $3, $4, $5 FROM winners WHERE user_id IS NOT NULL ON CONFLICT DO NOTHING RETURNING *"#,
+                rule.id,
+                rule.size as i64,
+                rule.badge,
+                rule.points,
+                now
+            )
+            .fetch_all(conn)
+            .await
+        }
+        "monthly_top" => {
+            sqlx::query_as!(
+                RewardModel,
+                r#"WITH month AS (SELECT date_trunc('month', $5::timestamptz) - INTERVAL '1 month' AS start),
+                winners AS (SELECT s.referrer_id AS user_id, to_char(month.start, 'YYYY-MM') AS period FROM signups s, month WHERE s.referrer_id IS NOT NULL AND NOT EXISTS (SELECT 1 FROM fraud_flags f WHERE f.user_id = s.user_id AND f.status <> 'approved') AND s.created_at >= month.start AND s.created_at < month.start + INTERVAL '1 month' GROUP BY s.referrer_id, month.start ORDER BY COUNT(*) DESC, MAX(s.created_at), s.referrer_id LIMIT $2)
+                INSERT INTO rewards (user_id, rule_id, period, badge, points, created_at) SELECT user_id, $1, period, $3, $4, $5 FROM winners WHERE user_id IS NOT NULL AND period IS NOT NULL ON CONFLICT DO NOTHING RETURNING *"#,
+                rule.id,
+                rule.size as i64,
+                rule.badge,
+                rule.points,
+                now
+            )
+            .fetch_all(conn)
+            .await
+        }
+        _ => Ok(Vec::new()),
+    }
+}
@@ -1,31 +1,275 @@
 use std::sync::Arc;
 
 use axum::{
-    routing::get,
+    extract::DefaultBodyLimit,
+    http::{header, HeaderName, HeaderValue, Method, Request, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{delete, get, patch, post, put},
     Router,
 };
+use tower_http::{
+    catch_panic::CatchPanicLayer,
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+};
 
 use crate::{
     handler::{
-        create_user_handler, delete_user_handler, edit_user_handler, get_user_handler,
-        health_checker_handler, users_list_handler, sse_handler
+        analytics::{analytics_handler, analytics_series_handler},
+        attachment::{
+            attachments_list_handler, delete_attachment_handler, get_attachment_handler,
+            upload_attachment_handler,
+        },
+        billing::stripe_webhook_handler,
+        block::{block_user_handler, blocks_list_handler, unblock_user_handler},
+        contact::{
+            contacts_list_handler, create_contact_handler, delete_contact_handler,
+            edit_contact_handler, get_contact_handler, import_contacts_handler,
+        },
+        export::{create_export_handler, export_handler, storage_download_handler},
+        email::{
+            create_email_domain_rule_handler, delete_email_domain_rule_handler,
+            delete_suppression_handler, email_domain_rules_list_handler, email_webhook_handler,
+            suppressions_list_handler, unsubscribe_handler, unsubscribe_info_handler,
+        },
+        fraud::{fraud_flags_list_handler, review_fraud_flag_handler},
+        invitation::{guest_invitation_handler, guest_rsvp_handler},
+        leaderboard::{leaderboard_handler, refresh_leaderboard_handler},
+        load_test::emit_events_handler,
+        org::{
+            add_org_member_handler, attendance_handler, create_org_contact_handler, create_org_handler,
+            delete_org_contact_handler, get_org_contact_handler, get_org_handler,
+            import_invitations_handler, invite_org_member_handler, org_contacts_list_handler, org_invitations_list_handler,
+            org_members_list_handler, org_usage_handler, reissue_invite_token_handler, invitation_delivery_handler,
+            invite_tag_group_handler, remove_org_member_handler, revoke_invite_token_handler,
+            revoke_org_invitation_handler, set_org_capacity_handler, tag_invitation_handler, untag_invitation_handler,
+            update_org_member_handler,
+        },
+        retention::{retention_handler, retention_preview_handler},
+        reward::{
+            create_reward_rule_handler, delete_reward_rule_handler, evaluate_rewards_handler,
+            reward_rules_list_handler,
+        },
+        snapshot::snapshot_handler,
+        sync::sync_handler,
+        batch_get_users_handler, challenge_handler, bulk_delete_users_handler, bulk_update_users_handler,
+        create_session_handler, create_user_handler, delete_user_handler, edit_user_handler,
+        get_user_handler, health_checker_handler, version_handler, readiness_handler, privacy_settings_handler, update_privacy_settings_handler, referral_stats_handler, user_activity_handler, user_badges_handler, users_list_handler, users_export_handler, maintenance_handler, query_metrics_handler, replay_events_handler, set_maintenance_handler, reload_config_handler, sse_connections_handler, sse_handler
     },
-    AppState,
+    attachments, challenge, client_ip, concurrency, error, format, http_log, i18n, maintenance, rate_limit, report, request_signing, security_headers, AppState,
 };
 
 pub fn create_router(app_state: Arc<AppState>) -> Router {
-    Router::new()
+    // for the expensive routes, see `concurrency::ConcurrencyLimits`
+    let heavy = middleware::from_fn_with_state(app_state.clone(), concurrency::limit_concurrency);
+
+    // operator endpoints, only for API clients signing their requests
+    let admin = Router::new()
+        .route("/users/bulk-delete", post(bulk_delete_users_handler).layer(heavy.clone()))
+        .route("/users/bulk-update", post(bulk_update_users_handler))
+        .route("/users/:id/sessions", post(create_session_handler))
+        .route(
+            "/users/export",
+            get(users_export_handler).layer(heavy.clone()).post(create_export_handler),
+        )
+        .route("/exports/:id", get(export_handler))
+        .route("/sse-connections", get(sse_connections_handler))
+        .route("/query-metrics", get(query_metrics_handler))
+        .route("/email-suppressions", get(suppressions_list_handler))
+        .route("/email-suppressions/:id", delete(delete_suppression_handler))
+        .route(
+            "/email-domains",
+            get(email_domain_rules_list_handler).post(create_email_domain_rule_handler),
+        )
+        .route("/email-domains/:id", delete(delete_email_domain_rule_handler))
+        .route("/fraud-flags", get(fraud_flags_list_handler))
+        .route("/fraud-flags/:id/review", post(review_fraud_flag_handler))
+        .route("/analytics", get(analytics_handler))
+        .route("/analytics/:series", get(analytics_series_handler))
+        .route("/leaderboard/refresh", post(refresh_leaderboard_handler))
+        .route(
+            "/reward-rules",
+            get(reward_rules_list_handler).post(create_reward_rule_handler),
+        )
+        .route("/reward-rules/:id", delete(delete_reward_rule_handler))
+        .route("/reward-rules/evaluate", post(evaluate_rewards_handler))
+        .route("/snapshot", get(snapshot_handler).layer(heavy.clone()))
+        .route("/test/emit-events", post(emit_events_handler))
+        .route("/retention", get(retention_handler))
+        .route("/retention/preview", get(retention_preview_handler))
+        .route(
+            "/maintenance",
+            get(maintenance_handler).post(set_maintenance_handler),
+        )
+        .route("/config/reload", post(reload_config_handler))
+        .layer(middleware::from_fn_with_state(app_state.clone(), request_signing::require_client));
+
+    let router = Router::new()
         .route("/api/healthchecker", get(health_checker_handler))
+        .route("/readyz", get(readiness_handler))
+        .route("/api/version", get(version_handler))
+        .route("/api/challenge", get(challenge_handler))
         .route("/api/user-events", get(sse_handler))
+        .route("/api/events/stream/replay", get(replay_events_handler))
+        .route("/api/sync", get(sync_handler))
         .route(
             "/api/users",
             get(users_list_handler).post(create_user_handler),
         )
+        .route("/api/storage/*key", get(storage_download_handler))
+        .route("/api/users/batch-get", post(batch_get_users_handler))
         .route(
             "/api/user/:id",
             get(get_user_handler)
                 .patch(edit_user_handler)
                 .delete(delete_user_handler),
         )
+        .route("/api/user/:id/referral-stats", get(referral_stats_handler))
+        .route("/api/user/:id/badges", get(user_badges_handler))
+        .route("/api/user/:id/activity", get(user_activity_handler))
+        .route(
+            "/api/user/:id/privacy",
+            get(privacy_settings_handler).patch(update_privacy_settings_handler),
+        )
+        .route(
+            "/api/user/:id/blocks",
+            get(blocks_list_handler).post(block_user_handler),
+        )
+        .route("/api/user/:id/blocks/:blocked_id", delete(unblock_user_handler))
+        .route("/api/leaderboard", get(leaderboard_handler))
+        .route(
+            "/api/user/:id/contacts",
+            get(contacts_list_handler).post(create_contact_handler),
+        )
+        .route("/api/user/:id/contacts/import", post(import_contacts_handler).layer(heavy.clone()))
+        .route(
+            "/api/user/:id/contacts/:contact_id",
+            get(get_contact_handler)
+                .patch(edit_contact_handler)
+                .delete(delete_contact_handler),
+        )
+        .route("/api/orgs", post(create_org_handler))
+        .route("/api/orgs/:org_id", get(get_org_handler))
+        .route(
+            "/api/orgs/:org_id/members",
+            get(org_members_list_handler).post(add_org_member_handler),
+        )
+        .route(
+            "/api/orgs/:org_id/members/:user_id",
+            patch(update_org_member_handler).delete(remove_org_member_handler),
+        )
+        .route(
+            "/api/orgs/:org_id/invitations",
+            get(org_invitations_list_handler).post(invite_org_member_handler),
+        )
+        .route(
+            "/api/orgs/:org_id/invitations/import",
+            post(import_invitations_handler).layer(heavy.clone()),
+        )
+        .route(
+            "/api/orgs/:org_id/invitations/from-contacts",
+            post(invite_tag_group_handler).layer(heavy),
+        )
+        .route(
+            "/api/orgs/:org_id/invitations/:invitation_id",
+            delete(revoke_org_invitation_handler),
+        )
+        .route(
+            "/api/orgs/:org_id/invitations/:invitation_id/token",
+            post(reissue_invite_token_handler).delete(revoke_invite_token_handler),
+        )
+        .route(
+            "/api/orgs/:org_id/invitations/:invitation_id/tags",
+            post(tag_invitation_handler),
+        )
+        .route(
+            "/api/orgs/:org_id/invitations/:invitation_id/tags/:tag",
+            delete(untag_invitation_handler),
+        )
+        .route(
+            "/api/orgs/:org_id/invitations/:invitation_id/delivery",
+            get(invitation_delivery_handler),
+        )
+        .route("/api/invitations/guest", get(guest_invitation_handler))
+        .route("/api/invitations/guest/rsvp", post(guest_rsvp_handler))
+        .route("/api/orgs/:org_id/usage", get(org_usage_handler))
+        .route("/api/orgs/:org_id/capacity", put(set_org_capacity_handler))
+        .route("/api/orgs/:org_id/attendance", get(attendance_handler))
+        .route(
+            "/api/orgs/:org_id/attachments",
+            get(attachments_list_handler).post(upload_attachment_handler).layer(DefaultBodyLimit::max(
+                app_state.attachments.max_bytes + attachments::FRAMING_BYTES,
+            )),
+        )
+        .route(
+            "/api/orgs/:org_id/attachments/:attachment_id",
+            get(get_attachment_handler).delete(delete_attachment_handler),
+        )
+        .route(
+            "/api/orgs/:org_id/contacts",
+            get(org_contacts_list_handler).post(create_org_contact_handler),
+        )
+        .route(
+            "/api/orgs/:org_id/contacts/:contact_id",
+            get(get_org_contact_handler).delete(delete_org_contact_handler),
+        )
+        .route("/api/billing/stripe/webhook", post(stripe_webhook_handler))
+        .route(
+            "/api/email/unsubscribe",
+            get(unsubscribe_info_handler).post(unsubscribe_handler),
+        )
+        .route("/api/email/webhook", post(email_webhook_handler))
+        .nest("/api/admin", admin);
+    // the page invite emails link to, when built with it
+    #[cfg(feature = "invite-page")]
+    let router = router.route(
+        "/i/:invite_token",
+        get(crate::handler::invite_page::invite_page_handler)
+            .post(crate::handler::invite_page::invite_page_rsvp_handler),
+    );
+
+    router
+        .fallback(error::route_not_found)
+        .layer(CatchPanicLayer::custom(error::handle_panic))
+        .layer(middleware::from_fn(answer_options))
+        .layer(middleware::map_response(error::method_not_allowed))
+        .layer(middleware::from_fn_with_state(app_state.clone(), request_signing::verify_signatures))
+        .layer(middleware::from_fn_with_state(app_state.clone(), challenge::require_challenge))
+        .layer(middleware::from_fn_with_state(app_state.clone(), maintenance::reject_writes))
+        .layer(middleware::from_fn_with_state(app_state.clone(), rate_limit::limit_requests))
+        .layer(middleware::from_fn_with_state(app_state.clone(), client_ip::client_ip_layer))
+        .layer(middleware::from_fn(i18n::locale_layer))
+        .layer(middleware::from_fn(format::format_layer))
+        .layer(middleware::from_fn(report::context_layer))
+        .layer(middleware::from_fn_with_state(app_state.clone(), security_headers::set_headers))
+        .layer(middleware::from_fn_with_state(app_state.clone(), http_log::log_requests))
+        .layer(PropagateRequestIdLayer::new(HeaderName::from_static(report::REQUEST_ID_HEADER)))
+        .layer(SetRequestIdLayer::new(
+            HeaderName::from_static(report::REQUEST_ID_HEADER),
+            MakeRequestUuid,
+        ))
         .with_state(app_state)
 }
+
+/// Answers OPTIONS on any route with the methods it takes. No route handles
+/// OPTIONS itself, so the 405 axum gives it already carries the route's
+/// `Allow` list, HEAD included wherever GET is. CORS preflights never get
+/// here, the CORS layer answers them.
+async fn answer_options<B>(req: Request<B>, next: Next<B>) -> Response {
+    if req.method() != Method::OPTIONS {
+        return next.run(req).await;
+    }
+
+    let response = next.run(req).await;
+    if response.status() != StatusCode::METHOD_NOT_ALLOWED {
+        return response;
+    }
+    let Some(allow) = response.headers().get(header::ALLOW).and_then(|allow| allow.to_str().ok()) else {
+        return response;
+    };
+    let Ok(allow) = HeaderValue::from_str(&format!("{},OPTIONS", allow)) else {
+        return response;
+    };
+
+    (StatusCode::NO_CONTENT, [(header::ALLOW, allow)]).into_response()
+}
@@ -0,0 +1,268 @@
+use unicode_normalization::UnicodeNormalization;
+
+use crate::{
+    error::AppError,
+    schema::{
+        BulkUpdateUsersSchema, CreateContactSchema, CreateOrganizationSchema,
+        CreateRewardRuleSchema, CreateUserSchema, ImportInvitationsSchema, InviteOrgMemberSchema,
+        InviteTagGroupSchema, RsvpSchema, TagInvitationSchema, UpdateContactSchema, UpdateUserSchema,
+    },
+};
+
+pub const NAME_MAX: usize = 255;
+pub const PHONE_MAX: usize = 64;
+pub const TAG_MAX: usize = 64;
+pub const BADGE_MAX: usize = 64;
+pub const REWARD_RULE_NAME_MAX: usize = 100;
+
+/// A request body whose free text gets cleaned before it's stored, see
+/// `extract::Sanitized`.
+pub trait Sanitize {
+    fn sanitize(&mut self) -> Result<(), AppError>;
+}
+
+/// Cleans a line of text someone typed: NFC-normalized, without HTML tags
+/// or surrounding whitespace, at most `max` characters, and without control
+/// or bidirectional override characters, which are turned away rather than
+/// dropped. Text left empty is turned away as well.
+pub fn text(field: &'static str, value: &str, max: usize) -> Result<String, AppError> {
+    let normalized: String = value.nfc().collect();
+    if normalized.chars().any(is_forbidden) {
+        return Err(AppError::TextInvalid(field));
+    }
+
+    // removing a tag can leave another one behind, as in `<<b>b>`, or put a
+    // combining mark next to a letter it composes with
+    let mut stripped = normalized;
+    loop {
+        let next = strip_tags(&stripped);
+        if next == stripped {
+            break;
+        }
+        stripped = next;
+    }
+    let cleaned: String = stripped.trim().nfc().collect();
+    if cleaned.is_empty() {
+        return Err(AppError::TextRequired(field));
+    }
+    if cleaned.chars().count() > max {
+        return Err(AppError::TextTooLong(field, max));
+    }
+    Ok(cleaned)
+}
+
+pub fn optional_text(field: &'static str, value: &mut Option<String>, max: usize) -> Result<(), AppError> {
+    if let Some(inner) = value {
+        *inner = text(field, inner, max)?;
+    }
+    Ok(())
+}
+
+pub fn texts(field: &'static str, values: &mut [String], max: usize) -> Result<(), AppError> {
+    for value in values {
+        *value = text(field, value, max)?;
+    }
+    Ok(())
+}
+
+// an invitation's tags, each once
+fn tags(values: &mut Vec<String>) -> Result<(), AppError> {
+    texts("tags", values, TAG_MAX)?;
+    let mut seen = std::collections::HashSet::new();
+    values.retain(|value| seen.insert(value.clone()));
+    Ok(())
+}
+
+impl Sanitize for CreateUserSchema {
+    fn sanitize(&mut self) -> Result<(), AppError> {
+        self.user_name = text("user_name", &self.user_name, NAME_MAX)?;
+        Ok(())
+    }
+}
+
+impl Sanitize for UpdateUserSchema {
+    fn sanitize(&mut self) -> Result<(), AppError> {
+        optional_text("user_name", &mut self.user_name, NAME_MAX)
+    }
+}
+
+impl Sanitize for BulkUpdateUsersSchema {
+    fn sanitize(&mut self) -> Result<(), AppError> {
+        optional_text("user_name", &mut self.patch.user_name, NAME_MAX)
+    }
+}
+
+impl Sanitize for CreateContactSchema {
+    fn sanitize(&mut self) -> Result<(), AppError> {
+        self.name = text("name", &self.name, NAME_MAX)?;
+        optional_text("phone", &mut self.phone, PHONE_MAX)?;
+        texts("tags", &mut self.tags, TAG_MAX)
+    }
+}
+
+impl Sanitize for UpdateContactSchema {
+    fn sanitize(&mut self) -> Result<(), AppError> {
+        optional_text("name", &mut self.name, NAME_MAX)?;
+        optional_text("phone", &mut self.phone, PHONE_MAX)?;
+        if let Some(tags) = &mut self.tags {
+            texts("tags", tags, TAG_MAX)?;
+        }
+        Ok(())
+    }
+}
+
+impl Sanitize for CreateOrganizationSchema {
+    fn sanitize(&mut self) -> Result<(), AppError> {
+        self.name = text("name", &self.name, NAME_MAX)?;
+        Ok(())
+    }
+}
+
+impl Sanitize for InviteOrgMemberSchema {
+    fn sanitize(&mut self) -> Result<(), AppError> {
+        tags(&mut self.tags)
+    }
+}
+
+impl Sanitize for ImportInvitationsSchema {
+    fn sanitize(&mut self) -> Result<(), AppError> {
+        tags(&mut self.tags)
+    }
+}
+
+impl Sanitize for TagInvitationSchema {
+    fn sanitize(&mut self) -> Result<(), AppError> {
+        tags(&mut self.tags)
+    }
+}
+
+impl Sanitize for InviteTagGroupSchema {
+    fn sanitize(&mut self) -> Result<(), AppError> {
+        self.tag = text("tag", &self.tag, TAG_MAX)?;
+        Ok(())
+    }
+}
+
+impl Sanitize for RsvpSchema {
+    fn sanitize(&mut self) -> Result<(), AppError> {
+        texts("guest_names", &mut self.guest_names, NAME_MAX)
+    }
+}
+
+impl Sanitize for CreateRewardRuleSchema {
+    fn sanitize(&mut self) -> Result<(), AppError> {
+        self.name = text("name", &self.name, REWARD_RULE_NAME_MAX)?;
+        optional_text("badge", &mut self.badge, BADGE_MAX)
+    }
+}
+
+fn is_forbidden(c: char) -> bool {
+    // the bidi overrides and isolates can make text read differently from
+    // what it is
+    c.is_control() || matches!(c, '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}')
+}
+
+// Drops anything that looks like a tag or comment. A `<` not followed by a
+// letter, `/`, `!` or `?` is left alone, so "3 < 4" survives. What's left
+// still gets escaped wherever it's rendered as HTML.
+fn strip_tags(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('<') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let opens_tag = after
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || matches!(c, '/' | '!' | '?'));
+        match after.find('>') {
+            Some(end) if opens_tag => rest = &after[end + 1..],
+            _ => {
+                out.push('<');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn plain_text_round_trips() {
+        for value in ["Ada Lovelace", "Zoë O'Brien", "3 < 4 & 5 > 2", "李小龍", "+33 6 12 34 56 78"] {
+            assert_eq!(text("name", value, NAME_MAX).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn cleaning_is_idempotent() {
+        for value in ["  <b>Ada</b> ", "<script>alert(1)</script>Bob", "a <<i>b</i>", "Zoe\u{0308}"] {
+            let once = text("name", value, NAME_MAX).unwrap();
+            assert_eq!(text("name", &once, NAME_MAX).unwrap(), once);
+        }
+    }
+
+    #[test]
+    fn strips_tags() {
+        assert_eq!(text("name", "<b>Ada</b> <!-- hi -->Lovelace", NAME_MAX).unwrap(), "Ada Lovelace");
+        assert_eq!(text("name", "<img src=x onerror=alert(1)>Bob", NAME_MAX).unwrap(), "Bob");
+        assert!(matches!(text("name", "<b></b>", NAME_MAX), Err(AppError::TextRequired("name"))));
+    }
+
+    #[test]
+    fn normalizes_to_nfc() {
+        assert_eq!(text("name", "Zoe\u{0308}", NAME_MAX).unwrap(), "Zo\u{00EB}");
+    }
+
+    #[test]
+    fn rejects_control_characters() {
+        for value in ["Ada\u{0000}", "Ada\nLovelace", "evil\u{202E}gnp.exe"] {
+            assert!(matches!(text("name", value, NAME_MAX), Err(AppError::TextInvalid("name"))));
+        }
+    }
+
+    #[test]
+    fn enforces_max_length_in_characters() {
+        assert_eq!(text("tag", &"é".repeat(TAG_MAX), TAG_MAX).unwrap().chars().count(), TAG_MAX);
+        assert!(matches!(
+            text("tag", &"é".repeat(TAG_MAX + 1), TAG_MAX),
+            Err(AppError::TextTooLong("tag", TAG_MAX))
+        ));
+    }
+
+    #[test]
+    fn strips_tags_left_behind_by_stripping() {
+        assert!(matches!(text("name", "<<b>b>", NAME_MAX), Err(AppError::TextRequired("name"))));
+        assert_eq!(text("name", "e<b>\u{0308}", NAME_MAX).unwrap(), "\u{00EB}");
+    }
+
+    proptest! {
+        #[test]
+        fn accepted_text_is_clean(value in "\\PC*|[<>a-z /!\u{0300}-\u{0308}]{0,40}", max in 1usize..64) {
+            if let Ok(cleaned) = text("name", &value, max) {
+                prop_assert!(!cleaned.is_empty());
+                prop_assert!(cleaned.chars().count() <= max);
+                prop_assert_eq!(cleaned.trim(), cleaned.as_str());
+                prop_assert!(!cleaned.chars().any(is_forbidden));
+                prop_assert_eq!(strip_tags(&cleaned), cleaned.clone());
+                prop_assert_eq!(text("name", &cleaned, max).unwrap(), cleaned);
+            }
+        }
+
+        #[test]
+        fn forbidden_characters_are_turned_away(
+            before in "\\PC{0,10}",
+            forbidden in prop::sample::select(vec!['\u{0000}', '\n', '\u{007F}', '\u{202E}', '\u{2066}']),
+            after in "\\PC{0,10}",
+        ) {
+            let value = format!("{}{}{}", before, forbidden, after);
+            prop_assert!(matches!(text("name", &value, NAME_MAX), Err(AppError::TextInvalid("name"))));
+        }
+    }
+}
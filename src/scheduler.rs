@@ -0,0 +1,113 @@
+use std::{future::Future, sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+use sqlx::{Pool, Postgres};
+
+use crate::{clock::Clock, AppState};
+
+/// Runs `job` in the background every `period` by the app's clock, first
+/// right away. A failed run is logged and the job carries on at the next
+/// tick. Runs never overlap, a slow one pushes the next one back.
+pub fn every<F, Fut>(name: &'static str, period: Duration, data: Arc<AppState>, job: F)
+where
+    F: Fn(Arc<AppState>) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<(), sqlx::Error>> + Send + 'static,
+{
+    let clock = data.clock.clone();
+    tokio::spawn(repeat(clock, period, move || {
+        let run = job(data.clone());
+        async move {
+            if let Err(e) = run.await {
+                println!("🔥 Scheduled job {} failed: {:?}", name, e);
+            }
+        }
+    }));
+}
+
+async fn repeat<F, Fut>(clock: Arc<dyn Clock>, period: Duration, run: F)
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = ()>,
+{
+    loop {
+        let started = clock.now();
+        run().await;
+        let elapsed = (clock.now() - started).to_std().unwrap_or_default();
+        clock.sleep(period.saturating_sub(elapsed)).await;
+    }
+}
+
+/// Refreshes a materialized view without blocking reads of it and records
+/// when in `view_refreshes`. One instance refreshes a view at a time, the
+/// others skip it while it's underway and get `false`.
+pub async fn refresh_view(db: &Pool<Postgres>, view: &'static str, now: DateTime<Utc>) -> Result<bool, sqlx::Error> {
+    let mut tx = db.begin().await?;
+    let locked: bool = sqlx::query_scalar("SELECT pg_try_advisory_xact_lock(hashtext($1))")
+        .bind(view)
+        .fetch_one(&mut *tx)
+        .await?;
+    if !locked {
+        return Ok(false);
+    }
+
+    // view names come from the code, never from a request
+    sqlx::query(&format!("REFRESH MATERIALIZED VIEW CONCURRENTLY {}", view))
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!(
+        "INSERT INTO view_refreshes (view_name, refreshed_at) VALUES ($1, $2) ON CONFLICT (view_name) DO UPDATE SET refreshed_at = $2",
+        view,
+        now
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// When the oldest of the views was last refreshed, `None` until each of
+/// them has been.
+pub async fn refreshed_at(db: &Pool<Postgres>, views: &[&str]) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+    let views: Vec<String> = views.iter().map(|view| view.to_string()).collect();
+    let refreshes = sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!", MIN(refreshed_at) AS refreshed_at FROM view_refreshes WHERE view_name = ANY($1)"#,
+        &views
+    )
+    .fetch_one(db)
+    .await?;
+    Ok(refreshes.refreshed_at.filter(|_| refreshes.count == views.len() as i64))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::clock::ManualClock;
+
+    #[tokio::test]
+    async fn runs_once_per_period_of_the_clock() {
+        let clock = Arc::new(ManualClock::new(Utc.timestamp_opt(1_760_520_600, 0).unwrap()));
+        let runs = Arc::new(AtomicUsize::new(0));
+        let job = tokio::spawn(repeat(clock.clone(), Duration::from_secs(3600), {
+            let runs = runs.clone();
+            move || {
+                runs.fetch_add(1, Ordering::SeqCst);
+                async {}
+            }
+        }));
+        tokio::task::yield_now().await;
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+        clock.advance(Duration::from_secs(3599));
+        tokio::task::yield_now().await;
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+        clock.advance(Duration::from_secs(1));
+        tokio::task::yield_now().await;
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+        job.abort();
+    }
+}
@@ -1,12 +1,9 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Debug, Default)]
-pub struct FilterOptions {
-    pub page: Option<usize>,
-    pub limit: Option<usize>,
-}
+use crate::events::SchemaVersion;
 
 #[derive(Deserialize, Debug)]
+#[allow(dead_code)]
 pub struct ParamOptions {
     pub id: String,
 }
@@ -22,4 +19,362 @@ pub struct CreateUserSchema {
 pub struct UpdateUserSchema {
     pub user_name: Option<String>,
     pub email: Option<String>,
+    // expected version of the user, alternative to the If-Match header
+    pub version: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct BatchGetUsersSchema {
+    #[serde(default)]
+    pub ids: Vec<uuid::Uuid>,
+    #[serde(default)]
+    pub user_names: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BulkDeleteUsersSchema {
+    pub ids: Vec<uuid::Uuid>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct MaintenanceSchema {
+    pub enabled: bool,
+    pub retry_after: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct EmitEventsOptions {
+    // events per second, 10 unless set
+    pub rate: Option<u32>,
+    // how many to emit in all, 100 unless set
+    pub count: Option<u32>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct DryRunOptions {
+    pub dry_run: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BulkUpdateUsersSchema {
+    pub ids: Vec<uuid::Uuid>,
+    pub patch: BulkUserPatch,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BulkUserPatch {
+    pub user_name: Option<String>,
+    pub email: Option<String>,
+    pub added_by_ref_code: Option<i32>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct ContactFilterOptions {
+    pub tag: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CreateContactSchema {
+    pub name: String,
+    pub email: String,
+    pub phone: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UpdateContactSchema {
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub phone: Option<String>,
+    pub tags: Option<Vec<String>>,
 }
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CreateOrganizationSchema {
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AddOrgMemberSchema {
+    pub user_id: uuid::Uuid,
+    pub role: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct InviteOrgMemberSchema {
+    pub email: String,
+    pub role: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    // how many the guest may bring, themselves included, 1 unless set
+    pub max_party_size: Option<i32>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct InvitationFilterOptions {
+    pub tag: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TagInvitationSchema {
+    pub tags: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct InviteTagGroupSchema {
+    // the tag of the organization's contacts to invite
+    pub tag: String,
+    pub role: Option<String>,
+    pub max_party_size: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GuestListFormat {
+    #[default]
+    Csv,
+    GoogleContacts,
+}
+
+// which of the file's columns hold what, by header
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ColumnMapping {
+    pub email: String,
+    pub role: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ImportInvitationsSchema {
+    #[serde(default)]
+    pub format: GuestListFormat,
+    // the file itself
+    pub data: String,
+    // without one, nothing is imported and the columns are described instead
+    pub mapping: Option<ColumnMapping>,
+    // for rows without a role of their own
+    pub role: Option<String>,
+    // given to everyone invited
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub max_party_size: Option<i32>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OrgCapacitySchema {
+    // null for no limit
+    pub capacity: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UpdateOrgMemberSchema {
+    pub role: String,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct SseOptions {
+    // count the connection against this organization's quota, the signed-in user must be a member
+    pub org_id: Option<uuid::Uuid>,
+    pub schema_version: Option<SchemaVersion>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ReplayOptions {
+    // an event id, or an RFC 3339 timestamp
+    pub since: String,
+    pub limit: Option<usize>,
+    pub schema_version: Option<SchemaVersion>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SyncOptions {
+    // the cursor of the previous sync
+    pub since: Option<i64>,
+    pub schema_version: Option<SchemaVersion>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct UnsubscribeOptions {
+    pub token: String,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RsvpResponse {
+    Accepted,
+    Declined,
+}
+
+impl RsvpResponse {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RsvpResponse::Accepted => "accepted",
+            RsvpResponse::Declined => "declined",
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RsvpSchema {
+    pub response: RsvpResponse,
+    // when accepting, how many are coming, the guest included, 1 unless set
+    pub guest_count: Option<i32>,
+    // of those coming along with the guest
+    #[serde(default)]
+    pub guest_names: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum DomainRule {
+    Block,
+    Allow,
+}
+
+impl DomainRule {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DomainRule::Block => "block",
+            DomainRule::Allow => "allow",
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CreateEmailDomainRuleSchema {
+    pub domain: String,
+    pub rule: DomainRule,
+    // only for this organization's invitations, signups are never restricted by it
+    pub org_id: Option<uuid::Uuid>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct EmailDomainRuleOptions {
+    pub org_id: Option<uuid::Uuid>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RewardKind {
+    // the first `size` users to refer someone, once
+    FirstReferrers,
+    // the `size` users with the most referrals in each calendar month
+    MonthlyTop,
+}
+
+impl RewardKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RewardKind::FirstReferrers => "first_referrers",
+            RewardKind::MonthlyTop => "monthly_top",
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CreateRewardRuleSchema {
+    pub name: String,
+    pub kind: RewardKind,
+    pub size: i32,
+    // what each winner gets, a badge, points or both
+    pub badge: Option<String>,
+    pub points: Option<i32>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct UserListOptions {
+    // RFC 3339 timestamps or unix seconds, both bounds exclusive
+    #[serde(default, with = "crate::timestamp::option")]
+    pub created_after: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default, with = "crate::timestamp::option")]
+    pub created_before: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Ndjson,
+}
+
+// kept as the job's params, so it's read back by the worker
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ExportUsersSchema {
+    #[serde(default)]
+    pub format: ExportFormat,
+    pub fields: Option<Vec<String>>,
+    #[serde(default, with = "crate::timestamp::option")]
+    pub created_after: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default, with = "crate::timestamp::option")]
+    pub created_before: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+// what a storage backend signed a download link with
+#[derive(Deserialize, Debug)]
+pub struct SignedLinkOptions {
+    pub expires: i64,
+    pub signature: String,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct ProfileOptions {
+    // related collections to embed, e.g. `referrals,badges:5`
+    pub include: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProfileVisibility {
+    Public,
+    // just enough to sign up with their referral code
+    RefCodeOnly,
+    Private,
+}
+
+impl ProfileVisibility {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ProfileVisibility::Public => "public",
+            ProfileVisibility::RefCodeOnly => "ref_code_only",
+            ProfileVisibility::Private => "private",
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct UpdatePrivacySchema {
+    pub profile_visibility: Option<ProfileVisibility>,
+    pub hide_email: Option<bool>,
+    pub hide_referral_stats: Option<bool>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct BlockUserSchema {
+    pub user_id: uuid::Uuid,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct AnalyticsOptions {
+    // how far back the series go, 30 days unless set
+    pub days: Option<i32>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct FraudFlagOptions {
+    // `pending` unless set
+    pub status: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReviewDecision {
+    Approve,
+    Reject,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ReviewFraudFlagSchema {
+    pub decision: ReviewDecision,
+}
+
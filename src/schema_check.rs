@@ -0,0 +1,348 @@
+use std::collections::{HashMap, HashSet};
+
+use sqlx::{Pool, Postgres};
+
+// extensions the migrations and queries rely on
+const REQUIRED_EXTENSIONS: [&str; 1] = ["uuid-ossp"];
+
+// The tables and columns, with their types, that the queries were compiled
+// against. Keep it in step with the migrations.
+const EXPECTED_COLUMNS: &[(&str, &[&str])] = &[
+    (
+        "audit_logs",
+        &[
+            "id uuid", "action varchar", "details jsonb", "created_at timestamptz", "ip varchar",
+            "api_client_id uuid",
+        ],
+    ),
+    (
+        "api_clients",
+        &[
+            "id uuid", "name varchar", "secret varchar", "created_at timestamptz",
+            "revoked_at timestamptz",
+        ],
+    ),
+    (
+        "badges",
+        &[
+            "user_id uuid", "badge varchar", "earned_at timestamptz",
+        ],
+    ),
+    (
+        "blocks",
+        &[
+            "blocker_id uuid", "blocked_id uuid", "created_at timestamptz",
+        ],
+    ),
+    (
+        "collection_changes",
+        &[
+            "collection varchar", "changed_at timestamptz",
+        ],
+    ),
+    (
+        "contacts",
+        &[
+            "id uuid", "owner_id uuid", "name varchar", "email varchar", "phone varchar",
+            "tags _text", "user_id uuid", "created_at timestamptz", "updated_at timestamptz",
+            "org_id uuid", "email_index varchar",
+        ],
+    ),
+    (
+        "email_domain_rules",
+        &[
+            "id uuid", "domain varchar", "rule varchar", "org_id uuid", "created_at timestamptz",
+        ],
+    ),
+    (
+        "email_log",
+        &[
+            "id uuid", "invitation_id uuid", "email varchar", "status varchar", "details text",
+            "sent_at timestamptz", "delivered_at timestamptz", "opened_at timestamptz",
+            "bounced_at timestamptz", "created_at timestamptz", "updated_at timestamptz",
+        ],
+    ),
+    (
+        "email_suppressions",
+        &[
+            "email varchar", "reason varchar", "details text", "created_at timestamptz",
+            "email_index varchar", "id uuid",
+        ],
+    ),
+    (
+        "events",
+        &[
+            "id int8", "event_type varchar", "payload jsonb", "created_at timestamptz",
+            "published_at timestamptz", "bus_published_at timestamptz",
+        ],
+    ),
+    (
+        "export_jobs",
+        &[
+            "id uuid", "status varchar", "params jsonb", "total_rows int8", "rows_read int8",
+            "rows_written int8", "artifact varchar", "error text", "created_at timestamptz",
+            "started_at timestamptz", "finished_at timestamptz", "updated_at timestamptz",
+        ],
+    ),
+    (
+        "fraud_flags",
+        &[
+            "id uuid", "user_id uuid", "referrer_id uuid", "reasons _text", "details jsonb",
+            "status varchar", "reviewed_at timestamptz", "created_at timestamptz",
+        ],
+    ),
+    (
+        "org_attachments",
+        &[
+            "id uuid", "org_id uuid", "file_name varchar", "content_type varchar", "size int8",
+            "storage_key varchar", "uploaded_by uuid", "created_at timestamptz",
+        ],
+    ),
+    (
+        "org_invitations",
+        &[
+            "id uuid", "org_id uuid", "email varchar", "role varchar", "invited_by uuid",
+            "accepted_at timestamptz", "created_at timestamptz", "token_version int4", "rsvp varchar",
+            "rsvp_at timestamptz", "email_index varchar", "tags _text",
+            "max_party_size int4", "guest_count int4", "guest_names _text",
+        ],
+    ),
+    (
+        "org_members",
+        &[
+            "org_id uuid", "user_id uuid", "role varchar", "created_at timestamptz",
+        ],
+    ),
+    (
+        "org_quotas",
+        &[
+            "org_id uuid", "metric varchar", "max_value int4",
+        ],
+    ),
+    (
+        "org_usage",
+        &[
+            "org_id uuid", "metric varchar", "period date", "used int4",
+        ],
+    ),
+    (
+        "organizations",
+        &[
+            "id uuid", "name varchar", "created_at timestamptz", "updated_at timestamptz",
+            "plan_id varchar", "stripe_customer_id varchar", "stripe_subscription_id varchar",
+            "subscription_status varchar", "capacity int4",
+        ],
+    ),
+    (
+        "plans",
+        &[
+            "id varchar", "name varchar", "stripe_price_id varchar", "invites_per_month int4",
+            "sse_connections int4", "features _text", "created_at timestamptz",
+        ],
+    ),
+    (
+        "privacy_settings",
+        &[
+            "user_id uuid", "profile_visibility varchar", "hide_email bool",
+            "hide_referral_stats bool", "updated_at timestamptz",
+        ],
+    ),
+    (
+        "request_signatures",
+        &[
+            "signature varchar", "client_id uuid", "created_at timestamptz",
+        ],
+    ),
+    (
+        "reward_rules",
+        &[
+            "id uuid", "name varchar", "kind varchar", "size int4", "badge varchar", "points int4",
+            "created_at timestamptz",
+        ],
+    ),
+    (
+        "rewards",
+        &[
+            "id uuid", "user_id uuid", "rule_id uuid", "period varchar", "badge varchar",
+            "points int4", "created_at timestamptz",
+        ],
+    ),
+    (
+        "signups",
+        &[
+            "user_id uuid", "ip varchar", "referrer_id uuid", "created_at timestamptz",
+            "country varchar", "region varchar",
+        ],
+    ),
+    (
+        "sse_samples",
+        &[
+            "instance_id uuid", "connections int4", "sampled_at timestamptz",
+        ],
+    ),
+    (
+        "stripe_events",
+        &[
+            "id varchar", "event_type varchar", "received_at timestamptz",
+        ],
+    ),
+    (
+        "tombstones",
+        &[
+            "entity_type varchar", "entity_id uuid", "org_id uuid", "event_id int8",
+            "deleted_at timestamptz",
+        ],
+    ),
+    (
+        "unsubscribe_links",
+        &[
+            "id uuid", "email varchar", "email_index varchar", "created_at timestamptz",
+        ],
+    ),
+    (
+        "users",
+        &[
+            "id uuid", "user_name varchar", "email varchar", "ref_code text",
+            "added_by_ref_code int4", "created_at timestamptz", "updated_at timestamptz",
+            "version int4", "email_index varchar",
+        ],
+    ),
+    (
+        "view_refreshes",
+        &[
+            "view_name varchar", "refreshed_at timestamptz",
+        ],
+    ),
+];
+
+/// Checks that the database is what this build expects before serving
+/// anything: required extensions are installed, tables have the columns
+/// and types the queries use, and, when migrations are tracked in
+/// `_sqlx_migrations`, every migration this build ships was applied
+/// unchanged. `SCHEMA_CHECK` is `fail` (the default) to refuse to start on a
+/// mismatch, `warn` to only log it, or `off`.
+pub async fn run(db: &Pool<Postgres>) {
+    let mode = std::env::var("SCHEMA_CHECK").unwrap_or_else(|_| "fail".to_string());
+    if mode == "off" {
+        return;
+    }
+
+    let problems = match problems(db).await {
+        Ok(problems) => problems,
+        Err(e) => vec![format!("could not inspect the schema: {:?}", e)],
+    };
+    if problems.is_empty() {
+        println!("✅ Database schema matches this build");
+        return;
+    }
+
+    println!("🔥 Database schema does not match this build:");
+    for problem in &problems {
+        println!("   - {}", problem);
+    }
+    if mode != "warn" {
+        println!("🔥 Apply the migrations, or start with SCHEMA_CHECK=warn to run anyway");
+        std::process::exit(1);
+    }
+}
+
+async fn problems(db: &Pool<Postgres>) -> Result<Vec<String>, sqlx::Error> {
+    let mut problems = Vec::new();
+
+    let installed: HashSet<String> = sqlx::query_scalar!("SELECT extname::text AS \"extname!\" FROM pg_extension")
+        .fetch_all(db)
+        .await?
+        .into_iter()
+        .collect();
+    for extension in REQUIRED_EXTENSIONS {
+        if !installed.contains(extension) {
+            problems.push(format!("extension `{}` is not installed", extension));
+        }
+    }
+
+    let rows = sqlx::query!(
+        r#"SELECT table_name::text AS "table_name!", column_name::text AS "column_name!", udt_name::text AS "udt_name!" FROM information_schema.columns WHERE table_schema = current_schema()"#
+    )
+    .fetch_all(db)
+    .await?;
+    let mut live: HashMap<String, HashMap<String, String>> = HashMap::new();
+    for row in rows {
+        live.entry(row.table_name).or_default().insert(row.column_name, row.udt_name);
+    }
+    for (table, columns) in EXPECTED_COLUMNS {
+        let Some(live_columns) = live.get(*table) else {
+            problems.push(format!("table `{}` is missing", table));
+            continue;
+        };
+        for column in *columns {
+            let (name, expected) = column.split_once(' ').unwrap_or((column, ""));
+            match live_columns.get(name) {
+                None => problems.push(format!("column `{}.{}` is missing", table, name)),
+                Some(actual) if actual != expected => problems.push(format!(
+                    "column `{}.{}` is `{}`, expected `{}`",
+                    table, name, actual, expected
+                )),
+                Some(_) => {}
+            }
+        }
+    }
+
+    problems.extend(migration_problems(db).await?);
+    Ok(problems)
+}
+
+/// The latest migration applied to the database, when they're applied with
+/// sqlx and so recorded.
+pub async fn applied_migration(db: &Pool<Postgres>) -> Result<Option<i64>, sqlx::Error> {
+    let tracked: bool = sqlx::query_scalar("SELECT to_regclass('_sqlx_migrations') IS NOT NULL")
+        .fetch_one(db)
+        .await?;
+    if !tracked {
+        return Ok(None);
+    }
+    sqlx::query_scalar("SELECT MAX(version) FROM _sqlx_migrations WHERE success")
+        .fetch_one(db)
+        .await
+}
+
+// only possible when migrations are applied with sqlx, which records them
+async fn migration_problems(db: &Pool<Postgres>) -> Result<Vec<String>, sqlx::Error> {
+    let tracked: bool = sqlx::query_scalar("SELECT to_regclass('_sqlx_migrations') IS NOT NULL")
+        .fetch_one(db)
+        .await?;
+    if !tracked {
+        return Ok(Vec::new());
+    }
+
+    let applied: HashMap<i64, (Vec<u8>, bool)> =
+        sqlx::query_as::<_, (i64, Vec<u8>, bool)>("SELECT version, checksum, success FROM _sqlx_migrations")
+            .fetch_all(db)
+            .await?
+            .into_iter()
+            .map(|(version, checksum, success)| (version, (checksum, success)))
+            .collect();
+
+    let mut problems = Vec::new();
+    let shipped: Vec<_> = sqlx::migrate!()
+        .iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+        .cloned()
+        .collect();
+    for migration in &shipped {
+        match applied.get(&migration.version) {
+            None => problems.push(format!("migration {} ({}) was not applied", migration.version, migration.description)),
+            Some((_, false)) => problems.push(format!("migration {} ({}) failed", migration.version, migration.description)),
+            Some((checksum, _)) if *checksum != *migration.checksum => problems.push(format!(
+                "migration {} ({}) was changed after it was applied",
+                migration.version, migration.description
+            )),
+            Some(_) => {}
+        }
+    }
+    for version in applied.keys() {
+        if !shipped.iter().any(|migration| migration.version == *version) {
+            problems.push(format!("migration {} is applied but unknown to this build, which may be older than the database", version));
+        }
+    }
+    Ok(problems)
+}
@@ -0,0 +1,250 @@
+use std::{
+    collections::HashMap,
+    sync::{OnceLock, RwLock},
+    time::Duration,
+};
+
+use axum::async_trait;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+pub type BackendError = Box<dyn std::error::Error + Send + Sync>;
+
+const DEFAULT_REFRESH_SECS: u64 = 300;
+
+/// Where secrets like `DATABASE_URL`, `INVITE_TOKEN_SECRET` or the provider
+/// keys are kept, when not in the environment. A backend hands back all of
+/// them at once, by the name of the variable they stand in for.
+#[async_trait]
+pub trait SecretsBackend: Send + Sync {
+    async fn load(&self) -> Result<HashMap<String, String>, BackendError>;
+}
+
+/// A key/value (v2) secret in HashiCorp Vault, read from `VAULT_ADDR` with
+/// `VAULT_TOKEN`. `VAULT_SECRET_PATH` is the path under the mount, as in
+/// `secret/data/invito`.
+struct VaultBackend {
+    http: reqwest::Client,
+    url: String,
+    token: String,
+}
+
+#[async_trait]
+impl SecretsBackend for VaultBackend {
+    async fn load(&self) -> Result<HashMap<String, String>, BackendError> {
+        let body: Value = self
+            .http
+            .get(&self.url)
+            .header("X-Vault-Token", &self.token)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(string_values(&body["data"]["data"]))
+    }
+}
+
+/// A secret in AWS Secrets Manager holding a JSON object, named by
+/// `AWS_SECRET_ID` in `AWS_REGION`, read with the usual `AWS_ACCESS_KEY_ID`,
+/// `AWS_SECRET_ACCESS_KEY` and, for temporary credentials,
+/// `AWS_SESSION_TOKEN`.
+struct AwsBackend {
+    http: reqwest::Client,
+    region: String,
+    secret_id: String,
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+}
+
+impl AwsBackend {
+    // a Signature Version 4 `Authorization` header for a Secrets Manager call
+    fn authorization(&self, host: &str, target: &str, amz_date: &str, payload: &str) -> String {
+        let date = &amz_date[..8];
+        let scope = format!("{}/{}/secretsmanager/aws4_request", date, self.region);
+        let mut headers = vec![
+            ("content-type", "application/x-amz-json-1.1".to_string()),
+            ("host", host.to_string()),
+            ("x-amz-date", amz_date.to_string()),
+            ("x-amz-target", target.to_string()),
+        ];
+        if let Some(token) = &self.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        let signed_headers = headers.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(";");
+        let canonical_headers: String = headers.iter().map(|(name, value)| format!("{}:{}\n", name, value)).collect();
+        let canonical_request = format!(
+            "POST\n/\n\n{}\n{}\n{}",
+            canonical_headers,
+            signed_headers,
+            hex::encode(Sha256::digest(payload))
+        );
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex::encode(Sha256::digest(canonical_request))
+        );
+
+        let key = [date, self.region.as_str(), "secretsmanager", "aws4_request"]
+            .iter()
+            .fold(format!("AWS4{}", self.secret_access_key).into_bytes(), |key, part| hmac(&key, part));
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key_id,
+            scope,
+            signed_headers,
+            hex::encode(hmac(&key, &string_to_sign))
+        )
+    }
+}
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+#[async_trait]
+impl SecretsBackend for AwsBackend {
+    async fn load(&self) -> Result<HashMap<String, String>, BackendError> {
+        let host = format!("secretsmanager.{}.amazonaws.com", self.region);
+        let target = "secretsmanager.GetSecretValue";
+        let amz_date = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+        let payload = json!({"SecretId": self.secret_id}).to_string();
+
+        let mut request = self
+            .http
+            .post(format!("https://{}/", host))
+            .header("content-type", "application/x-amz-json-1.1")
+            .header("x-amz-date", &amz_date)
+            .header("x-amz-target", target)
+            .header("authorization", self.authorization(&host, target, &amz_date, &payload));
+        if let Some(token) = &self.session_token {
+            request = request.header("x-amz-security-token", token);
+        }
+        let body: Value = request.body(payload).send().await?.error_for_status()?.json().await?;
+
+        let secret = body["SecretString"].as_str().ok_or("the secret has no SecretString")?;
+        Ok(string_values(&serde_json::from_str(secret)?))
+    }
+}
+
+fn string_values(object: &Value) -> HashMap<String, String> {
+    object
+        .as_object()
+        .map(|object| {
+            object
+                .iter()
+                .filter_map(|(name, value)| Some((name.clone(), value.as_str()?.to_string())))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+struct Store {
+    backend: Option<Box<dyn SecretsBackend>>,
+    values: RwLock<HashMap<String, String>>,
+}
+
+static STORE: OnceLock<Store> = OnceLock::new();
+
+fn required(name: &str) -> String {
+    match std::env::var(name) {
+        Ok(value) if !value.is_empty() => value,
+        _ => {
+            println!("🔥 {} must be set for the configured secrets backend", name);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn backend() -> Option<Box<dyn SecretsBackend>> {
+    match std::env::var("SECRETS_BACKEND").as_deref() {
+        Err(_) | Ok("env") => None,
+        Ok("vault") => Some(Box::new(VaultBackend {
+            http: reqwest::Client::new(),
+            url: format!(
+                "{}/v1/{}",
+                required("VAULT_ADDR").trim_end_matches('/'),
+                required("VAULT_SECRET_PATH").trim_start_matches('/')
+            ),
+            token: required("VAULT_TOKEN"),
+        })),
+        Ok("aws") => Some(Box::new(AwsBackend {
+            http: reqwest::Client::new(),
+            region: required("AWS_REGION"),
+            secret_id: required("AWS_SECRET_ID"),
+            access_key_id: required("AWS_ACCESS_KEY_ID"),
+            secret_access_key: required("AWS_SECRET_ACCESS_KEY"),
+            session_token: std::env::var("AWS_SESSION_TOKEN").ok().filter(|token| !token.is_empty()),
+        })),
+        Ok(other) => {
+            println!("🔥 `{}` is not a supported secrets backend", other);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Loads the secrets from `SECRETS_BACKEND`, which is `env` (the default),
+/// `vault` or `aws`. Startup stops if they can't be loaded.
+pub async fn init() {
+    let backend = backend();
+    let values = match &backend {
+        Some(backend) => match backend.load().await {
+            Ok(values) => values,
+            Err(e) => {
+                println!("🔥 Failed to load secrets: {}", e);
+                std::process::exit(1);
+            }
+        },
+        None => HashMap::new(),
+    };
+    STORE.get_or_init(|| Store {
+        backend,
+        values: RwLock::new(values),
+    });
+}
+
+/// The secret stored as `name`, or the environment variable of that name
+/// when the backend has none.
+pub fn var(name: &str) -> Option<String> {
+    let stored = STORE
+        .get()
+        .and_then(|store| store.values.read().unwrap().get(name).cloned());
+    stored
+        .or_else(|| std::env::var(name).ok())
+        .filter(|value| !value.is_empty())
+}
+
+/// Loads the secrets again every `SECRETS_REFRESH_SECS`, 300 unless set, so
+/// those read on use pick up rotated values without a restart. A failed
+/// load keeps the values from the last one.
+pub fn spawn_refresh() {
+    let period = std::env::var("SECRETS_REFRESH_SECS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .filter(|secs| *secs > 0)
+        .unwrap_or(DEFAULT_REFRESH_SECS);
+
+    let Some(store) = STORE.get() else {
+        return;
+    };
+    let Some(backend) = &store.backend else {
+        return;
+    };
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(Duration::from_secs(period));
+        ticks.tick().await;
+        loop {
+            ticks.tick().await;
+            match backend.load().await {
+                Ok(values) => *store.values.write().unwrap() = values,
+                Err(e) => println!("🔥 Failed to refresh secrets, keeping the last ones: {}", e),
+            }
+        }
+    });
+}
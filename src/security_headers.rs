@@ -0,0 +1,96 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::AppState;
+
+// the API only ever answers with data, so nothing it sends may load or run anything
+const API_POLICY: &str = "default-src 'none'; frame-ancestors 'none'; base-uri 'none'; form-action 'none'";
+// the invitation page is plain HTML whose one form posts back to itself
+const PAGE_POLICY: &str = "default-src 'none'; frame-ancestors 'none'; base-uri 'none'; form-action 'self'";
+const PAGE_PREFIX: &str = "/i/";
+
+/// The `Strict-Transport-Security` the API sends, if any. Off unless
+/// `HSTS_MAX_AGE` is set, since a deployment reached over plain HTTP would
+/// lock browsers out; `HSTS_INCLUDE_SUBDOMAINS=true` widens it.
+pub struct SecurityHeaders {
+    hsts: Option<HeaderValue>,
+}
+
+impl SecurityHeaders {
+    pub fn from_env() -> Self {
+        let include_subdomains = std::env::var("HSTS_INCLUDE_SUBDOMAINS")
+            .map(|value| value == "true")
+            .unwrap_or(false);
+        let hsts = std::env::var("HSTS_MAX_AGE")
+            .ok()
+            .and_then(|value| value.parse::<u64>().ok())
+            .filter(|max_age| *max_age > 0)
+            .map(|max_age| {
+                let value = if include_subdomains {
+                    format!("max-age={}; includeSubDomains", max_age)
+                } else {
+                    format!("max-age={}", max_age)
+                };
+                HeaderValue::from_str(&value).expect("max-age is a number")
+            });
+
+        SecurityHeaders { hsts }
+    }
+}
+
+/// Sets the security headers on every response, errors included, leaving
+/// any a handler set itself alone.
+pub async fn set_headers(State(data): State<Arc<AppState>>, req: Request<Body>, next: Next<Body>) -> Response {
+    let policy = if req.uri().path().starts_with(PAGE_PREFIX) { PAGE_POLICY } else { API_POLICY };
+    let mut response = next.run(req).await;
+
+    let headers = response.headers_mut();
+    headers
+        .entry(header::CONTENT_SECURITY_POLICY)
+        .or_insert(HeaderValue::from_static(policy));
+    headers
+        .entry(header::X_CONTENT_TYPE_OPTIONS)
+        .or_insert(HeaderValue::from_static("nosniff"));
+    headers
+        .entry(header::REFERRER_POLICY)
+        .or_insert(HeaderValue::from_static("no-referrer"));
+    if let Some(hsts) = &data.security_headers.hsts {
+        headers
+            .entry(header::STRICT_TRANSPORT_SECURITY)
+            .or_insert(hsts.clone());
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Method;
+
+    use super::*;
+    use crate::testing::{builder, TestApp};
+
+    #[tokio::test]
+    async fn every_response_carries_the_security_headers() {
+        let app = TestApp::with(|state| {
+            state.security_headers = SecurityHeaders { hsts: Some(HeaderValue::from_static("max-age=31536000")) };
+        })
+        .await;
+
+        // errors included
+        for (uri, policy) in [("/api/version", API_POLICY), ("/api/nowhere", API_POLICY), ("/i/token", PAGE_POLICY)] {
+            let response = app.respond(builder(Method::GET, uri), None).await;
+            let headers = response.headers();
+            assert_eq!(headers[header::CONTENT_SECURITY_POLICY], policy, "{}", uri);
+            assert_eq!(headers[header::X_CONTENT_TYPE_OPTIONS], "nosniff", "{}", uri);
+            assert_eq!(headers[header::REFERRER_POLICY], "no-referrer", "{}", uri);
+            assert_eq!(headers[header::STRICT_TRANSPORT_SECURITY], "max-age=31536000", "{}", uri);
+        }
+    }
+}
@@ -0,0 +1,188 @@
+use std::{
+    io,
+    net::SocketAddr,
+    os::unix::fs::FileTypeExt,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use axum::{
+    extract::connect_info::{Connected, IntoMakeServiceWithConnectInfo},
+    Router,
+};
+use hyper::server::{accept, conn::AddrIncoming, Builder, Server};
+use listenfd::ListenFd;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::{UnixListener, UnixStream},
+};
+
+/// Connection-level settings for the listener, sized for many long-lived,
+/// mostly idle SSE streams rather than for short requests.
+pub struct Tuning {
+    // off with `HTTP2_ENABLED=false`; otherwise HTTP/2 is offered next to HTTP/1.1
+    http2: bool,
+    // `HTTP2_MAX_CONCURRENT_STREAMS`, streams one HTTP/2 connection may hold open
+    max_concurrent_streams: Option<u32>,
+    // `SSE_WRITE_BUFFER_BYTES`, what one connection may buffer for a slow reader
+    write_buffer: Option<usize>,
+    // `TCP_KEEPALIVE_SECS`, 60 unless set, so dead peers are noticed; 0 turns it off
+    tcp_keepalive: Option<Duration>,
+    // `HTTP2_KEEPALIVE_SECS`, pings that find HTTP/2 peers gone behind a proxy
+    http2_keepalive: Option<Duration>,
+}
+
+// hyper refuses anything smaller for HTTP/1
+const MIN_WRITE_BUFFER: usize = 8192;
+
+fn env<T: std::str::FromStr>(name: &str) -> Option<T> {
+    std::env::var(name).ok().and_then(|value| value.parse().ok())
+}
+
+impl Tuning {
+    pub fn from_env() -> Self {
+        Tuning {
+            http2: env("HTTP2_ENABLED").unwrap_or(true),
+            max_concurrent_streams: env("HTTP2_MAX_CONCURRENT_STREAMS").filter(|max| *max > 0),
+            write_buffer: env::<usize>("SSE_WRITE_BUFFER_BYTES").map(|bytes| bytes.max(MIN_WRITE_BUFFER)),
+            tcp_keepalive: Some(env("TCP_KEEPALIVE_SECS").unwrap_or(60))
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs),
+            http2_keepalive: env("HTTP2_KEEPALIVE_SECS")
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs),
+        }
+    }
+
+    pub fn apply(&self, builder: Builder<AddrIncoming>) -> Builder<AddrIncoming> {
+        self.apply_http(builder.tcp_nodelay(true).tcp_keepalive(self.tcp_keepalive))
+    }
+
+    fn apply_http<I>(&self, mut builder: Builder<I>) -> Builder<I> {
+        builder = builder
+            .http1_only(!self.http2)
+            .http2_max_concurrent_streams(self.max_concurrent_streams)
+            .http2_keep_alive_interval(self.http2_keepalive);
+        if let Some(bytes) = self.write_buffer {
+            builder = builder.http1_max_buf_size(bytes).http2_max_send_buf_size(bytes);
+        }
+        builder
+    }
+
+    /// The same settings for the HTTPS listener.
+    #[cfg(feature = "tls")]
+    pub fn apply_tls<A>(&self, server: axum_server::Server<A>) -> axum_server::Server<A> {
+        use axum_server::{AddrIncomingConfig, HttpConfig};
+
+        let mut http = HttpConfig::new();
+        http.http1_only(!self.http2)
+            .http2_max_concurrent_streams(self.max_concurrent_streams)
+            .http2_keep_alive_interval(self.http2_keepalive);
+        if let Some(bytes) = self.write_buffer {
+            http.max_buf_size(bytes).http2_max_send_buf_size(bytes);
+        }
+        let incoming = AddrIncomingConfig::new()
+            .tcp_nodelay(true)
+            .tcp_keepalive(self.tcp_keepalive)
+            .build();
+
+        server.http_config(http.build()).addr_incoming_config(incoming)
+    }
+}
+
+/// Where connections come from: the socket systemd handed over through
+/// `LISTEN_FDS` if there is one, else a Unix socket at `UNIX_SOCKET_PATH` for
+/// a proxy on the same host, else TCP port 3000.
+pub enum Listener {
+    Tcp(std::net::TcpListener),
+    Unix(UnixListener),
+}
+
+impl Listener {
+    pub fn from_env() -> io::Result<Self> {
+        let mut inherited = ListenFd::from_env();
+        if inherited.len() > 0 {
+            if let Ok(Some(listener)) = inherited.take_tcp_listener(0) {
+                return Ok(Listener::Tcp(listener));
+            }
+            let listener = inherited
+                .take_unix_listener(0)?
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no socket was passed in LISTEN_FDS"))?;
+            listener.set_nonblocking(true)?;
+            return Ok(Listener::Unix(UnixListener::from_std(listener)?));
+        }
+
+        if let Some(path) = std::env::var("UNIX_SOCKET_PATH").ok().filter(|path| !path.is_empty()) {
+            // a socket an earlier run left behind would fail the bind, anything else there is left alone
+            if std::fs::symlink_metadata(&path).is_ok_and(|meta| meta.file_type().is_socket()) {
+                std::fs::remove_file(&path)?;
+            }
+            return Ok(Listener::Unix(UnixListener::bind(path)?));
+        }
+
+        Ok(Listener::Tcp(std::net::TcpListener::bind("0.0.0.0:3000")?))
+    }
+}
+
+pub async fn serve(
+    listener: Listener,
+    tuning: &Tuning,
+    app: IntoMakeServiceWithConnectInfo<Router, SocketAddr>,
+) -> hyper::Result<()> {
+    match listener {
+        Listener::Tcp(listener) => tuning.apply(Server::from_tcp(listener)?).serve(app).await,
+        Listener::Unix(listener) => {
+            let incoming = accept::poll_fn(move |cx| {
+                listener
+                    .poll_accept(cx)
+                    .map(|accepted| Some(accepted.map(|(stream, _)| UnixConnection(stream))))
+            });
+            tuning.apply_http(Server::builder(incoming)).serve(app).await
+        }
+    }
+}
+
+/// A connection on the Unix socket, which only a proxy on the same host can
+/// reach.
+pub struct UnixConnection(UnixStream);
+
+// handlers take the peer as a socket address; over a Unix socket it is always
+// the local proxy, which passes the client's own in X-Forwarded-For
+impl Connected<&UnixConnection> for SocketAddr {
+    fn connect_info(_: &UnixConnection) -> Self {
+        SocketAddr::from(([127, 0, 0, 1], 0))
+    }
+}
+
+impl AsyncRead for UnixConnection {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for UnixConnection {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.0.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,188 @@
+use std::sync::Arc;
+
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{header, request::Parts},
+};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use hmac::{Hmac, Mac};
+use serde_json::json;
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::{error::AppError, invite_token::TokenError, secrets, timestamp, AppState};
+
+// a month, the integration signing users in hands out a new one before then
+const DEFAULT_TTL_HOURS: i64 = 720;
+
+/// Issues and checks the bearer tokens users act with. Whatever a request
+/// does as a user, it does as the one its `Authorization: Bearer` token was
+/// issued to, never as an id the client names. A token comes with the
+/// signup, and the integration that signs users in gets new ones from
+/// `POST /api/admin/users/:id/sessions`.
+pub struct Sessions {
+    ttl: Duration,
+}
+
+impl Sessions {
+    /// Tokens are signed with `SESSION_SECRET`, required at startup, and
+    /// last `SESSION_TTL_HOURS`, 720 unless set. Like the invite token
+    /// secret it's looked up on every use; `SESSION_SECRET_PREVIOUS` keeps
+    /// tokens signed with a rotated one working.
+    pub fn from_env() -> Self {
+        let ttl_hours = std::env::var("SESSION_TTL_HOURS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .filter(|hours| *hours > 0)
+            .unwrap_or(DEFAULT_TTL_HOURS);
+
+        Sessions {
+            ttl: Duration::hours(ttl_hours),
+        }
+    }
+
+    /// Stops startup without a secret to sign sessions with, there would be
+    /// no way to tell users apart.
+    pub fn check_secret() {
+        if secrets::var("SESSION_SECRET").is_none() {
+            println!("🔥 SESSION_SECRET must be set, users can't sign in without it");
+            std::process::exit(1);
+        }
+    }
+
+    /// A token for `user_id`, as `<user id>.<expiry unix time>.<hex hmac>`,
+    /// with the HMAC-SHA256 taken over everything before the last dot.
+    pub fn issue(&self, user_id: Uuid, now: DateTime<Utc>) -> Option<(String, DateTime<Utc>)> {
+        let expires_at = now + self.ttl;
+        let claims = format!("{}.{}", user_id, expires_at.timestamp());
+        let secret = secrets::var("SESSION_SECRET")?;
+        let signature = hex::encode(mac(&secret, &claims).finalize().into_bytes());
+        Some((format!("{}.{}", claims, signature), expires_at))
+    }
+
+    /// The response field carrying a fresh token, `null` without a secret.
+    pub fn to_json(&self, user_id: Uuid, now: DateTime<Utc>) -> serde_json::Value {
+        match self.issue(user_id, now) {
+            Some((token, expires_at)) => json!({"token": token, "expires_at": timestamp::json(&expires_at)}),
+            None => serde_json::Value::Null,
+        }
+    }
+
+    /// The user a token was issued to, once its signature and expiry check
+    /// out.
+    pub fn verify(&self, token: &str, now: DateTime<Utc>) -> Result<Uuid, TokenError> {
+        let (claims, signature) = token.rsplit_once('.').ok_or(TokenError::Malformed)?;
+        let (user_id, expires_at) = claims.split_once('.').ok_or(TokenError::Malformed)?;
+        let user_id = user_id.parse::<Uuid>().map_err(|_| TokenError::Malformed)?;
+        let expires_at = expires_at
+            .parse::<i64>()
+            .ok()
+            .and_then(|secs| Utc.timestamp_opt(secs, 0).single())
+            .ok_or(TokenError::Malformed)?;
+        let signature = hex::decode(signature).map_err(|_| TokenError::Malformed)?;
+
+        let signed = [secrets::var("SESSION_SECRET"), secrets::var("SESSION_SECRET_PREVIOUS")]
+            .into_iter()
+            .flatten()
+            .any(|secret| mac(&secret, claims).verify_slice(&signature).is_ok());
+        if !signed {
+            return Err(TokenError::Mismatch);
+        }
+        // only trusted once the signature matched
+        if expires_at <= now {
+            return Err(TokenError::Expired);
+        }
+        Ok(user_id)
+    }
+}
+
+fn mac(secret: &str, claims: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(claims.as_bytes());
+    mac
+}
+
+/// The signed-in user making the request. Turns the request away with 401
+/// without a valid session token, or when its user has since been deleted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Caller(pub Uuid);
+
+impl Caller {
+    /// Lets the request through only when it's made by `user_id`, for what
+    /// only a user can see or change of their own.
+    pub fn require(self, user_id: Uuid) -> Result<(), AppError> {
+        if self.0 != user_id {
+            return Err(AppError::UserAccessDenied);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for Caller {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &Arc<AppState>) -> Result<Self, Self::Rejection> {
+        let Viewer(viewer) = Viewer::from_request_parts(parts, state).await?;
+        viewer.map(Caller).ok_or(AppError::SessionRequired)
+    }
+}
+
+/// Who is looking, on routes anyone may read: the signed-in user, or `None`
+/// for a request without a session token. A token that doesn't check out is
+/// still turned away, rather than read as nobody.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Viewer(pub Option<Uuid>);
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for Viewer {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &Arc<AppState>) -> Result<Self, Self::Rejection> {
+        let Some(authorization) = parts.headers.get(header::AUTHORIZATION) else {
+            return Ok(Viewer(None));
+        };
+        let token = authorization
+            .to_str()
+            .ok()
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or(AppError::Session(TokenError::Malformed))?;
+        let user_id = state
+            .sessions
+            .verify(token.trim(), state.clock.now())
+            .map_err(AppError::Session)?;
+
+        // a deleted user's tokens go with them
+        let exists = sqlx::query_scalar!("SELECT EXISTS(SELECT 1 FROM users WHERE id = $1)", user_id)
+            .fetch_one(&state.db)
+            .await?;
+        if exists != Some(true) {
+            return Err(AppError::Session(TokenError::Mismatch));
+        }
+        Ok(Viewer(Some(user_id)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_name_their_user_until_they_expire() {
+        std::env::set_var("SESSION_SECRET", "test secret");
+        let sessions = Sessions { ttl: Duration::hours(720) };
+        let user_id = Uuid::new_v4();
+        let issued_at = Utc.timestamp_opt(1_760_520_600, 0).unwrap();
+
+        let (token, expires_at) = sessions.issue(user_id, issued_at).unwrap();
+        assert_eq!(sessions.verify(&token, expires_at - Duration::seconds(1)), Ok(user_id));
+        assert_eq!(sessions.verify(&token, expires_at), Err(TokenError::Expired));
+
+        // another user's id under the same signature
+        let (_, rest) = token.split_once('.').unwrap();
+        let forged = format!("{}.{}", Uuid::new_v4(), rest);
+        assert_eq!(sessions.verify(&forged, issued_at), Err(TokenError::Mismatch));
+        assert_eq!(sessions.verify("nope", issued_at), Err(TokenError::Malformed));
+    }
+}
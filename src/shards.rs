@@ -0,0 +1,23 @@
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+/// Which database an organization's rows live on. There is one for now,
+/// but tenant-scoped queries get their pool from here (through
+/// `Tenant::db`) rather than from `AppState::db`, so tenants can later be
+/// spread over several databases by changing only this. Users, events and
+/// the other tables not owned by an organization stay on `AppState::db`.
+pub struct Shards {
+    primary: Pool<Postgres>,
+}
+
+impl Shards {
+    pub fn new(primary: Pool<Postgres>) -> Self {
+        Shards { primary }
+    }
+
+    /// The pool holding `org_id`'s rows.
+    pub fn pool(&self, _org_id: Uuid) -> &Pool<Postgres> {
+        // every organization is on the primary until there is a second database
+        &self.primary
+    }
+}
@@ -0,0 +1,222 @@
+use std::io::Write;
+
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm, Key, Nonce,
+};
+use chrono::{DateTime, Utc};
+use flate2::{write::GzEncoder, Compression};
+use futures_util::TryStreamExt;
+use serde_json::{json, Value};
+use sqlx::{Pool, Postgres};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::secrets;
+
+pub type SnapshotError = Box<dyn std::error::Error + Send + Sync>;
+
+pub const FORMAT: &str = "invito-snapshot/1";
+
+// what an encrypted archive starts with, ahead of its frames
+const MAGIC: &[u8] = b"invito-snapshot-sealed/1\n";
+const NONCE_LEN: usize = 12;
+
+/// The tables a snapshot holds, each after the ones it references so a
+/// restore can load them in this order. Logs, queues and anything the app
+/// derives or can rebuild are left out.
+pub const TABLES: [&str; 17] = [
+    "plans",
+    "organizations",
+    "org_quotas",
+    "org_usage",
+    "users",
+    "privacy_settings",
+    "org_members",
+    "org_invitations",
+    "contacts",
+    "blocks",
+    "badges",
+    "reward_rules",
+    "rewards",
+    "fraud_flags",
+    "email_suppressions",
+    "email_domain_rules",
+    "api_clients",
+];
+
+// compressed bytes are handed on once this many have built up
+const CHUNK: usize = 64 * 1024;
+
+/// The key snapshots are encrypted with, `SNAPSHOT_KEY`, 32 bytes in hex.
+/// Snapshots hold every user's personal data and the API client secrets,
+/// so none are taken without it.
+pub fn key() -> Option<Aes256Gcm> {
+    let bytes = hex::decode(secrets::var("SNAPSHOT_KEY")?).ok().filter(|bytes| bytes.len() == 32)?;
+    Some(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&bytes)))
+}
+
+/// Writes an encrypted, gzipped snapshot of `TABLES` to `out`, all read in
+/// one repeatable-read transaction so they agree with each other. Inside
+/// it's JSON lines: a manifest, then `{"table": ..., "row": ...}` for every
+/// row, then `{"end": ...}` with the row counts. A snapshot without that
+/// last line was cut short. Emails and phone numbers are as stored, which is
+/// only sealed when a PII key is set, so the archive is encrypted as a
+/// whole, see `Sealer`. Returns the row counts.
+pub async fn write(
+    db: &Pool<Postgres>,
+    cipher: Aes256Gcm,
+    taken_at: DateTime<Utc>,
+    out: &mut (impl AsyncWrite + Unpin),
+) -> Result<Value, SnapshotError> {
+    let mut sealer = Sealer { cipher, frame: 0 };
+    out.write_all(MAGIC).await?;
+
+    let mut tx = db.begin().await?;
+    sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ, READ ONLY")
+        .execute(&mut *tx)
+        .await?;
+
+    let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+    let manifest = json!({"manifest": {"format": FORMAT, "taken_at": taken_at, "tables": TABLES}});
+    writeln!(gz, "{}", manifest)?;
+
+    let mut counts = serde_json::Map::new();
+    for table in TABLES {
+        // table names come from the list above, never from a request
+        let sql = format!("SELECT to_jsonb(t) FROM {} t", table);
+        let mut rows = sqlx::query_scalar::<_, Value>(&sql).fetch(&mut *tx);
+        let mut count = 0;
+        while let Some(row) = rows.try_next().await? {
+            writeln!(gz, "{}", json!({"table": table, "row": row}))?;
+            count += 1;
+            if gz.get_ref().len() >= CHUNK {
+                out.write_all(&sealer.seal(&std::mem::take(gz.get_mut()), false)).await?;
+            }
+        }
+        counts.insert(table.to_string(), json!(count));
+    }
+    tx.commit().await?;
+
+    let counts = Value::Object(counts);
+    writeln!(gz, "{}", json!({"end": {"rows": counts}}))?;
+    out.write_all(&sealer.seal(&gz.finish()?, true)).await?;
+    out.flush().await?;
+    Ok(counts)
+}
+
+/// Writes the gzipped JSON lines inside an archive `write` made. Fails on
+/// the wrong key, and on an archive that was cut short or tampered with.
+pub async fn open(
+    cipher: &Aes256Gcm,
+    archive: &mut (impl AsyncRead + Unpin),
+    out: &mut (impl AsyncWrite + Unpin),
+) -> Result<(), SnapshotError> {
+    let mut magic = [0u8; MAGIC.len()];
+    archive.read_exact(&mut magic).await.map_err(|_| "not a snapshot archive")?;
+    if magic != MAGIC {
+        return Err("not a snapshot archive".into());
+    }
+
+    for frame in 0.. {
+        let mut header = [0u8; NONCE_LEN + 4];
+        archive.read_exact(&mut header).await.map_err(|_| "the snapshot was cut short")?;
+        let (nonce, len) = header.split_at(NONCE_LEN);
+        let mut sealed = vec![0u8; u32::from_be_bytes(len.try_into()?) as usize];
+        archive.read_exact(&mut sealed).await.map_err(|_| "the snapshot was cut short")?;
+
+        // the last frame says so, there's no telling it apart otherwise
+        let opened = [false, true].into_iter().find_map(|last| {
+            let payload = Payload { msg: &sealed, aad: &frame_aad(frame, last) };
+            cipher.decrypt(Nonce::from_slice(nonce), payload).ok().map(|plain| (plain, last))
+        });
+        let Some((plain, last)) = opened else {
+            return Err("the snapshot doesn't open with this key, or was altered".into());
+        };
+        out.write_all(&plain).await?;
+        if last {
+            break;
+        }
+    }
+    if archive.read(&mut [0u8; 1]).await? != 0 {
+        return Err("the snapshot has data past its end".into());
+    }
+    out.flush().await?;
+    Ok(())
+}
+
+/// Encrypts the archive as it's handed on, in frames of the nonce, the
+/// ciphertext's length as 4 bytes big-endian and the AES-256-GCM
+/// ciphertext. Each frame is bound to its place and to whether it's the
+/// last, so frames can't be dropped, reordered or cut off unnoticed.
+struct Sealer {
+    cipher: Aes256Gcm,
+    frame: u64,
+}
+
+impl Sealer {
+    fn seal(&mut self, chunk: &[u8], last: bool) -> Vec<u8> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let aad = frame_aad(self.frame, last);
+        let sealed = self
+            .cipher
+            .encrypt(&nonce, Payload { msg: chunk, aad: &aad })
+            .expect("AES-GCM encrypts any chunk this size");
+        self.frame += 1;
+
+        let mut frame = Vec::with_capacity(NONCE_LEN + 4 + sealed.len());
+        frame.extend_from_slice(&nonce);
+        frame.extend_from_slice(&(sealed.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&sealed);
+        frame
+    }
+}
+
+fn frame_aad(frame: u64, last: bool) -> [u8; 9] {
+    let mut aad = [0u8; 9];
+    aad[..8].copy_from_slice(&frame.to_be_bytes());
+    aad[8] = last as u8;
+    aad
+}
+
+/// The name to save a snapshot taken at `taken_at` under.
+pub fn file_name(taken_at: DateTime<Utc>) -> String {
+    format!("invito-snapshot-{}.sealed", taken_at.format("%Y%m%dT%H%M%SZ"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use flate2::read::GzDecoder;
+
+    use super::*;
+    use crate::testing::TestApp;
+
+    fn cipher(byte: u8) -> Aes256Gcm {
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&[byte; 32]))
+    }
+
+    #[tokio::test]
+    async fn snapshots_only_open_whole_and_with_their_key() {
+        let app = TestApp::new().await;
+        app.create_user("ada", "ada@example.com").await;
+
+        let mut archive = Vec::new();
+        write(app.db(), cipher(1), Utc::now(), &mut archive).await.unwrap();
+        assert!(archive.starts_with(MAGIC));
+        assert!(!String::from_utf8_lossy(&archive).contains("ada@example.com"));
+
+        let mut opened = Vec::new();
+        open(&cipher(1), &mut archive.as_slice(), &mut opened).await.unwrap();
+        let mut lines = String::new();
+        GzDecoder::new(opened.as_slice()).read_to_string(&mut lines).unwrap();
+        // rows go in as stored, the email sealed
+        assert!(lines.contains(r#""user_name":"ada""#), "{}", lines);
+        assert!(!lines.contains("ada@example.com"));
+        assert!(lines.lines().last().unwrap().starts_with(r#"{"end":"#));
+
+        assert!(open(&cipher(2), &mut archive.as_slice(), &mut Vec::new()).await.is_err());
+        let cut = &archive[..archive.len() - 1];
+        assert!(open(&cipher(1), &mut &cut[..], &mut Vec::new()).await.is_err());
+    }
+}
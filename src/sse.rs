@@ -0,0 +1,206 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+use crate::{events::SchemaVersion, scheduler, AppState};
+
+/// One open SSE stream.
+pub struct ConnectionState {
+    pub id: Uuid,
+    pub user_id: Option<Uuid>,
+    pub org_id: Option<Uuid>,
+    pub user_agent: String,
+    pub schema_version: SchemaVersion,
+    pub connected_at: DateTime<Utc>,
+    // last time the client took something off the stream, heartbeats included
+    last_seen: Mutex<DateTime<Utc>>,
+    missed: AtomicU64,
+    closing: AtomicBool,
+    close: Notify,
+}
+
+impl ConnectionState {
+    /// Records that the client is still reading.
+    pub fn touch(&self, now: DateTime<Utc>) {
+        *self.last_seen.lock().unwrap() = now;
+    }
+
+    /// Records events the client fell too far behind to receive. Returns
+    /// whether it has now missed more than the registry tolerates.
+    pub fn record_lag(&self, missed: u64, max_lag: u64) -> bool {
+        let total = self.missed.fetch_add(missed, Ordering::Relaxed) + missed;
+        if total > max_lag {
+            self.disconnect();
+            return true;
+        }
+        false
+    }
+
+    /// Ends the stream the next time it is polled.
+    pub fn disconnect(&self) {
+        if !self.closing.swap(true, Ordering::Relaxed) {
+            self.close.notify_one();
+        }
+    }
+
+    /// Resolves once the connection has been told to close.
+    pub async fn closed(&self) {
+        self.close.notified().await
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConnectionInfo {
+    pub id: Uuid,
+    pub user_id: Option<Uuid>,
+    pub org_id: Option<Uuid>,
+    pub user_agent: String,
+    pub schema_version: SchemaVersion,
+    #[serde(with = "crate::timestamp")]
+    pub connected_at: DateTime<Utc>,
+    pub age_secs: i64,
+    #[serde(with = "crate::timestamp")]
+    pub last_seen_at: DateTime<Utc>,
+    pub missed_events: u64,
+    pub closing: bool,
+}
+
+type Connections = Arc<Mutex<HashMap<Uuid, Arc<ConnectionState>>>>;
+
+/// Every open SSE connection with its liveness. Clients that fall more than
+/// `max_lag` events behind are disconnected, and `spawn_reaper` reaps the
+/// ones that stopped reading heartbeats altogether.
+pub struct SseRegistry {
+    pub heartbeat: Duration,
+    pub max_lag: u64,
+    pub schema_version: SchemaVersion,
+    connections: Connections,
+}
+
+impl SseRegistry {
+    /// Heartbeats go out every `SSE_HEARTBEAT_SECS` (15 by default). A client
+    /// is reaped after missing three of them or more than `SSE_MAX_LAG`
+    /// events (50 by default). Clients that don't ask for an event schema
+    /// version get `SSE_SCHEMA_VERSION`, 1 unless set.
+    pub fn from_env() -> Self {
+        let heartbeat_secs = std::env::var("SSE_HEARTBEAT_SECS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .filter(|secs| *secs > 0)
+            .unwrap_or(15);
+        let max_lag = std::env::var("SSE_MAX_LAG")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(50);
+        let schema_version = std::env::var("SSE_SCHEMA_VERSION")
+            .ok()
+            .and_then(|value| value.parse::<u8>().ok())
+            .and_then(|version| SchemaVersion::try_from(version).ok())
+            .unwrap_or(SchemaVersion::V1);
+
+        SseRegistry {
+            heartbeat: Duration::from_secs(heartbeat_secs),
+            max_lag,
+            schema_version,
+            connections: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn register(
+        &self,
+        user_id: Option<Uuid>,
+        org_id: Option<Uuid>,
+        user_agent: &str,
+        schema_version: SchemaVersion,
+        now: DateTime<Utc>,
+    ) -> SseConnection {
+        let state = Arc::new(ConnectionState {
+            id: Uuid::new_v4(),
+            user_id,
+            org_id,
+            user_agent: user_agent.to_string(),
+            schema_version,
+            connected_at: now,
+            last_seen: Mutex::new(now),
+            missed: AtomicU64::new(0),
+            closing: AtomicBool::new(false),
+            close: Notify::new(),
+        });
+        self.connections.lock().unwrap().insert(state.id, state.clone());
+
+        SseConnection {
+            state,
+            connections: self.connections.clone(),
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.connections.lock().unwrap().len()
+    }
+
+    pub fn list(&self, now: DateTime<Utc>) -> Vec<ConnectionInfo> {
+        let mut connections: Vec<ConnectionInfo> = self
+            .connections
+            .lock()
+            .unwrap()
+            .values()
+            .map(|state| ConnectionInfo {
+                id: state.id,
+                user_id: state.user_id,
+                org_id: state.org_id,
+                user_agent: state.user_agent.clone(),
+                schema_version: state.schema_version,
+                connected_at: state.connected_at,
+                age_secs: (now - state.connected_at).num_seconds(),
+                last_seen_at: *state.last_seen.lock().unwrap(),
+                missed_events: state.missed.load(Ordering::Relaxed),
+                closing: state.closing.load(Ordering::Relaxed),
+            })
+            .collect();
+        connections.sort_by_key(|connection| connection.connected_at);
+        connections
+    }
+
+    // disconnects the clients that missed three heartbeats by `now`
+    fn reap(&self, now: DateTime<Utc>) {
+        let stale_after = chrono::Duration::from_std(self.heartbeat * 3).unwrap_or(chrono::Duration::max_value());
+        for state in self.connections.lock().unwrap().values() {
+            let closing = state.closing.load(Ordering::Relaxed);
+            if !closing && now - *state.last_seen.lock().unwrap() > stale_after {
+                println!("Reaping SSE connection {}, it stopped reading", state.id);
+                state.disconnect();
+            }
+        }
+    }
+}
+
+/// Checks for stale connections once every heartbeat by the app's clock.
+pub fn spawn_reaper(data: Arc<AppState>) {
+    let heartbeat = data.sse.heartbeat;
+    scheduler::every("sse_reaper", heartbeat, data, |data| async move {
+        data.sse.reap(data.clock.now());
+        Ok(())
+    });
+}
+
+/// Keeps a connection listed for as long as its stream is alive.
+pub struct SseConnection {
+    pub state: Arc<ConnectionState>,
+    connections: Connections,
+}
+
+impl Drop for SseConnection {
+    fn drop(&mut self) {
+        self.connections.lock().unwrap().remove(&self.state.id);
+    }
+}
@@ -0,0 +1,201 @@
+use std::{
+    io,
+    path::{Component, Path, PathBuf},
+};
+
+use axum::async_trait;
+use chrono::{DateTime, TimeZone, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::{error::AppError, secrets};
+
+pub type Reader = Box<dyn AsyncRead + Send + Unpin>;
+pub type Writer = Box<dyn AsyncWrite + Send + Unpin>;
+
+/// Where files the API produces, like finished exports, are kept. Clients
+/// fetch them by a link that's signed to expire, without credentials.
+#[async_trait]
+pub trait StorageBackend: Send + Sync {
+    /// A new file at `key`, replacing whatever was there.
+    async fn create(&self, key: &str) -> io::Result<Writer>;
+
+    /// Removes the file at `key`. One that's already gone isn't an error.
+    async fn delete(&self, key: &str) -> io::Result<()>;
+
+    /// A link to the file at `key` that works until `expires_at`.
+    fn signed_url(&self, key: &str, expires_at: DateTime<Utc>) -> String;
+
+    /// The file a link from `signed_url` leads to, for backends whose links
+    /// come back to this API. Those of others never do.
+    async fn open_signed(&self, _key: &str, _expires: i64, _signature: &str, _now: DateTime<Utc>) -> Result<Reader, AppError> {
+        Err(AppError::DownloadLinkInvalid)
+    }
+}
+
+/// Files on this instance's disk under `STORAGE_DIR`, `storage` unless set,
+/// served by `/api/storage/*key` at `PUBLIC_URL`. Links are signed with
+/// `STORAGE_URL_SECRET`; without one a key is made up at startup, and links
+/// stop working on a restart.
+pub struct LocalStorage {
+    dir: PathBuf,
+    public_url: String,
+    secret: Vec<u8>,
+}
+
+impl LocalStorage {
+    fn from_env() -> Self {
+        let secret = match secrets::var("STORAGE_URL_SECRET") {
+            Some(secret) => secret.into_bytes(),
+            None => {
+                println!("🔥 STORAGE_URL_SECRET is not set, download links won't survive a restart");
+                rand::random::<[u8; 32]>().to_vec()
+            }
+        };
+        LocalStorage {
+            dir: std::env::var("STORAGE_DIR").unwrap_or_else(|_| "storage".to_string()).into(),
+            public_url: std::env::var("PUBLIC_URL")
+                .map(|url| url.trim_end_matches('/').to_string())
+                .unwrap_or_else(|_| "http://localhost:3000".to_string()),
+            secret,
+        }
+    }
+
+    // keys are ours, but never let one reach outside the directory
+    fn path(&self, key: &str) -> io::Result<PathBuf> {
+        let key = Path::new(key);
+        if !key.components().all(|component| matches!(component, Component::Normal(_))) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "not a storage key"));
+        }
+        Ok(self.dir.join(key))
+    }
+
+    fn mac(&self, key: &str, expires: i64) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.secret).unwrap();
+        mac.update(format!("{}.{}", key, expires).as_bytes());
+        mac
+    }
+}
+
+#[async_trait]
+impl StorageBackend for LocalStorage {
+    async fn create(&self, key: &str) -> io::Result<Writer> {
+        let path = self.path(key)?;
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        Ok(Box::new(tokio::fs::File::create(path).await?))
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        match tokio::fs::remove_file(self.path(key)?).await {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+
+    fn signed_url(&self, key: &str, expires_at: DateTime<Utc>) -> String {
+        let expires = expires_at.timestamp();
+        let signature = hex::encode(self.mac(key, expires).finalize().into_bytes());
+        format!(
+            "{}/api/storage/{}?expires={}&signature={}",
+            self.public_url, key, expires, signature
+        )
+    }
+
+    async fn open_signed(&self, key: &str, expires: i64, signature: &str, now: DateTime<Utc>) -> Result<Reader, AppError> {
+        let signature = hex::decode(signature).map_err(|_| AppError::DownloadLinkInvalid)?;
+        self.mac(key, expires)
+            .verify_slice(&signature)
+            .map_err(|_| AppError::DownloadLinkInvalid)?;
+        // only trusted once the signature matched
+        let expires_at = Utc.timestamp_opt(expires, 0).single().ok_or(AppError::DownloadLinkInvalid)?;
+        if expires_at <= now {
+            return Err(AppError::DownloadLinkInvalid);
+        }
+
+        let path = self.path(key).map_err(|_| AppError::DownloadNotFound)?;
+        match tokio::fs::File::open(path).await {
+            Ok(file) => Ok(Box::new(file)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Err(AppError::DownloadNotFound),
+            Err(e) => {
+                println!("🔥 Failed to open {} in storage: {}", key, e);
+                Err(AppError::Unexpected)
+            }
+        }
+    }
+}
+
+/// The backend named by `STORAGE_BACKEND`, `local` being the only one for
+/// now and the default.
+pub fn from_env() -> Box<dyn StorageBackend> {
+    match std::env::var("STORAGE_BACKEND").as_deref() {
+        Err(_) | Ok("local") => Box::new(LocalStorage::from_env()),
+        Ok(other) => {
+            println!("🔥 `{}` is not a supported storage backend", other);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    fn storage() -> LocalStorage {
+        LocalStorage {
+            dir: std::env::temp_dir().join(format!("invito-storage-{}", std::process::id())),
+            public_url: "https://api.example".to_string(),
+            secret: b"secret".to_vec(),
+        }
+    }
+
+    fn query(url: &str) -> (i64, String) {
+        let (_, query) = url.split_once('?').unwrap();
+        let (expires, signature) = query.split_once('&').unwrap();
+        (
+            expires.trim_start_matches("expires=").parse().unwrap(),
+            signature.trim_start_matches("signature=").to_string(),
+        )
+    }
+
+    #[tokio::test]
+    async fn signed_links_open_until_they_expire() {
+        let storage = storage();
+        let now = Utc.timestamp_opt(1_760_520_600, 0).unwrap();
+        tokio::io::AsyncWriteExt::write_all(&mut storage.create("exports/a.json").await.unwrap(), b"[]")
+            .await
+            .unwrap();
+
+        let url = storage.signed_url("exports/a.json", now + Duration::minutes(15));
+        assert!(url.starts_with("https://api.example/api/storage/exports/a.json?"));
+        let (expires, signature) = query(&url);
+
+        assert!(storage.open_signed("exports/a.json", expires, &signature, now).await.is_ok());
+        assert!(matches!(
+            storage.open_signed("exports/b.json", expires, &signature, now).await,
+            Err(AppError::DownloadLinkInvalid)
+        ));
+        assert!(matches!(
+            storage.open_signed("exports/a.json", expires, &signature, now + Duration::minutes(16)).await,
+            Err(AppError::DownloadLinkInvalid)
+        ));
+
+        storage.delete("exports/a.json").await.unwrap();
+        assert!(matches!(
+            storage.open_signed("exports/a.json", expires, &signature, now).await,
+            Err(AppError::DownloadNotFound)
+        ));
+        let _ = std::fs::remove_dir_all(&storage.dir);
+    }
+
+    #[test]
+    fn keys_stay_inside_the_directory() {
+        assert!(storage().path("../etc/passwd").is_err());
+        assert!(storage().path("/etc/passwd").is_err());
+        assert!(storage().path("exports/a.json").is_ok());
+    }
+}
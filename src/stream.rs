@@ -0,0 +1,155 @@
+use std::{future::Future, io};
+
+use axum::body::{Bytes, StreamBody};
+use futures_util::{Stream, StreamExt};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    sync::mpsc,
+};
+use tokio_stream::wrappers::ReceiverStream;
+use uuid::Uuid;
+
+use crate::{fields::Fields, model::UserModel, privacy, AppState};
+
+// rows presented and sent on together, what's held in memory at a time
+pub const BATCH: usize = 100;
+
+/// Where a streamed body is written to, a chunk at a time.
+pub struct Sink(mpsc::Sender<io::Result<Bytes>>);
+
+/// Why writing a streamed body stopped early.
+#[derive(Debug)]
+pub enum Stop {
+    /// The client went away, nothing more needs writing.
+    Gone,
+    Failed(sqlx::Error),
+}
+
+impl From<sqlx::Error> for Stop {
+    fn from(e: sqlx::Error) -> Self {
+        Stop::Failed(e)
+    }
+}
+
+impl Sink {
+    pub async fn send(&self, chunk: impl Into<Bytes>) -> Result<(), Stop> {
+        self.0.send(Ok(chunk.into())).await.map_err(|_| Stop::Gone)
+    }
+}
+
+/// A response body written by `write` as it goes, for lists too long to
+/// hold in memory. Only a few chunks are buffered, so a slow client slows
+/// the writing down. A failure partway through breaks off the response, so
+/// the client sees it didn't get everything rather than a short list.
+pub fn body<F, Fut>(write: F) -> StreamBody<ReceiverStream<io::Result<Bytes>>>
+where
+    F: FnOnce(Sink) -> Fut,
+    Fut: Future<Output = Result<(), Stop>> + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel(4);
+    let writing = write(Sink(tx.clone()));
+    tokio::spawn(async move {
+        if let Err(Stop::Failed(e)) = writing.await {
+            println!("🔥 Streamed response failed: {:?}", e);
+            let _ = tx.send(Err(io::Error::other(e))).await;
+        }
+    });
+    StreamBody::new(ReceiverStream::new(rx))
+}
+
+/// How the rows of a streamed list are laid out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// The elements of a JSON array, without the brackets.
+    Array,
+    /// One JSON document per line, NDJSON.
+    Lines,
+}
+
+/// Writes the users `rows` yields as `viewer` may see them, with only
+/// `fields`. Returns how many rows were read and how many of them were
+/// written, private profiles being left out.
+pub async fn users<S>(
+    data: &AppState,
+    rows: S,
+    viewer: Option<Uuid>,
+    fields: &Fields,
+    layout: Layout,
+    sink: &Sink,
+) -> Result<(usize, usize), Stop>
+where
+    S: Stream<Item = Result<UserModel, sqlx::Error>>,
+{
+    let mut batches = std::pin::pin!(rows.chunks(BATCH));
+    let (mut read, mut written) = (0, 0);
+    while let Some(batch) = batches.next().await {
+        let batch = batch.into_iter().collect::<Result<Vec<_>, _>>()?;
+        read += batch.len();
+
+        let mut chunk = Vec::new();
+        encode(data, &batch, viewer, fields, layout, &mut chunk, &mut written).await?;
+        sink.send(chunk).await?;
+    }
+    Ok((read, written))
+}
+
+/// Appends the users in `batch` to `out`, as for `users`. `written` is how
+/// many were written before, for the commas of an array, and is counted up.
+pub async fn encode(
+    data: &AppState,
+    batch: &[UserModel],
+    viewer: Option<Uuid>,
+    fields: &Fields,
+    layout: Layout,
+    out: &mut Vec<u8>,
+    written: &mut usize,
+) -> Result<(), sqlx::Error> {
+    for user in privacy::present_all(&data.db, batch, viewer).await? {
+        if layout == Layout::Array && *written > 0 {
+            out.push(b',');
+        }
+        out.extend_from_slice(fields.select(user).to_string().as_bytes());
+        if layout == Layout::Lines {
+            out.push(b'\n');
+        }
+        *written += 1;
+    }
+    Ok(())
+}
+
+/// A response body read from `reader` as the client takes it.
+pub fn read<R>(reader: R) -> StreamBody<impl Stream<Item = io::Result<Bytes>>>
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    StreamBody::new(futures_util::stream::unfold(reader, |mut reader| async move {
+        let mut buf = vec![0; 16 * 1024];
+        match reader.read(&mut buf).await {
+            Ok(0) => None,
+            Ok(read) => {
+                buf.truncate(read);
+                Some((Ok(Bytes::from(buf)), reader))
+            }
+            Err(e) => Some((Err(e), reader)),
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::HttpBody;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn a_failure_partway_cuts_the_body_off() {
+        let body = body(|sink| async move {
+            sink.send("[1,").await?;
+            Err(Stop::Failed(sqlx::Error::PoolTimedOut))
+        });
+        let mut body = std::pin::pin!(body);
+        assert_eq!(body.data().await.unwrap().unwrap(), "[1,");
+        assert!(body.data().await.unwrap().is_err());
+        assert!(body.data().await.is_none());
+    }
+}
@@ -0,0 +1,243 @@
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde_json::json;
+use sha2::Sha256;
+use sqlx::{Executor, PgConnection, Postgres};
+use uuid::Uuid;
+
+use crate::{ids, pii::{self, Pii}, secrets};
+
+/// Keeps email from going to addresses that unsubscribed, bounced or
+/// complained. Signs the one-click unsubscribe links (RFC 8058) put in
+/// outgoing email and checks the email provider's bounce and complaint
+/// webhooks.
+pub struct Suppressions {
+    unsubscribe_secret: Option<String>,
+    webhook_secret: Option<String>,
+    public_url: String,
+}
+
+impl Suppressions {
+    /// Unsubscribe links are signed with `UNSUBSCRIBE_SECRET` and point at
+    /// `PUBLIC_URL`, provider webhooks are checked with
+    /// `EMAIL_WEBHOOK_SECRET`. Either is off while its secret is unset.
+    pub fn from_env() -> Self {
+        Suppressions {
+            unsubscribe_secret: secrets::var("UNSUBSCRIBE_SECRET"),
+            webhook_secret: secrets::var("EMAIL_WEBHOOK_SECRET"),
+            public_url: std::env::var("PUBLIC_URL")
+                .map(|url| url.trim_end_matches('/').to_string())
+                .unwrap_or_else(|_| "http://localhost:3000".to_string()),
+        }
+    }
+
+    pub fn webhook_configured(&self) -> bool {
+        self.webhook_secret.is_some()
+    }
+
+    /// An unsubscribe token for an address, `<link id>.<hex hmac>`. The id
+    /// is random and stands for the address in `unsubscribe_links`, one per
+    /// address, so links don't carry it. It doesn't expire, links in old
+    /// emails have to keep working.
+    pub async fn unsubscribe_token(
+        &self,
+        conn: &mut PgConnection,
+        email: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Option<String>, sqlx::Error> {
+        let Some(secret) = self.unsubscribe_secret.as_deref() else {
+            return Ok(None);
+        };
+        let email = email.trim().to_lowercase();
+        let id = sqlx::query_scalar!(
+            "INSERT INTO unsubscribe_links (id, email, email_index, created_at) VALUES ($1, $2, $3, $4) ON CONFLICT (email_index) DO UPDATE SET email_index = EXCLUDED.email_index RETURNING id",
+            ids::new(),
+            pii::seal(&email),
+            pii::blind_index(&email),
+            now
+        )
+        .fetch_one(conn)
+        .await?;
+        let signature = hex::encode(mac(secret, id.to_string().as_bytes()).finalize().into_bytes());
+        Ok(Some(format!("{}.{}", id, signature)))
+    }
+
+    /// The address an unsubscribe token was made for, if it's genuine.
+    /// Tokens from before links had ids, `<hex email>.<hex hmac>`, are still
+    /// taken so links in emails already sent keep working.
+    pub async fn verify_unsubscribe<'e>(
+        &self,
+        db: impl Executor<'e, Database = Postgres>,
+        token: &str,
+    ) -> Result<Option<String>, sqlx::Error> {
+        let Some(secret) = self.unsubscribe_secret.as_deref() else {
+            return Ok(None);
+        };
+        let Some((claim, signature)) = token.split_once('.') else {
+            return Ok(None);
+        };
+        let signed = hex::decode(signature)
+            .is_ok_and(|signature| mac(secret, claim.as_bytes()).verify_slice(&signature).is_ok());
+        if !signed {
+            return Ok(None);
+        }
+
+        let Ok(id) = claim.parse::<Uuid>() else {
+            return Ok(hex::decode(claim).ok().and_then(|email| String::from_utf8(email).ok()));
+        };
+        let email = sqlx::query_scalar!(r#"SELECT email AS "email: Pii" FROM unsubscribe_links WHERE id = $1"#, id)
+            .fetch_optional(db)
+            .await?;
+        Ok(email.map(|email| email.to_string()))
+    }
+
+    /// The headers that make mail clients offer one-click unsubscribe, for
+    /// whatever sends the email. `null` while unsubscribe links are off.
+    pub async fn headers(
+        &self,
+        conn: &mut PgConnection,
+        email: &str,
+        now: DateTime<Utc>,
+    ) -> Result<serde_json::Value, sqlx::Error> {
+        Ok(match self.unsubscribe_token(conn, email, now).await? {
+            Some(token) => json!({
+                "List-Unsubscribe": format!("<{}/api/email/unsubscribe?token={}>", self.public_url, token),
+                "List-Unsubscribe-Post": "List-Unsubscribe=One-Click",
+            }),
+            None => serde_json::Value::Null,
+        })
+    }
+
+    /// Checks the `X-Webhook-Signature` header, a hex HMAC-SHA256 of the raw
+    /// body keyed with the webhook secret.
+    pub fn verify_webhook(&self, signature: &str, payload: &[u8]) -> bool {
+        let (Some(secret), Ok(signature)) = (self.webhook_secret.as_deref(), hex::decode(signature.trim())) else {
+            return false;
+        };
+        mac(secret, payload).verify_slice(&signature).is_ok()
+    }
+}
+
+fn mac(secret: &str, payload: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(payload);
+    mac
+}
+
+pub async fn is_suppressed<'e>(db: impl Executor<'e, Database = Postgres>, email: &str) -> Result<bool, sqlx::Error> {
+    let suppressed = sqlx::query_scalar!(
+        "SELECT EXISTS(SELECT 1 FROM email_suppressions WHERE email_index = $1)",
+        pii::blind_index(email)
+    )
+    .fetch_one(db)
+    .await?;
+    Ok(suppressed == Some(true))
+}
+
+/// Stops email to an address, stored sealed under its blind index. An
+/// address already suppressed keeps its first reason, and `false` is
+/// returned.
+pub async fn suppress<'e>(
+    db: impl Executor<'e, Database = Postgres>,
+    email: &str,
+    reason: &str,
+    details: Option<&str>,
+) -> Result<bool, sqlx::Error> {
+    let email = email.trim().to_lowercase();
+    let added = sqlx::query_scalar!(
+        "INSERT INTO email_suppressions (id, email, email_index, reason, details) VALUES ($1, $2, $3, $4, $5) ON CONFLICT DO NOTHING RETURNING email_index",
+        ids::new(),
+        pii::seal(&email),
+        pii::blind_index(&email),
+        reason,
+        details
+    )
+    .fetch_optional(db)
+    .await?;
+    Ok(added.is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+
+    use super::*;
+    use crate::testing::TestApp;
+
+    #[tokio::test]
+    async fn suppressions_go_by_blind_index_and_stay_out_of_the_audit_log() {
+        let app = TestApp::new().await;
+        assert!(suppress(app.db(), " Ada@Example.com", "bounced", None).await.unwrap());
+        assert!(!suppress(app.db(), "ada@example.com", "complained", None).await.unwrap());
+        assert!(is_suppressed(app.db(), "ADA@example.com").await.unwrap());
+
+        let index = sqlx::query_scalar!("SELECT email_index FROM email_suppressions")
+            .fetch_one(app.db())
+            .await
+            .unwrap();
+        assert_eq!(index, pii::blind_index("ada@example.com"));
+
+        let id = sqlx::query_scalar!("SELECT id FROM email_suppressions")
+            .fetch_one(app.db())
+            .await
+            .unwrap();
+        let (status, _) = app
+            .signed(Method::DELETE, &format!("/api/admin/email-suppressions/{}", id), None)
+            .await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, body) = app
+            .signed(Method::DELETE, &format!("/api/admin/email-suppressions/{}", Uuid::new_v4()), None)
+            .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["code"], "SUPPRESSION_NOT_FOUND");
+        assert!(!is_suppressed(app.db(), "ada@example.com").await.unwrap());
+
+        let details = sqlx::query_scalar!("SELECT details FROM audit_logs WHERE action = 'email.unsuppressed'")
+            .fetch_one(app.db())
+            .await
+            .unwrap();
+        assert_eq!(details["email_index"], json!(index));
+        assert!(!details.to_string().contains("ada@"), "{}", details);
+    }
+
+    #[tokio::test]
+    async fn unsubscribe_links_carry_an_id_and_not_the_address() {
+        let app = TestApp::with(|state| {
+            state.suppressions.unsubscribe_secret = Some("unsubscribe secret".to_string());
+        })
+        .await;
+        let suppressions = &app.state().suppressions;
+        let now = app.state().clock.now();
+        let mut conn = app.db().acquire().await.unwrap();
+
+        let token = suppressions.unsubscribe_token(&mut conn, "Ada@Example.com", now).await.unwrap().unwrap();
+        let again = suppressions.unsubscribe_token(&mut conn, "ada@example.com", now).await.unwrap().unwrap();
+        assert_eq!(token, again);
+        assert!(!token.contains(&hex::encode("ada@example.com")), "{}", token);
+        assert!(!token.to_lowercase().contains("ada"), "{}", token);
+
+        let (status, body) = app.get(&format!("/api/email/unsubscribe?token={}", token)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["email"], "ada@example.com");
+
+        // another link's id under this one's signature
+        let (id, signature) = token.split_once('.').unwrap();
+        assert!(id.parse::<Uuid>().is_ok());
+        let forged = format!("{}.{}", Uuid::new_v4(), signature);
+        let (status, _) = app.post(&format!("/api/email/unsubscribe?token={}", forged), json!({})).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, _) = app.post(&format!("/api/email/unsubscribe?token={}", token), json!({})).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(is_suppressed(app.db(), "ada@example.com").await.unwrap());
+
+        // links in email sent before they had ids
+        let legacy = hex::encode("grace@example.com");
+        let signature = hex::encode(mac("unsubscribe secret", legacy.as_bytes()).finalize().into_bytes());
+        let (status, _) = app
+            .post(&format!("/api/email/unsubscribe?token={}.{}", legacy, signature), json!({}))
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(is_suppressed(app.db(), "grace@example.com").await.unwrap());
+    }
+}
@@ -0,0 +1,146 @@
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Path},
+    http::request::Parts,
+};
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+use crate::{error::AppError, handler::org::{ORG_ADMIN_ROLES, ORG_ROLES}, session::Caller, AppState};
+
+pub const ORG_HEADER: &str = "x-org-id";
+
+/// The organization a request is scoped to, taken from the `:org_id` path
+/// segment or, on routes without one, the `X-Org-Id` header. Only resolves
+/// for organizations that exist and for a signed-in member of them, so every
+/// query made with it stays inside one tenant and is made by one of its
+/// members.
+#[derive(Debug, Clone, Copy)]
+pub struct Tenant {
+    pub org_id: Uuid,
+    // the member making the request, and their role in the organization
+    pub user_id: Uuid,
+    role: &'static str,
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for Tenant {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let from_path = Path::<HashMap<String, String>>::from_request_parts(parts, state)
+            .await
+            .ok()
+            .and_then(|Path(params)| params.get("org_id").cloned());
+        let raw = from_path.or_else(|| {
+            parts
+                .headers
+                .get(ORG_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        });
+
+        let Some(raw) = raw else {
+            return Err(AppError::OrgRequired);
+        };
+
+        let Ok(org_id) = raw.trim().parse::<Uuid>() else {
+            return Err(AppError::OrgIdInvalid(raw));
+        };
+
+        let exists = sqlx::query_scalar!("SELECT EXISTS(SELECT 1 FROM organizations WHERE id = $1)", org_id)
+            .fetch_one(state.shards.pool(org_id))
+            .await?;
+
+        if exists != Some(true) {
+            return Err(AppError::OrgNotFound(org_id));
+        }
+
+        let Caller(user_id) = Caller::from_request_parts(parts, state).await?;
+        let role = sqlx::query_scalar!(
+            "SELECT role FROM org_members WHERE org_id = $1 AND user_id = $2",
+            org_id,
+            user_id
+        )
+        .fetch_optional(state.shards.pool(org_id))
+        .await?
+        .ok_or(AppError::OrgMembershipRequired(user_id))?;
+
+        Ok(Tenant {
+            org_id,
+            user_id,
+            role: ORG_ROLES.into_iter().find(|known| *known == role).unwrap_or("member"),
+        })
+    }
+}
+
+impl Tenant {
+    /// The pool this organization's rows live on, for every query scoped to it.
+    pub fn db<'a>(&self, data: &'a AppState) -> &'a Pool<Postgres> {
+        data.shards.pool(self.org_id)
+    }
+
+    /// Lets the request through only when it's made by one of the
+    /// organization's owners or admins.
+    pub fn require_admin(&self) -> Result<(), AppError> {
+        if !ORG_ADMIN_ROLES.contains(&self.role) {
+            return Err(AppError::OrgAdminRequired);
+        }
+        Ok(())
+    }
+
+    /// Lets the request through only when it's made by an owner, for
+    /// granting or taking away ownership.
+    pub fn require_owner(&self) -> Result<(), AppError> {
+        if self.role != "owner" {
+            return Err(AppError::OrgOwnerRequired);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use crate::testing::TestApp;
+
+    #[tokio::test]
+    async fn only_members_see_inside_an_organization() {
+        let app = TestApp::new().await;
+        let ada = app.create_user("ada", "ada@example.com").await;
+        let bob = app.create_user("bob", "bob@example.com").await;
+        let (status, created) = app.as_user(&ada, Method::POST, "/api/orgs", Some(json!({"name": "Acme"}))).await;
+        assert_eq!(status, StatusCode::CREATED, "{}", created);
+        let org = format!("/api/orgs/{}", created["data"]["organization"]["id"].as_str().unwrap());
+
+        let some_id = Uuid::new_v4();
+        for uri in [
+            org.clone(),
+            format!("{}/members", org),
+            format!("{}/invitations", org),
+            format!("{}/invitations/{}/delivery", org, some_id),
+            format!("{}/contacts", org),
+            format!("{}/contacts/{}", org, some_id),
+        ] {
+            let (status, body) = app.as_user(&bob, Method::GET, &uri, None).await;
+            assert_eq!(status, StatusCode::FORBIDDEN, "{}", uri);
+            assert_eq!(body["code"], "ORG_MEMBERSHIP_REQUIRED");
+            let (status, _) = app.get(&uri).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED, "{}", uri);
+        }
+        let (status, _) = app.as_user(&bob, Method::DELETE, &format!("{}/contacts/{}", org, some_id), None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let (status, members) = app.as_user(&ada, Method::GET, &format!("{}/members", org), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(members["members"][0]["user_id"], ada["id"]);
+    }
+}
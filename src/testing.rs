@@ -0,0 +1,204 @@
+use std::{net::SocketAddr, str::FromStr, sync::Arc};
+
+use axum::{
+    body::Body,
+    extract::ConnectInfo,
+    http::{header, Method, Request, StatusCode},
+    response::Response,
+    Router,
+};
+use serde_json::{json, Value};
+use sqlx::{
+    postgres::{PgConnectOptions, PgPoolOptions},
+    ConnectOptions, Connection, Pool, Postgres,
+};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use tower::ServiceExt;
+use uuid::Uuid;
+
+use crate::{query_metrics::QueryMetrics, request_signing, route::create_router, AppState};
+
+/// The app on a database of its own, migrated from scratch for the test and
+/// dropped with it. Needs `DATABASE_URL` to point at a server it may create
+/// databases on.
+pub struct TestApp {
+    state: Arc<AppState>,
+    router: Router,
+    database: String,
+    // an API client to sign admin requests with
+    client: (Uuid, String),
+}
+
+impl TestApp {
+    pub async fn new() -> Self {
+        Self::with(|_| {}).await
+    }
+
+    /// The app with its state changed first, e.g. to run on a manual clock.
+    pub async fn with(configure: impl FnOnce(&mut AppState)) -> Self {
+        let server = server_options();
+        let database = format!("invito_test_{}", Uuid::new_v4().simple());
+        let mut conn = server.connect().await.expect("connect to the test database server");
+        sqlx::query(&format!("CREATE DATABASE \"{}\"", database))
+            .execute(&mut conn)
+            .await
+            .expect("create the test database");
+        let _ = conn.close().await;
+
+        let db = PgPoolOptions::new()
+            .max_connections(5)
+            .connect_with(server.database(&database))
+            .await
+            .expect("connect to the test database");
+        sqlx::migrate!().run(&db).await.expect("migrate the test database");
+        let secret = hex::encode(rand::random::<[u8; 32]>());
+        let client_id = sqlx::query_scalar!("INSERT INTO api_clients (name, secret) VALUES ('tests', $1) RETURNING id", secret)
+            .fetch_one(&db)
+            .await
+            .expect("create the test API client");
+
+        // the users the tests act as sign in with it, and personal data is
+        // sealed as it is in production
+        std::env::set_var("SESSION_SECRET", "test secret");
+        std::env::set_var("PII_ENCRYPTION_KEY", "00".repeat(32));
+        std::env::set_var("PII_INDEX_KEY", "11".repeat(32));
+        let mut state = AppState::from_env(db, QueryMetrics::from_env()).await;
+        configure(&mut state);
+        let state = Arc::new(state);
+        TestApp {
+            router: create_router(state.clone()),
+            state,
+            database,
+            client: (client_id, secret),
+        }
+    }
+
+    /// The test's database, to check what the app wrote.
+    pub fn db(&self) -> &Pool<Postgres> {
+        &self.state.db
+    }
+
+    /// The app's state, to call into what the routes use.
+    pub fn state(&self) -> &AppState {
+        &self.state
+    }
+
+    pub async fn get(&self, uri: &str) -> (StatusCode, Value) {
+        self.request(Method::GET, uri, None).await
+    }
+
+    pub async fn post(&self, uri: &str, body: Value) -> (StatusCode, Value) {
+        self.request(Method::POST, uri, Some(body)).await
+    }
+
+    /// Sends a request from 127.0.0.1 and reads the response as JSON, or as
+    /// a JSON string when it isn't any.
+    pub async fn request(&self, method: Method, uri: &str, body: Option<Value>) -> (StatusCode, Value) {
+        self.send(builder(method, uri), body).await
+    }
+
+    /// Sends a request signed by the test's API client, as the admin
+    /// endpoints want. The same request can't be signed twice in a second,
+    /// the second signature would be a replay.
+    pub async fn signed(&self, method: Method, uri: &str, body: Option<Value>) -> (StatusCode, Value) {
+        let (client_id, secret) = &self.client;
+        let timestamp = self.state.clock.now().timestamp();
+        let body_hash = hex::encode(Sha256::digest(body.as_ref().map(Value::to_string).unwrap_or_default()));
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(format!("{}\n{}\n{}\n{}", timestamp, method, uri, body_hash).as_bytes());
+
+        let builder = builder(method, uri)
+            .header(request_signing::CLIENT_HEADER, client_id.to_string())
+            .header(request_signing::TIMESTAMP_HEADER, timestamp)
+            .header(request_signing::BODY_HASH_HEADER, body_hash)
+            .header(request_signing::SIGNATURE_HEADER, hex::encode(mac.finalize().into_bytes()));
+        self.send(builder, body).await
+    }
+
+    /// Sends a request as `user`, with a session token issued to them.
+    pub async fn as_user(&self, user: &Value, method: Method, uri: &str, body: Option<Value>) -> (StatusCode, Value) {
+        let builder = builder(method, uri).header(header::AUTHORIZATION, self.bearer(user));
+        self.send(builder, body).await
+    }
+
+    /// An `Authorization` header value for `user`, for requests that need
+    /// other headers too.
+    pub fn bearer(&self, user: &Value) -> String {
+        let user_id = user["id"].as_str().and_then(|id| id.parse().ok()).expect("a user with an id");
+        let (token, _) = self.state.sessions.issue(user_id, self.state.clock.now()).unwrap();
+        format!("Bearer {}", token)
+    }
+
+    pub async fn send(&self, builder: axum::http::request::Builder, body: Option<Value>) -> (StatusCode, Value) {
+        read(self.respond(builder, body).await).await
+    }
+
+    /// Sends a body that isn't JSON, as it is.
+    pub async fn send_bytes(
+        &self,
+        builder: axum::http::request::Builder,
+        content_type: &str,
+        body: Vec<u8>,
+    ) -> (StatusCode, Value) {
+        let request = builder.header(header::CONTENT_TYPE, content_type).body(Body::from(body)).unwrap();
+        read(self.router.clone().oneshot(request).await.unwrap()).await
+    }
+
+    /// Sends a request and returns the whole response, for tests that look
+    /// at its headers.
+    pub async fn respond(&self, builder: axum::http::request::Builder, body: Option<Value>) -> Response {
+        let request = match body {
+            Some(body) => builder
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string())),
+            None => builder.body(Body::empty()),
+        }
+        .unwrap();
+        self.router.clone().oneshot(request).await.unwrap()
+    }
+
+    /// Signs a user up, returning them as the API answered.
+    pub async fn create_user(&self, user_name: &str, email: &str) -> Value {
+        let (status, body) = self.post("/api/users", json!({"user_name": user_name, "email": email})).await;
+        assert_eq!(status, StatusCode::CREATED, "{}", body);
+        body["data"]["user"].clone()
+    }
+}
+
+impl Drop for TestApp {
+    fn drop(&mut self) {
+        let database = std::mem::take(&mut self.database);
+        // dropping can't wait on the test's runtime, so it gets one of its own
+        let _ = std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+            runtime.block_on(async {
+                if let Ok(mut conn) = server_options().connect().await {
+                    let _ = sqlx::query(&format!("DROP DATABASE IF EXISTS \"{}\" WITH (FORCE)", database))
+                        .execute(&mut conn)
+                        .await;
+                }
+            });
+        })
+        .join();
+    }
+}
+
+async fn read(response: Response) -> (StatusCode, Value) {
+    let status = response.status();
+    let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body = serde_json::from_slice(&bytes).unwrap_or_else(|_| json!(String::from_utf8_lossy(&bytes)));
+    (status, body)
+}
+
+pub fn builder(method: Method, uri: &str) -> axum::http::request::Builder {
+    Request::builder()
+        .method(method)
+        .uri(uri)
+        .extension(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 40000))))
+}
+
+fn server_options() -> PgConnectOptions {
+    let url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set to run the database tests");
+    PgConnectOptions::from_str(&url).expect("DATABASE_URL is a Postgres URL")
+}
@@ -0,0 +1,86 @@
+// How timestamps look in responses: RFC 3339 in UTC with a `Z` and
+// millisecond precision, e.g. `2026-10-15T09:30:00.000Z`, whatever
+// precision the database or clock had. Model fields opt in with
+// `#[serde(with = "crate::timestamp")]`, or `crate::timestamp::option` for
+// nullable ones. Input accepts RFC 3339 with any offset, or unix seconds.
+
+use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+use serde::{de, Deserialize, Deserializer, Serializer};
+use serde_json::Value;
+
+pub fn format(at: &DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// An RFC 3339 timestamp in any offset, or seconds since the unix epoch.
+pub fn parse(input: &str) -> Option<DateTime<Utc>> {
+    let input = input.trim();
+    match input.parse::<i64>() {
+        Ok(secs) => Utc.timestamp_opt(secs, 0).single(),
+        Err(_) => DateTime::parse_from_rfc3339(input).ok().map(|at| at.with_timezone(&Utc)),
+    }
+}
+
+/// The JSON value for a timestamp, for responses built with `json!`.
+pub fn json(at: &DateTime<Utc>) -> Value {
+    Value::String(format(at))
+}
+
+pub fn serialize<S: Serializer>(at: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format(at))
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DateTime<Utc>, D::Error> {
+    // query strings hand every value over as a string, JSON bodies may send a number
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Input {
+        Secs(i64),
+        Text(String),
+    }
+
+    let parsed = match Input::deserialize(deserializer)? {
+        Input::Secs(secs) => Utc.timestamp_opt(secs, 0).single(),
+        Input::Text(text) => parse(&text),
+    };
+    parsed.ok_or_else(|| de::Error::custom("expected an RFC 3339 timestamp or unix seconds"))
+}
+
+pub mod option {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(at: &Option<DateTime<Utc>>, serializer: S) -> Result<S::Ok, S::Error> {
+        match at {
+            Some(at) => super::serialize(at, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error> {
+        #[derive(Deserialize)]
+        struct Wrapped(#[serde(with = "super")] DateTime<Utc>);
+
+        Ok(Option::<Wrapped>::deserialize(deserializer)?.map(|Wrapped(at)| at))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_utc_with_milliseconds() {
+        let at = Utc.timestamp_opt(1_760_520_600, 123_456_789).unwrap();
+        assert_eq!(format(&at), "2025-10-15T09:30:00.123Z");
+        assert_eq!(format(&Utc.timestamp_opt(1_760_520_600, 0).unwrap()), "2025-10-15T09:30:00.000Z");
+    }
+
+    #[test]
+    fn parses_offsets_and_epoch_seconds() {
+        let at = Utc.timestamp_opt(1_760_520_600, 0).unwrap();
+        assert_eq!(parse("2025-10-15T09:30:00Z"), Some(at));
+        assert_eq!(parse("2025-10-15T11:30:00+02:00"), Some(at));
+        assert_eq!(parse("1760520600"), Some(at));
+        assert_eq!(parse("yesterday"), None);
+    }
+}
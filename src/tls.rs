@@ -0,0 +1,112 @@
+use std::{net::SocketAddr, path::PathBuf};
+
+use axum::{
+    extract::{connect_info::IntoMakeServiceWithConnectInfo, Host},
+    http::{StatusCode, Uri},
+    response::{IntoResponse, Redirect, Response},
+    Router,
+};
+use axum_server::tls_rustls::RustlsConfig;
+use tokio::signal::unix::{signal, SignalKind};
+
+use crate::server;
+
+/// Serves HTTPS directly, for deployments without a proxy in front to
+/// terminate TLS. On when `TLS_CERT_PATH` and `TLS_KEY_PATH` both point at
+/// PEM files. `TLS_PORT` is 443 unless set, and `TLS_REDIRECT_PORT`, when
+/// set, also listens there in plain HTTP to send browsers over to HTTPS.
+pub struct Tls {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    port: u16,
+    redirect_port: Option<u16>,
+}
+
+impl Tls {
+    pub fn from_env() -> Option<Self> {
+        let cert_path = std::env::var("TLS_CERT_PATH").ok().filter(|path| !path.is_empty())?;
+        let key_path = std::env::var("TLS_KEY_PATH").ok().filter(|path| !path.is_empty())?;
+        let port = std::env::var("TLS_PORT")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(443);
+        let redirect_port = std::env::var("TLS_REDIRECT_PORT")
+            .ok()
+            .and_then(|value| value.parse().ok());
+
+        Some(Tls {
+            cert_path: cert_path.into(),
+            key_path: key_path.into(),
+            port,
+            redirect_port,
+        })
+    }
+
+    pub async fn serve(self, app: IntoMakeServiceWithConnectInfo<Router, SocketAddr>, tuning: &server::Tuning) {
+        let config = match RustlsConfig::from_pem_file(&self.cert_path, &self.key_path).await {
+            Ok(config) => config,
+            Err(err) => {
+                println!("🔥 Failed to load the TLS certificate: {}", err);
+                std::process::exit(1);
+            }
+        };
+        tokio::spawn(reload_on_hangup(config.clone(), self.cert_path, self.key_path));
+        if let Some(redirect_port) = self.redirect_port {
+            tokio::spawn(redirect_to_https(redirect_port, self.port));
+        }
+
+        tuning
+            .apply_tls(axum_server::bind_rustls(SocketAddr::from(([0, 0, 0, 0], self.port)), config))
+            .serve(app)
+            .await
+            .unwrap();
+    }
+}
+
+/// Picks up a renewed certificate on SIGHUP. Connections already open keep
+/// the one they started with, and a certificate that fails to load leaves
+/// the old one in place.
+async fn reload_on_hangup(config: RustlsConfig, cert_path: PathBuf, key_path: PathBuf) {
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(err) => {
+            println!("🔥 Cannot listen for SIGHUP, the TLS certificate won't reload: {}", err);
+            return;
+        }
+    };
+    while hangups.recv().await.is_some() {
+        match config.reload_from_pem_file(&cert_path, &key_path).await {
+            Ok(()) => println!("✅ Reloaded the TLS certificate"),
+            Err(err) => println!("🔥 Failed to reload the TLS certificate: {}", err),
+        }
+    }
+}
+
+async fn redirect_to_https(port: u16, https_port: u16) {
+    let app = Router::new().fallback(move |Host(host): Host, uri: Uri| async move {
+        https_location(&host, https_port, &uri)
+    });
+    axum::Server::bind(&SocketAddr::from(([0, 0, 0, 0], port)))
+        .serve(app.into_make_service())
+        .await
+        .unwrap();
+}
+
+fn https_location(host: &str, https_port: u16, uri: &Uri) -> Response {
+    // drop whatever port the plain request came in on, brackets and all for IPv6
+    let hostname = match host.rsplit_once(':') {
+        Some((name, port)) if !port.contains(']') => name,
+        _ => host,
+    };
+    let authority = if https_port == 443 {
+        hostname.to_string()
+    } else {
+        format!("{}:{}", hostname, https_port)
+    };
+    let path = uri.path_and_query().map(|path| path.as_str()).unwrap_or("/");
+
+    match format!("https://{}{}", authority, path).parse::<Uri>() {
+        Ok(location) => Redirect::permanent(&location.to_string()).into_response(),
+        Err(_) => StatusCode::BAD_REQUEST.into_response(),
+    }
+}
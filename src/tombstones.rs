@@ -0,0 +1,72 @@
+use std::{sync::Arc, time::Duration};
+
+use crate::{scheduler, AppState};
+
+/// How long tombstones of deleted users and invitations are kept. A client
+/// that syncs from a cursor older than that may miss deletions and should
+/// fetch everything again.
+pub struct Tombstones {
+    retention_days: i32,
+}
+
+impl Tombstones {
+    /// Kept for `TOMBSTONE_RETENTION_DAYS`, 90 unless set.
+    pub fn from_env() -> Self {
+        let retention_days = std::env::var("TOMBSTONE_RETENTION_DAYS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .filter(|days| *days > 0)
+            .unwrap_or(90);
+
+        Tombstones { retention_days }
+    }
+}
+
+pub fn spawn_pruning(data: Arc<AppState>) {
+    scheduler::every("tombstone_pruning", Duration::from_secs(60 * 60), data, |data| async move {
+        let pruned = sqlx::query!(
+            "DELETE FROM tombstones WHERE deleted_at < $1",
+            data.clock.now() - chrono::Duration::days(data.tombstones.retention_days.into())
+        )
+        .execute(&data.db)
+        .await?
+        .rows_affected();
+        if pruned > 0 {
+            println!("✅ Pruned {} tombstones", pruned);
+        }
+        Ok(())
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+
+    use crate::{model::TombstoneModel, testing::TestApp};
+
+    #[tokio::test]
+    async fn deleting_a_user_leaves_a_tombstone() {
+        let app = TestApp::new().await;
+        let user = app.create_user("ada", "ada@example.com").await;
+        let id: uuid::Uuid = user["id"].as_str().unwrap().parse().unwrap();
+
+        let (status, _) = app.as_user(&user, Method::DELETE, &format!("/api/user/{}", id), None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        let tombstone = sqlx::query_as!(
+            TombstoneModel,
+            "SELECT entity_type, entity_id, org_id, deleted_at FROM tombstones WHERE entity_id = $1",
+            id
+        )
+        .fetch_one(app.db())
+        .await
+        .unwrap();
+        assert_eq!(tombstone.entity_type, "user");
+        assert_eq!(tombstone.org_id, None);
+
+        // the sync feed lists it with the time it was deleted
+        let (_, sync) = app.get("/api/sync?since=0").await;
+        assert_eq!(sync["deleted"][0]["id"], user["id"]);
+        assert!(sync["deleted"][0]["deletedAt"].is_string(), "{}", sync);
+    }
+}
@@ -0,0 +1,70 @@
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Path, Query},
+    http::request::Parts,
+};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{error::AppError, AppState};
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum RefType {
+    Id,
+    UserName,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct RefParams {
+    #[serde(rename = "type")]
+    ref_type: Option<RefType>,
+}
+
+/// The user the `:id` path segment of a user route names, by id or by user
+/// name. `?type=id` or `?type=user_name` says which when it could be both,
+/// without it a segment that is one user's id and another's name is turned
+/// away rather than guessed at. Only resolves for users that exist.
+#[derive(Debug, Clone, Copy)]
+pub struct UserRef(pub Uuid);
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for UserRef {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let Path(params) = Path::<HashMap<String, String>>::from_request_parts(parts, state)
+            .await
+            .map_err(AppError::PathInvalid)?;
+        let Query(RefParams { ref_type }) = Query::<RefParams>::from_request_parts(parts, state)
+            .await
+            .map_err(AppError::QueryInvalid)?;
+        let raw = params.get("id").cloned().unwrap_or_default();
+
+        let as_id = match ref_type {
+            Some(RefType::UserName) => None,
+            _ => raw.parse::<Uuid>().ok(),
+        };
+        let by_name = ref_type != Some(RefType::Id);
+
+        let matches = sqlx::query_scalar!(
+            "SELECT id FROM users WHERE id = $1 OR ($2 AND user_name = $3)",
+            as_id,
+            by_name,
+            raw
+        )
+        .fetch_all(&state.db)
+        .await?;
+
+        match matches.as_slice() {
+            [id] => Ok(UserRef(*id)),
+            [] => Err(AppError::UserNotFound(raw)),
+            _ => Err(AppError::UserIdAmbiguous(raw)),
+        }
+    }
+}